@@ -6,12 +6,19 @@ use constants::{
         FileStat, FsStat, InodeMode, IoVec, MountFlags, OpenFlags, Renameat2Flags, SeekFrom,
         StatFlags,
     },
-    AlienResult, LinuxErrno, AT_FDCWD,
+    signal::SignalNumber,
+    AlienResult, LinuxErrno, RLimitResource, AT_FDCWD, RLIM_INFINITY,
 };
 use gmanager::ManagerError;
 use log::{info, warn};
 use syscall_table::syscall_func;
-use vfs::{eventfd::eventfd, kfile::KernelFile, system_root_fs};
+use vfs::{
+    eventfd::eventfd,
+    kfile::{File, KernelFile},
+    page_cache::find_page_cache,
+    perm::{apply_inode_owner, set_inode_owner},
+    system_root_fs,
+};
 use vfscore::{
     path::VfsPath,
     utils::{VfsFileStat, VfsFsStat, VfsNodeType, VfsRenameFlag},
@@ -19,11 +26,21 @@ use vfscore::{
 
 use super::im2vim;
 use crate::{
-    fs::{syscontext_for_vfs, user_path_at},
+    fs::{
+        file_inode,
+        perm::{
+            inode_permission, may_rename, new_inode_owner, parent_path_at, MAY_EXEC, MAY_READ,
+            MAY_WRITE,
+        },
+        syscontext_for_vfs, user_path_at,
+    },
+    ipc::send_signal,
     task::current_task,
 };
 
-/// 用于将一个设备(通常是存储设备)挂载到一个已经存在的目录上，可以挂载文件系统。
+/// 用于将一个设备(通常是存储设备)挂载到一个已经存在的目录上，可以挂载文件系统。只有超级用户可以挂载文件系统。
+///
+/// 块设备上的文件系统(`diskfs` 以及 `fat32`、`ext4` 等实际的类型名)可以挂载 `/dev` 中任意的磁盘或分区。
 #[syscall_func(40)]
 pub fn sys_mount(
     source: *const u8,
@@ -33,6 +50,9 @@ pub fn sys_mount(
     data: *const u8,
 ) -> AlienResult<isize> {
     let task = current_task().unwrap();
+    if !task.access_inner().cred.is_root() {
+        return Err(LinuxErrno::EPERM);
+    }
     let source = task.transfer_str(source);
     let dir = task.transfer_str(dir);
     let fs_type = task.transfer_str(fs_type);
@@ -42,37 +62,40 @@ pub fn sys_mount(
         "mount special:{:?},dir:{:?},fs_type:{:?},flags:{:?},data:{:?}",
         source, dir, fs_type, flags, data
     );
-    let find = vfs::system_support_fs(&fs_type).ok_or(LinuxErrno::EINVAL)?;
     let path = VfsPath::new(vfs::system_root_fs(), system_root_fs());
-    let fs_root = match find.fs_name().as_str() {
-        name @ ("tmpfs" | "ramfs" | "fat32") => {
-            let fs = vfs::system_support_fs(name).unwrap();
-            let dev = if name.eq("fat32") {
-                let dev = path.join(source)?.open(None)?;
-                Some(dev.inode()?)
-            } else {
-                None
-            };
-            let new_fs = fs.i_mount(0, &dir, dev, &[])?;
-            new_fs
-        }
-        _ => return Err(LinuxErrno::EINVAL),
-    };
-    path.join(dir)?.mount(fs_root, flags.bits())?;
+    let fs_root = vfs::mount_fs(&fs_type, &source, &dir)?;
+    let res = path
+        .join(dir)
+        .and_then(|target| target.mount(fs_root.clone(), flags.bits()));
+    if let Err(e) = res {
+        vfs::unregister_disk_fs(&fs_root);
+        return Err(e.into());
+    }
     Ok(0)
 }
 
-/// 用于取消一个目录上的文件挂载(卸载一个文件系统)。
+/// 用于取消一个目录上的文件挂载(卸载一个文件系统)。只有超级用户可以卸载文件系统。
 #[syscall_func(39)]
 pub fn sys_umount(dir: *const u8) -> AlienResult<isize> {
     let process = current_task().unwrap();
+    if !process.access_inner().cred.is_root() {
+        return Err(LinuxErrno::EPERM);
+    }
     let dir = process.transfer_str(dir);
     info!("umount dir:{:?}", dir);
-    let path = VfsPath::new(vfs::system_root_fs(), system_root_fs());
-    path.join(dir)?.umount()?;
+    let path = VfsPath::new(vfs::system_root_fs(), system_root_fs()).join(dir)?;
+    // write back the dirty data before the filesystem is gone
+    let fs_root = path.open(None)?;
+    vfs::sync_fs(&fs_root.inode()?)?;
+    path.umount()?;
+    vfs::unregister_disk_fs(&fs_root);
     Ok(0)
 }
 
+/// 一个系统调用，用于打开或者创建一个文件。
+///
+/// 打开已经存在的文件时，根据 `flag` 中的访问模式检查当前任务对文件的读写权限；创建新文件时，需要对所在目录有写和执行权限，
+/// 新文件的属主为当前任务的有效用户号和有效用户组号。没有权限时返回 `EACCES`。
 #[syscall_func(56)]
 pub fn sys_openat(dirfd: isize, path: *const u8, flag: usize, mode: u32) -> AlienResult<isize> {
     if path.is_null() {
@@ -96,10 +119,27 @@ pub fn sys_openat(dirfd: isize, path: *const u8, flag: usize, mode: u32) -> Alie
         file_mode
     );
 
-    let dentry = path.open(file_mode)?;
-    let file = KernelFile::new(dentry, flag);
+    let dentry = match path.open(None) {
+        Ok(dentry) => {
+            inode_permission(&dentry.inode()?, open_permission_mask(flag))?;
+            path.open(file_mode)?
+        }
+        Err(_) if file_mode.is_some() => {
+            let parent = parent_path_at(dirfd, &path_str)?.open(None)?;
+            inode_permission(&parent.inode()?, MAY_WRITE | MAY_EXEC)?;
+            let dentry = path.open(file_mode)?;
+            // 文件系统无法保存属主时，新文件使用文件系统默认的属主和权限位
+            let _ = set_inode_owner(&dentry.inode()?, new_inode_owner(mode));
+            dentry
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let file: Arc<dyn File> = match vfs::dev::open_device(&dentry, flag) {
+        Some(file) => file?,
+        None => Arc::new(KernelFile::new(dentry, flag)),
+    };
 
-    let fd = process.add_file(Arc::new(file));
+    let fd = process.add_file(file);
     warn!("openat fd: {:?}", fd);
     if fd.is_err() {
         let error = ManagerError::from((fd.unwrap_err()) as usize);
@@ -113,6 +153,20 @@ pub fn sys_openat(dirfd: isize, path: *const u8, flag: usize, mode: u32) -> Alie
     }
 }
 
+/// 打开文件时需要的访问权限，`O_TRUNC` 需要写权限
+fn open_permission_mask(flag: OpenFlags) -> u32 {
+    let mask = match flag.bits() & 0b11 {
+        0 => MAY_READ,
+        1 => MAY_WRITE,
+        _ => MAY_READ | MAY_WRITE,
+    };
+    if flag.contains(OpenFlags::O_TRUNC) {
+        mask | MAY_WRITE
+    } else {
+        mask
+    }
+}
+
 /// 一个系统调用，用于关闭一个文件描述符，以便回收该文件描述符。
 ///
 /// 传入的文件描述符`fd`指向要关闭的文件。如果`fd`所指向的文件已经被`unlink`，
@@ -174,7 +228,15 @@ pub fn sys_truncate(path: usize, len: usize) -> AlienResult<isize> {
     let process = current_task().unwrap();
     let path = process.transfer_str(path as *const u8);
     let path = user_path_at(AT_FDCWD, &path)?;
+    if len as u64 > file_size_limit() {
+        return Err(file_too_large());
+    }
+    let inode = path.open(None)?.inode()?;
+    inode_permission(&inode, MAY_WRITE)?;
     path.truncate(len as u64)?;
+    if let Some(cache) = find_page_cache(&inode) {
+        cache.truncate(len);
+    }
     Ok(0)
 }
 
@@ -191,6 +253,9 @@ pub fn sys_truncate(path: usize, len: usize) -> AlienResult<isize> {
 pub fn sys_ftruncate(fd: usize, len: usize) -> AlienResult<isize> {
     let process = current_task().unwrap();
     let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    if len as u64 > file_size_limit() {
+        return Err(file_too_large());
+    }
     file.truncate(len as u64)?;
     Ok(0)
 }
@@ -230,6 +295,7 @@ pub fn sys_read(fd: usize, buf: *mut u8, len: usize) -> AlienResult<isize> {
 pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
     let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    let len = fsize_limited_len(&file, None, len)?;
     let mut buf = task.transfer_buffer(buf, len);
     let mut count = 0;
     for b in buf.iter_mut() {
@@ -243,6 +309,48 @@ pub fn sys_write(fd: usize, buf: *const u8, len: usize) -> AlienResult<isize> {
     Ok(count as _)
 }
 
+/// 当前进程 `RLIMIT_FSIZE` 的软限制
+fn file_size_limit() -> u64 {
+    current_task()
+        .unwrap()
+        .access_inner()
+        .get_prlimit(RLimitResource::RlimitFsize)
+        .rlim_cur
+}
+
+/// 文件长度将超过 `RLIMIT_FSIZE` 时向当前线程发送 `SIGXFSZ`，并返回 `EFBIG`
+fn file_too_large() -> LinuxErrno {
+    let tid = current_task().unwrap().get_tid() as usize;
+    send_signal(tid, SignalNumber::SIGXFSZ as usize);
+    LinuxErrno::EFBIG
+}
+
+/// 按照 `RLIMIT_FSIZE` 限制向普通文件写入的长度，其它类型的文件不受限制。
+///
+/// `offset` 为 `None` 时从文件当前的偏移量(以追加方式打开时为文件末尾)开始写入。
+/// 写入位置已经达到限制时返回 `EFBIG`，否则返回不超过限制的写入长度。
+fn fsize_limited_len(file: &Arc<dyn File>, offset: Option<u64>, len: usize) -> AlienResult<usize> {
+    let limit = file_size_limit();
+    if limit == RLIM_INFINITY || len == 0 {
+        return Ok(len);
+    }
+    let Ok(kfile) = file.clone().downcast_arc::<KernelFile>() else {
+        return Ok(len);
+    };
+    if kfile.inode().inode_type() != VfsNodeType::File {
+        return Ok(len);
+    }
+    let offset = match offset {
+        Some(offset) => offset,
+        None if kfile.is_append() => kfile.get_attr()?.st_size as u64,
+        None => kfile.seek(SeekFrom::Current(0))?,
+    };
+    if offset >= limit {
+        return Err(file_too_large());
+    }
+    Ok(min(len as u64, limit - offset) as usize)
+}
+
 /// 一个系统调用，用于获取当前工作目录。
 /// 获取的工作目录将直接保存在 `buf` 所指向的缓冲区中，`len` 用于指明 `buf` 的长度。
 ///
@@ -315,13 +423,17 @@ pub fn sys_fchdir(fd: usize) -> AlienResult<isize> {
 #[syscall_func(34)]
 pub fn sys_mkdirat(dirfd: isize, path: *const u8, mode: u32) -> AlienResult<isize> {
     let process = current_task().unwrap();
-    let path = process.transfer_str(path);
+    let path_str = process.transfer_str(path);
+    let perm = mode;
     let mut mode = InodeMode::from_bits_truncate(mode);
-    warn!("mkdirat path: {}, mode: {:?}", path, mode);
-    let path = user_path_at(dirfd, &path)?;
+    warn!("mkdirat path: {}, mode: {:?}", path_str, mode);
+    let path = user_path_at(dirfd, &path_str)?;
     mode |= InodeMode::DIR;
     assert_eq!(mode & InodeMode::TYPE_MASK, InodeMode::DIR);
-    let _ = path.open(Some(im2vim(mode)))?;
+    let parent = parent_path_at(dirfd, &path_str)?.open(None)?;
+    inode_permission(&parent.inode()?, MAY_WRITE | MAY_EXEC)?;
+    let dentry = path.open(Some(im2vim(mode)))?;
+    let _ = set_inode_owner(&dentry.inode()?, new_inode_owner(perm));
     Ok(0)
 }
 
@@ -367,12 +479,19 @@ pub fn sys_writev(fd: usize, iovec: usize, iovcnt: usize) -> AlienResult<isize>
             // busybox 可能会给stdout两个io_vec，第二个是空地址
             continue;
         }
-        let len = iov.len;
+        let len = match fsize_limited_len(&file, None, iov.len) {
+            Ok(len) => len,
+            Err(_) if count > 0 => break,
+            Err(e) => return Err(e),
+        };
         let buf = process.transfer_buffer(base as *mut u8, len);
         for b in buf.iter() {
             let r = file.write(b)?;
             count += r;
         }
+        if len < iov.len {
+            break;
+        }
     }
     Ok(count as isize)
 }
@@ -438,6 +557,7 @@ pub fn sys_pread(fd: usize, buf: usize, count: usize, offset: u64) -> AlienResul
 pub fn sys_pwrite(fd: usize, buf: usize, count: usize, offset: u64) -> AlienResult<isize> {
     let task = current_task().unwrap();
     let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    let count = fsize_limited_len(&file, Some(offset), count)?;
     let buf = task.transfer_buffer(buf as *mut u8, count);
     let mut offset = offset;
     let mut count = 0;
@@ -473,7 +593,9 @@ pub fn sys_fstateat(
     let path = user_path_at(dir_fd, &path)?;
 
     let dt = path.open(None)?;
-    let attr = dt.inode()?.get_attr()?;
+    let inode = dt.inode()?;
+    let mut attr = inode.get_attr()?;
+    apply_inode_owner(&inode, &mut attr);
 
     let mut file_stat = FileStat::default();
     unsafe {
@@ -518,7 +640,7 @@ pub fn sys_fstatfs(fd: isize, buf: *mut u8) -> AlienResult<isize> {
     let process = current_task().unwrap();
     let buf = process.transfer_raw_ptr(buf as *mut FsStat);
     let file = process.get_file(fd as usize).ok_or(LinuxErrno::EBADF)?;
-    let fs_stat = file.inode().get_super_block()?.stat_fs()?;
+    let fs_stat = file_inode(&file)?.get_super_block()?.stat_fs()?;
     unsafe {
         (&mut *buf as *mut FsStat as *mut usize as *mut VfsFsStat).write(fs_stat);
     }
@@ -566,6 +688,7 @@ pub fn sys_renameat(
         "renameat2: {:?} {:?} {:?} {:?}",
         old_dirfd, old_path, new_dirfd, new_path
     );
+    may_rename(old_dirfd, &old_path, new_dirfd, &new_path)?;
     let old_path = user_path_at(old_dirfd, &old_path)?;
     let new_path = user_path_at(new_dirfd, &new_path)?;
     old_path.rename_to(
@@ -597,6 +720,7 @@ pub fn sys_renameat2(
         "renameat2: {:?} {:?} {:?} {:?}, flag: {:?}",
         old_dirfd, old_path, new_dirfd, new_path, flag
     );
+    may_rename(old_dirfd, &old_path, new_dirfd, &new_path)?;
     let old_path = user_path_at(old_dirfd, &old_path)?;
     let new_path = user_path_at(new_dirfd, &new_path)?;
 
@@ -640,6 +764,7 @@ pub fn send_file(
     if !(in_file.is_readable() && out_file.is_writable()) {
         return Err(LinuxErrno::EBADF);
     }
+    let count = fsize_limited_len(&out_file, None, count)?;
 
     let mut buf = vec![0u8; count];
 
@@ -660,18 +785,35 @@ pub fn send_file(
 }
 
 /// 一个系统调用函数，用于包把含更新文件的所有内核缓冲区(包含数据块、指针块、元数据等)都flush到磁盘上。
+///
+/// 函数会阻塞直到所有数据都已写入设备。
 #[syscall_func(81)]
-pub fn sync() -> isize {
-    0
+pub fn sync() -> AlienResult<isize> {
+    vfs::sync_all()?;
+    Ok(0)
 }
 
 /// 用于把打开的文件描述符fd相关的所有缓冲元数据和数据都刷新到磁盘上。
+///
+/// 函数会阻塞直到数据已写入设备。
 #[syscall_func(82)]
 pub fn fsync(fd: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
     let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
-    let fs = file.inode().get_super_block()?;
-    fs.sync_fs(false)?;
+    vfs::fsync_inode(&file_inode(&file)?)?;
+    Ok(0)
+}
+
+/// 用于把打开的文件描述符fd所在文件系统的所有缓冲元数据和数据都刷新到磁盘上。
+///
+/// 函数会阻塞直到数据已写入设备。
+///
+/// Reference: [syncfs](https://man7.org/linux/man-pages/man2/syncfs.2.html)
+#[syscall_func(267)]
+pub fn syncfs(fd: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    vfs::sync_fs(&file_inode(&file)?)?;
     Ok(0)
 }
 
@@ -694,6 +836,12 @@ pub fn copy_file_range(
     if !(in_file.is_readable() && out_file.is_writable()) {
         return Err(LinuxErrno::EBADF);
     }
+    let out_offset = if off_out_ptr == 0 {
+        None
+    } else {
+        Some(*task.transfer_raw_ptr(off_out_ptr as *mut u64))
+    };
+    let len = fsize_limited_len(&out_file, out_offset, len)?;
     let mut buf = vec![0u8; len];
     let r = if off_in_ptr == 0 {
         in_file.read(&mut buf)?
@@ -8,9 +8,18 @@ use syscall_table::syscall_func;
 use timer::{TimeNow, ToVfsTimeSpec};
 use vfscore::utils::*;
 
-use crate::{fs::user_path_at, task::current_task};
+use crate::{
+    fs::{
+        file_inode,
+        perm::{check_permission, chmod_inode, chown_inode, MAY_EXEC, MAY_READ, MAY_WRITE},
+        user_path_at,
+    },
+    task::current_task,
+};
 
 const FD_CLOEXEC: usize = 1;
+/// `faccessat` 使用有效用户号和有效用户组号进行检查
+const AT_EACCESS: u32 = 0x200;
 
 /// 一个系统调用，用于对一个文件提供控制。
 ///
@@ -194,9 +203,10 @@ pub fn utimensat(
 /// 一个系统调用，用于检测当前进程是否有权限访问一个文件。
 ///
 /// 文件的路径由 `dirfd` 和 `path` 解析得到。解析相关信息可见 [`user_path_at`]。
-/// 目前 `mode` 和 `flag` 都未使用。仅检测当前进程是否有打开对应路径下文件的权限。
+/// `mode` 为 `F_OK` 时仅检查文件是否存在，否则按照 `R_OK`、`W_OK`、`X_OK` 检查对应的权限。
+/// 与其它权限检查不同，默认使用实际用户号和实际用户组号进行检查，`flag` 中包含 `AT_EACCESS` 时使用有效用户号和有效用户组号。
 ///
-/// 如果有打开的权限，则返回 0；否则返回 -1。
+/// 如果有对应的权限，则返回 0；文件不存在时返回 `ENOENT`，没有权限时返回 `EACCES`。
 #[syscall_func(48)]
 pub fn faccessat(dirfd: isize, path: usize, mode: usize, flag: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
@@ -208,25 +218,37 @@ pub fn faccessat(dirfd: isize, path: usize, mode: usize, flag: usize) -> AlienRe
         path, flag, mode
     );
     // todo! check the AT_SYMLINK_NOFOLLOW flag
-    let _path = user_path_at(dirfd, &path)?.open(None)?;
+    let dentry = user_path_at(dirfd, &path)?.open(None)?;
+    let mask = mode.bits() as u32 & (MAY_READ | MAY_WRITE | MAY_EXEC);
+    if mask != 0 {
+        let mut cred = task.credentials();
+        if flag.bits() as u32 & AT_EACCESS == 0 {
+            cred.euid = cred.ruid;
+            cred.egid = cred.rgid;
+        }
+        check_permission(&cred, &dentry.inode()?, mask)?;
+    }
     Ok(0)
 }
 
-/// 一个系统调用函数，用于修改文件或目录的权限。(待实现)
+/// 一个系统调用函数，用于修改文件或目录的权限。
 ///
 /// 在Alien系统中，每个文件或目录都有一个权限位，
 /// 用于控制该文件或目录的访问权限。sys_chmod函数可以用于修改这些权限位。
 ///
 /// sys_chmod函数需要传入两个参数：第一个参数是需要要修改的文件的文件描述符，
-/// 第二个参数是新的权限值。
+/// 第二个参数是新的权限值。只有文件的属主或者超级用户可以修改权限，否则返回 `EPERM`。
 ///
 /// Reference: [chmod](https:///man7.org/linux/man-pages/man2/chmod.2.html)
 #[syscall_func(52)]
-pub fn chmod(_fd: usize, _mode: usize) -> isize {
-    0
+pub fn chmod(fd: usize, mode: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    chmod_inode(&file_inode(&file)?, mode as u32)?;
+    Ok(0)
 }
 
-/// (待实现)一个系统调用函数，用于修改相对于某目录某位置处文件或目录的权限。
+/// 一个系统调用函数，用于修改相对于某目录某位置处文件或目录的权限。
 ///
 /// 当传入的`path`是一个相对地址时，那么`path`会被解析成基于文件描述符`dirfd`
 /// 所指向的目录地址的一个地址；当传入的`path`是一个相对地址并且
@@ -241,23 +263,51 @@ pub fn chmod(_fd: usize, _mode: usize) -> isize {
 /// `flag`处可以传入的值及其含义包括：
 /// + AT_SYMLINK_NOFOLLOW: 0x200，如果`path`解析之后指向的文件是一个软链接时，不对软链接进行解析，直接修改该文件的权限
 ///
-/// `flag`可以置为AT_SYMLINK_NOFOLLOW或者为0。
+/// `flag`可以置为AT_SYMLINK_NOFOLLOW或者为0。权限规则与 [`chmod`] 相同。
 ///
 /// Reference: [chmod](https:///man7.org/linux/man-pages/man2/chmod.2.html)
 #[syscall_func(53)]
-pub fn chmodat(_dirfd: usize, _path: usize, _mode: usize, _flags: usize) -> isize {
-    0
+pub fn chmodat(dirfd: isize, path: *const u8, mode: usize, _flags: usize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let path = task.transfer_str(path);
+    let dentry = user_path_at(dirfd, &path)?.open(None)?;
+    chmod_inode(&dentry.inode()?, mode as u32)?;
+    Ok(0)
 }
 
-/// 一个系统调用，用于获取并设置当前进程的 `unmask`。在一个进程中，unmask 用于定义新建文件或目录的默认权限。
-/// 每次新建一个文件时，文件的默认权限是由 unmask 的值决定的。如果 unmask 值的某位被设置，在新建文件或目录时将禁用对应的权限。
+/// 一个系统调用函数，用于修改相对于某目录某位置处文件的属主和属组，路径的解析规则与 [`chmodat`] 相同。
 ///
-/// 函数执行成功后，将会把当前进程的 unmask 值置为传入的 `unmask`，同时返回原来的 unmask 值。
+/// `uid`/`gid` 为 -1 时表示不修改。只有超级用户可以修改属主，文件的属主可以将属组修改为自己所在的用户组，否则返回 `EPERM`。
+///
+/// Reference: [chown](https://man7.org/linux/man-pages/man2/chown.2.html)
+#[syscall_func(54)]
+pub fn fchownat(
+    dirfd: isize,
+    path: *const u8,
+    uid: u32,
+    gid: u32,
+    _flags: usize,
+) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let path = task.transfer_str(path);
+    let dentry = user_path_at(dirfd, &path)?.open(None)?;
+    chown_inode(&dentry.inode()?, uid, gid)?;
+    Ok(0)
+}
+
+/// 一个系统调用函数，用于修改文件描述符 `fd` 指向的文件的属主和属组，权限规则与 [`fchownat`] 相同。
 #[syscall_func(55)]
-pub fn fchown() -> isize {
-    0
+pub fn fchown(fd: usize, uid: u32, gid: u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
+    chown_inode(&file_inode(&file)?, uid, gid)?;
+    Ok(0)
 }
 
+/// 一个系统调用，用于获取并设置当前进程的 `unmask`。在一个进程中，unmask 用于定义新建文件或目录的默认权限。
+/// 每次新建一个文件时，文件的默认权限是由 unmask 的值决定的。如果 unmask 值的某位被设置，在新建文件或目录时将禁用对应的权限。
+///
+/// 函数执行成功后，将会把当前进程的 unmask 值置为传入的 `unmask`，同时返回原来的 unmask 值。
 #[syscall_func(166)]
 pub fn unmask(unmask: usize) -> isize {
     let task = current_task().unwrap();
@@ -7,7 +7,13 @@ use constants::{
 use log::{info, warn};
 use syscall_table::syscall_func;
 
-use crate::{fs::user_path_at, task::current_task};
+use crate::{
+    fs::{
+        perm::{inode_permission, may_delete, parent_path_at, MAY_EXEC, MAY_WRITE},
+        user_path_at,
+    },
+    task::current_task,
+};
 /// 一个系统调用，用于创建相对于一个目录某位置处的一个文件的(硬)链接。
 ///
 /// 当传入的 `old_name` 是一个相对地址时，那么 `old_name` 会被解析成基于文件描述符 `old_fd`
@@ -49,7 +55,8 @@ pub fn sys_linkat(
     );
 
     let old_dt = old_path.open(None)?;
-
+    let parent = parent_path_at(new_fd, &new_name)?.open(None)?;
+    inode_permission(&parent.inode()?, MAY_WRITE | MAY_EXEC)?;
     new_path.link(old_dt)?;
     Ok(0)
 }
@@ -76,6 +83,9 @@ pub fn sys_linkat(
 ///
 /// `flag`可以置为AT_REMOVEDIR或者为0。
 ///
+/// 删除链接需要对所在目录有写和执行权限，否则返回 `EACCES`；如果目录设置了 sticky 位，
+/// 只有文件的属主、目录的属主或者超级用户可以删除，否则返回 `EPERM`。
+///
 /// 如果成功删除文件链接，`sys_linkat`将返回0；否则返回-1或错误类型。
 ///
 /// Reference:
@@ -85,10 +95,13 @@ pub fn sys_linkat(
 #[syscall_func(35)]
 pub fn sys_unlinkat(fd: isize, path: *const u8, flag: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
-    let path = task.transfer_str(path);
+    let path_str = task.transfer_str(path);
     let flag = UnlinkatFlags::from_bits_truncate(flag as u32);
-    info!("unlinkat path: {:?}, flag: {:?}", path, flag);
-    let path = user_path_at(fd, &path)?;
+    info!("unlinkat path: {:?}, flag: {:?}", path_str, flag);
+    let path = user_path_at(fd, &path_str)?;
+    let inode = path.open(None)?.inode()?;
+    let dir = parent_path_at(fd, &path_str)?.open(None)?.inode()?;
+    may_delete(&dir, &inode)?;
     if flag.contains(UnlinkatFlags::AT_REMOVEDIR) {
         path.rmdir()?;
     } else {
@@ -2,21 +2,36 @@ pub mod basic;
 pub mod control;
 pub mod ext;
 pub mod link;
+pub mod perm;
 pub mod poll;
+pub mod proc;
 pub mod select;
 pub mod stdio;
 
-use alloc::vec::Vec;
+use alloc::{sync::Arc, vec::Vec};
 
 use constants::{io::InodeMode, AlienResult, LinuxErrno, AT_FDCWD};
-use log::info;
-use vfs::system_root_fs;
+use log::{info, warn};
+use platform::config::CLOCK_FREQ;
+use timer::read_timer;
+use vfs::{
+    kfile::{File, KernelFile},
+    system_root_fs,
+};
 use vfscore::{
+    inode::VfsInode,
     path::{SysContext, VfsPath},
     utils::{VfsInodeMode, VfsNodeType},
 };
 
-use crate::task::{current_task, FsContext};
+use crate::{
+    fs::perm::may_lookup,
+    task::{current_task, FsContext},
+    time::sleep_until,
+};
+
+/// 写回内核线程将脏数据写回设备的间隔(秒)
+const WRITEBACK_INTERVAL: usize = 5;
 
 /// 地址解析函数，通过 `fd` 所指向的一个目录文件 和 相对于该目录文件的路径或绝对路径 `path` 解析出某目标文件的绝对路径。
 ///
@@ -26,22 +41,35 @@ use crate::task::{current_task, FsContext};
 /// 在`Alien`使用的`rvfs`中，对一个文件路径`path`是相对路径还是绝对路径的的判断条件如下：
 /// + 绝对路径：以`/`开头，如`/file1.txt`，表示根目录下的`file1.txt`文件；
 /// + 相对路径: 以`./`或者`../`或者其它开头，如`./file1.txt`，表示`dirfd`所指向的目录下的`file1.txt`文件。
-fn user_path_at(fd: isize, path: &str) -> AlienResult<VfsPath> {
+///
+/// 解析时经过的目录需要有搜索权限，否则返回`EACCES`，具体可见 [`may_lookup`]。
+pub fn user_path_at(fd: isize, path: &str) -> AlienResult<VfsPath> {
     info!("user_path_at fd: {},path:{}", fd, path);
     let process = current_task().unwrap();
-    let res = if !path.starts_with("/") {
+    let base = if !path.starts_with("/") {
         if fd == AT_FDCWD {
-            let fs_context = process.access_inner().fs_info.clone();
-            VfsPath::new(system_root_fs(), fs_context.cwd).join(path)
+            process.access_inner().fs_info.cwd.clone()
         } else {
             let fd = fd as usize;
             let file = process.get_file(fd).ok_or(LinuxErrno::EBADF)?;
-            VfsPath::new(system_root_fs(), file.dentry()).join(path)
+            file.dentry()
         }
     } else {
-        VfsPath::new(system_root_fs(), system_root_fs()).join(path)
+        system_root_fs()
     };
-    res.map_err(|e| e.into())
+    may_lookup(&base, path)?;
+    VfsPath::new(system_root_fs(), base)
+        .join(path)
+        .map_err(|e| e.into())
+}
+
+/// 获取通过路径打开的文件 `file` 的 inode
+///
+/// epoll、eventfd、pidfd、套接字以及 BPF 等文件没有 inode，对它们调用 [`File::inode`] 会 panic，此时返回 `EINVAL`。
+pub fn file_inode(file: &Arc<dyn File>) -> AlienResult<Arc<dyn VfsInode>> {
+    file.downcast_ref::<KernelFile>()
+        .map(|file| file.inode())
+        .ok_or(LinuxErrno::EINVAL)
 }
 
 pub fn read_all(file_name: &str, buf: &mut Vec<u8>) -> bool {
@@ -77,7 +105,7 @@ pub fn read_all(file_name: &str, buf: &mut Vec<u8>) -> bool {
 }
 
 /// [InodeMode](InodeMode)转换为[VfsInodeMode](VfsInodeMode)
-fn im2vim(mode: InodeMode) -> VfsInodeMode {
+pub fn im2vim(mode: InodeMode) -> VfsInodeMode {
     VfsInodeMode::from_bits_truncate(mode.bits())
 }
 
@@ -90,3 +118,13 @@ fn syscontext_for_vfs(fs_info: FsContext) -> SysContext {
         root: fs_info.root.clone(),
     }
 }
+
+/// 写回内核线程，定期将页缓存、文件系统元数据以及块设备缓存中的脏数据写回设备
+pub fn writeback_kthread() {
+    loop {
+        let _ = sleep_until(read_timer() + WRITEBACK_INTERVAL * CLOCK_FREQ);
+        if let Err(e) = vfs::sync_all() {
+            warn!("writeback failed: {:?}", e);
+        }
+    }
+}
@@ -0,0 +1,183 @@
+//! 文件访问权限检查
+//!
+//! 按照 Linux 的规则，根据任务凭证中的用户号和用户组号选择文件权限位中属主、属组或其他用户的部分进行检查。
+//! 超级用户可以读写任意文件，但只能执行至少设置了一个执行位的文件。文件的属主和权限位由 [`vfs::perm`] 提供。
+use alloc::{sync::Arc, vec::Vec};
+
+use constants::{AlienResult, LinuxErrno};
+use vfs::{
+    perm::{inode_owner, set_inode_owner, InodeOwner, MODE_MASK, S_ISGID, S_ISUID, S_ISVTX},
+    system_root_fs,
+};
+use vfscore::{dentry::VfsDentry, inode::VfsInode, path::VfsPath, utils::VfsNodeType};
+
+use crate::{
+    fs::user_path_at,
+    task::{current_task, Credentials},
+};
+
+pub const MAY_EXEC: u32 = 1;
+pub const MAY_WRITE: u32 = 2;
+pub const MAY_READ: u32 = 4;
+
+/// 检查凭证 `cred` 是否可以以 `mask` 指定的方式访问 `inode`，使用有效用户号和有效用户组号
+pub fn check_permission(
+    cred: &Credentials,
+    inode: &Arc<dyn VfsInode>,
+    mask: u32,
+) -> AlienResult<()> {
+    let owner = inode_owner(inode)?;
+    if cred.is_root() {
+        let executable = inode.inode_type() == VfsNodeType::Dir || owner.mode & 0o111 != 0;
+        return if mask & MAY_EXEC == 0 || executable {
+            Ok(())
+        } else {
+            Err(LinuxErrno::EACCES)
+        };
+    }
+    let shift = if cred.euid == owner.uid {
+        6
+    } else if cred.in_group(owner.gid) {
+        3
+    } else {
+        0
+    };
+    let perm = (owner.mode >> shift) & 0o7;
+    if perm & mask == mask {
+        Ok(())
+    } else {
+        Err(LinuxErrno::EACCES)
+    }
+}
+
+/// 检查当前任务是否可以以 `mask` 指定的方式访问 `inode`
+pub fn inode_permission(inode: &Arc<dyn VfsInode>, mask: u32) -> AlienResult<()> {
+    let cred = current_task().unwrap().credentials();
+    check_permission(&cred, inode, mask)
+}
+
+/// 获取 `path` 所在目录的路径，用于创建和删除文件时检查目录的权限
+pub fn parent_path_at(fd: isize, path: &str) -> AlienResult<VfsPath> {
+    let trimmed = path.trim_end_matches('/');
+    let parent = match trimmed.rfind('/') {
+        Some(0) => "/",
+        Some(pos) => &trimmed[..pos],
+        None if path.starts_with('/') => "/",
+        None => ".",
+    };
+    user_path_at(fd, parent)
+}
+
+/// 检查当前任务是否可以删除目录 `dir` 中的 `inode`。
+///
+/// 需要对目录有写和执行权限；如果目录设置了 sticky 位，只有文件的属主、目录的属主或者超级用户可以删除，否则返回 `EPERM`。
+pub fn may_delete(dir: &Arc<dyn VfsInode>, inode: &Arc<dyn VfsInode>) -> AlienResult<()> {
+    let cred = current_task().unwrap().credentials();
+    check_permission(&cred, dir, MAY_WRITE | MAY_EXEC)?;
+    let dir_owner = inode_owner(dir)?;
+    if dir_owner.mode & S_ISVTX != 0 && !cred.is_root() {
+        let owner = inode_owner(inode)?;
+        if cred.euid != owner.uid && cred.euid != dir_owner.uid {
+            return Err(LinuxErrno::EPERM);
+        }
+    }
+    Ok(())
+}
+
+/// 检查当前任务是否可以将 `old_path` 重命名为 `new_path`，路径分别相对于 `old_fd` 和 `new_fd` 解析。
+///
+/// 源文件需要满足 [`may_delete`]；目标文件已经存在时同样需要满足 [`may_delete`]，否则需要对目标所在目录有写和执行权限。
+pub fn may_rename(old_fd: isize, old_path: &str, new_fd: isize, new_path: &str) -> AlienResult<()> {
+    let old_inode = user_path_at(old_fd, old_path)?.open(None)?.inode()?;
+    let old_dir = parent_path_at(old_fd, old_path)?.open(None)?.inode()?;
+    may_delete(&old_dir, &old_inode)?;
+    let new_dir = parent_path_at(new_fd, new_path)?.open(None)?.inode()?;
+    match user_path_at(new_fd, new_path)?.open(None) {
+        Ok(dentry) => may_delete(&new_dir, &dentry.inode()?),
+        Err(_) => inode_permission(&new_dir, MAY_WRITE | MAY_EXEC),
+    }
+}
+
+/// 检查当前任务对解析 `path` 时经过的每一个目录都有搜索(执行)权限，否则返回 `EACCES`。`base` 为解析相对路径的起始目录。
+///
+/// 路径的最后一个分量本身不需要搜索权限；中间的目录不存在或者不是目录时，留给之后的路径解析报告错误。
+pub fn may_lookup(base: &Arc<dyn VfsDentry>, path: &str) -> AlienResult<()> {
+    let components = path
+        .split('/')
+        .filter(|component| !component.is_empty() && *component != ".")
+        .collect::<Vec<_>>();
+    let cred = current_task().unwrap().credentials();
+    // 超级用户总是可以搜索目录
+    if components.is_empty() || cred.is_root() {
+        return Ok(());
+    }
+    check_permission(&cred, &base.inode()?, MAY_EXEC)?;
+    for i in 1..components.len() {
+        let prefix = components[..i].join("/");
+        let Ok(dentry) = VfsPath::new(system_root_fs(), base.clone())
+            .join(&prefix)
+            .and_then(|path| path.open(None))
+        else {
+            return Ok(());
+        };
+        let inode = dentry.inode()?;
+        if inode.inode_type() != VfsNodeType::Dir {
+            return Ok(());
+        }
+        check_permission(&cred, &inode, MAY_EXEC)?;
+    }
+    Ok(())
+}
+
+/// 新建文件的属主为当前任务的有效用户号和有效用户组号，权限位为 `mode` 去掉 umask 中的位
+pub fn new_inode_owner(mode: u32) -> InodeOwner {
+    let task = current_task().unwrap();
+    let inner = task.access_inner();
+    InodeOwner {
+        uid: inner.cred.euid,
+        gid: inner.cred.egid,
+        mode: mode & !(inner.unmask as u32),
+    }
+}
+
+/// 修改文件的权限位，只有文件的属主或者超级用户可以修改，否则返回 `EPERM`。
+///
+/// 普通用户不属于文件的属组时，set-group-ID 位会被清除。
+pub fn chmod_inode(inode: &Arc<dyn VfsInode>, mode: u32) -> AlienResult<()> {
+    let cred = current_task().unwrap().credentials();
+    let owner = inode_owner(inode)?;
+    if !cred.is_root() && cred.euid != owner.uid {
+        return Err(LinuxErrno::EPERM);
+    }
+    let mut mode = mode & MODE_MASK;
+    if !cred.is_root() && !cred.in_group(owner.gid) {
+        mode &= !S_ISGID;
+    }
+    set_inode_owner(inode, InodeOwner { mode, ..owner })
+}
+
+/// 修改文件的属主和属组，`uid`/`gid` 为 -1 时表示不修改。
+///
+/// 只有超级用户可以修改属主；文件的属主可以将属组修改为自己所在的用户组。
+/// 修改普通文件的属主或属组后，set-user-ID 和 set-group-ID 位会被清除。
+pub fn chown_inode(inode: &Arc<dyn VfsInode>, uid: u32, gid: u32) -> AlienResult<()> {
+    let cred = current_task().unwrap().credentials();
+    let mut owner = inode_owner(inode)?;
+    let change_uid = uid != u32::MAX && uid != owner.uid;
+    let change_gid = gid != u32::MAX && gid != owner.gid;
+    if !cred.is_root()
+        && (change_uid || (change_gid && (cred.euid != owner.uid || !cred.in_group(gid))))
+    {
+        return Err(LinuxErrno::EPERM);
+    }
+    if change_uid {
+        owner.uid = uid;
+    }
+    if change_gid {
+        owner.gid = gid;
+    }
+    if (change_uid || change_gid) && inode.inode_type() != VfsNodeType::Dir {
+        owner.mode &= !(S_ISUID | S_ISGID);
+    }
+    set_inode_owner(inode, owner)
+}
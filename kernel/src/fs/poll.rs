@@ -1,4 +1,5 @@
 use alloc::{sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicUsize, Ordering};
 
 use constants::{
     epoll::{EpollCtlOp, EpollEvent},
@@ -6,22 +7,115 @@ use constants::{
     time::TimeSpec,
     AlienResult, LinuxErrno,
 };
+use ksync::{poll::PollWaiter, wait::WaitQueue};
 use log::{info, warn};
+use platform::config::CLOCK_FREQ;
 use syscall_table::syscall_func;
-use timer::{get_time_ms, TimeNow, ToClock};
-use vfs::epoll::EpollFile;
+use timer::{read_timer, TimeNow, ToClock};
+use vfs::{epoll::EpollFile, kfile::File};
 
-use crate::task::{current_task, do_suspend};
+use crate::task::current_task;
+
+/// epoll 中不支持通知的文件的轮询间隔，单位为时钟周期(10ms)
+const EPOLL_POLL_INTERVAL: usize = CLOCK_FREQ / 100;
+
+/// [`ppoll`] 和 [`pselect6`](super::select::pselect6) 等待期间注册在被监听文件上的等待者
+struct PollTableWaiter {
+    wait_queue: WaitQueue,
+    /// 收到通知的次数
+    seq: AtomicUsize,
+}
+
+impl PollWaiter for PollTableWaiter {
+    fn notify(&self, _events: PollEvents) -> bool {
+        self.seq.fetch_add(1, Ordering::AcqRel);
+        self.wait_queue.wake_all();
+        true
+    }
+}
+
+/// 一次 `ppoll`/`pselect6` 调用监听的文件，任何一个文件的状态发生变化时唤醒等待的任务
+///
+/// 释放时从所有文件的通知队列中注销。
+pub(crate) struct PollTable {
+    waiter: Arc<PollTableWaiter>,
+    /// 注册了通知的文件
+    files: Vec<Arc<dyn File>>,
+    /// 是否有不支持通知、需要定期轮询的文件
+    polled: bool,
+}
+
+impl PollTable {
+    pub fn new() -> Self {
+        Self {
+            waiter: Arc::new(PollTableWaiter {
+                wait_queue: WaitQueue::new(),
+                seq: AtomicUsize::new(0),
+            }),
+            files: Vec::new(),
+            polled: false,
+        }
+    }
+
+    /// 监听文件 `file` 的状态变化
+    pub fn add(&mut self, file: Arc<dyn File>) {
+        match file.poll_queue() {
+            Some(queue) => {
+                queue.register(&(self.waiter.clone() as Arc<dyn PollWaiter>));
+                self.files.push(file);
+            }
+            None => self.polled = true,
+        }
+    }
+
+    /// 当前收到通知的次数，需要在检查文件的状态之前获取，再传给 [`PollTable::wait`]
+    pub fn seq(&self) -> usize {
+        self.waiter.seq.load(Ordering::Acquire)
+    }
+
+    /// 等待直到获取 `seq` 之后收到了新的通知，或者 cpu 时钟到达 `deadline`
+    ///
+    /// 有不支持通知的文件时最多等待 [`EPOLL_POLL_INTERVAL`]。超时返回 `Ok`，收到信号时返回 `EINTR`。
+    pub fn wait(&self, seq: usize, deadline: Option<usize>) -> AlienResult<()> {
+        let mut wake_time = deadline;
+        if self.polled {
+            wake_time = Some(
+                wake_time
+                    .unwrap_or(usize::MAX)
+                    .min(read_timer() + EPOLL_POLL_INTERVAL),
+            );
+        }
+        let res = self
+            .waiter
+            .wait_queue
+            .wait_event_interruptible_timeout(|| self.seq() != seq, wake_time);
+        match res {
+            Ok(()) | Err(LinuxErrno::ETIMEDOUT) => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for PollTable {
+    fn drop(&mut self) {
+        let waiter = self.waiter.clone() as Arc<dyn PollWaiter>;
+        for file in self.files.iter() {
+            if let Some(queue) = file.poll_queue() {
+                queue.unregister(&waiter);
+            }
+        }
+    }
+}
 
 /// 一个系统调用，用于在一些文件描述符上等待事件。作用与 [`pselect6`] 相似。
 ///
 /// 与 'pselect6' 不同，`ppoll` 并不按照等待事件的类型将所有要等待的文件描述符分成`readfds`、`writefds`、`exceptfds`，
 /// 而是按照需要等待的文件描述符，将其加入 `fds_ptr`，再对每一个文件描述符进行等待事件的约束。其中 `fds_ptr` 指向的是一个
 /// [`PollFd'] 向量，每个 Pollfd 结构中都保存了文件描述符、等待事件类型和获取到的事件类型三方面信息。因此对于 `ppoll`，
-/// 会周期性检测 `fds_ptr` 中是否有文件描述符发生了所要等待的事件，如果有，那么就把事件的类型记录在 Pollfd 结构的 revents
+/// 会检测 `fds_ptr` 中是否有文件描述符发生了所要等待的事件，如果有，那么就把事件的类型记录在 Pollfd 结构的 revents
 /// 字段下，并使得计数器自增。在 `fds_ptr` 指向的向量中所有的文件描述符都被遍历一遍后，如果有需要处理的事件，那么此时 `ppoll`
-/// 会返回需要处理的事件个数。如果没有，和 'pselect6' 相同，`ppoll` 也会让渡 CPU 后循环查询，直到发生超时事件，此时会返回 0，
-/// 表示没有收到需要处理的事件。
+/// 会返回需要处理的事件个数。如果没有，和 'pselect6' 相同，`ppoll` 会睡眠直到某个文件的状态发生变化后再次检查，
+/// 直到发生超时事件，此时会返回 0，表示没有收到需要处理的事件。不支持通知的文件每 10ms 检查一次。
 ///
 /// 参数：
 /// + `fds_ptr`: 用于指明需要等待的文件描述符和等待的事件类型。具体可见 [`PollFd`] 结构 和 [`PollEvents`]结构。
@@ -55,9 +149,15 @@ pub fn ppoll(fds_ptr: usize, nfds: usize, time: usize, _mask: usize) -> AlienRes
     } else {
         None
     }; // wait forever
+    let mut table = PollTable::new();
+    for pfd in fds.iter() {
+        if let Some(file) = task.get_file(pfd.fd as usize) {
+            table.add(file);
+        }
+    }
     let mut res = 0;
     loop {
-        let task = current_task().unwrap();
+        let seq = table.seq();
         for pfd in fds.iter_mut() {
             if let Some(file) = task.get_file(pfd.fd as usize) {
                 let event = file.poll(pfd.events)?;
@@ -85,17 +185,9 @@ pub fn ppoll(fds_ptr: usize, nfds: usize, time: usize, _mask: usize) -> AlienRes
                 return Ok(0);
             }
         }
-        info!("[poll] suspend");
-        // suspend
-        do_suspend();
-        let task = current_task().unwrap();
+        info!("[poll] wait");
         // interrupt by signal
-        // let task = current_task().unwrap();
-        let task_inner = task.access_inner();
-        let receiver = task_inner.signal_receivers.lock();
-        if receiver.have_signal() {
-            return Err(LinuxErrno::EINTR.into());
-        }
+        table.wait(seq, wait_time)?;
     }
 }
 
@@ -111,31 +203,45 @@ pub fn poll_createl(flags: usize) -> AlienResult<isize> {
 }
 
 #[syscall_func(21)]
-/// See https://man7.org/linux/man-pages/man2/epoll_ctl.2.html
+/// 一个系统调用，用于添加、修改或者删除 epoll 实例 `epfd` 中对文件描述符 `fd` 的监听。
+///
+/// `event_ptr` 指向的 [`EpollEvent`] 中除了需要监听的事件外，还可以设置 `EPOLLET`、`EPOLLONESHOT`、`EPOLLEXCLUSIVE` 等标志位。
+/// 删除监听时会忽略 `event_ptr`。
+///
+/// `epfd` 或 `fd` 不是合法的文件描述符时返回 `EBADF`；`epfd` 不是 epoll 实例、`op` 非法、`fd` 与 `epfd` 相同或者
+/// `EPOLLEXCLUSIVE` 使用不当时返回 `EINVAL`；重复添加时返回 `EEXIST`；修改或删除不存在的监听时返回 `ENOENT`；
+/// epoll 实例之间形成环或者嵌套过深时返回 `ELOOP`。
+///
+/// Reference: [epoll_ctl](https://man7.org/linux/man-pages/man2/epoll_ctl.2.html)
 pub fn epoll_ctl(epfd: usize, op: u32, fd: usize, event_ptr: usize) -> AlienResult<isize> {
-    let op = EpollCtlOp::try_from(op).unwrap();
+    let op = EpollCtlOp::try_from(op).map_err(|_| LinuxErrno::EINVAL)?;
     let task = current_task().unwrap();
     let mut event = EpollEvent::default();
-    task.access_inner()
-        .copy_from_user(event_ptr as _, &mut event);
-    // println_color!(
-    //     32,
-    //     "epoll_ctl: epfd: {}, op: {:?}, fd: {}, event: {:?}",
-    //     epfd,
-    //     op,
-    //     fd,
-    //     event
-    // );
+    if !matches!(op, EpollCtlOp::EpollCtlDel) {
+        if event_ptr == 0 {
+            return Err(LinuxErrno::EFAULT);
+        }
+        task.access_inner()
+            .copy_from_user(event_ptr as _, &mut event);
+    }
     let epoll_file = task.get_file(epfd).ok_or(LinuxErrno::EBADF)?;
+    let file = task.get_file(fd).ok_or(LinuxErrno::EBADF)?;
     let epoll_file = epoll_file
         .downcast_arc::<EpollFile>()
         .map_err(|_| LinuxErrno::EINVAL)?;
-    epoll_file.ctl(op, fd, event)?;
+    epoll_file.ctl(op, fd, file, event)?;
     Ok(0)
 }
 
 #[syscall_func(22)]
-/// See https://man7.org/linux/man-pages/man2/epoll_pwait.2.html
+/// 一个系统调用，等待 epoll 实例 `epfd` 中监听的文件发生事件，最多返回 `maxevents` 个事件。
+///
+/// 只有收到通知的监听项以及不支持通知的文件会被检查，没有事件时任务进入睡眠，直到监听的文件发生变化、
+/// 超时或者收到信号。`timeout_ms` 为 -1 时一直等待，为 0 时立即返回。
+///
+/// 返回就绪的事件个数，超时返回 0；`maxevents` 不大于 0 时返回 `EINVAL`，被信号中断时返回 `EINTR`。
+///
+/// Reference: [epoll_pwait](https://man7.org/linux/man-pages/man2/epoll_pwait.2.html)
 pub fn epoll_pwait(
     epfd: usize,
     events_ptr: usize,
@@ -143,62 +249,43 @@ pub fn epoll_pwait(
     timeout_ms: isize,
     _sigmask: usize,
 ) -> AlienResult<isize> {
-    // println_color!(
-    //     32,
-    //     "epoll_pwait: epfd: {}, maxevents: {}, timeout: {:#x}",
-    //     epfd,
-    //     maxevents,
-    //     timeout_ms
-    // );
-    let now_ms = get_time_ms() as usize;
+    if maxevents as isize <= 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap().clone();
+    let epoll_file = task.get_file(epfd).ok_or(LinuxErrno::EBADF)?;
+    let epoll_file = epoll_file
+        .downcast_arc::<EpollFile>()
+        .map_err(|_| LinuxErrno::EINVAL)?;
+    let deadline =
+        (timeout_ms >= 0).then(|| read_timer() + timeout_ms as usize * CLOCK_FREQ / 1000);
     let res = loop {
-        let task = current_task().unwrap();
-        let epoll_file = task.get_file(epfd).ok_or(LinuxErrno::EBADF)?;
-        let epoll_file = epoll_file
-            .downcast_arc::<EpollFile>()
-            .map_err(|_| LinuxErrno::EINVAL)?;
-
-        let interset = epoll_file.interest();
-        let mut res = Vec::with_capacity(interset.len());
-        for (fd, event) in interset.iter() {
-            let file = task.get_file(*fd);
-            if let Some(file) = file {
-                let event_res = file.poll(event.events).unwrap();
-                if !event_res.is_empty() {
-                    res.push(EpollEvent {
-                        events: event_res,
-                        data: event.data,
-                    });
-                }
-            }
-        }
+        let res = epoll_file.collect(maxevents);
         if !res.is_empty() {
             break res;
         }
-        let now = get_time_ms() as usize;
-        if now - now_ms >= timeout_ms as usize {
-            break Vec::new();
+        let now = read_timer();
+        if deadline.is_some_and(|deadline| now >= deadline) {
+            break res;
         }
-        // suspend
-        do_suspend();
-        let task = current_task().unwrap();
-        // interrupt by signal
-        // let task = current_task().unwrap();
-        let task_inner = task.access_inner();
-        let receiver = task_inner.signal_receivers.lock();
-        if receiver.have_signal() {
-            return Err(LinuxErrno::EINTR.into());
+        // 不支持通知的文件需要定期轮询
+        let mut wake_time = deadline;
+        if epoll_file.has_polled() {
+            wake_time = Some(
+                wake_time
+                    .unwrap_or(usize::MAX)
+                    .min(now + EPOLL_POLL_INTERVAL),
+            );
+        }
+        match epoll_file.wait(wake_time) {
+            Ok(()) | Err(LinuxErrno::ETIMEDOUT) => {}
+            Err(e) => return Err(e),
         }
     };
-    if res.len() > maxevents {
-        panic!("epoll_pwait: res.len() > maxevents");
-    }
     if res.is_empty() {
         return Ok(0);
     }
-    let task = current_task().unwrap();
     task.access_inner()
         .copy_to_user_buffer(res.as_ptr(), events_ptr as *mut EpollEvent, res.len());
-    // println_color!(32, "epoll_pwait: res: {:?}", res);
     Ok(res.len() as isize)
 }
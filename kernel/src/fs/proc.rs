@@ -0,0 +1,747 @@
+//! 与进程相关的 procfs 文件
+//!
+//! 每个存活的进程在 procfs 中拥有一个 `/proc/<pid>` 目录，目录中的内容在读取时根据进程控制块动态生成：
+//!
+//! ```bash
+//! /proc/<pid>
+//! |-- stat
+//! |-- status
+//! |-- cmdline
+//! |-- environ
+//! |-- maps
+//! |-- limits
+//! |-- cwd -> <cwd>
+//! |-- exe -> <exe>
+//! |-- fd
+//! |   |-- 0 -> <file>
+//! |   `-- ...
+//! `-- task
+//!     |-- <tid>
+//!     `-- ...
+//! ```
+//!
+//! `/proc/self` 是一个指向当前进程的 `/proc/<pid>` 目录的软链接。
+use alloc::{
+    format,
+    string::{String, ToString},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::{cmp::min, fmt::Write};
+
+use config::FRAME_SIZE;
+use constants::{
+    io::{InodeMode, MMapFlags},
+    RLimitResource, RLIM_INFINITY,
+};
+use log::warn;
+use platform::config::CLOCK_FREQ;
+use vfs::proc::{ProcFsDirInodeImpl, PROC_FS_ROOT};
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    utils::{VfsDirEntry, VfsFileStat, VfsNodePerm, VfsNodeType},
+    VfsResult,
+};
+
+use crate::{
+    mm::map::ProtFlags,
+    task::{current_task, get_thread_group, Task, TaskState},
+};
+
+/// 每秒的时钟滴答数，`stat` 文件中的时间以此为单位
+const USER_HZ: usize = 100;
+
+/// 初始化与进程相关的 procfs 文件，目前只有 `/proc/self`
+pub fn init_procfs() {
+    let root = PROC_FS_ROOT.get().unwrap();
+    let root_inode = root
+        .inode()
+        .unwrap()
+        .downcast_arc::<ProcFsDirInodeImpl>()
+        .map_err(|_| VfsError::Invalid)
+        .unwrap();
+    root_inode
+        .add_file_manually("self", Arc::new(ProcSelf), "rwxrwxrwx".into())
+        .unwrap();
+    println!("procfs for process init success");
+}
+
+/// 创建进程时，在 procfs 中添加对应的 `/proc/<pid>` 目录
+pub fn proc_register_process(task: &Arc<Task>) {
+    let Some(root) = PROC_FS_ROOT.get() else {
+        return;
+    };
+    let root_inode = root
+        .inode()
+        .unwrap()
+        .downcast_arc::<ProcFsDirInodeImpl>()
+        .map_err(|_| VfsError::Invalid)
+        .unwrap();
+    let name = task.get_pid().to_string();
+    let dir = Arc::new(ProcTaskDir::new(task, false));
+    if let Err(e) = root_inode.add_file_manually(&name, dir, "r-xr-xr-x".into()) {
+        warn!("proc: register process {} failed, err: {:?}", name, e);
+    }
+}
+
+/// 进程被回收时，从 procfs 中删除对应的 `/proc/<pid>` 目录
+pub fn proc_unregister_process(pid: usize) {
+    let Some(root) = PROC_FS_ROOT.get() else {
+        return;
+    };
+    let root_inode = root
+        .inode()
+        .unwrap()
+        .downcast_arc::<ProcFsDirInodeImpl>()
+        .map_err(|_| VfsError::Invalid)
+        .unwrap();
+    let name = pid.to_string();
+    // 只有进程才会注册目录，线程退出时这里什么也不做
+    if root_inode.remove_manually(&name).is_ok() {
+        let _ = root.remove(&name);
+    }
+}
+
+/// 将 `content` 中从 `offset` 开始的内容复制到 `buf` 中
+fn read_content(content: &[u8], offset: u64, buf: &mut [u8]) -> usize {
+    let offset = offset as usize;
+    if offset >= content.len() {
+        return 0;
+    }
+    let len = min(buf.len(), content.len() - offset);
+    buf[..len].copy_from_slice(&content[offset..offset + len]);
+    len
+}
+
+/// 从弱引用中获取任务控制块，任务已经被回收时返回 `ENOENT`
+fn upgrade_task(task: &Weak<Task>) -> VfsResult<Arc<Task>> {
+    task.upgrade().ok_or(VfsError::ENOENT)
+}
+
+/// 任务的有效用户号和有效用户组号，作为 `/proc/<pid>` 下文件的属主，任务已经被回收时为 root
+fn task_owner(task: &Weak<Task>) -> (u32, u32) {
+    task.upgrade().map_or((0, 0), |task| {
+        let cred = task.credentials();
+        (cred.euid, cred.egid)
+    })
+}
+
+fn dir_stat(task: &Weak<Task>, perm: u32) -> VfsFileStat {
+    let (uid, gid) = task_owner(task);
+    VfsFileStat {
+        st_mode: InodeMode::DIR.bits() | perm,
+        st_nlink: 2,
+        st_uid: uid as _,
+        st_gid: gid as _,
+        ..Default::default()
+    }
+}
+
+/// 检查当前任务是否可以查看 `task` 的环境变量、内存映射和打开的文件
+///
+/// 与 Linux 的 ptrace 访问检查一致：调用者是超级用户或者任务本身时允许访问，否则调用者的有效用户号和
+/// 有效用户组号需要与任务的实际、有效、保存的用户号和用户组号都相同。
+fn may_inspect(task: &Arc<Task>) -> VfsResult<()> {
+    let Some(current) = current_task() else {
+        return Ok(());
+    };
+    if Arc::ptr_eq(current, task) {
+        return Ok(());
+    }
+    let cred = current.credentials();
+    if cred.is_root() {
+        return Ok(());
+    }
+    let target = task.credentials();
+    let uid_match = [target.ruid, target.euid, target.suid]
+        .iter()
+        .all(|&uid| uid == cred.euid);
+    let gid_match = [target.rgid, target.egid, target.sgid]
+        .iter()
+        .all(|&gid| gid == cred.egid);
+    if uid_match && gid_match {
+        Ok(())
+    } else {
+        Err(VfsError::PermissionDenied)
+    }
+}
+
+/// 进程名，即可执行文件路径的最后一部分，最多 15 个字符
+fn task_comm(task: &Arc<Task>) -> String {
+    let name = task.get_name();
+    let comm = name.rsplit('/').next().unwrap_or("");
+    comm.chars().take(15).collect()
+}
+
+fn task_state(state: TaskState) -> (char, &'static str) {
+    match state {
+        TaskState::Ready | TaskState::Running => ('R', "running"),
+        TaskState::Waiting => ('S', "sleeping"),
+        TaskState::Zombie => ('Z', "zombie"),
+        TaskState::Terminated => ('X', "dead"),
+    }
+}
+
+fn task_ppid(task: &Arc<Task>) -> isize {
+    let parent = task.access_inner().parent.clone();
+    parent
+        .and_then(|parent| parent.upgrade())
+        .map_or(0, |parent| parent.get_pid())
+}
+
+/// 进程虚拟地址空间中已知区域的总大小，包括堆、栈以及 mmap 映射的区域
+fn task_vm_size(task: &Arc<Task>) -> usize {
+    let inner = task.access_inner();
+    let heap = inner.heap.lock();
+    let heap_size = heap.current - heap.start;
+    drop(heap);
+    let mmap_size = inner
+        .mmap
+        .regions()
+        .iter()
+        .map(|region| region.map_len)
+        .sum::<usize>();
+    heap_size + mmap_size + inner.stack.len()
+}
+
+fn cycles_to_clock_ticks(cycles: usize) -> usize {
+    cycles * USER_HZ / CLOCK_FREQ
+}
+
+/// `/proc/<pid>` 目录下的普通文件
+#[derive(Debug, Copy, Clone)]
+enum ProcEntry {
+    Stat,
+    Status,
+    Cmdline,
+    Environ,
+    Maps,
+    Limits,
+}
+
+/// `/proc/<pid>` 目录下的软链接
+#[derive(Debug, Copy, Clone)]
+enum ProcLink {
+    Cwd,
+    Exe,
+    Fd(usize),
+}
+
+/// `/proc/<pid>` 目录下的所有文件，线程目录 `/proc/<pid>/task/<tid>` 中没有 `task` 子目录
+const PROC_TASK_ENTRIES: [(&str, VfsNodeType); 10] = [
+    ("stat", VfsNodeType::File),
+    ("status", VfsNodeType::File),
+    ("cmdline", VfsNodeType::File),
+    ("environ", VfsNodeType::File),
+    ("maps", VfsNodeType::File),
+    ("limits", VfsNodeType::File),
+    ("cwd", VfsNodeType::SymLink),
+    ("exe", VfsNodeType::SymLink),
+    ("fd", VfsNodeType::Dir),
+    ("task", VfsNodeType::Dir),
+];
+
+/// `/proc/<pid>` 或 `/proc/<pid>/task/<tid>` 目录
+struct ProcTaskDir {
+    task: Weak<Task>,
+    /// 是否为线程目录
+    thread: bool,
+}
+
+impl ProcTaskDir {
+    fn new(task: &Arc<Task>, thread: bool) -> Self {
+        Self {
+            task: Arc::downgrade(task),
+            thread,
+        }
+    }
+
+    fn entries(&self) -> &'static [(&'static str, VfsNodeType)] {
+        if self.thread {
+            &PROC_TASK_ENTRIES[..PROC_TASK_ENTRIES.len() - 1]
+        } else {
+            &PROC_TASK_ENTRIES
+        }
+    }
+}
+
+impl VfsFile for ProcTaskDir {
+    fn readdir(&self, start_index: usize) -> VfsResult<Option<VfsDirEntry>> {
+        upgrade_task(&self.task)?;
+        Ok(self
+            .entries()
+            .get(start_index)
+            .map(|(name, ty)| VfsDirEntry {
+                ino: start_index as u64 + 1,
+                ty: *ty,
+                name: name.to_string(),
+            }))
+    }
+}
+
+impl VfsInode for ProcTaskDir {
+    fn node_perm(&self) -> VfsNodePerm {
+        "r-xr-xr-x".into()
+    }
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn VfsInode>> {
+        let task = self.task.clone();
+        let inode: Arc<dyn VfsInode> = match name {
+            "stat" => Arc::new(ProcTaskFile::new(task, ProcEntry::Stat)),
+            "status" => Arc::new(ProcTaskFile::new(task, ProcEntry::Status)),
+            "cmdline" => Arc::new(ProcTaskFile::new(task, ProcEntry::Cmdline)),
+            "environ" => Arc::new(ProcTaskFile::new(task, ProcEntry::Environ)),
+            "maps" => Arc::new(ProcTaskFile::new(task, ProcEntry::Maps)),
+            "limits" => Arc::new(ProcTaskFile::new(task, ProcEntry::Limits)),
+            "cwd" => Arc::new(ProcTaskLink::new(task, ProcLink::Cwd)),
+            "exe" => Arc::new(ProcTaskLink::new(task, ProcLink::Exe)),
+            "fd" => Arc::new(ProcFdDir { task }),
+            "task" if !self.thread => Arc::new(ProcThreadDir { task }),
+            _ => return Err(VfsError::ENOENT),
+        };
+        Ok(inode)
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(dir_stat(&self.task, 0o555))
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::Dir
+    }
+}
+
+/// `/proc/<pid>/task` 目录，其中每个子目录对应线程组中的一个线程
+struct ProcThreadDir {
+    task: Weak<Task>,
+}
+
+impl VfsFile for ProcThreadDir {
+    fn readdir(&self, start_index: usize) -> VfsResult<Option<VfsDirEntry>> {
+        let task = upgrade_task(&self.task)?;
+        let mut threads = get_thread_group(task.get_pid() as usize);
+        threads.sort_by_key(|thread| thread.get_tid());
+        Ok(threads.get(start_index).map(|thread| VfsDirEntry {
+            ino: thread.get_tid() as u64,
+            ty: VfsNodeType::Dir,
+            name: thread.get_tid().to_string(),
+        }))
+    }
+}
+
+impl VfsInode for ProcThreadDir {
+    fn node_perm(&self) -> VfsNodePerm {
+        "r-xr-xr-x".into()
+    }
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn VfsInode>> {
+        let task = upgrade_task(&self.task)?;
+        let tid = name.parse::<isize>().map_err(|_| VfsError::ENOENT)?;
+        let thread = get_thread_group(task.get_pid() as usize)
+            .into_iter()
+            .find(|thread| thread.get_tid() == tid)
+            .ok_or(VfsError::ENOENT)?;
+        Ok(Arc::new(ProcTaskDir::new(&thread, true)))
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(dir_stat(&self.task, 0o555))
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::Dir
+    }
+}
+
+/// `/proc/<pid>/fd` 目录，其中每个软链接对应进程打开的一个文件
+struct ProcFdDir {
+    task: Weak<Task>,
+}
+
+impl VfsFile for ProcFdDir {
+    fn readdir(&self, start_index: usize) -> VfsResult<Option<VfsDirEntry>> {
+        let task = upgrade_task(&self.task)?;
+        may_inspect(&task)?;
+        let fd_table = task.access_inner().fd_table.clone();
+        let fd = fd_table.lock().iter().nth(start_index).map(|(fd, _)| fd);
+        Ok(fd.map(|fd| VfsDirEntry {
+            ino: fd as u64 + 1,
+            ty: VfsNodeType::SymLink,
+            name: fd.to_string(),
+        }))
+    }
+}
+
+impl VfsInode for ProcFdDir {
+    fn node_perm(&self) -> VfsNodePerm {
+        "r-x------".into()
+    }
+    fn lookup(&self, name: &str) -> VfsResult<Arc<dyn VfsInode>> {
+        let task = upgrade_task(&self.task)?;
+        may_inspect(&task)?;
+        let fd = name.parse::<usize>().map_err(|_| VfsError::ENOENT)?;
+        task.get_file(fd).ok_or(VfsError::ENOENT)?;
+        Ok(Arc::new(ProcTaskLink::new(
+            self.task.clone(),
+            ProcLink::Fd(fd),
+        )))
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(dir_stat(&self.task, 0o500))
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::Dir
+    }
+}
+
+/// `/proc/<pid>` 目录下的软链接，链接的目标在读取时动态获取
+struct ProcTaskLink {
+    task: Weak<Task>,
+    link: ProcLink,
+}
+
+impl ProcTaskLink {
+    fn new(task: Weak<Task>, link: ProcLink) -> Self {
+        Self { task, link }
+    }
+
+    fn target(&self) -> VfsResult<String> {
+        let task = upgrade_task(&self.task)?;
+        may_inspect(&task)?;
+        let target = match self.link {
+            ProcLink::Cwd => task.access_inner().fs_info.cwd.path(),
+            ProcLink::Exe => task.get_name(),
+            ProcLink::Fd(fd) => task.get_file(fd).ok_or(VfsError::ENOENT)?.dentry().path(),
+        };
+        Ok(target)
+    }
+}
+
+impl VfsFile for ProcTaskLink {}
+
+impl VfsInode for ProcTaskLink {
+    fn node_perm(&self) -> VfsNodePerm {
+        "rwxrwxrwx".into()
+    }
+    fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let target = self.target()?;
+        Ok(read_content(target.as_bytes(), 0, buf))
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        let (uid, gid) = task_owner(&self.task);
+        Ok(VfsFileStat {
+            st_mode: InodeMode::LINK.bits() | 0o777,
+            st_size: self.target().map_or(0, |target| target.len() as u64),
+            st_uid: uid as _,
+            st_gid: gid as _,
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::SymLink
+    }
+}
+
+/// `/proc/self`，指向当前进程的 `/proc/<pid>` 目录
+struct ProcSelf;
+
+impl VfsFile for ProcSelf {}
+
+impl VfsInode for ProcSelf {
+    fn node_perm(&self) -> VfsNodePerm {
+        "rwxrwxrwx".into()
+    }
+    fn readlink(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        let task = current_task().ok_or(VfsError::ENOENT)?;
+        let target = task.get_pid().to_string();
+        Ok(read_content(target.as_bytes(), 0, buf))
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_mode: InodeMode::LINK.bits() | 0o777,
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::SymLink
+    }
+}
+
+/// `/proc/<pid>` 目录下的普通文件，内容在读取时根据进程控制块生成
+struct ProcTaskFile {
+    task: Weak<Task>,
+    entry: ProcEntry,
+}
+
+impl ProcTaskFile {
+    fn new(task: Weak<Task>, entry: ProcEntry) -> Self {
+        Self { task, entry }
+    }
+
+    /// 环境变量只有任务的属主可以读取
+    fn perm(&self) -> u32 {
+        match self.entry {
+            ProcEntry::Environ => 0o400,
+            _ => 0o444,
+        }
+    }
+
+    fn content(&self) -> VfsResult<Vec<u8>> {
+        let task = upgrade_task(&self.task)?;
+        if matches!(self.entry, ProcEntry::Environ | ProcEntry::Maps) {
+            may_inspect(&task)?;
+        }
+        let content = match self.entry {
+            ProcEntry::Stat => stat(&task).into_bytes(),
+            ProcEntry::Status => status(&task).into_bytes(),
+            ProcEntry::Cmdline => task.access_inner().cmdline.clone(),
+            ProcEntry::Environ => task.access_inner().environ.clone(),
+            ProcEntry::Maps => maps(&task).into_bytes(),
+            ProcEntry::Limits => limits(&task).into_bytes(),
+        };
+        Ok(content)
+    }
+}
+
+impl VfsFile for ProcTaskFile {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let content = self.content()?;
+        Ok(read_content(&content, offset, buf))
+    }
+}
+
+impl VfsInode for ProcTaskFile {
+    fn node_perm(&self) -> VfsNodePerm {
+        match self.entry {
+            ProcEntry::Environ => "r--------".into(),
+            _ => "r--r--r--".into(),
+        }
+    }
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Err(VfsError::PermissionDenied)
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        let (uid, gid) = task_owner(&self.task);
+        // 与 Linux 一致，procfs 中文件的大小为 0
+        Ok(VfsFileStat {
+            st_mode: InodeMode::FILE.bits() | self.perm(),
+            st_uid: uid as _,
+            st_gid: gid as _,
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::File
+    }
+}
+
+/// `/proc/<pid>/stat`，格式见 [proc(5)](https://man7.org/linux/man-pages/man5/proc.5.html)
+fn stat(task: &Arc<Task>) -> String {
+    let threads = get_thread_group(task.get_pid() as usize).len();
+    let ppid = task_ppid(task);
+    let vm_size = task_vm_size(task);
+    let comm = task_comm(task);
+    let pgrp = task.get_pgid();
+    let session = task.get_sid();
+    // 没有控制终端时为 -1
+    let tpgid = devices::controlling_tty(session).map_or(-1, |tty| tty.foreground_pgid() as isize);
+    let inner = task.access_inner();
+    let (state, _) = task_state(inner.state);
+    let data = inner.statistical_data();
+    let sched = &inner.sched;
+    let priority = if sched.policy.is_rt() {
+        -1 - sched.rt_priority as isize
+    } else {
+        20 + sched.nice as isize
+    };
+    let heap = inner.heap.lock();
+    format!(
+        "{pid} ({comm}) {state} {ppid} {pgrp} {session} 0 {tpgid} 0 0 0 0 0 {utime} {stime} {cutime} {cstime} \
+         {priority} {nice} {threads} 0 0 {vsize} {rss} {rsslim} 0 0 {start_stack} 0 0 0 0 0 0 0 0 0 17 \
+         {processor} {rt_priority} {policy} 0 0 0 0 0 {start_brk} 0 0 0 0 {exit_code}\n",
+        pid = task.get_pid(),
+        utime = cycles_to_clock_ticks(data.tms_utime),
+        stime = cycles_to_clock_ticks(data.tms_stime),
+        cutime = cycles_to_clock_ticks(data.tms_cutime),
+        cstime = cycles_to_clock_ticks(data.tms_cstime),
+        nice = sched.nice,
+        vsize = vm_size,
+        // 页面在访问时才分配，这里按照已经映射的区域估算
+        rss = vm_size / FRAME_SIZE,
+        rsslim = u64::MAX,
+        start_stack = inner.stack.end,
+        processor = sched.cpu,
+        rt_priority = sched.rt_priority,
+        policy = sched.policy as usize,
+        start_brk = heap.start,
+        exit_code = inner.exit_code,
+    )
+}
+
+/// `/proc/<pid>/status`
+fn status(task: &Arc<Task>) -> String {
+    let threads = get_thread_group(task.get_pid() as usize).len();
+    let ppid = task_ppid(task);
+    let vm_size = task_vm_size(task);
+    let comm = task_comm(task);
+    let inner = task.access_inner();
+    let (state, state_name) = task_state(inner.state);
+    let fd_size = inner.fd_table.lock().max();
+    let mut res = String::new();
+    writeln!(res, "Name:\t{}", comm).unwrap();
+    writeln!(res, "State:\t{} ({})", state, state_name).unwrap();
+    writeln!(res, "Tgid:\t{}", task.get_pid()).unwrap();
+    writeln!(res, "Pid:\t{}", task.get_tid()).unwrap();
+    writeln!(res, "PPid:\t{}", ppid).unwrap();
+    // 文件系统访问使用有效用户号和有效用户组号
+    let cred = &inner.cred;
+    writeln!(
+        res,
+        "Uid:\t{}\t{}\t{}\t{}",
+        cred.ruid, cred.euid, cred.suid, cred.euid
+    )
+    .unwrap();
+    writeln!(
+        res,
+        "Gid:\t{}\t{}\t{}\t{}",
+        cred.rgid, cred.egid, cred.sgid, cred.egid
+    )
+    .unwrap();
+    writeln!(res, "FDSize:\t{}", fd_size).unwrap();
+    writeln!(res, "VmSize:\t{:>8} kB", vm_size / 1024).unwrap();
+    writeln!(res, "VmStk:\t{:>8} kB", inner.stack.len() / 1024).unwrap();
+    writeln!(res, "Threads:\t{}", threads).unwrap();
+    writeln!(res, "Cpus_allowed:\t{:x}", inner.cpu_affinity).unwrap();
+    res
+}
+
+/// `/proc/<pid>/maps`，包括堆、栈以及 mmap 映射的区域
+fn maps(task: &Arc<Task>) -> String {
+    let inner = task.access_inner();
+    let mut res = String::new();
+    let heap = inner.heap.lock();
+    if heap.current > heap.start {
+        writeln!(
+            res,
+            "{:08x}-{:08x} rw-p 00000000 00:00 0          [heap]",
+            heap.start, heap.current
+        )
+        .unwrap();
+    }
+    drop(heap);
+    let mut regions = inner.mmap.regions().to_vec();
+    regions.sort_by_key(|region| region.start);
+    for region in regions {
+        let prot = region.prot;
+        let perm = format!(
+            "{}{}{}{}",
+            if prot.contains(ProtFlags::PROT_READ) {
+                'r'
+            } else {
+                '-'
+            },
+            if prot.contains(ProtFlags::PROT_WRITE) {
+                'w'
+            } else {
+                '-'
+            },
+            if prot.contains(ProtFlags::PROT_EXEC) {
+                'x'
+            } else {
+                '-'
+            },
+            if region.flags.contains(MMapFlags::MAP_SHARED) {
+                's'
+            } else {
+                'p'
+            },
+        );
+        let path = region
+            .fd
+            .as_ref()
+            .map(|file| file.dentry().path())
+            .unwrap_or_default();
+        writeln!(
+            res,
+            "{:08x}-{:08x} {} {:08x} 00:00 0          {}",
+            region.start,
+            region.start + region.map_len,
+            perm,
+            region.offset,
+            path
+        )
+        .unwrap();
+    }
+    writeln!(
+        res,
+        "{:08x}-{:08x} rw-p 00000000 00:00 0          [stack]",
+        inner.stack.start, inner.stack.end
+    )
+    .unwrap();
+    res
+}
+
+/// `/proc/<pid>/limits`
+fn limits(task: &Arc<Task>) -> String {
+    let inner = task.access_inner();
+    let limit = |value: u64| {
+        if value == RLIM_INFINITY {
+            "unlimited".to_string()
+        } else {
+            value.to_string()
+        }
+    };
+    let resources = [
+        ("Max cpu time", RLimitResource::RlimitCpu, "seconds"),
+        ("Max file size", RLimitResource::RlimitFsize, "bytes"),
+        ("Max data size", RLimitResource::RlimitData, "bytes"),
+        ("Max stack size", RLimitResource::RlimitStack, "bytes"),
+        ("Max core file size", RLimitResource::RlimitCore, "bytes"),
+        ("Max resident set", RLimitResource::RlimitRss, "bytes"),
+        ("Max processes", RLimitResource::RlimitNproc, "processes"),
+        ("Max open files", RLimitResource::RlimitNofile, "files"),
+        ("Max locked memory", RLimitResource::RlimitMemlock, "bytes"),
+        ("Max address space", RLimitResource::RlimitAs, "bytes"),
+        ("Max file locks", RLimitResource::RlimitLocks, "locks"),
+        (
+            "Max pending signals",
+            RLimitResource::RlimitSigpending,
+            "signals",
+        ),
+        ("Max msgqueue size", RLimitResource::RlimitMsgqueue, "bytes"),
+        ("Max nice priority", RLimitResource::RlimitNice, ""),
+        ("Max realtime priority", RLimitResource::RlimitRtprio, ""),
+        ("Max realtime timeout", RLimitResource::RlimitRttime, "us"),
+    ];
+    let mut res = String::new();
+    writeln!(
+        res,
+        "{:<26}{:<21}{:<21}{:<10}",
+        "Limit", "Soft Limit", "Hard Limit", "Units"
+    )
+    .unwrap();
+    for (name, resource, units) in resources {
+        let value = inner.get_prlimit(resource);
+        writeln!(
+            res,
+            "{:<26}{:<21}{:<21}{:<10}",
+            name,
+            limit(value.rlim_cur),
+            limit(value.rlim_max),
+            units
+        )
+        .unwrap();
+    }
+    res
+}
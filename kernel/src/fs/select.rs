@@ -13,17 +13,18 @@ use log::{info, trace};
 use syscall_table::syscall_func;
 use timer::{TimeNow, ToClock};
 
-use crate::task::{current_task, do_suspend};
+use super::poll::PollTable;
+use crate::task::current_task;
 
 /// 一个系统调用，实现 IO 端口的复用。一般用于用户程序的一段循环体中，
 /// 用于周期性检测一组关注的文件描述符集里是否有需要进行处理的IO事件发生。
 ///
-/// 具体的，pselect6 会周期性检测在 `readfds`、`writefds`、`exceptfds`中的文件描述符，
+/// 具体的，pselect6 会检测在 `readfds`、`writefds`、`exceptfds`中的文件描述符，
 /// 是否符合可读、可写、发生异常。如果有这样的文件描述符，那么就会记录下来，并使得计数器
 /// 自增。如果在一次循环后，发现有需要处理的IO事件，那么 pselect6 会直接返回计数器的值(即
 /// 事件个数)，如果一直没有需要处理的IO事件，pselect6 也会在 `timeout` 所指明的一段时间后
-/// 返回 0，表示在该段时间内没有接收到需要处理的IO事件。pselect6 还可能因为 `SIGKILL` 而被
-/// 打断返回。
+/// 返回 0，表示在该段时间内没有接收到需要处理的IO事件。没有事件时 pselect6 会睡眠，直到某个文件的状态
+/// 发生变化后再次检查，或者因为收到信号而被打断返回。
 ///
 /// 参数有：
 /// + `nfds`: 用于指明需要检测的文件描述符中的最大值 + 1，用于作为下面三个 `fds` 中查询
@@ -78,7 +79,7 @@ pub fn pselect6(
             Some(time_spec.clone()),
         )
     } else {
-        (Some(usize::MAX), None)
+        (None, None)
     };
     // assert!(nfds <= 64);
     let nfds = min(nfds, 64);
@@ -103,12 +104,17 @@ pub fn pselect6(
         0
     };
 
-    // at iperf test, if readfds hav one fd is ok, but writefds is empty,
-    // it still return 1 and cause recursion error
-    do_suspend();
+    let mut table = PollTable::new();
+    for i in 0..nfds {
+        if (ori_readfds | ori_writefds | ori_exceptfds).get_bit(i) {
+            if let Some(file) = task.get_file(i) {
+                table.add(file);
+            }
+        }
+    }
 
     loop {
-        let task = current_task().unwrap();
+        let seq = table.seq();
         let mut set = 0;
         // 如果设置了监视是否可读的 fd
         if readfds != 0 {
@@ -200,9 +206,6 @@ pub fn pselect6(
             }
         }
 
-        // 否则暂时 block 住
-        do_suspend();
-
         if let Some(wait_time) = wait_time {
             if wait_time <= TimeSpec::now().to_clock() {
                 info!(
@@ -213,14 +216,8 @@ pub fn pselect6(
                 return Ok(0);
             }
         }
-        let task = current_task().unwrap();
 
-        // interrupt by signal
-        let task_inner = task.access_inner();
-        let receiver = task_inner.signal_receivers.lock();
-        let res = receiver.have_signal_with_number();
-        if res.is_some() && res.unwrap() == SignalNumber::SIGKILL as usize {
-            return Err(LinuxErrno::EINTR.into());
-        }
+        // 否则睡眠直到某个文件的状态发生变化，被信号打断时返回 EINTR
+        table.wait(seq, wait_time)?;
     }
 }
@@ -1,12 +1,18 @@
 //! GUI 相关的系统调用
 use devices::{GPU_DEVICE, KEYBOARD_INPUT_DEVICE, MOUSE_INPUT_DEVICE};
 use page_table::addr::{align_up_4k, PhysAddr, VirtAddr};
+use platform::config::CLOCK_FREQ;
+use timer::read_timer;
 
-use crate::task::current_task;
+use crate::{task::current_task, time::sleep_until};
 
 const FB_VADDR: usize = 0x1000_0000;
+/// 映射 `/dev/fb0` 之后帧缓存每秒刷新的次数
+const FB_REFRESH_HZ: usize = 30;
 
 /// 一个系统调用，用于获取一段帧缓存。执行成功后返回帧缓存的首地址，Alien 中默认该地址为 FB_VADDR (0x1000_0000)
+///
+/// 新的程序应当通过 `mmap` 映射 `/dev/fb0`，并通过 `FBIOGET_VSCREENINFO` 获取分辨率和像素格式。
 #[syscall_func(2000)]
 pub fn sys_framebuffer() -> isize {
     let fb = GPU_DEVICE.get().unwrap().get_framebuffer();
@@ -32,6 +38,14 @@ pub fn sys_framebuffer_flush() -> isize {
     0
 }
 
+/// 帧缓存刷新内核线程，`/dev/fb0` 被映射期间或写入之后定期将帧缓存刷新到屏幕上
+pub fn fb_refresh_kthread() {
+    loop {
+        let _ = sleep_until(read_timer() + CLOCK_FREQ / FB_REFRESH_HZ);
+        vfs::dev::fb_refresh();
+    }
+}
+
 /// 一个系统调用函数，用于获取鼠标和键盘事件。
 ///
 /// `sys_event_get`会将获取到的事件将保存在event_buf所指向的内存位置处，
@@ -39,6 +53,8 @@ pub fn sys_framebuffer_flush() -> isize {
 ///
 /// 函数将返回成功获取到的事件个数。
 ///
+/// 该系统调用只能读到作为键盘和鼠标的两个设备的事件，并且事件没有时间戳，
+/// 新的程序应当通过 `/dev/input/eventN` 读取 `struct input_event`。
 #[syscall_func(2002)]
 pub fn sys_event_get(event_buf: *mut u64, len: usize) -> isize {
     let task = current_task().unwrap();
@@ -12,47 +12,123 @@
 //!
 //! Reference: https://cloud.tencent.com/developer/article/1176832
 //!
-use alloc::{collections::BTreeMap, sync::Arc, vec, vec::Vec};
-use core::cmp::min;
+//! Alien 中 futex 的键和 Linux 一致：私有的 futex 以及位于私有映射中的 futex 由地址空间和虚拟地址确定，
+//! 位于 `MAP_SHARED` 映射或者 System V 共享内存中的非私有 futex 则由其所在的物理地址确定，
+//! 因此不同进程将同一块共享内存映射到不同的虚拟地址时，仍然可以通过 futex 同步。
+//!
+//! 除了普通的等待和唤醒操作外，这里还实现了 `FUTEX_WAIT_BITSET`、`FUTEX_WAKE_OP`、`FUTEX_CMP_REQUEUE` 等操作、
+//! 带有优先级继承的 PI futex，以及线程退出时对 robust 锁列表的处理。
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
+use core::{
+    mem::size_of,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use constants::{io::MMapFlags, time::TimeSpec, AlienResult, LinuxErrno};
+use ksync::wait::Waiter;
+use page_table::{addr::VirtAddr, pte::MappingFlags};
+use timer::{TimeNow, ToClock};
+
+use crate::{
+    ipc::FUTEX_WAITER,
+    task::{get_task_from_tid, Task, GLOBAL_TASK_MANAGER},
+    trap::trap_common_read_file,
+};
+
+const FUTEX_WAIT: u32 = 0;
+const FUTEX_WAKE: u32 = 1;
+const FUTEX_FD: u32 = 2;
+const FUTEX_REQUEUE: u32 = 3;
+const FUTEX_CMP_REQUEUE: u32 = 4;
+const FUTEX_WAKE_OP: u32 = 5;
+const FUTEX_LOCK_PI: u32 = 6;
+const FUTEX_UNLOCK_PI: u32 = 7;
+const FUTEX_TRYLOCK_PI: u32 = 8;
+const FUTEX_WAIT_BITSET: u32 = 9;
+const FUTEX_WAKE_BITSET: u32 = 10;
+const FUTEX_WAIT_REQUEUE_PI: u32 = 11;
+const FUTEX_CMP_REQUEUE_PI: u32 = 12;
+const FUTEX_LOCK_PI2: u32 = 13;
+
+/// futex 只在进程内使用，此时不需要查找共享内存
+const FUTEX_PRIVATE_FLAG: u32 = 128;
+/// 超时时间以 `CLOCK_REALTIME` 计算
+const FUTEX_CLOCK_REALTIME: u32 = 256;
+const FUTEX_CMD_MASK: u32 = !(FUTEX_PRIVATE_FLAG | FUTEX_CLOCK_REALTIME);
 
-use constants::{AlienError, AlienResult};
-use ksync::Mutex;
-use smpscheduler::FifoTask;
-use timer::read_timer;
+/// 与任意的等待者匹配的 bitset
+pub const FUTEX_BITSET_MATCH_ANY: u32 = 0xffff_ffff;
+/// PI futex 以及 robust futex 中表示有等待者的位
+const FUTEX_WAITERS: u32 = 0x8000_0000;
+/// robust futex 的持有者没有释放锁就退出了
+const FUTEX_OWNER_DIED: u32 = 0x4000_0000;
+/// PI futex 以及 robust futex 中表示持有者 tid 的位
+const FUTEX_TID_MASK: u32 = 0x3fff_ffff;
+/// 遍历 robust 锁列表时最多处理的锁数量，避免用户态构造的环形链表导致内核死循环
+const ROBUST_LIST_LIMIT: usize = 2048;
 
-use crate::task::{Task, GLOBAL_TASK_MANAGER};
+/// `FUTEX_WAKE_OP` 中的操作数 `oparg` 表示 `1 << oparg`
+const FUTEX_OP_OPARG_SHIFT: u32 = 8;
+const FUTEX_OP_SET: u32 = 0;
+const FUTEX_OP_ADD: u32 = 1;
+const FUTEX_OP_OR: u32 = 2;
+const FUTEX_OP_ANDN: u32 = 3;
+const FUTEX_OP_XOR: u32 = 4;
+const FUTEX_OP_CMP_EQ: u32 = 0;
+const FUTEX_OP_CMP_NE: u32 = 1;
+const FUTEX_OP_CMP_LT: u32 = 2;
+const FUTEX_OP_CMP_LE: u32 = 3;
+const FUTEX_OP_CMP_GT: u32 = 4;
+const FUTEX_OP_CMP_GE: u32 = 5;
 
-/// 用于记录一个进程等待一个 futex 的相关信息
-pub struct FutexWaiter {
-    /// 进程的控制块
-    task: Option<Arc<Task>>,
-    /// 进程等待 futex 的等待时间
-    wait_time: Option<usize>,
-    /// 超时事件的标志位，标识该进程对于 futex 等待是否超时
-    timeout_flag: Arc<Mutex<bool>>,
+/// 用于查找 futex 等待队列的键
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd)]
+pub enum FutexKey {
+    /// 由地址空间和虚拟地址确定的 futex
+    Private { mm: usize, addr: usize },
+    /// 位于共享内存中的 futex，由其物理地址(物理页加上页内偏移)确定
+    Shared { paddr: usize },
 }
 
-/// 用于管理 futex 等待队列的数据结构
-///
-/// 包含一个 futex id -> futexWait Vec 的 map
-pub struct FutexWaitManager {
-    map: BTreeMap<usize, Vec<FutexWaiter>>,
+/// 等待在 futex 上的一个任务
+struct FutexQ {
+    waiter: Arc<Waiter>,
+    tid: usize,
+    /// `FUTEX_WAIT_BITSET` 和 `FUTEX_WAKE_BITSET` 使用的位掩码
+    bitset: u32,
+    /// 等待者的实时优先级，用于优先级继承，分时任务为 0
+    priority: u8,
+    /// 是否在等待获取 PI futex
+    pi: bool,
+    /// `FUTEX_WAIT_REQUEUE_PI` 的等待者在被唤醒后需要获取的 PI futex
+    requeue_pi: Option<FutexKey>,
 }
 
-impl FutexWaiter {
-    /// 创建一个新的 `FutexWaiter` 保存等待在某 futex 上的一个进程 有关等待的相关信息
-    pub fn new(task: Arc<Task>, wait_time: Option<usize>, timeout_flag: Arc<Mutex<bool>>) -> Self {
+impl FutexQ {
+    fn new(task: &Task, bitset: u32) -> Self {
+        let priority = task.access_inner().sched.base_rt_priority();
         Self {
-            task: Some(task),
-            wait_time,
-            timeout_flag,
+            waiter: Waiter::current(),
+            tid: task.get_tid() as usize,
+            bitset,
+            priority,
+            pi: false,
+            requeue_pi: None,
         }
     }
+}
 
-    /// 唤醒该进程，返回该进程的控制块
-    pub fn wake(&mut self) -> Arc<Task> {
-        self.task.take().unwrap()
-    }
+/// 用于管理 futex 等待队列的数据结构
+///
+/// 包含一个 futex 键 -> 等待队列 的 map。等待者被唤醒时已经由唤醒者从队列中移除；
+/// 等待者因为超时或者被信号中断而醒来时仍在队列中，需要自行调用 [`FutexWaitManager::dequeue`] 离开等待队列。
+pub struct FutexWaitManager {
+    map: BTreeMap<FutexKey, VecDeque<FutexQ>>,
+    /// 有等待者的 PI futex 的持有者，用于计算优先级继承
+    pi_owners: BTreeMap<FutexKey, usize>,
 }
 
 impl FutexWaitManager {
@@ -60,116 +136,676 @@ impl FutexWaitManager {
     pub fn new() -> Self {
         Self {
             map: BTreeMap::new(),
+            pi_owners: BTreeMap::new(),
         }
     }
 
-    /// 在某等待队列中加入等待进程
-    pub fn add_waiter(&mut self, futex: usize, waiter: FutexWaiter) {
-        self.map.entry(futex).or_insert(Vec::new()).push(waiter);
+    /// 在 futex 的等待队列中加入等待者，并将其任务设置为等待状态
+    fn enqueue(&mut self, key: FutexKey, q: FutexQ) {
+        q.waiter.prepare();
+        self.map.entry(key).or_default().push_back(q);
     }
 
-    ///由于信号引发的唤醒操作
-    pub fn wake_for_signal(&mut self) {
-        for (_, waiters) in self.map.iter_mut() {
-            let mut record = vec![];
-            for (index, waiter) in waiters.iter_mut().enumerate() {
-                let task = waiter.task.as_ref().unwrap();
-                let task_inner = task.access_inner();
-                let receiver = task_inner.signal_receivers.lock();
-                if receiver.have_signal() {
-                    drop(receiver);
-                    drop(task_inner);
-                    let task = waiter.wake();
-                    GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
-                    record.push(index);
-                }
+    /// 取出 futex 等待队列中的第 `index` 个等待者，队列为空时将其删除
+    fn take(&mut self, key: FutexKey, index: usize) -> FutexQ {
+        let queue = self.map.get_mut(&key).unwrap();
+        let q = queue.remove(index).unwrap();
+        if queue.is_empty() {
+            self.map.remove(&key);
+            self.pi_owners.remove(&key);
+        }
+        q
+    }
+
+    /// 将等待者从等待队列中移除，返回等待者所在的 futex
+    ///
+    /// 返回 `None` 说明等待者已经被唤醒。
+    fn dequeue(&mut self, waiter: &Arc<Waiter>) -> Option<FutexKey> {
+        let (key, index) = self.map.iter().find_map(|(key, queue)| {
+            queue
+                .iter()
+                .position(|q| Arc::ptr_eq(&q.waiter, waiter))
+                .map(|index| (*key, index))
+        })?;
+        self.take(key, index);
+        Some(key)
+    }
+
+    /// futex 上是否有等待获取锁的 PI 等待者
+    fn has_pi_waiters(&self, key: FutexKey) -> bool {
+        self.map
+            .get(&key)
+            .is_some_and(|queue| queue.iter().any(|q| q.pi))
+    }
+
+    /// 唤醒 futex 上至多 num 个与 `bitset` 匹配的等待者，返回唤醒的等待者数量
+    ///
+    /// PI futex 的等待者只能通过 `FUTEX_UNLOCK_PI` 唤醒，遇到它们时返回 `EINVAL`。
+    fn wake(&mut self, key: FutexKey, num: usize, bitset: u32) -> AlienResult<usize> {
+        let Some(queue) = self.map.get_mut(&key) else {
+            return Ok(0);
+        };
+        if queue
+            .iter()
+            .any(|q| q.bitset & bitset != 0 && (q.pi || q.requeue_pi.is_some()))
+        {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let mut count = 0;
+        let mut index = 0;
+        while index < queue.len() && count < num {
+            if queue[index].bitset & bitset != 0 {
+                queue.remove(index).unwrap().waiter.wake();
+                count += 1;
+            } else {
+                index += 1;
             }
-            record.iter().for_each(|index| {
-                waiters.remove(*index);
-            })
-        }
-        self.delete_empty_waiters();
-    }
-
-    /// 由于超时引发的唤醒操作
-    pub fn wake_for_timeout(&mut self) {
-        let now = read_timer();
-        for (_, waiters) in self.map.iter_mut() {
-            let mut record = vec![];
-            for (index, waiter) in waiters.iter_mut().enumerate() {
-                if let Some(wait_time) = waiter.wait_time {
-                    if wait_time <= now {
-                        *waiter.timeout_flag.lock() = true;
-                        let task = waiter.wake();
-                        GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
-                        record.push(index);
-                    }
+        }
+        if queue.is_empty() {
+            self.map.remove(&key);
+        }
+        Ok(count)
+    }
+
+    /// 将原来等待在 `old` 上至多 num 个等待者转移到 `new` 上等待，返回转移的等待者数量
+    fn requeue(&mut self, old: FutexKey, new: FutexKey, num: usize) -> AlienResult<usize> {
+        if num == 0 || old == new {
+            return Ok(0);
+        }
+        let Some(queue) = self.map.get(&old) else {
+            return Ok(0);
+        };
+        if queue.iter().any(|q| q.pi || q.requeue_pi.is_some()) {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let mut count = 0;
+        while count < num && self.map.contains_key(&old) {
+            let q = self.take(old, 0);
+            self.map.entry(new).or_default().push_back(q);
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// 尝试让 `tid` 获取 PI futex，成功时返回 `None`，失败时在 futex 字上设置 `FUTEX_WAITERS` 并返回持有者的 tid
+    fn try_lock_pi(
+        &self,
+        key: FutexKey,
+        word: &AtomicU32,
+        tid: usize,
+    ) -> AlienResult<Option<usize>> {
+        let mut val = word.load(Ordering::SeqCst);
+        loop {
+            let owner = (val & FUTEX_TID_MASK) as usize;
+            if owner == tid {
+                return Err(LinuxErrno::EDEADLK);
+            }
+            let new = if owner == 0 {
+                // 保留 FUTEX_OWNER_DIED，让新的持有者得知上一个持有者没有释放锁就退出了
+                let waiters = if self.has_pi_waiters(key) {
+                    FUTEX_WAITERS
+                } else {
+                    0
+                };
+                tid as u32 | (val & FUTEX_OWNER_DIED) | waiters
+            } else {
+                if get_task_from_tid(owner).is_none() {
+                    return Err(LinuxErrno::ESRCH);
                 }
+                val | FUTEX_WAITERS
+            };
+            match word.compare_exchange(val, new, Ordering::SeqCst, Ordering::SeqCst) {
+                Ok(_) => return Ok((owner != 0).then_some(owner)),
+                Err(current) => val = current,
             }
-            record.iter().for_each(|index| {
-                waiters.remove(*index);
-            })
         }
-        // delete empty waiters
-        self.delete_empty_waiters();
     }
 
-    /// 清空所有空的等待队列
-    fn delete_empty_waiters(&mut self) {
-        let mut record = vec![];
-        for (futex, waiters) in self.map.iter() {
-            if waiters.is_empty() {
-                record.push(*futex);
+    /// 释放 `owner` 持有的 PI futex，将其交给优先级最高的等待者
+    ///
+    /// `died` 为交给下一个持有者时额外设置的标志位，持有者退出时为 `FUTEX_OWNER_DIED`。
+    fn unlock_pi(&mut self, key: FutexKey, word: &AtomicU32, owner: usize, died: u32) {
+        self.pi_owners.remove(&key);
+        let top = self.map.get(&key).and_then(|queue| {
+            queue
+                .iter()
+                .enumerate()
+                .filter(|(_, q)| q.pi)
+                // 优先级相同时选择先进入队列的等待者
+                .max_by(|(i, a), (j, b)| a.priority.cmp(&b.priority).then(j.cmp(i)))
+                .map(|(index, _)| index)
+        });
+        match top.map(|index| self.take(key, index)) {
+            Some(next) => {
+                let waiters = if self.has_pi_waiters(key) {
+                    self.pi_owners.insert(key, next.tid);
+                    FUTEX_WAITERS
+                } else {
+                    0
+                };
+                word.store(next.tid as u32 | waiters | died, Ordering::SeqCst);
+                next.waiter.wake();
+                self.update_pi_boost(next.tid);
             }
+            None => word.store(died, Ordering::SeqCst),
+        }
+        self.update_pi_boost(owner);
+    }
+
+    /// 根据任务持有的 PI futex 上等待者的优先级，重新计算任务继承的优先级
+    ///
+    /// 通过 [`TaskManager::update_sched`] 修改，使得已经在运行队列中的任务按照新的优先级重新排队。
+    /// 加锁顺序为 `FUTEX_WAITER`、运行队列、任务，调用者不能持有任务的锁。
+    ///
+    /// [`TaskManager::update_sched`]: crate::task::TaskManager::update_sched
+    fn update_pi_boost(&self, tid: usize) {
+        let priority = self
+            .pi_owners
+            .iter()
+            .filter(|(_, owner)| **owner == tid)
+            .filter_map(|(key, _)| self.map.get(key))
+            .flat_map(|queue| queue.iter())
+            .filter(|q| q.pi && q.priority > 0)
+            .map(|q| q.priority)
+            .max();
+        if let Some(task) = get_task_from_tid(tid) {
+            GLOBAL_TASK_MANAGER.update_sched(&task, |sched| sched.set_pi_boost(priority));
         }
-        record.iter().for_each(|futex| {
-            self.map.remove(futex);
-        })
     }
+}
 
-    /// 唤醒 futex 上的至多 num 个等待的进程
-    pub fn wake(&mut self, futex: usize, num: usize) -> AlienResult<usize> {
-        if let Some(waiters) = self.map.get_mut(&futex) {
-            error!("there are {} waiters, wake {}", waiters.len(), num);
-            let min_index = min(num, waiters.len());
-            for i in 0..min_index {
-                let task = waiters[i].wake();
-                GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
+/// 获取用户地址 `uaddr` 处的 futex 字以及对应的键
+///
+/// futex 字所在的页还没有被映射或者需要写时复制时，先处理缺页。`uaddr` 没有对齐时返回 `EINVAL`，
+/// 无法访问时返回 `EFAULT`。
+fn get_futex(
+    task: &Task,
+    uaddr: usize,
+    private: bool,
+    write: bool,
+) -> AlienResult<(&'static AtomicU32, FutexKey)> {
+    if uaddr % size_of::<u32>() != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    // 最多需要处理一次缺页和一次写时复制
+    for _ in 0..3 {
+        let fault = {
+            let mut inner = task.access_inner();
+            let query = inner.address_space.lock().query(VirtAddr::from(uaddr));
+            // 共享文件映射的页在第一次访问时才建立页表项
+            if query.is_err() && inner.map_shared_page(uaddr)? {
+                continue;
+            }
+            let (phy, flags, _) = query.map_err(|_| LinuxErrno::EFAULT)?;
+            if !flags.contains(MappingFlags::V) {
+                inner.do_load_page_fault(uaddr)
+            } else if write && flags.contains(MappingFlags::RSD) {
+                inner.do_store_page_fault(uaddr)
+            } else if !flags.contains(MappingFlags::U)
+                || (write && !flags.contains(MappingFlags::W))
+            {
+                return Err(LinuxErrno::EFAULT);
+            } else {
+                let shared = !private
+                    && (inner
+                        .shm
+                        .values()
+                        .any(|shm| (shm.start_va..shm.end_va).contains(&uaddr))
+                        || inner
+                            .mmap
+                            .get_region(uaddr)
+                            .is_some_and(|region| region.flags.contains(MMapFlags::MAP_SHARED)));
+                let key = if shared {
+                    FutexKey::Shared {
+                        paddr: phy.as_usize(),
+                    }
+                } else {
+                    FutexKey::Private {
+                        mm: Arc::as_ptr(&inner.address_space) as usize,
+                        addr: uaddr,
+                    }
+                };
+                let word = unsafe { &*(phy.as_usize() as *const AtomicU32) };
+                return Ok((word, key));
             }
-            // delete waiters
-            waiters.drain(0..min_index);
-            warn!("wake {} tasks", min_index);
-            Ok(min_index)
-        } else {
-            log::warn!("futex {} not found", futex);
-            Err(AlienError::EINVAL)
-        }
-    }
-
-    /// 将原来等待在 old_futex 上至多 num 个进程转移到 requeue_futex 上等待，返回转移的进程数
-    pub fn requeue(
-        &mut self,
-        requeue_futex: usize,
-        num: usize,
-        old_futex: usize,
-    ) -> AlienResult<usize> {
-        if num == 0 {
+        };
+        if let Some((Some(file), buf, offset)) = fault.map_err(|_| LinuxErrno::EFAULT)? {
+            trap_common_read_file(file, buf, offset);
+        }
+    }
+    Err(LinuxErrno::EFAULT)
+}
+
+/// 读取用户传入的超时时间，返回以 cpu 时钟表示的截止时间
+///
+/// `absolute` 为真时超时时间为绝对时间，否则为相对于当前的时间。
+fn futex_deadline(task: &Task, timeout: usize, absolute: bool) -> AlienResult<Option<usize>> {
+    if timeout == 0 {
+        return Ok(None);
+    }
+    let mut time_spec = TimeSpec::new(0, 0);
+    task.access_inner()
+        .copy_from_user(timeout as *const TimeSpec, &mut time_spec);
+    if time_spec.tv_nsec >= 1_000_000_000 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    if absolute {
+        Ok(Some(time_spec.to_clock()))
+    } else {
+        Ok(Some(time_spec.to_clock() + TimeSpec::now().to_clock()))
+    }
+}
+
+/// 对 futex 进行操作，参数的含义见系统调用 `futex`
+pub fn do_futex(
+    task: &Task,
+    uaddr: usize,
+    futex_op: u32,
+    val: u32,
+    val2: usize,
+    uaddr2: usize,
+    val3: u32,
+) -> AlienResult<isize> {
+    let private = futex_op & FUTEX_PRIVATE_FLAG != 0;
+    let cmd = futex_op & FUTEX_CMD_MASK;
+    if futex_op & FUTEX_CLOCK_REALTIME != 0
+        && !matches!(
+            cmd,
+            FUTEX_WAIT | FUTEX_WAIT_BITSET | FUTEX_WAIT_REQUEUE_PI | FUTEX_LOCK_PI2
+        )
+    {
+        return Err(LinuxErrno::ENOSYS);
+    }
+    match cmd {
+        FUTEX_WAIT => {
+            let deadline = futex_deadline(task, val2, false)?;
+            futex_wait(task, uaddr, private, val, deadline, FUTEX_BITSET_MATCH_ANY)
+        }
+        FUTEX_WAIT_BITSET => {
+            let deadline = futex_deadline(task, val2, true)?;
+            futex_wait(task, uaddr, private, val, deadline, val3)
+        }
+        FUTEX_WAKE => futex_wake(task, uaddr, private, val as usize, FUTEX_BITSET_MATCH_ANY),
+        FUTEX_WAKE_BITSET => futex_wake(task, uaddr, private, val as usize, val3),
+        FUTEX_REQUEUE => futex_requeue(task, uaddr, private, uaddr2, val, val2, None),
+        FUTEX_CMP_REQUEUE => futex_requeue(task, uaddr, private, uaddr2, val, val2, Some(val3)),
+        FUTEX_WAKE_OP => futex_wake_op(task, uaddr, private, uaddr2, val, val2, val3),
+        FUTEX_LOCK_PI | FUTEX_LOCK_PI2 => {
+            let deadline = futex_deadline(task, val2, true)?;
+            futex_lock_pi(task, uaddr, private, deadline, false)
+        }
+        FUTEX_TRYLOCK_PI => futex_lock_pi(task, uaddr, private, None, true),
+        FUTEX_UNLOCK_PI => futex_unlock_pi(task, uaddr, private),
+        FUTEX_WAIT_REQUEUE_PI => {
+            let deadline = futex_deadline(task, val2, true)?;
+            futex_wait_requeue_pi(task, uaddr, private, val, deadline, uaddr2)
+        }
+        FUTEX_CMP_REQUEUE_PI => futex_cmp_requeue_pi(task, uaddr, private, uaddr2, val, val2, val3),
+        // FUTEX_FD 在 Linux 2.6.26 中已经被移除
+        FUTEX_FD => Err(LinuxErrno::ENOSYS),
+        _ => Err(LinuxErrno::ENOSYS),
+    }
+}
+
+/// 若 futex 的值等于 `val`，则在其上等待直到被 bitset 与 `bitset` 相交的唤醒操作唤醒
+fn futex_wait(
+    task: &Task,
+    uaddr: usize,
+    private: bool,
+    val: u32,
+    deadline: Option<usize>,
+    bitset: u32,
+) -> AlienResult<isize> {
+    if bitset == 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let (word, key) = get_futex(task, uaddr, private, false)?;
+    let q = FutexQ::new(task, bitset);
+    let waiter = q.waiter.clone();
+    {
+        // 在持有锁时比较 futex 的值，避免在比较之后、进入等待队列之前到来的唤醒被遗漏
+        let mut manager = FUTEX_WAITER.lock();
+        if word.load(Ordering::SeqCst) != val {
+            return Err(LinuxErrno::EAGAIN);
+        }
+        manager.enqueue(key, q);
+    }
+    if let Err(e) = waiter.wait(deadline, true) {
+        // 如果醒来时仍然位于等待队列中，说明是因为超时或者信号而醒来的
+        if FUTEX_WAITER.lock().dequeue(&waiter).is_some() {
+            return Err(e);
+        }
+    }
+    Ok(0)
+}
+
+/// 唤醒至多 `num` 个在 `uaddr` 上等待、且 bitset 与 `bitset` 相交的任务，返回唤醒的任务数
+pub fn futex_wake(
+    task: &Task,
+    uaddr: usize,
+    private: bool,
+    num: usize,
+    bitset: u32,
+) -> AlienResult<isize> {
+    if bitset == 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let (_, key) = get_futex(task, uaddr, private, false)?;
+    let count = FUTEX_WAITER.lock().wake(key, num, bitset)?;
+    Ok(count as isize)
+}
+
+/// 唤醒至多 `nr_wake` 个在 `uaddr` 上等待的任务，再将至多 `nr_requeue` 个剩下的任务转移到 `uaddr2` 上等待
+///
+/// `cmpval` 不为 `None` 时，先比较 `uaddr` 上的值，不相等时返回 `EAGAIN`。返回唤醒和转移的任务数之和。
+fn futex_requeue(
+    task: &Task,
+    uaddr: usize,
+    private: bool,
+    uaddr2: usize,
+    nr_wake: u32,
+    nr_requeue: usize,
+    cmpval: Option<u32>,
+) -> AlienResult<isize> {
+    if (nr_wake as i32) < 0 || (nr_requeue as i32) < 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let (word, key) = get_futex(task, uaddr, private, false)?;
+    let (_, key2) = get_futex(task, uaddr2, private, false)?;
+    let mut manager = FUTEX_WAITER.lock();
+    if cmpval.is_some_and(|cmpval| word.load(Ordering::SeqCst) != cmpval) {
+        return Err(LinuxErrno::EAGAIN);
+    }
+    let woken = manager.wake(key, nr_wake as usize, FUTEX_BITSET_MATCH_ANY)?;
+    let moved = manager.requeue(key, key2, nr_requeue as u32 as usize)?;
+    Ok((woken + moved) as isize)
+}
+
+/// 原子地修改 `uaddr2` 上的值并唤醒 `uaddr` 上至多 `nr_wake` 个任务，
+/// 如果 `uaddr2` 上原来的值满足 `encoded` 中的比较条件，再唤醒 `uaddr2` 上至多 `nr_wake2` 个任务
+///
+/// `encoded` 的编码方式为 `(op << 28) | (cmp << 24) | (oparg << 12) | cmparg`。
+fn futex_wake_op(
+    task: &Task,
+    uaddr: usize,
+    private: bool,
+    uaddr2: usize,
+    nr_wake: u32,
+    nr_wake2: usize,
+    encoded: u32,
+) -> AlienResult<isize> {
+    let op = (encoded >> 28) & 0xf;
+    let cmp = (encoded >> 24) & 0xf;
+    // oparg 和 cmparg 都是 12 位的有符号数
+    let mut oparg = ((encoded << 8) as i32) >> 20;
+    let cmparg = ((encoded << 20) as i32) >> 20;
+    if cmp > FUTEX_OP_CMP_GE {
+        return Err(LinuxErrno::ENOSYS);
+    }
+    if op & FUTEX_OP_OPARG_SHIFT != 0 {
+        oparg = 1 << (oparg & 31);
+    }
+    let (_, key) = get_futex(task, uaddr, private, false)?;
+    let (word2, key2) = get_futex(task, uaddr2, private, true)?;
+    let mut manager = FUTEX_WAITER.lock();
+    let oparg = oparg as u32;
+    let old = match op & !FUTEX_OP_OPARG_SHIFT {
+        FUTEX_OP_SET => word2.swap(oparg, Ordering::SeqCst),
+        FUTEX_OP_ADD => word2.fetch_add(oparg, Ordering::SeqCst),
+        FUTEX_OP_OR => word2.fetch_or(oparg, Ordering::SeqCst),
+        FUTEX_OP_ANDN => word2.fetch_and(!oparg, Ordering::SeqCst),
+        FUTEX_OP_XOR => word2.fetch_xor(oparg, Ordering::SeqCst),
+        _ => return Err(LinuxErrno::ENOSYS),
+    } as i32;
+    let mut count = manager.wake(key, nr_wake as usize, FUTEX_BITSET_MATCH_ANY)?;
+    let matched = match cmp {
+        FUTEX_OP_CMP_EQ => old == cmparg,
+        FUTEX_OP_CMP_NE => old != cmparg,
+        FUTEX_OP_CMP_LT => old < cmparg,
+        FUTEX_OP_CMP_LE => old <= cmparg,
+        FUTEX_OP_CMP_GT => old > cmparg,
+        _ => old >= cmparg,
+    };
+    if matched {
+        count += manager.wake(key2, nr_wake2, FUTEX_BITSET_MATCH_ANY)?;
+    }
+    Ok(count as isize)
+}
+
+/// 获取 PI futex，锁被其它任务持有时等待，并将持有者的优先级提升到等待者的优先级
+///
+/// `trylock` 为真时不等待，锁被持有时返回 `EAGAIN`。
+fn futex_lock_pi(
+    task: &Task,
+    uaddr: usize,
+    private: bool,
+    deadline: Option<usize>,
+    trylock: bool,
+) -> AlienResult<isize> {
+    let (word, key) = get_futex(task, uaddr, private, true)?;
+    let mut q = FutexQ::new(task, FUTEX_BITSET_MATCH_ANY);
+    q.pi = true;
+    let waiter = q.waiter.clone();
+    {
+        let mut manager = FUTEX_WAITER.lock();
+        let Some(owner) = manager.try_lock_pi(key, word, q.tid)? else {
+            return Ok(0);
+        };
+        if trylock {
+            return Err(LinuxErrno::EAGAIN);
+        }
+        manager.enqueue(key, q);
+        manager.pi_owners.insert(key, owner);
+        manager.update_pi_boost(owner);
+    }
+    // 等待持有者释放锁时直接将锁交给我们
+    if let Err(e) = waiter.wait(deadline, false) {
+        let mut manager = FUTEX_WAITER.lock();
+        if manager.dequeue(&waiter).is_some() {
+            if let Some(owner) = manager.pi_owners.get(&key) {
+                manager.update_pi_boost(*owner);
+            }
+            return Err(e);
+        }
+    }
+    Ok(0)
+}
+
+/// 释放当前任务持有的 PI futex，锁上有等待者时将锁交给优先级最高的等待者
+fn futex_unlock_pi(task: &Task, uaddr: usize, private: bool) -> AlienResult<isize> {
+    let tid = task.get_tid() as usize;
+    let (word, key) = get_futex(task, uaddr, private, true)?;
+    let mut manager = FUTEX_WAITER.lock();
+    if (word.load(Ordering::SeqCst) & FUTEX_TID_MASK) as usize != tid {
+        return Err(LinuxErrno::EPERM);
+    }
+    manager.unlock_pi(key, word, tid, 0);
+    Ok(0)
+}
+
+/// 若 `uaddr` 的值等于 `val`，则在其上等待，被 `FUTEX_CMP_REQUEUE_PI` 唤醒或转移后获取 `uaddr2` 上的 PI futex
+///
+/// 成功返回时当前任务已经持有 `uaddr2` 上的锁。
+fn futex_wait_requeue_pi(
+    task: &Task,
+    uaddr: usize,
+    private: bool,
+    val: u32,
+    deadline: Option<usize>,
+    uaddr2: usize,
+) -> AlienResult<isize> {
+    if uaddr == uaddr2 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let (word, key) = get_futex(task, uaddr, private, false)?;
+    let (_, key2) = get_futex(task, uaddr2, private, true)?;
+    let mut q = FutexQ::new(task, FUTEX_BITSET_MATCH_ANY);
+    q.requeue_pi = Some(key2);
+    let waiter = q.waiter.clone();
+    {
+        let mut manager = FUTEX_WAITER.lock();
+        if word.load(Ordering::SeqCst) != val {
+            return Err(LinuxErrno::EAGAIN);
+        }
+        manager.enqueue(key, q);
+    }
+    let mut interruptible = true;
+    loop {
+        let Err(e) = waiter.wait(deadline, interruptible) else {
+            return Ok(0);
+        };
+        let mut manager = FUTEX_WAITER.lock();
+        let requeued = manager
+            .map
+            .get(&key2)
+            .is_some_and(|queue| queue.iter().any(|q| Arc::ptr_eq(&q.waiter, &waiter)));
+        if requeued && e == LinuxErrno::EINTR {
+            // 已经被转移到 PI futex 上，此时不再响应信号，继续等待获取锁
+            interruptible = false;
+            continue;
+        }
+        if manager.dequeue(&waiter).is_none() {
             return Ok(0);
         }
-        // move waiters
-        let mut waiters = self.map.remove(&old_futex).unwrap();
-        // create new waiters
-        let new_waiters = self.map.entry(requeue_futex).or_insert(Vec::new());
-        let min_index = min(num, waiters.len());
-        error!("requeue {} waiters", min_index);
-        for _ in 0..min_index {
-            let waiter = waiters.pop().unwrap();
-            new_waiters.push(waiter);
-        }
-        // insert old waiters
-        if !waiters.is_empty() {
-            self.map.insert(old_futex, waiters);
-        }
-        Ok(min_index)
+        if let Some(owner) = manager.pi_owners.get(&key2) {
+            manager.update_pi_boost(*owner);
+        }
+        return Err(e);
+    }
+}
+
+/// 唤醒 `uaddr` 上通过 `FUTEX_WAIT_REQUEUE_PI` 等待的任务，让其获取 `uaddr2` 上的 PI futex
+///
+/// 锁空闲时队首的任务直接获取锁并被唤醒，其余至多 `nr_requeue` 个任务被转移到 `uaddr2` 上等待获取锁。
+/// `nr_wake` 必须为 1，返回唤醒和转移的任务数之和。
+fn futex_cmp_requeue_pi(
+    task: &Task,
+    uaddr: usize,
+    private: bool,
+    uaddr2: usize,
+    nr_wake: u32,
+    nr_requeue: usize,
+    cmpval: u32,
+) -> AlienResult<isize> {
+    if nr_wake != 1 || (nr_requeue as i32) < 0 || uaddr == uaddr2 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let (word, key) = get_futex(task, uaddr, private, false)?;
+    let (word2, key2) = get_futex(task, uaddr2, private, true)?;
+    let mut manager = FUTEX_WAITER.lock();
+    if word.load(Ordering::SeqCst) != cmpval {
+        return Err(LinuxErrno::EAGAIN);
+    }
+    let Some(queue) = manager.map.get(&key) else {
+        return Ok(0);
+    };
+    if queue.iter().any(|q| q.requeue_pi != Some(key2)) {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let mut count = 0;
+    let top = queue[0].tid;
+    if manager.try_lock_pi(key2, word2, top)?.is_none() {
+        manager.take(key, 0).waiter.wake();
+        count += 1;
+    }
+    let mut moved = 0;
+    while moved < nr_requeue as u32 as usize && manager.map.contains_key(&key) {
+        let mut q = manager.take(key, 0);
+        q.pi = true;
+        manager.map.entry(key2).or_default().push_back(q);
+        moved += 1;
+    }
+    if moved > 0 {
+        let owner = (word2.fetch_or(FUTEX_WAITERS, Ordering::SeqCst) & FUTEX_TID_MASK) as usize;
+        manager.pi_owners.insert(key2, owner);
+        manager.update_pi_boost(owner);
+    }
+    Ok((count + moved) as isize)
+}
+
+/// 读取用户地址空间中的一个 usize，地址无效时返回 `None`
+fn read_user_usize(task: &Task, addr: usize) -> Option<usize> {
+    if addr % size_of::<usize>() != 0 {
+        return None;
+    }
+    let inner = task.access_inner();
+    let (phy, flags, _) = inner
+        .address_space
+        .lock()
+        .query(VirtAddr::from(addr))
+        .ok()?;
+    if !flags.contains(MappingFlags::V) || !flags.contains(MappingFlags::U) {
+        return None;
+    }
+    Some(unsafe { *(phy.as_usize() as *const usize) })
+}
+
+/// 持有 robust futex 的任务退出时，释放该锁并设置 `FUTEX_OWNER_DIED`
+///
+/// 普通的 robust futex 唤醒一个等待者，让其通过 `FUTEX_OWNER_DIED` 得知锁的状态可能不一致；
+/// PI futex 则直接交给优先级最高的等待者。
+fn handle_futex_death(task: &Task, uaddr: usize, tid: usize, pi: bool) {
+    // robust futex 可能位于进程间共享的内存中，按照非私有的 futex 查找等待队列
+    let Ok((word, key)) = get_futex(task, uaddr, false, true) else {
+        return;
+    };
+    let mut manager = FUTEX_WAITER.lock();
+    let val = word.load(Ordering::SeqCst);
+    if (val & FUTEX_TID_MASK) as usize != tid {
+        return;
+    }
+    if pi {
+        manager.unlock_pi(key, word, tid, FUTEX_OWNER_DIED);
+    } else {
+        word.store((val & FUTEX_WAITERS) | FUTEX_OWNER_DIED, Ordering::SeqCst);
+        if val & FUTEX_WAITERS != 0 {
+            let _ = manager.wake(key, 1, FUTEX_BITSET_MATCH_ANY);
+        }
+    }
+}
+
+/// 任务退出时遍历其 robust 锁列表，释放其仍然持有的锁
+///
+/// 列表头的结构为 `{ next, futex_offset, list_op_pending }`，列表中的每一项加上 `futex_offset` 即为锁的 futex 字的地址，
+/// 项的最低位表示该锁是否为 PI futex。`list_op_pending` 指向正在加锁或者解锁、尚未加入或移出列表的锁。
+pub fn exit_robust_list(task: &Task) {
+    let head = task.access_inner().robust.head;
+    if head == 0 {
+        return;
+    }
+    let tid = task.get_tid() as usize;
+    let word = size_of::<usize>();
+    let (Some(mut entry), Some(offset), Some(pending)) = (
+        read_user_usize(task, head),
+        read_user_usize(task, head + word),
+        read_user_usize(task, head + 2 * word),
+    ) else {
+        return;
+    };
+    let offset = offset as isize;
+    let mut limit = ROBUST_LIST_LIMIT;
+    while entry & !1 != head && limit > 0 {
+        // 释放锁之后用户态可能修改列表项，因此先读取下一项
+        let Some(next) = read_user_usize(task, entry & !1) else {
+            return;
+        };
+        if entry & !1 != pending & !1 {
+            handle_futex_death(
+                task,
+                (entry & !1).wrapping_add_signed(offset),
+                tid,
+                entry & 1 != 0,
+            );
+        }
+        entry = next;
+        limit -= 1;
+    }
+    if pending != 0 {
+        handle_futex_death(
+            task,
+            (pending & !1).wrapping_add_signed(offset),
+            tid,
+            pending & 1 != 0,
+        );
     }
 }
@@ -1,32 +1,26 @@
 //! IPC 进程间通信，目前 Alien 支持管道、共享内存、信号以及futex'等进程间的通信机制。
 //!
 //! [`futex`] 子模块指明了 Alien 中的 futex (快速用户空间互斥体)结构。
+//! [`pidfd`] 子模块指明了 Alien 中指向进程的文件描述符。
 //! [`pipe`] 子模块指明了 Alien 中管道结构。
 //! [`shm`] 子模块指明了 Alien 中的共享内存结构。
 //! [`signal`] 子模块指明了 Alien 中使用的信号机制。
 
-use alloc::sync::Arc;
-use core::sync::atomic::{AtomicI32, Ordering};
-
-use constants::{
-    ipc::{FutexOp, RobustList},
-    time::TimeSpec,
-    AlienResult, LinuxErrno,
-};
+use constants::{ipc::RobustList, AlienResult, LinuxErrno};
 use ksync::Mutex;
 pub use pipe::*;
 pub use shm::*;
 pub use signal::*;
 use spin::Lazy;
-use timer::{TimeNow, ToClock};
 
 use crate::{
     fs::basic::sys_close,
-    ipc::futex::{FutexWaitManager, FutexWaiter},
-    task::{current_task, schedule::schedule, TaskState},
+    ipc::futex::FutexWaitManager,
+    task::{current_task, get_task_from_tid},
 };
 
 pub mod futex;
+pub mod pidfd;
 mod pipe;
 pub mod shm;
 pub mod signal;
@@ -107,26 +101,28 @@ pub fn sys_dup2(old_fd: usize, new_fd: usize, _flag: usize) -> AlienResult<isize
     Ok(new_fd as isize)
 }
 
-static FCOUNT: Mutex<usize> = Mutex::new(0);
-
 /// 一个系统调用，对 futex 进行操作。 有关 `futex` 的相关信息请见 [`futex`]。
 ///
 /// 参数：
-/// + `uaddr`: 用户态下共享内存的地址，里面存放的是一个对齐的整型计数器，指向一个 futex。
-/// + `futex_op`: 指明操作的类型。具体操作类型可见 [`FutexOp`]。目前 Alien 识别的 futex_op 包括：
-///     + FutexOp::FutexWaitPrivate | FutexOp::FutexWait: 先比较 uaddr 上计数器的值和 val 是否相等，如果不相等则将直接返回 `EAGAIN`；否则
-/// 该进程将等待在 uaddr 上，并根据 val2 的值确定等待的逻辑。若 val2 值为0，则表示进程一直等待；若 val2 是一个正数，则表示进程将在等待 val2 时间后因超时被唤醒
-///     + FutexOp::FutexCmpRequeuePiPrivate: 先比较 uaddr 上计数器的值和 val3 是否相等，如果不相等则将直接返回 `EAGAIN`；否则
-/// 唤醒至多 val 个在 uaddr 上等待的进程后，将原来等待在 uaddr 上至多 val2 个进程转移到 uaddr2 上等待，最后返回 唤醒的进程数 + 转移的进程数
-///     + FutexOp::FutexRequeuePrivate: 唤醒至多 val 个在 uaddr 上等待的进程后，将原来等待在 uaddr 上至多 val2 个进程转移到 uaddr2 上等待
-/// 最后返回 唤醒的进程数 + 转移的进程数
-///     + FutexOp::FutexWakePrivate | FutexOp::FutexWake: 唤醒至多 val 个在 uaddr 上等待的进程。最后返回 唤醒的进程数。
+/// + `uaddr`: 用户态下共享内存的地址，里面存放的是一个对齐的 32 位整型计数器，指向一个 futex。
+/// + `futex_op`: 指明操作的类型，低 7 位为具体的操作，此外可以带有 `FUTEX_PRIVATE_FLAG` 和 `FUTEX_CLOCK_REALTIME` 标志。
+/// 没有 `FUTEX_PRIVATE_FLAG` 标志且位于共享内存中的 futex 按照物理地址匹配，可以用于进程间同步。支持的操作包括：
+///     + FUTEX_WAIT / FUTEX_WAIT_BITSET: 先比较 uaddr 上计数器的值和 val 是否相等，如果不相等则将直接返回 `EAGAIN`；否则
+/// 该进程将等待在 uaddr 上，直到被唤醒、超时或者被信号中断。val2 指向超时时间，FUTEX_WAIT 的超时时间为相对时间，
+/// FUTEX_WAIT_BITSET 的超时时间为绝对时间，且只会被 bitset 与 val3 相交的唤醒操作唤醒
+///     + FUTEX_WAKE / FUTEX_WAKE_BITSET: 唤醒至多 val 个在 uaddr 上等待(且 bitset 与 val3 相交)的进程。最后返回 唤醒的进程数。
+///     + FUTEX_REQUEUE / FUTEX_CMP_REQUEUE: 唤醒至多 val 个在 uaddr 上等待的进程后，将原来等待在 uaddr 上至多 val2 个进程转移到 uaddr2 上等待，
+/// 最后返回 唤醒的进程数 + 转移的进程数。FUTEX_CMP_REQUEUE 会先比较 uaddr 上计数器的值和 val3 是否相等，如果不相等则将直接返回 `EAGAIN`
+///     + FUTEX_WAKE_OP: 按照 val3 的编码原子地修改 uaddr2 上的值，唤醒至多 val 个在 uaddr 上等待的进程，
+/// 若 uaddr2 上原来的值满足 val3 中的比较条件，再唤醒至多 val2 个在 uaddr2 上等待的进程
+///     + FUTEX_LOCK_PI / FUTEX_LOCK_PI2 / FUTEX_TRYLOCK_PI / FUTEX_UNLOCK_PI: 带有优先级继承的锁，等待锁时持有者的优先级会被提升到等待者的优先级
+///     + FUTEX_WAIT_REQUEUE_PI / FUTEX_CMP_REQUEUE_PI: 在 uaddr 上等待，被唤醒或转移后获取 uaddr2 上的 PI 锁，用于实现条件变量
 /// + `val`: 传入的参数1，将根据 futex_op 发挥不同的作用。
 /// + `val2`: 传入的参数2，将根据 futex_op 发挥不同的作用。
 /// + `uaddr2`: 传入的地址2，将根据 futex_op 发挥不同的作用。
 /// + `val3`: 传入的参数3，将根据 futex_op 发挥不同的作用。
 ///
-/// 在此过程中，如果出现异常，会返回异常类型。
+/// 在此过程中，如果出现异常，会返回异常类型。不支持的操作返回 `ENOSYS`。
 ///
 /// Reference: [futex](https://man7.org/linux/man-pages/man2/futex.2.html)
 #[syscall_func(98)]
@@ -137,92 +133,13 @@ pub fn futex(
     val2: usize,
     uaddr2: usize,
     val3: u32,
-) -> isize {
-    *FCOUNT.lock() += 1;
-    let futex_op = FutexOp::try_from(futex_op).unwrap();
+) -> AlienResult<isize> {
     let task = current_task().unwrap();
-    let task_inner = task.access_inner();
     warn!(
-        "futex: {:?} {:?} {:?} {:?} {:?} {:?}",
+        "futex: {:#x} {:#x} {:?} {:#x} {:#x} {:#x}",
         uaddr, futex_op, val, val2, uaddr2, val3
     );
-    match futex_op {
-        FutexOp::FutexWaitPrivate | FutexOp::FutexWait => {
-            let uaddr_ref = task_inner.transfer_raw_ptr_mut(uaddr as *mut i32);
-            let uaddr_atomic = AtomicI32::from_mut(uaddr_ref);
-
-            if uaddr_atomic.load(Ordering::SeqCst) != val as i32 {
-                error!("FutexWait: uaddr_ref != val");
-                return LinuxErrno::EAGAIN as isize;
-            }
-            // we checkout the timeout
-            let wait_time = if val2 != 0 {
-                let time_spec = task_inner.transfer_raw_ptr(val2 as *const TimeSpec);
-                Some(time_spec.to_clock() + TimeSpec::now().to_clock())
-            } else {
-                // wait forever
-                None
-            };
-            // add to wait queue
-            drop(task_inner);
-            warn!("Futex wait time: {:?}", wait_time);
-            let timeout_flag = Arc::new(Mutex::new(false));
-            let waiter = FutexWaiter::new(task.clone(), wait_time, timeout_flag.clone());
-            FUTEX_WAITER.lock().add_waiter(uaddr, waiter);
-            // switch to other task
-            task.update_state(TaskState::Waiting);
-            warn!("Because of futex, we switch to other task");
-            schedule();
-            // checkout the timeout flag
-            let timeout_flag = timeout_flag.lock();
-            if *timeout_flag {
-                return 0;
-            }
-        }
-        FutexOp::FutexCmpRequeuePiPrivate => {
-            let uaddr_ref = task_inner.transfer_raw_ptr(uaddr as *const u32);
-            if *uaddr_ref != val3 {
-                error!("FutexRequeuePrivate: uaddr_ref != val");
-                return LinuxErrno::EAGAIN as isize;
-            }
-            // wake val tasks
-            let res = FUTEX_WAITER.lock().wake(uaddr, val as usize);
-            if res.is_err() {
-                return LinuxErrno::EINVAL as isize;
-            }
-            // requeue val2 tasks to uaddr2
-            let res2 = FUTEX_WAITER.lock().requeue(uaddr2, val2, uaddr);
-            if res2.is_err() {
-                return LinuxErrno::EINVAL as isize;
-            }
-            return res2.unwrap() as isize + res.unwrap() as isize;
-        }
-        FutexOp::FutexRequeuePrivate => {
-            // wake val tasks
-            let res = FUTEX_WAITER.lock().wake(uaddr, val as usize);
-            if res.is_err() {
-                return LinuxErrno::EINVAL as isize;
-            }
-            // requeue val2 tasks to uaddr2
-            let res2 = FUTEX_WAITER.lock().requeue(uaddr2, val2, uaddr);
-            if res2.is_err() {
-                return LinuxErrno::EINVAL as isize;
-            }
-            return res2.unwrap() as isize + res.unwrap() as isize;
-        }
-        FutexOp::FutexWakePrivate | FutexOp::FutexWake => {
-            let res = FUTEX_WAITER.lock().wake(uaddr, val as usize);
-            if res.is_err() {
-                return LinuxErrno::EINVAL as isize;
-            }
-            return res.unwrap() as isize;
-        }
-        _ => {
-            panic!("futex: unimplemented futex_op: {:?}", futex_op);
-            // return LinuxErrno::EINVAL as isize;
-        }
-    }
-    0
+    futex::do_futex(task, uaddr, futex_op, val, val2, uaddr2, val3)
 }
 
 /// 一个系统调用，用于设置当前进程的 robust 锁的列表头。robust 锁主要是解决当一个持有互斥锁的线程退出之后这个锁成为不可用状态的问题。
@@ -243,24 +160,24 @@ pub fn set_robust_list(head: usize, len: usize) -> isize {
 ///
 /// `pid` 指明了要获取相关信息的进程号；`head_ptr` 指明了获取信息后保存的位置；`len_ptr` 指明了获取列表长度信息后保存的位置。
 ///
-/// 当 `pid` 的值为 0 时，将会导致函数 panic；当函数正确执行时，返回 0。
+/// `pid` 为 0 时获取当前线程的信息，找不到 `pid` 对应的线程时返回 `ESRCH`；当函数正确执行时，返回 0。
 #[syscall_func(100)]
-pub fn get_robust_list(pid: usize, head_ptr: usize, len_ptr: usize) -> isize {
-    assert_eq!(pid, 0);
+pub fn get_robust_list(pid: usize, head_ptr: usize, len_ptr: usize) -> AlienResult<isize> {
     let task = current_task().unwrap();
+    let head = if pid == 0 {
+        task.access_inner().robust.head
+    } else {
+        get_task_from_tid(pid)
+            .ok_or(LinuxErrno::ESRCH)?
+            .access_inner()
+            .robust
+            .head
+    };
     let task_inner = task.access_inner();
-    let head = task_inner.robust.head;
     let len = RobustList::HEAD_SIZE;
     let head_ref = task_inner.transfer_raw_ptr_mut(head_ptr as *mut usize);
     let len_ref = task_inner.transfer_raw_ptr_mut(len_ptr as *mut usize);
     *head_ref = head;
     *len_ref = len;
-    0
-}
-
-/// 唤醒所有当前正在等待 futex 但因为超时或者信号而需要被唤醒的进程
-pub fn solve_futex_wait() {
-    let mut futex_waiter = FUTEX_WAITER.lock();
-    futex_waiter.wake_for_timeout();
-    futex_waiter.wake_for_signal();
+    Ok(0)
 }
@@ -0,0 +1,185 @@
+//! pidfd 是指向一个进程的文件描述符。
+//!
+//! 与进程号不同，pidfd 持有的是进程控制块的弱引用，即使进程退出后进程号被重新分配给其它进程，
+//! 也不会误将信号发送给新的进程。进程退出后 pidfd 变为可读，因此可以通过 `poll`/`epoll` 等待进程退出。
+use alloc::sync::{Arc, Weak};
+
+use constants::{
+    io::{OpenFlags, PollEvents, SeekFrom},
+    signal::SignalNumber,
+    AlienResult, LinuxErrno,
+};
+use log::warn;
+use syscall_table::syscall_func;
+use vfs::kfile::File;
+use vfscore::{dentry::VfsDentry, inode::VfsInode, utils::VfsFileStat};
+
+use crate::{
+    ipc::send_process_signal,
+    task::{current_task, get_task_from_tid, Task, TaskState},
+};
+
+/// `pidfd_open` 中唯一支持的标志位，与 `O_NONBLOCK` 相同
+const PIDFD_NONBLOCK: usize = OpenFlags::O_NONBLOCK.bits();
+
+/// 指向一个进程的文件
+#[derive(Debug)]
+pub struct PidFd {
+    pid: usize,
+    task: Weak<Task>,
+    flags: OpenFlags,
+}
+
+impl PidFd {
+    /// 创建一个指向进程 `task` 的 pidfd，`task` 必须是线程组的主线程
+    pub fn new(task: &Arc<Task>, flags: OpenFlags) -> Self {
+        Self {
+            pid: task.get_pid() as usize,
+            task: Arc::downgrade(task),
+            flags,
+        }
+    }
+
+    /// 创建一个尚未指向任何进程的 pidfd，用于在创建子进程之前预先占用文件描述符
+    pub fn empty() -> Self {
+        Self {
+            pid: 0,
+            task: Weak::new(),
+            flags: OpenFlags::empty(),
+        }
+    }
+
+    /// 返回 pidfd 所指向进程的进程号
+    pub fn pid(&self) -> usize {
+        self.pid
+    }
+
+    /// 返回 pidfd 所指向的进程，进程已经退出时返回 `None`
+    pub fn task(&self) -> Option<Arc<Task>> {
+        self.task
+            .upgrade()
+            .filter(|task| !matches!(task.state(), TaskState::Zombie | TaskState::Terminated))
+    }
+
+    /// 是否设置了 `PIDFD_NONBLOCK`
+    pub fn is_nonblock(&self) -> bool {
+        self.flags.contains(OpenFlags::O_NONBLOCK)
+    }
+}
+
+impl File for PidFd {
+    fn read(&self, _buf: &mut [u8]) -> AlienResult<usize> {
+        Err(LinuxErrno::EINVAL)
+    }
+
+    fn write(&self, _buf: &[u8]) -> AlienResult<usize> {
+        Err(LinuxErrno::EINVAL)
+    }
+
+    fn seek(&self, _pos: SeekFrom) -> AlienResult<u64> {
+        Err(LinuxErrno::ESPIPE)
+    }
+
+    fn get_attr(&self) -> AlienResult<VfsFileStat> {
+        Err(LinuxErrno::ENOSYS)
+    }
+
+    fn get_open_flag(&self) -> OpenFlags {
+        self.flags
+    }
+
+    fn dentry(&self) -> Arc<dyn VfsDentry> {
+        panic!("PidFd does not have dentry")
+    }
+
+    fn inode(&self) -> Arc<dyn VfsInode> {
+        panic!("PidFd does not have inode")
+    }
+
+    fn is_readable(&self) -> bool {
+        true
+    }
+
+    fn is_writable(&self) -> bool {
+        false
+    }
+
+    fn is_append(&self) -> bool {
+        false
+    }
+
+    fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        if self.task().is_none() && event.contains(PollEvents::EPOLLIN) {
+            return Ok(PollEvents::EPOLLIN);
+        }
+        Ok(PollEvents::empty())
+    }
+}
+
+/// 一个系统调用，创建一个指向进程 `pid` 的 pidfd。
+///
+/// `flags` 只能为 0 或者 `PIDFD_NONBLOCK`，否则返回 `EINVAL`。`pid` 必须是一个存活的进程(线程组的主线程)，
+/// 进程不存在时返回 `ESRCH`，`pid` 指向的是一个线程时返回 `EINVAL`。
+///
+/// 成功时返回 pidfd 的文件描述符。
+///
+/// Reference: [pidfd_open](https://man7.org/linux/man-pages/man2/pidfd_open.2.html)
+#[syscall_func(434)]
+pub fn pidfd_open(pid: isize, flags: usize) -> AlienResult<isize> {
+    if pid <= 0 || flags & !PIDFD_NONBLOCK != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let target = get_task_from_tid(pid as usize)
+        .filter(|task| !matches!(task.state(), TaskState::Zombie | TaskState::Terminated))
+        .ok_or(LinuxErrno::ESRCH)?;
+    if target.get_pid() != pid {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let pidfd = PidFd::new(&target, OpenFlags::from_bits_truncate(flags));
+    let fd = current_task()
+        .unwrap()
+        .add_file(Arc::new(pidfd))
+        .map_err(|_| LinuxErrno::EMFILE)?;
+    Ok(fd as isize)
+}
+
+/// 一个系统调用，向 `pidfd` 指向的进程发送信号 `sig`。
+///
+/// `flags` 目前必须为 0。`info` 指向的 `siginfo_t` 结构目前会被忽略，接收者看到的信号与 `kill` 发送的相同。
+/// `sig` 为 0 时不发送信号，仅检查目标进程是否存在。
+///
+/// 成功时返回 0；`pidfd` 不是合法的文件描述符时返回 `EBADF`，不是 pidfd 或者参数非法时返回 `EINVAL`，
+/// 目标进程已经退出时返回 `ESRCH`。
+///
+/// Reference: [pidfd_send_signal](https://man7.org/linux/man-pages/man2/pidfd_send_signal.2.html)
+#[syscall_func(424)]
+pub fn pidfd_send_signal(
+    pidfd: usize,
+    sig: usize,
+    _info: usize,
+    flags: usize,
+) -> AlienResult<isize> {
+    if flags != 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    if sig != 0 {
+        SignalNumber::try_from(sig as u8).map_err(|_| LinuxErrno::EINVAL)?;
+    }
+    let file = current_task()
+        .unwrap()
+        .get_file(pidfd)
+        .ok_or(LinuxErrno::EBADF)?;
+    let pidfd = file
+        .downcast_arc::<PidFd>()
+        .map_err(|_| LinuxErrno::EINVAL)?;
+    let target = pidfd.task().ok_or(LinuxErrno::ESRCH)?;
+    warn!(
+        "pidfd_send_signal pid {}, signal id {}",
+        target.get_pid(),
+        sig
+    );
+    if sig != 0 {
+        send_process_signal(target.get_pid() as usize, sig);
+    }
+    Ok(0)
+}
@@ -24,7 +24,7 @@ use constants::{
     io::{OpenFlags, PollEvents, SeekFrom},
     AlienResult, LinuxErrno,
 };
-use ksync::Mutex;
+use ksync::{poll::PollQueue, wait::WaitQueue, Mutex};
 use vfs::{
     kfile::File,
     pipefs::{PipeFsDirInodeImpl, PIPE_FS_ROOT},
@@ -40,8 +40,6 @@ use vfscore::{
     VfsResult,
 };
 
-use crate::task::{current_task, do_suspend};
-
 static PIPE: AtomicUsize = AtomicUsize::new(0);
 
 /// 管道文件
@@ -142,11 +140,20 @@ impl File for PipeFile {
             .map(|e| PollEvents::from_bits_truncate(e.bits() as u32));
         res.map_err(Into::into)
     }
+    fn poll_queue(&self) -> Option<&PollQueue> {
+        Some(&self.inode_copy.poll_queue)
+    }
 }
 
 /// 环形缓冲区，用于在内存中维护管道的相关信息。
 pub struct PipeInode {
     data: Mutex<PipeInodeData>,
+    /// 等待缓冲区中有数据可读的读者
+    read_queue: WaitQueue,
+    /// 等待缓冲区中有空间可写的写者
+    write_queue: WaitQueue,
+    /// 缓冲区中的数据或者空间发生变化、某一端被关闭时通知等待者
+    poll_queue: PollQueue,
 }
 
 struct PipeInodeData {
@@ -234,6 +241,9 @@ impl PipeInode {
                 read_wait: None,
                 write_wait: None,
             }),
+            read_queue: WaitQueue::new(),
+            write_queue: WaitQueue::new(),
+            poll_queue: PollQueue::new(),
         }
     }
 
@@ -259,24 +269,21 @@ impl VfsFile for PipeInode {
                 if !buf.is_write_wait() {
                     // if there is no process waiting for writing, we should return
                     break;
-                } else {
-                    // wait for writing
-                    drop(buf);
-                    do_suspend();
-                    warn!("pipe_read: suspend");
-                    // check signal
-                    let task = current_task().unwrap();
-                    // interrupt by signal
-                    let task_inner = task.access_inner();
-                    let receiver = task_inner.signal_receivers.lock();
-                    if receiver.have_signal() {
-                        error!("pipe_write: have signal");
-                        return Err(VfsError::EINTR);
-                    }
                 }
+                drop(buf);
+                // wait for writing, interrupt by signal
+                self.read_queue
+                    .wait_event_interruptible(|| {
+                        let buf = self.data.lock();
+                        buf.available_read() > 0 || !buf.is_write_wait()
+                    })
+                    .map_err(|_| VfsError::EINTR)?;
             } else {
                 let min = core::cmp::min(available, user_buf.len() - count);
                 count += buf.read(&mut user_buf[count..count + min]);
+                drop(buf);
+                self.write_queue.wake_all();
+                self.poll_queue.notify(PollEvents::EPOLLOUT);
                 break;
             }
         }
@@ -296,19 +303,20 @@ impl VfsFile for PipeInode {
                 }
                 // release lock
                 drop(buf);
-                // wait for reading
-                do_suspend();
-                let task = current_task().unwrap();
-                let task_inner = task.access_inner();
-                let receiver = task_inner.signal_receivers.lock();
-                if receiver.have_signal() {
-                    error!("pipe_write: have signal");
-                    return Err(VfsError::EINTR);
-                }
+                // wait for reading, interrupt by signal
+                self.write_queue
+                    .wait_event_interruptible(|| {
+                        let buf = self.data.lock();
+                        buf.available_write() > 0 || !buf.is_read_wait()
+                    })
+                    .map_err(|_| VfsError::EINTR)?;
             } else {
                 let min = core::cmp::min(available, user_buf.len() - count);
                 info!("pipe_write: min:{}, count:{}", min, count);
                 count += buf.write(&user_buf[count..count + min]);
+                drop(buf);
+                self.read_queue.wake_all();
+                self.poll_queue.notify(PollEvents::EPOLLIN);
                 break;
             }
         }
@@ -391,6 +399,15 @@ impl Drop for PipeFile {
                 .unwrap();
             root.remove(&name).unwrap();
             root_inode.remove_manually(&name).unwrap();
+            return;
         }
+        drop(data);
+        // 另一端阻塞的读者会读到文件结束，写者会返回
+        self.inode_copy.read_queue.wake_all();
+        self.inode_copy.write_queue.wake_all();
+        // 另一端的读者会看到挂起，写者会看到错误
+        self.inode_copy
+            .poll_queue
+            .notify(PollEvents::EPOLLHUP | PollEvents::EPOLLERR);
     }
 }
@@ -2,15 +2,21 @@
 //! 内核也可以因为内部事件而给进程发送信号，通知进程发生了某个事件。
 //!
 //! 有关 Alien 中信号的具体处理流程可见 [`signal_handler`]。
+//!
+//! 作业控制：停止信号 (`SIGSTOP`、`SIGTSTP`、`SIGTTIN`、`SIGTTOU`) 的默认动作会使整个进程停止执行，
+//! 直到进程收到 `SIGCONT` 或者 `SIGKILL`，进程停止和恢复时会向父进程发送 `SIGCHLD`。
 use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
 use core::mem::size_of;
 
 use constants::{signal::*, time::TimeSpec, AlienResult, LinuxErrno};
-use ksync::Mutex;
+use ksync::{wait::WaitQueue, Mutex};
 use syscall_table::syscall_func;
 use timer::{read_timer, ToClock};
 
-use crate::task::{current_task, do_exit, do_suspend};
+use crate::task::{
+    current_task, do_suspend, exit_group_by_signal, get_all_processes, get_process_group,
+    get_task_from_tid, get_thread_group, JobStatus, Task,
+};
 
 /// 记录每个线程的信号量，从 tid 获取信号相关信息
 static TID2SIGNALS: Mutex<BTreeMap<usize, Arc<Mutex<SignalReceivers>>>> =
@@ -31,6 +37,12 @@ pub fn get_signals_from_tid(tid: usize) -> Option<Arc<Mutex<SignalReceivers>>> {
     TID2SIGNALS.lock().get(&tid).map(|s| s.clone())
 }
 
+/// 处于停止状态的线程在此等待，发送 `SIGCONT` 或者 `SIGKILL` 时唤醒
+static STOPPED_WAIT: WaitQueue = WaitQueue::new();
+
+/// 在 `sigtimedwait` 中等待信号的线程，发送任何信号时唤醒
+static SIGWAIT_QUEUE: WaitQueue = WaitQueue::new();
+
 /// 发送一个信号给进程 tid
 pub fn send_signal(tid: usize, signum: usize) {
     if let Some(signals) = get_signals_from_tid(tid) {
@@ -41,6 +53,149 @@ pub fn send_signal(tid: usize, signum: usize) {
             tid
         );
         signals.lock().try_add_bit(signum);
+        SIGWAIT_QUEUE.wake_all();
+        if signum == SignalNumber::SIGCONT as usize || signum == SignalNumber::SIGKILL as usize {
+            STOPPED_WAIT.wake_all();
+        }
+        // 在 vfork 中等待子进程的线程只会被 SIGKILL 打断
+        if signum == SignalNumber::SIGKILL as usize {
+            if let Some(task) = get_task_from_tid(tid) {
+                task.child_wait.wake_all();
+            }
+        }
+    }
+}
+
+/// 作业控制中使进程停止的信号
+const STOP_SIGNALS: [SignalNumber; 4] = [
+    SignalNumber::SIGSTOP,
+    SignalNumber::SIGTSTP,
+    SignalNumber::SIGTTIN,
+    SignalNumber::SIGTTOU,
+];
+
+fn is_stop_signal(sig: SignalNumber) -> bool {
+    STOP_SIGNALS.contains(&sig)
+}
+
+/// 向进程 `pid` 发送一个信号，信号由进程中 tid 与 pid 相同的线程接收。
+///
+/// 发送前会处理作业控制信号之间的相互作用：
+/// + 发送 `SIGCONT` 时，丢弃进程中所有线程尚未处理的停止信号，并使已经停止的进程恢复执行；
+/// + 发送停止信号时，丢弃进程中所有线程尚未处理的 `SIGCONT`。
+pub fn send_process_signal(pid: usize, signum: usize) {
+    match SignalNumber::try_from(signum as u8) {
+        Ok(SignalNumber::SIGCONT) => {
+            let mut resumed = false;
+            for thread in get_thread_group(pid) {
+                let mut inner = thread.access_inner();
+                {
+                    let mut receiver = inner.signal_receivers.lock();
+                    for sig in STOP_SIGNALS {
+                        receiver.check_signal(sig as usize);
+                    }
+                }
+                resumed |= core::mem::replace(&mut inner.stopped, false);
+            }
+            if resumed {
+                set_job_status(pid, JobStatus::Continued);
+                notify_parent(pid);
+            }
+        }
+        Ok(sig) if is_stop_signal(sig) => {
+            for thread in get_thread_group(pid) {
+                thread
+                    .access_inner()
+                    .signal_receivers
+                    .lock()
+                    .check_signal(SignalNumber::SIGCONT as usize);
+            }
+        }
+        _ => {}
+    }
+    send_signal(pid, signum);
+}
+
+/// 进程 `pid` 停止或者恢复执行时，向其父进程发送 `SIGCHLD`
+fn notify_parent(pid: usize) {
+    let parent = get_task_from_tid(pid)
+        .and_then(|task| task.access_inner().parent.clone())
+        .and_then(|parent| parent.upgrade());
+    if let Some(parent) = parent {
+        send_signal(parent.get_tid() as usize, SignalNumber::SIGCHLD as usize);
+    }
+}
+
+/// 记录进程 `pid` 停止或恢复执行的事件，供父进程通过 `wait4`/`waitid` 获取
+fn set_job_status(pid: usize, status: JobStatus) {
+    if let Some(leader) = get_task_from_tid(pid) {
+        let parent = {
+            let mut inner = leader.access_inner();
+            inner.job_status = Some(status);
+            inner.parent.clone()
+        };
+        if let Some(parent) = parent.and_then(|parent| parent.upgrade()) {
+            parent.child_changed();
+        }
+    }
+}
+
+/// 如果信号 `sig` 对线程 `task` 而言会被忽略（设置为忽略或者没有设置处理函数且默认动作为忽略），则直接丢弃尚未处理的该信号。
+///
+/// 用于阻塞的系统调用判断是否被信号打断，例如等待子进程时收到的 `SIGCHLD` 通常不应该打断等待。
+pub fn discard_ignored_signal(task: &Task, sig: SignalNumber) {
+    let inner = task.access_inner();
+    let ignored = match inner.signal_handlers.lock().get_action_ref(sig as usize) {
+        Some(action) => action.is_ignore(),
+        None => matches!(SigActionDefault::of_signal(sig), SigActionDefault::Ignore),
+    };
+    if ignored {
+        inner.signal_receivers.lock().check_signal(sig as usize);
+    }
+}
+
+/// 信号 `sig` 是否被线程 `task` 屏蔽或者忽略，用于终端判断是否需要向后台进程组发送 `SIGTTIN`/`SIGTTOU`
+pub fn signal_blocked_or_ignored(task: &Task, sig: usize) -> bool {
+    let inner = task.access_inner();
+    let blocked = inner.signal_receivers.lock().mask.bits() & (1 << (sig - 1)) != 0;
+    let ignored = inner
+        .signal_handlers
+        .lock()
+        .get_action_ref(sig)
+        .map_or(false, |action| action.is_ignore());
+    blocked || ignored
+}
+
+/// 因信号 `sig` 使当前进程中的所有线程停止执行，并通知父进程
+fn job_stop(sig: SignalNumber) {
+    let task = current_task().unwrap();
+    let pid = task.get_pid() as usize;
+    warn!("process {} stopped by {:?}", pid, sig);
+    get_thread_group(pid)
+        .iter()
+        .for_each(|thread| thread.access_inner().stopped = true);
+    set_job_status(pid, JobStatus::Stopped(sig));
+    notify_parent(pid);
+    wait_while_stopped();
+}
+
+/// 如果当前线程处于停止状态，则睡眠直到被 `SIGCONT` 恢复；停止期间收到 `SIGKILL` 时整个进程直接退出
+///
+/// 停止期间收到的其它信号不会唤醒线程。
+fn wait_while_stopped() {
+    let task = current_task().unwrap();
+    let mut killed = false;
+    STOPPED_WAIT.wait_event(|| {
+        let inner = task.access_inner();
+        killed = inner.stopped
+            && inner
+                .signal_receivers
+                .lock()
+                .check_signal(SignalNumber::SIGKILL as usize);
+        !inner.stopped || killed
+    });
+    if killed {
+        exit_group_by_signal(SignalNumber::SIGKILL);
     }
 }
 
@@ -95,7 +250,8 @@ pub fn sigaction(sig: usize, action: usize, old_action: usize) -> isize {
 ///
 /// 当函数在规定的时间内成功接收到 `set` 中包含的某个信号时，将会返回该信号的序号；
 /// 当函数在规定的时间内未接收到 `set` 中包含的某个信号时，将返回 `EAGAIN` 表示超时；
-/// 如果 `time` 所指明的时间为 0，那么函数将直接返回-1。
+/// 如果 `time` 所指明的时间为 0，那么函数只检查一次是否有 `set` 中的信号；`time` 为空时将一直等待。
+/// 等待期间收到其它信号时返回 `EINTR`，使得进程可以被 `SIGKILL` 杀死或者处理其它信号。
 ///
 /// Reference: [sigtimedwait](https://linux.die.net/man/2/sigtimedwait)
 #[syscall_func(137)]
@@ -105,60 +261,42 @@ pub fn sigtimewait(set: usize, info: usize, time: usize) -> isize {
         set, info, time
     );
 
-    let mut flag = false;
-    let mut target_time = 0;
-
     let task = current_task().unwrap().clone();
-    let mut time_spec = TimeSpec::new(0, 0);
-    task.access_inner()
-        .copy_from_user(time as *const TimeSpec, &mut time_spec);
-    loop {
-        let mut task_inner = task.access_inner();
-        let mut signal_receivers = task_inner.signal_receivers.lock();
-        for i in 1..64 {
-            if set & (1 << i) != 0 {
-                if signal_receivers.check_signal(i) {
-                    if info != 0 {
-                        let mut tmp_info = SigInfo::default();
-                        tmp_info.si_signo = i as i32;
-                        tmp_info.si_code = 0;
-                        drop(signal_receivers);
-                        task_inner.copy_to_user(&tmp_info, info as *mut SigInfo);
-                    }
-                    return i as isize;
-                }
-            }
-        }
-
-        // wait time
-        if time_spec.tv_sec == 0 && time_spec.tv_nsec == 0 {
-            return -1;
-        }
-        drop(signal_receivers);
-        drop(task_inner);
-        if !flag {
-            warn!("sigtimewait: sleep for {:?}", time_spec);
-            let t_time = read_timer() + time_spec.to_clock();
-            target_time = t_time;
-            flag = true;
-        }
-        let now = read_timer();
-        if now >= target_time {
-            warn!("sigtimewait: timeout");
-            break;
-        }
-        do_suspend();
-        let task = current_task().unwrap();
-
-        // interrupt by signal
+    let deadline = if time == 0 {
+        None
+    } else {
+        let mut time_spec = TimeSpec::new(0, 0);
+        task.access_inner()
+            .copy_from_user(time as *const TimeSpec, &mut time_spec);
+        Some(read_timer() + time_spec.to_clock())
+    };
+    // 取出一个 `set` 中的未决信号
+    let take_signal = || {
         let task_inner = task.access_inner();
-        let receiver = task_inner.signal_receivers.lock();
-        if receiver.have_signal() {
-            let sig = receiver.have_signal_with_number().unwrap();
-            return sig as isize;
-        }
+        let mut signal_receivers = task_inner.signal_receivers.lock();
+        (1..64).find(|&i| set & (1 << i) != 0 && signal_receivers.check_signal(i))
+    };
+    let mut sig = None;
+    let cond = || {
+        sig = take_signal();
+        sig.is_some()
+    };
+    let res = SIGWAIT_QUEUE.wait_event_interruptible_timeout(cond, deadline);
+    let Some(sig) = sig else {
+        warn!("sigtimewait: {:?}", res);
+        return match res {
+            Err(LinuxErrno::EINTR) => LinuxErrno::EINTR.into(),
+            _ => LinuxErrno::EAGAIN.into(),
+        };
+    };
+    if info != 0 {
+        let mut tmp_info = SigInfo::default();
+        tmp_info.si_signo = sig as i32;
+        tmp_info.si_code = 0;
+        task.access_inner()
+            .copy_to_user(&tmp_info, info as *mut SigInfo);
     }
-    LinuxErrno::EAGAIN.into()
+    sig as isize
 }
 
 /// 一个系统调用，用于获取和设置信号的屏蔽位。通过 `sigprocmask`，进程可以方便的屏蔽某些信号。
@@ -202,39 +340,44 @@ pub fn sigprocmask(how: usize, set: usize, oldset: usize, _sig_set_size: usize)
     0
 }
 
-/// 一个系统调用函数，向 `pid` 指定的进程发送信号。
-/// 如果进程中有多个线程，则会发送给任意一个未阻塞的线程。
+/// 一个系统调用函数，向 `pid` 指定的进程发送信号。信号由进程中 tid 与 pid 相同的线程接收。
 ///
 /// pid 有如下情况
 /// 1. pid > 0，则发送给指定进程
-/// 2. pid = 0，则发送给所有同组进程
-/// 3. pid = -1，则发送给除了初始进程(pid=1)外的所有当前进程有权限的进程
-/// 4. pid < -2，则发送给组内 pid 为参数相反数的进程
+/// 2. pid = 0，则发送给当前进程所在进程组中的所有进程
+/// 3. pid = -1，则发送给除了初始进程(pid=1)和当前进程外的所有进程
+/// 4. pid < -1，则发送给进程组号为 -pid 的进程组中的所有进程
 ///
-/// 目前 2/3/4 未实现。对于 1，仿照 zCore 的设置，认为**当前进程自己或其直接子进程** 是"有权限"或者"同组"的进程。
-///  
-/// 目前如果函数成功执行后会返回0；否则返回错误类型。
+/// 在实现多用户权限前，认为当前进程有权限向所有进程发送信号。`sig` 为 0 时不发送信号，仅检查目标进程是否存在。
+///
+/// 函数成功执行后返回0；`sig` 不合法时返回 `EINVAL`，找不到目标进程时返回 `ESRCH`。
 ///
 /// Reference: [kill](https://man7.org/linux/man-pages/man2/kill.2.html)
 #[syscall_func(129)]
-pub fn kill(pid: usize, sig: usize) -> isize {
-    warn!(
-        "kill pid {}, signal id {:?}",
-        pid,
-        SignalNumber::try_from(sig as u8).unwrap()
-    );
-    if pid > 0 {
-        //println!("kill pid {}, signal id {}", pid, signal_id);
-        if sig > 0 {
-            send_signal(pid, sig);
-        }
-        0
-    } else if pid == 0 {
-        LinuxErrno::ESRCH as isize
-    } else {
-        // 如果 signal_id == 0，则仅为了检查是否存在对应进程，此时应该返回参数错误。是的，用户库是会刻意触发这个错误的
-        LinuxErrno::EINVAL as isize
+pub fn kill(pid: isize, sig: usize) -> AlienResult<isize> {
+    if sig != 0 {
+        SignalNumber::try_from(sig as u8).map_err(|_| LinuxErrno::EINVAL)?;
+    }
+    warn!("kill pid {}, signal id {}", pid, sig);
+    let task = current_task().unwrap();
+    let targets = match pid {
+        0 => get_process_group(task.get_pgid()),
+        -1 => get_all_processes()
+            .into_iter()
+            .filter(|process| process.get_pid() != 1 && process.get_pid() != task.get_pid())
+            .collect(),
+        pid if pid < 0 => get_process_group(pid.unsigned_abs()),
+        pid => get_task_from_tid(pid as usize).into_iter().collect(),
+    };
+    if targets.is_empty() {
+        return Err(LinuxErrno::ESRCH);
+    }
+    if sig != 0 {
+        targets
+            .iter()
+            .for_each(|process| send_process_signal(process.get_pid() as usize, sig));
     }
+    Ok(0)
 }
 
 /// 一个系统调用函数，向 `tid` 指定的线程发送信号。在`Alien`中`tid`是task的唯一标识，故 `tid` 只会指向一个线程。
@@ -279,9 +422,12 @@ pub fn signal_return() -> isize {
 /// 先检查此种信号是否满足上面所有的前提，如果有一项以上不满足，直接continue;
 /// 否则需要根据该信号是否已经设置非默认的处理函数进行接下来的操作。
 ///
-/// + 对于一些固定采用采用默认信号处理方式的信号，或由于未设置其它信号处理函数的信号，仍然使用默认信号处理方式，Alien 中采用 [`SigActionDefault`] 对该信号进行判定：
-///     + 如果属于 `Terminate` 类型，将导致进程终止。
-///     + 如果属于 `Ignore` 类型，进程将直接忽略该信号。
+/// + 对于一些固定采用采用默认信号处理方式的信号，或由于未设置其它信号处理函数的信号，仍然使用默认信号处理方式：
+///     + 停止信号将使整个进程停止执行，`SIGCONT` 的恢复动作在发送时已经完成，这里直接忽略；
+///     + 其余信号采用 [`SigActionDefault`] 进行判定，如果属于 `Terminate` 类型，将导致整个进程终止；
+///       如果属于 `Ignore` 类型，进程将直接忽略该信号。
+///
+/// 如果当前线程处于停止状态，会先等待进程恢复执行。
 /// + 如果进程已经设置过信号处理函数，由于信号处理函数的位置位于用户虚拟内存空间，需要回到用户态下进行信号处理函数的执行，
 /// 但由于原来在用户态下我们还保存有一个 trap 上下文，因此我们需要记录这个 trap 上下文，同时将设计好的新的执行信号处理函数的上下文转移至原trap上下文的位置，
 /// 以便其执行用户态下的信号处理函数。
@@ -289,6 +435,7 @@ pub fn signal_return() -> isize {
 /// 待用户态下的信号处理函数执行完毕后进程将重新陷入内核态，调用 [`signal_return`] 重新装载回原 trap 上下文。
 /// 至此，一个信号被处理完毕。
 pub fn signal_handler() {
+    wait_while_stopped();
     let task = current_task().unwrap();
     let mut task_inner = task.access_inner();
     let receiver = task_inner.signal_receivers.clone();
@@ -299,13 +446,19 @@ pub fn signal_handler() {
         let sig = SignalNumber::try_from(signum as u8).unwrap();
         log::info!("task {:?} receive signal {:?}", task.tid, sig);
         match sig {
-            SignalNumber::SIGSEGV | SignalNumber::SIGBUS => {
+            SignalNumber::SIGSEGV | SignalNumber::SIGBUS | SignalNumber::SIGKILL => {
                 // we need exit the process
                 drop(task_inner);
                 drop(handler);
                 drop(receiver);
                 warn!("task {:?} exit by signal {:?}", task.tid, sig);
-                do_exit(-1, 0);
+                exit_group_by_signal(sig);
+            }
+            SignalNumber::SIGSTOP => {
+                drop(task_inner);
+                drop(handler);
+                drop(receiver);
+                job_stop(sig);
             }
             _ => {
                 if let Some(action) = handler.get_action_ref(signum) {
@@ -374,13 +527,24 @@ pub fn signal_handler() {
                 } else {
                     // find the default handler
                     // 否则，查找默认处理方式
+                    if is_stop_signal(sig) {
+                        drop(task_inner);
+                        drop(handler);
+                        drop(receiver);
+                        job_stop(sig);
+                        return;
+                    }
+                    if sig == SignalNumber::SIGCONT {
+                        // 恢复执行已经在发送信号时完成
+                        return;
+                    }
                     match SigActionDefault::of_signal(sig) {
                         SigActionDefault::Terminate => {
                             // 这里不需要 drop(task)，因为当前函数没有用到 task_inner，在 task.save_trap... 内部用过后已经 drop 了
                             drop(task_inner);
                             drop(handler);
                             drop(receiver);
-                            do_exit(-1, 0);
+                            exit_group_by_signal(sig);
                         }
                         SigActionDefault::Ignore => {
                             // 忽略信号时，要将已保存的上下文删除
@@ -53,9 +53,11 @@ fn main(hart_id: usize) {
         println!("{:#?}", machine_info);
         mem::init_memory_system(machine_info.memory.end, true);
         interrupt::init_plic(machine_info.plic.start);
+        krandom::init_random();
         shim::register_task_func(Box::new(DriverTaskImpl));
         devices::init_device();
         vfs::init_filesystem().expect("init filesystem failed");
+        fs::proc::init_procfs();
         trap::init_trap_subsystem();
         arch::allow_access_user_memory();
         task::init_task();
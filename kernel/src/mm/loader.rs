@@ -1,10 +1,9 @@
 use alloc::{
-    collections::BTreeMap,
     string::{String, ToString},
     vec,
     vec::Vec,
 };
-use core::{cmp::min, fmt::Debug};
+use core::{cmp::min, fmt::Debug, ops::Range};
 
 use config::*;
 use mem::{VmmPageAllocator, FRAME_REF_MANAGER};
@@ -18,7 +17,6 @@ use xmas_elf::program::{SegmentData, Type};
 
 use crate::{
     fs,
-    ipc::ShmInfo,
     mm::elf::{ELFError, ELFInfo, ELFReader},
     trap::TrapFrame,
 };
@@ -121,22 +119,15 @@ pub fn build_thread_address_space(
 
 pub fn build_cow_address_space(
     p_table: &mut Sv39PageTable<VmmPageAllocator>,
-    shm: BTreeMap<usize, ShmInfo>,
+    shared: Vec<Range<usize>>,
 ) -> Sv39PageTable<VmmPageAllocator> {
     let mut address_space = Sv39PageTable::<VmmPageAllocator>::try_new().unwrap();
     for (v_addr, target) in p_table.get_record().into_iter() {
         trace!("v_addr: {:?}, target: {}", v_addr, target);
         let (phy, flag, page_size) = p_table.query(v_addr).unwrap();
 
-        // shm should remap, we can't use cow for it
-        let is_in_segs = |addr: usize| -> bool {
-            for (_id, shminfo) in shm.iter() {
-                if addr >= shminfo.start_va && addr < shminfo.end_va {
-                    return true;
-                }
-            }
-            false
-        };
+        // shm and shared file mappings should remap, we can't use cow for them
+        let is_in_segs = |addr: usize| -> bool { shared.iter().any(|range| range.contains(&addr)) };
 
         if v_addr.as_usize() == TRAP_CONTEXT_BASE {
             // for Trap_context, we remap it
@@ -151,7 +142,7 @@ pub fn build_cow_address_space(
                 core::ptr::copy(src_ptr, dst_ptr, usize::from(page_size));
             }
         } else if is_in_segs(v_addr.as_usize()) {
-            // for shm, we now skip it
+            // for shm and shared file mappings, share the same physical page
             address_space.map(v_addr, phy, page_size, flag).unwrap();
         } else {
             // cow
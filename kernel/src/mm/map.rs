@@ -1,15 +1,16 @@
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{collections::BTreeMap, sync::Arc, vec::Vec};
 use core::ops::Range;
 
 use bitflags::bitflags;
 use config::{FRAME_SIZE, PROCESS_HEAP_MAX};
 use constants::{io::MMapFlags, AlienResult, LinuxErrno};
-use page_table::{
-    addr::{align_up_4k, VirtAddr},
-    pte::MappingFlags,
-};
+use page_table::{addr::align_up_4k, pte::MappingFlags};
 use syscall_table::syscall_func;
-use vfs::kfile::File;
+use vfs::{
+    kfile::{File, KernelFile},
+    page_cache::{page_cache, CachePage, PageCache},
+};
+use vfscore::utils::VfsNodeType;
 
 use crate::task::current_task;
 
@@ -28,8 +29,9 @@ impl Into<MappingFlags> for ProtFlags {
         if self.contains(ProtFlags::PROT_READ) {
             perm |= MappingFlags::R;
         }
+        // RISC-V 中不允许只写不读的页，与 Linux 一致，可写的页同时也是可读的
         if self.contains(ProtFlags::PROT_WRITE) {
-            perm |= MappingFlags::W;
+            perm |= MappingFlags::R | MappingFlags::W;
         }
         if self.contains(ProtFlags::PROT_EXEC) {
             perm |= MappingFlags::X;
@@ -39,6 +41,14 @@ impl Into<MappingFlags> for ProtFlags {
     }
 }
 
+bitflags! {
+    pub struct MSyncFlags: u32 {
+        const MS_ASYNC = 0x1;
+        const MS_INVALIDATE = 0x2;
+        const MS_SYNC = 0x4;
+    }
+}
+
 #[derive(Debug, Clone)]
 /// The Process should manage the mmap info
 pub struct MMapInfo {
@@ -63,6 +73,30 @@ pub struct MMapRegion {
     pub fd: Option<Arc<dyn File>>,
     /// The offset in the file to start from
     pub offset: usize,
+    /// 共享文件映射中已经被访问并映射的缓存页，以虚拟地址为键，映射期间由映射区持有，以免被回收
+    pub pages: BTreeMap<usize, Arc<CachePage>>,
+    /// 设备内存的共享映射，切分得到的区域以及 fork 得到的子进程共享同一个映射
+    pub device: Option<Arc<DeviceMapping>>,
+}
+
+/// 通过 [`File::mmap_phys`] 建立的设备内存映射，最后一个引用它的区域被释放时通知设备映射已经解除
+#[derive(Debug)]
+pub struct DeviceMapping {
+    file: Arc<dyn File>,
+    offset: usize,
+    len: usize,
+}
+
+impl DeviceMapping {
+    pub fn new(file: Arc<dyn File>, offset: usize, len: usize) -> Self {
+        Self { file, offset, len }
+    }
+}
+
+impl Drop for DeviceMapping {
+    fn drop(&mut self) {
+        self.file.munmap_phys(self.offset, self.len);
+    }
 }
 
 impl MMapInfo {
@@ -81,6 +115,10 @@ impl MMapInfo {
         addr..self.map_start
     }
 
+    pub fn regions(&self) -> &[MMapRegion] {
+        &self.regions
+    }
+
     pub fn add_region(&mut self, region: MMapRegion) {
         self.regions.push(region);
     }
@@ -103,6 +141,64 @@ impl MMapInfo {
         None
     }
 
+    /// 在 `start` 和 `end` 处切分跨越边界的区域，使得每个区域要么完全位于 `[start, end)` 之内，要么完全位于其外
+    fn split_at_boundaries(&mut self, start: usize, end: usize) {
+        let mut regions = Vec::with_capacity(self.regions.len() + 2);
+        for region in self.regions.drain(..) {
+            let mut rest = region;
+            for addr in [start, end] {
+                if rest.start < addr && addr < rest.start + rest.map_len {
+                    let (left, right) = rest.split(addr);
+                    regions.push(left);
+                    rest = right;
+                }
+            }
+            regions.push(rest);
+        }
+        self.regions = regions;
+    }
+
+    /// 删除 `[start, end)` 范围内的所有映射，部分位于范围内的区域会被切分，返回被删除的区域
+    pub fn remove_range(&mut self, start: usize, end: usize) -> Vec<MMapRegion> {
+        self.split_at_boundaries(start, end);
+        let (removed, kept) = self
+            .regions
+            .drain(..)
+            .partition(|region| region.start >= start && region.start + region.map_len <= end);
+        self.regions = kept;
+        removed
+    }
+
+    /// 修改 `[start, end)` 范围内所有映射的保护位，部分位于范围内的区域会被切分，修改后相邻的相同区域会被合并
+    ///
+    /// 范围内不属于任何区域的地址由调用者检查。
+    pub fn protect_range(&mut self, start: usize, end: usize, prot: ProtFlags) {
+        self.split_at_boundaries(start, end);
+        self.regions
+            .iter_mut()
+            .filter(|region| region.start >= start && region.start + region.map_len <= end)
+            .for_each(|region| region.set_prot(prot));
+        self.merge();
+    }
+
+    /// 合并地址相邻且属性相同的区域
+    fn merge(&mut self) {
+        self.regions.sort_by_key(|region| region.start);
+        let mut merged: Vec<MMapRegion> = Vec::with_capacity(self.regions.len());
+        for mut region in self.regions.drain(..) {
+            if let Some(last) = merged.last_mut() {
+                if last.can_merge(&region) {
+                    last.len = region.start + region.len - last.start;
+                    last.map_len += region.map_len;
+                    last.pages.append(&mut region.pages);
+                    continue;
+                }
+            }
+            merged.push(region);
+        }
+        self.regions = merged;
+    }
+
     pub fn remove_region(&mut self, addr: usize) {
         let mut index = 0;
         for region in self.regions.iter() {
@@ -133,6 +229,8 @@ impl MMapRegion {
             flags,
             fd,
             offset,
+            pages: BTreeMap::new(),
+            device: None,
         }
     }
     // [a-b]
@@ -146,9 +244,50 @@ impl MMapRegion {
         region2.len = self.start + self.len - addr;
         region2.map_len = align_up_4k(region2.len);
         region2.offset += region1.len;
+        region2.pages = region1.pages.split_off(&(self.start + region1.map_len));
         (region1, region2)
     }
 
+    /// 判断 `next` 是否紧跟在当前区域之后，并且二者的属性相同，可以合并为一个区域
+    fn can_merge(&self, next: &MMapRegion) -> bool {
+        if self.start + self.map_len != next.start
+            || self.len != self.map_len
+            || self.prot != next.prot
+            || self.flags != next.flags
+        {
+            return false;
+        }
+        match (&self.fd, &next.fd) {
+            (None, None) => true,
+            (Some(file), Some(next_file)) => {
+                Arc::ptr_eq(file, next_file) && self.offset + self.map_len == next.offset
+            }
+            _ => false,
+        }
+    }
+
+    /// 如果区域是普通文件的共享映射，返回该文件的页缓存
+    ///
+    /// 这类区域直接映射页缓存中的物理页，不同进程对同一文件的映射共享同一组物理页。
+    pub fn page_cache(&self) -> Option<Arc<PageCache>> {
+        if !self.flags.contains(MMapFlags::MAP_SHARED) {
+            return None;
+        }
+        let file = self.fd.as_ref()?.downcast_ref::<KernelFile>()?;
+        let inode = file.inode();
+        if inode.inode_type() != VfsNodeType::File {
+            return None;
+        }
+        Some(page_cache(&inode))
+    }
+
+    /// 区域是否为设备内存(例如帧缓存)的共享映射
+    ///
+    /// 这类区域直接映射设备的物理内存，不同进程的映射以及 fork 后的父子进程都访问同一段内存。
+    pub fn is_device_memory(&self) -> bool {
+        self.device.is_some()
+    }
+
     pub fn set_prot(&mut self, prot: ProtFlags) {
         self.prot = prot;
     }
@@ -157,8 +296,10 @@ impl MMapRegion {
     }
 }
 
-/// 一个函数调用，用于消除内存映射。
-/// 注意：传入的`start`必须是某段内存映射的首地址，`len`必须是该段内存映射的长度，否则将导致函数返回`EINVAL`。函数正常执行将返回0。
+/// 一个函数调用，用于消除`[start, start + len)`范围内的内存映射。
+///
+/// `start`必须与4K对齐，`len`会被调整为与4K对齐。范围可以只覆盖某段内存映射的一部分，也可以跨越多段内存映射，
+/// 部分位于范围内的内存映射会被切分。范围内没有内存映射时不会返回错误。函数正常执行将返回0。
 #[syscall_func(215)]
 pub fn do_munmap(start: usize, len: usize) -> isize {
     let task = current_task().unwrap();
@@ -208,9 +349,13 @@ pub fn do_mmap(
 }
 
 /// 一个系统调用，用于修改内存映射的保护位，从而修改对内存映射的访问权限。
-/// 函数会检查传入的`start`和`len`所指示的内存映射区是否已经处于被映射状态，如果是，则将对应内存映射区的保护位与`prot`做或运算。
 ///
-/// 如果函数正常执行，则返回0；如果`start`和`len`所指示的内存映射区未已经处于被映射状态，函数将返回-1。
+/// `start`必须与4K对齐，`len`会被调整为与4K对齐。`[start, start + len)`范围内所有内存映射的保护位都会被替换为`prot`，
+/// 部分位于范围内的内存映射会被切分，对应页表项的权限位也会随之更新。
+///
+/// 如果函数正常执行，则返回0；如果`start`未对齐，返回`EINVAL`；如果范围内存在未被映射的地址，返回`ENOMEM`。
+///
+/// Reference: [mprotect](https://man7.org/linux/man-pages/man2/mprotect.2.html)
 #[syscall_func(226)]
 pub fn map_protect(start: usize, len: usize, prot: u32) -> AlienResult<isize> {
     let process = current_task().unwrap();
@@ -224,24 +369,29 @@ pub fn map_protect(start: usize, len: usize, prot: u32) -> AlienResult<isize> {
     Ok(0)
 }
 
-/// (待实现)一个系统调用，用于同步文件在内存映射中的修改。一个文件通过[`do_mmap`]映射到内存中，可以在内存中对其进行快速的读写。
-/// 当我们对文件的映射进行修改后，如果不调用`msync`系统调用，那么在调用[`do_munmap`]之前内存中的相应内容都不会写回磁盘文件，有可能导致不一致性问题。
-/// 目前函数仅会检查所传入的`addr`是否已经被映射，如果没有被映射，则会返回`EFAULT`；否则直接返回0。
+/// 一个系统调用，用于同步文件在内存映射中的修改。一个文件通过[`do_mmap`]以`MAP_SHARED`方式映射到内存中后，
+/// 对映射的修改会直接反映在文件的页缓存中，但只有在调用`msync`、[`do_munmap`]或进程退出时才会写回磁盘文件。
 ///
-/// Reference: [madvise](https://man7.org/linux/man-pages/man2/madvise.2.html)
+/// `addr`必须与4K对齐，`flags`中`MS_ASYNC`与`MS_SYNC`不能同时设置。由于写回总是同步完成的，`MS_ASYNC`与`MS_SYNC`的行为相同，
+/// `MS_INVALIDATE`不需要额外处理(所有映射共享同一组物理页)。
+///
+/// 如果函数正常执行，则返回0；如果参数不合法，返回`EINVAL`；如果范围内存在未被映射的地址，返回`ENOMEM`。
+///
+/// Reference: [msync](https://man7.org/linux/man-pages/man2/msync.2.html)
 #[syscall_func(227)]
-pub fn msync(addr: usize, len: usize, flags: usize) -> isize {
+pub fn msync(addr: usize, len: usize, flags: usize) -> AlienResult<isize> {
     warn!(
         "msync: addr: {:#x}, len: {:#x}, flags: {:#x}",
         addr, len, flags
     );
-    let task = current_task().unwrap();
-    let address_space = &task.access_inner().address_space;
-    let res = address_space.lock().query(VirtAddr::from(addr));
-    if res.is_err() {
-        return LinuxErrno::EFAULT as isize;
+    let flags = MSyncFlags::from_bits(flags as u32).ok_or(LinuxErrno::EINVAL)?;
+    if addr % FRAME_SIZE != 0 || flags.contains(MSyncFlags::MS_ASYNC | MSyncFlags::MS_SYNC) {
+        return Err(LinuxErrno::EINVAL);
     }
-    0
+    let task = current_task().unwrap();
+    task.access_inner()
+        .sync_shared_mmap(addr, addr + align_up_4k(len))?;
+    Ok(0)
 }
 
 /// (待实现)一个系统调用，用于向内核提供使用内存的建议。目前直接返回0。
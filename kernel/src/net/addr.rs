@@ -1,20 +1,30 @@
 use alloc::{
+    format,
     string::{String, ToString},
+    sync::Arc,
     vec,
 };
 use core::net::{IpAddr, Ipv4Addr, SocketAddr};
 
-use constants::{net::Domain, AlienResult, LinuxErrno};
-use knet::addr::{RawIpV4Addr, SocketAddrExt};
+use constants::{io::InodeMode, net::Domain, AlienResult, LinuxErrno, AT_FDCWD};
+use knet::addr::{RawIpV4Addr, RawUnixAddr, SocketAddrExt, UnixAddr, UnixInode};
+use vfscore::dentry::VfsDentry;
 
-use crate::task::current_task;
+use crate::{
+    fs::{im2vim, user_path_at},
+    task::current_task,
+};
 
 /// 地址解析，将根据`family_user_addr`的[`Domain`]类型分类进行解析。
 ///
 /// 对于`AF_INET`将解析成SocketAddrExt::SocketAddr(SocketAddr)，
-/// 对于`AF_UNIX`将解析成ocketAddrExt::LocalPath(String)，详情可见[`SocketAddrExt`]。
+/// 对于`AF_UNIX`将解析成SocketAddrExt::Unix(UnixAddr)，其中的相对路径会被转换为基于当前工作目录的绝对路径，
+/// 详情可见[`SocketAddrExt`]。
 pub fn socket_addr_resolution(family_user_addr: usize, len: usize) -> AlienResult<SocketAddrExt> {
     let task = current_task().unwrap();
+    if len < core::mem::size_of::<u16>() {
+        return Err(LinuxErrno::EINVAL);
+    }
     let family = task
         .access_inner()
         .transfer_raw_ptr(family_user_addr as *const u16);
@@ -33,15 +43,105 @@ pub fn socket_addr_resolution(family_user_addr: usize, len: usize) -> AlienResul
             )))
         }
         Domain::AF_UNIX => {
-            // local path
+            if len > core::mem::size_of::<RawUnixAddr>() {
+                return Err(LinuxErrno::EINVAL);
+            }
             let mut buf = vec![0u8; len];
             task.access_inner().copy_from_user_buffer(
                 family_user_addr as *const u8,
                 buf.as_mut_ptr(),
                 len,
             );
-            let path = String::from_utf8_lossy(&buf[2..len - 2]).to_string();
-            Ok(SocketAddrExt::LocalPath(path))
+            let name = &buf[core::mem::size_of::<u16>()..];
+            let addr = match name.first() {
+                None => UnixAddr::Unnamed,
+                Some(0) => UnixAddr::Abstract(name[1..].to_vec()),
+                Some(_) => {
+                    let end = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+                    let path = String::from_utf8_lossy(&name[..end]).to_string();
+                    if path.starts_with('/') {
+                        UnixAddr::Path(path)
+                    } else {
+                        let cwd = task.access_inner().fs_info.cwd.path();
+                        UnixAddr::Path(format!("{}/{}", cwd.trim_end_matches('/'), path))
+                    }
+                }
+            };
+            Ok(SocketAddrExt::Unix(addr))
+        }
+    }
+}
+
+/// 将套接字地址写回到用户态的 `user_addr` 处，`addr_len` 处保存用户缓冲区的长度，返回时被设置为地址的实际长度。
+///
+/// 当用户缓冲区的长度小于地址的实际长度时，地址会被截断。
+pub fn socket_addr_to_user(
+    addr: &SocketAddrExt,
+    user_addr: usize,
+    addr_len: usize,
+) -> AlienResult<()> {
+    let task = current_task().unwrap();
+    let len_ref = task
+        .access_inner()
+        .transfer_raw_ptr_mut(addr_len as *mut u32);
+    let raw_ip;
+    let raw_unix;
+    let (raw, len) = match addr {
+        SocketAddrExt::SocketAddr(addr) => {
+            raw_ip = RawIpV4Addr::from(*addr);
+            let raw = &raw_ip as *const RawIpV4Addr as *const u8;
+            (raw, core::mem::size_of::<RawIpV4Addr>())
+        }
+        SocketAddrExt::Unix(addr) => {
+            let (raw, len) = addr.to_raw();
+            raw_unix = raw;
+            (&raw_unix as *const RawUnixAddr as *const u8, len)
+        }
+    };
+    let copy_len = len.min(*len_ref as usize);
+    if copy_len > 0 {
+        task.access_inner()
+            .copy_to_user_buffer(raw, user_addr as *mut u8, copy_len);
+    }
+    *len_ref = len as u32;
+    Ok(())
+}
+
+/// 套接字文件的设备号和 inode 号，作为路径地址在地址表中的键
+fn unix_socket_inode(dentry: Arc<dyn VfsDentry>) -> AlienResult<UnixInode> {
+    let attr = dentry.inode()?.get_attr()?;
+    Ok(UnixInode {
+        dev: attr.st_dev as u64,
+        ino: attr.st_ino as u64,
+    })
+}
+
+/// 在文件系统中为绑定到 `path` 的 Unix 套接字创建套接字文件，如果文件已存在则返回 `EADDRINUSE`。
+pub fn unix_socket_create(path: &str) -> AlienResult<UnixInode> {
+    let path = user_path_at(AT_FDCWD, path)?;
+    if path.open(None).is_ok() {
+        return Err(LinuxErrno::EADDRINUSE);
+    }
+    let mode = InodeMode::SOCKET | InodeMode::from_bits_truncate(0o777);
+    unix_socket_inode(path.open(Some(im2vim(mode)))?)
+}
+
+/// 删除 [`unix_socket_create`] 创建的套接字文件，用于绑定失败时撤销创建
+pub fn unix_socket_remove(path: &str) {
+    if let Ok(path) = user_path_at(AT_FDCWD, path) {
+        let _ = path.unlink();
+    }
+}
+
+/// 查找路径地址 `addr` 处的套接字文件，用于连接或者发送到路径地址之前，其它地址返回 `None`。
+///
+/// 并非所有文件系统都支持套接字文件，这里不检查文件的类型，是否有套接字绑定到该文件由地址表决定。
+pub fn unix_socket_lookup(addr: &SocketAddrExt) -> AlienResult<Option<UnixInode>> {
+    match addr {
+        SocketAddrExt::Unix(UnixAddr::Path(path)) => {
+            let dentry = user_path_at(AT_FDCWD, path)?.open(None)?;
+            unix_socket_inode(dentry).map(Some)
         }
+        _ => Ok(None),
     }
 }
@@ -3,24 +3,49 @@
 //! [`addr`] 子模块指明了在 Alien 内核中使用的 socket 套接字地址结构。
 //! [`port`] 子模块现为将网络异常类型 [`NetError`] 转为 系统异常类型 [`LinuxErrno`]的模块。
 //! [`socket`] 子模块指明了Alien 内核中使用的套接字。
-//! [`unix`] 子模块指明了有关 Unix 协议族下的套接字结构。(目前有关的功能有待支持)
+//! [`unix`] 子模块指明了有关 Unix 协议族下的套接字结构。
 //!
 use alloc::{sync::Arc, vec, vec::Vec};
 
-use constants::{io::OpenFlags, net::*, AlienResult, LinuxErrno};
+use constants::{
+    io::{IoVec, OpenFlags},
+    net::*,
+    AlienResult, LinuxErrno,
+};
 use knet::{
-    addr::RawIpV4Addr,
+    addr::{SocketAddrExt, UnixAddr},
+    option::MAX_OPTION_LEN,
     socket::{Socket, SocketData, SocketFile, SocketFileExt},
 };
+use platform::config::CLOCK_FREQ;
+use timer::read_timer;
 use vfs::kfile::File;
 
 use crate::{
-    net::addr::socket_addr_resolution,
-    task::{current_task, do_suspend},
+    net::addr::{
+        socket_addr_resolution, socket_addr_to_user, unix_socket_create, unix_socket_lookup,
+        unix_socket_remove,
+    },
+    task::current_task,
+    time::sleep_until,
 };
 
 pub mod addr;
 
+/// 网络协议栈每秒被轮询的次数
+const NET_POLL_HZ: usize = 100;
+
+/// 网络轮询内核线程，没有任务读写套接字时也定期推进协议栈，并通知就绪状态发生变化的 Tcp/Udp 套接字
+///
+/// 每次轮询之后唤醒在协议栈内部等待的任务。
+pub fn net_poll_kthread() {
+    loop {
+        let _ = sleep_until(read_timer() + CLOCK_FREQ / NET_POLL_HZ);
+        knet::socket::poll_net();
+        drivers::net::net_wakeup();
+    }
+}
+
 /// 一个系统调用，用于创建一个未绑定的socket套接字。
 ///
 /// + `domain`: 指明套接字被创建的协议簇(包括文件路径协议簇和网络地址协议簇，具体可见[`Domain`]);
@@ -62,7 +87,11 @@ pub fn bind(socketfd: usize, sockaddr: usize, len: usize) -> AlienResult<isize>
     let socket_fd = common_socket_syscall(socketfd)?;
     let socket_addr = socket_addr_resolution(sockaddr, len)?;
     let socket = socket_fd.get_socketdata()?;
-    match socket.bind(socket_addr.clone()) {
+    let inode = match &socket_addr {
+        SocketAddrExt::Unix(UnixAddr::Path(path)) => Some(unix_socket_create(path)?),
+        _ => None,
+    };
+    match socket.bind(socket_addr.clone(), inode) {
         Ok(()) => {
             let local_addr = socket.local_addr();
             info!(
@@ -71,7 +100,13 @@ pub fn bind(socketfd: usize, sockaddr: usize, len: usize) -> AlienResult<isize>
             );
             Ok(0)
         }
-        Err(e) => Err(e.into()),
+        Err(e) => {
+            // 绑定失败时删除刚刚创建的套接字文件，否则之后绑定到该路径会返回 EADDRINUSE
+            if let SocketAddrExt::Unix(UnixAddr::Path(path)) = &socket_addr {
+                unix_socket_remove(path);
+            }
+            Err(e.into())
+        }
     }
 }
 
@@ -114,13 +149,7 @@ pub fn accept(socketfd: usize, socket_addr: usize, addr_len: usize) -> AlienResu
                 let socket = file.get_socketdata()?;
                 let peer_addr = socket.peer_addr().unwrap();
                 info!("accept peer addr: {:?}", peer_addr);
-                let raw_ip_addr = RawIpV4Addr::from(peer_addr);
-                let addr_len_ref = task
-                    .access_inner()
-                    .transfer_raw_ptr_mut(addr_len as *mut u32);
-                *addr_len_ref = core::mem::size_of::<RawIpV4Addr>() as u32;
-                task.access_inner()
-                    .copy_to_user(&raw_ip_addr, socket_addr as *mut RawIpV4Addr);
+                socket_addr_to_user(&peer_addr, socket_addr, addr_len)?;
             }
             let fd = task.add_file(file).map_err(|_| LinuxErrno::EMFILE)?;
             Ok(fd as isize)
@@ -138,15 +167,16 @@ pub fn accept(socketfd: usize, socket_addr: usize, addr_len: usize) -> AlienResu
 /// 执行成功则返回0，否则返回错误信息。
 ///
 /// Note: For netperf_test, the server may be run after client, so we nedd allow
-/// client retry once
+/// client retry once, after sleeping until the next poll of the network stack
 #[syscall_func(203)]
 pub fn connect(socketfd: usize, socket_addr: usize, len: usize) -> AlienResult<isize> {
     let socket_addr = socket_addr_resolution(socket_addr, len)?;
+    let inode = unix_socket_lookup(&socket_addr)?;
     let socket_fd = common_socket_syscall(socketfd)?;
     let socket = socket_fd.get_socketdata()?;
     let mut retry = 1;
     while retry >= 0 {
-        match socket.connect(socket_addr.clone()) {
+        match socket.connect(socket_addr.clone(), inode) {
             Ok(_) => {
                 let local_addr = socket.local_addr();
                 info!(
@@ -163,7 +193,7 @@ pub fn connect(socketfd: usize, socket_addr: usize, len: usize) -> AlienResult<i
                     return Err(LinuxErrno::EINPROGRESS.into());
                 }
                 retry -= 1;
-                do_suspend();
+                sleep_until(read_timer() + CLOCK_FREQ / NET_POLL_HZ)?;
             }
         }
     }
@@ -183,12 +213,7 @@ pub fn getsockname(socketfd: usize, socket_addr: usize, len: usize) -> AlienResu
     let socket = socket_fd.get_socketdata()?;
     let local_addr = socket.local_addr().ok_or(LinuxErrno::EINVAL)?;
     info!("getsockname: {:?}", local_addr);
-    let raw_ip_addr = RawIpV4Addr::from(local_addr);
-    let task = current_task().unwrap();
-    task.access_inner()
-        .copy_to_user(&raw_ip_addr, socket_addr as *mut RawIpV4Addr);
-    let len_ref = task.access_inner().transfer_raw_ptr_mut(len as *mut u32);
-    *len_ref = core::mem::size_of::<RawIpV4Addr>() as u32;
+    socket_addr_to_user(&local_addr, socket_addr, len)?;
     Ok(0)
 }
 
@@ -203,14 +228,9 @@ pub fn getsockname(socketfd: usize, socket_addr: usize, len: usize) -> AlienResu
 pub fn get_peer_name(socketfd: usize, sockaddr: usize, len: usize) -> AlienResult<isize> {
     let socket_fd = common_socket_syscall(socketfd)?;
     let socket = socket_fd.get_socketdata()?;
-    let socket_addr = socket.peer_addr().ok_or(LinuxErrno::EINVAL)?;
+    let socket_addr = socket.peer_addr().ok_or(LinuxErrno::ENOTCONN)?;
     info!("get_peer_name: {:?}", socket_addr);
-    let raw_ip_addr = RawIpV4Addr::from(socket_addr);
-    let task = current_task().unwrap();
-    task.access_inner()
-        .copy_to_user(&raw_ip_addr, sockaddr as *mut RawIpV4Addr);
-    let len_ref = task.access_inner().transfer_raw_ptr_mut(len as *mut u32);
-    *len_ref = core::mem::size_of::<RawIpV4Addr>() as u32;
+    socket_addr_to_user(&socket_addr, sockaddr, len)?;
     Ok(0)
 }
 
@@ -253,11 +273,12 @@ pub fn sendto(
         }
         _ => {}
     }
-    let socket_addr = if dest_addr != 0 {
+    let (socket_addr, inode) = if dest_addr != 0 {
         let res = socket_addr_resolution(dest_addr, dest_len)?;
-        Some(res)
+        let inode = unix_socket_lookup(&res)?;
+        (Some(res), inode)
     } else {
-        None
+        (None, None)
     };
     info!(
         "sendto: {:?}, local_addr: {:?}, message len: {}",
@@ -265,8 +286,7 @@ pub fn sendto(
         socket.local_addr(),
         message.len()
     );
-    let send = socket.send_to(message.as_slice(), flags, socket_addr)?;
-    do_suspend();
+    let send = socket.send_to(message.as_slice(), flags, socket_addr, inode)?;
     Ok(send as isize)
 }
 
@@ -303,24 +323,18 @@ pub fn recvfrom(
     task.access_inner()
         .copy_to_user_buffer(tmp_buffer.as_ptr(), buffer, recv_info.0);
     if src_addr != 0 {
-        let raw_ip_addr = RawIpV4Addr::from(recv_info.1);
-        task.access_inner()
-            .copy_to_user(&raw_ip_addr, src_addr as *mut RawIpV4Addr);
-        let addr_len_ref = task
-            .access_inner()
-            .transfer_raw_ptr_mut(addr_len as *mut u32);
-        *addr_len_ref = core::mem::size_of::<RawIpV4Addr>() as u32;
+        socket_addr_to_user(&recv_info.1, src_addr, addr_len)?;
     }
     Ok(recv_info.0 as isize)
 }
 
-/// (待完成)一个系统调用函数，用于设置套接字的选项。
+/// 一个系统调用函数，用于设置套接字的选项。
 ///
 /// + `socketfd`: 指明要操作socket的文件描述符fd;
 /// + `level`: 定义选项的级别，包括`Ip`，`Socket`，`TCP`等，详情可见[`SocketLevel`];
-/// + `opt_name`: 在对应level下，为其设置值的套接字选项，详情可见[`SocketOption`];
-/// + `_opt_value`: 存储选项值位置的指针;
-/// + `_opt_len`: 选项值长度;
+/// + `opt_name`: 在对应level下，为其设置值的套接字选项，支持的选项可见 [`knet::option`];
+/// + `opt_value`: 存储选项值位置的指针;
+/// + `opt_len`: 选项值长度，超过 [`MAX_OPTION_LEN`] 时返回 `EINVAL`;
 ///
 /// 如果函数执行成功，则返回0；否则返回错误信息。
 #[syscall_func(208)]
@@ -328,23 +342,24 @@ pub fn setsockopt(
     socketfd: usize,
     level: usize,
     opt_name: usize,
-    _opt_value: usize,
-    _opt_len: u32,
+    opt_value: usize,
+    opt_len: u32,
 ) -> AlienResult<isize> {
     let socket_fd = common_socket_syscall(socketfd)?;
-    let _socket = socket_fd.get_socketdata()?;
-    let level = SocketLevel::try_from(level).map_err(|_| LinuxErrno::EINVAL)?;
-    match level {
-        SocketLevel::Ip => {}
-        SocketLevel::Socket => {
-            let opt_name = SocketOption::try_from(opt_name).map_err(|_| LinuxErrno::EINVAL)?;
-            info!("[setsockopt] level: {:?}, opt_name: {:?}", level, opt_name);
-        }
-        SocketLevel::Tcp => {
-            let opt_name = TcpSocketOption::try_from(opt_name).map_err(|_| LinuxErrno::EINVAL)?;
-            info!("[setsockopt] level: {:?}, opt_name: {:?}", level, opt_name);
-        }
+    let socket = socket_fd.get_socketdata()?;
+    let level = SocketLevel::try_from(level).map_err(|_| LinuxErrno::ENOPROTOOPT)?;
+    if opt_len as usize > MAX_OPTION_LEN {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let mut value = vec![0u8; opt_len as usize];
+    if opt_len > 0 {
+        current_task()
+            .unwrap()
+            .access_inner()
+            .copy_from_user_buffer(opt_value as *const u8, value.as_mut_ptr(), value.len());
     }
+    info!("[setsockopt] level: {:?}, opt_name: {:?}", level, opt_name);
+    socket.set_option(level, opt_name, &value)?;
     Ok(0)
 }
 
@@ -352,9 +367,9 @@ pub fn setsockopt(
 ///
 /// + `socketfd`: 指明要操作socket的文件描述符fd;
 /// + `level`: 定义选项的级别，包括`Ip`，`Socket`，`TCP`等，详情可见[`SocketLevel`];
-/// + `opt_name`: 在对应level下，要为其检索值的套接字选项，详情可见[`SocketOption`];
+/// + `opt_name`: 在对应level下，要为其检索值的套接字选项，支持的选项可见 [`knet::option`];
 /// + `opt_value`: 一个指向将要保存请求选项值的缓冲区的指针;
-/// + `_opt_len`: 指向保存选项值长度的指针;
+/// + `opt_len`: 指向保存选项值长度的指针，调用时为缓冲区的长度，返回时为选项值的实际长度;
 ///
 /// 如果函数执行成功，则返回0；否则返回错误信息。
 #[syscall_func(209)]
@@ -366,59 +381,18 @@ pub fn getsockopt(
     opt_len: usize,
 ) -> AlienResult<isize> {
     let socket_fd = common_socket_syscall(socketfd)?;
-    let _socket = socket_fd.get_socketdata()?;
-    let level = SocketLevel::try_from(level).map_err(|_| LinuxErrno::EINVAL)?;
-    match level {
-        SocketLevel::Ip => {}
-        SocketLevel::Socket => {
-            let opt_name = SocketOption::try_from(opt_name).map_err(|_| LinuxErrno::EINVAL)?;
-            info!("[getsockopt] level: {:?}, opt_name: {:?}", level, opt_name);
-            match opt_name {
-                SocketOption::SO_RCVBUF => {
-                    let opt_value_ref = current_task()
-                        .unwrap()
-                        .transfer_raw_ptr(opt_value as *mut u32);
-                    *opt_value_ref = netcore::common::SOCKET_RECV_BUFFER_SIZE as u32;
-                }
-                SocketOption::SO_SNDBUF => {
-                    let opt_value_ref = current_task()
-                        .unwrap()
-                        .transfer_raw_ptr(opt_value as *mut u32);
-                    *opt_value_ref = netcore::common::SOCKET_SEND_BUFFER_SIZE as u32;
-                }
-                SocketOption::SO_ERROR => {
-                    let opt_value_ref = current_task()
-                        .unwrap()
-                        .transfer_raw_ptr(opt_value as *mut u32);
-                    *opt_value_ref = 0;
-                }
-                _ => {}
-            }
-            let opt_len_ref = current_task()
-                .unwrap()
-                .transfer_raw_ptr(opt_len as *mut u32);
-            *opt_len_ref = core::mem::size_of::<u32>() as u32;
-        }
-        SocketLevel::Tcp => {
-            let opt_name = TcpSocketOption::try_from(opt_name).map_err(|_| LinuxErrno::EINVAL)?;
-            info!("[getsockopt] level: {:?}, opt_name: {:?}", level, opt_name);
-            match opt_name {
-                TcpSocketOption::TCP_MAXSEG => {
-                    let opt_value_ref = current_task()
-                        .unwrap()
-                        .transfer_raw_ptr(opt_value as *mut u32);
-                    *opt_value_ref = netcore::common::MAX_SEGMENT_SIZE as u32;
-                }
-                TcpSocketOption::TCP_NODELAY => {
-                    let opt_value_ref = current_task()
-                        .unwrap()
-                        .transfer_raw_ptr(opt_value as *mut u32);
-                    *opt_value_ref = 0;
-                }
-                _ => {}
-            }
-        }
+    let socket = socket_fd.get_socketdata()?;
+    let level = SocketLevel::try_from(level).map_err(|_| LinuxErrno::ENOPROTOOPT)?;
+    info!("[getsockopt] level: {:?}, opt_name: {:?}", level, opt_name);
+    let value = socket.get_option(level, opt_name)?;
+    let task = current_task().unwrap();
+    let opt_len_ref = task.transfer_raw_ptr(opt_len as *mut u32);
+    let len = value.len().min(*opt_len_ref as usize);
+    if len > 0 {
+        task.access_inner()
+            .copy_to_user_buffer(value.as_ptr(), opt_value as *mut u8, len);
     }
+    *opt_len_ref = len as u32;
     Ok(0)
 }
 
@@ -441,7 +415,201 @@ pub fn shutdown(socketfd: usize, how: usize) -> AlienResult<()> {
     socket.shutdown(flag)
 }
 
-/// (待实现)一个系统调用，创建一对未绑定的socket套接字，该对套接字可以用于全双工通信，或者用于父子进程之间的通信。
+/// 对应 `linux` 中 `socket.h` 的 `msghdr` 结构，用于 [`sendmsg`] 和 [`recvmsg`]。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct MsgHdr {
+    /// 消息的目的地址或源地址
+    name: usize,
+    name_len: u32,
+    /// 缓冲区向量的首地址
+    iov: usize,
+    iov_len: usize,
+    /// 辅助数据的首地址
+    control: usize,
+    control_len: usize,
+    flags: u32,
+}
+
+/// 对应 `linux` 中 `socket.h` 的 `cmsghdr` 结构，是辅助数据中每一项的头部。
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+struct CmsgHdr {
+    /// 包括头部在内的长度
+    len: usize,
+    level: i32,
+    ty: i32,
+}
+
+/// 通过 Unix 套接字传递文件描述符的辅助数据类型
+const SCM_RIGHTS: i32 = 1;
+/// 辅助数据的空间不足，部分辅助数据被丢弃
+const MSG_CTRUNC: u32 = 0x8;
+
+/// 辅助数据中每一项按照 `usize` 对齐
+fn cmsg_align(len: usize) -> usize {
+    (len + core::mem::size_of::<usize>() - 1) & !(core::mem::size_of::<usize>() - 1)
+}
+
+/// 一个系统调用，通过套接字发送消息。消息的内容由一组缓冲区给出，并且可以携带辅助数据。
+///
+/// + `socketfd`: 指明要操作socket的文件描述符fd;
+/// + `msg`: 指明消息头部([`MsgHdr`])的地址，其中包括目的地址、缓冲区向量和辅助数据;
+/// + `flags`: 指明发送操作的类型。
+///
+/// 辅助数据目前仅支持 Unix 套接字的 `SCM_RIGHTS`，用于向另一个进程传递打开的文件。
+///
+/// 如果发送成功，返回发送的字节数；否则返回错误信息。
+#[syscall_func(211)]
+pub fn sendmsg(socketfd: usize, msg: usize, flags: usize) -> AlienResult<isize> {
+    let socket_fd = common_socket_syscall(socketfd)?;
+    let task = current_task().unwrap();
+    let mut hdr = MsgHdr::default();
+    task.access_inner()
+        .copy_from_user(msg as *const MsgHdr, &mut hdr);
+    let (socket_addr, inode) = if hdr.name != 0 && hdr.name_len != 0 {
+        let res = socket_addr_resolution(hdr.name, hdr.name_len as usize)?;
+        let inode = unix_socket_lookup(&res)?;
+        (Some(res), inode)
+    } else {
+        (None, None)
+    };
+    let mut message = Vec::new();
+    for i in 0..hdr.iov_len {
+        let mut iov = IoVec::empty();
+        let ptr = unsafe { (hdr.iov as *mut IoVec).add(i) };
+        task.access_inner().copy_from_user(ptr, &mut iov);
+        if iov.base as usize == 0 || iov.len == 0 {
+            continue;
+        }
+        let buf = task.transfer_buffer(iov.base as *const u8, iov.len);
+        buf.iter().for_each(|b| message.extend_from_slice(b));
+    }
+    let mut files: Vec<Arc<dyn File>> = Vec::new();
+    let mut offset = 0;
+    while offset + core::mem::size_of::<CmsgHdr>() <= hdr.control_len {
+        let mut cmsg = CmsgHdr::default();
+        task.access_inner()
+            .copy_from_user((hdr.control + offset) as *const CmsgHdr, &mut cmsg);
+        if cmsg.len < core::mem::size_of::<CmsgHdr>() || offset + cmsg.len > hdr.control_len {
+            return Err(LinuxErrno::EINVAL);
+        }
+        if cmsg.level as usize != SocketLevel::Socket as usize || cmsg.ty != SCM_RIGHTS {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let count = (cmsg.len - core::mem::size_of::<CmsgHdr>()) / core::mem::size_of::<i32>();
+        let mut fds = vec![0i32; count];
+        task.access_inner().copy_from_user_buffer(
+            (hdr.control + offset + core::mem::size_of::<CmsgHdr>()) as *const i32,
+            fds.as_mut_ptr(),
+            count,
+        );
+        for fd in fds {
+            let file = task.get_file(fd as usize).ok_or(LinuxErrno::EBADF)?;
+            files.push(file);
+        }
+        offset += cmsg_align(cmsg.len);
+    }
+    let socket = socket_fd.get_socketdata()?;
+    info!(
+        "sendmsg: {:?}, message len: {}, files: {}",
+        socket_addr,
+        message.len(),
+        files.len()
+    );
+    let send = socket.send_msg(message.as_slice(), files, flags, socket_addr, inode)?;
+    Ok(send as isize)
+}
+
+/// 一个系统调用，通过套接字接收消息。消息的内容将被保存到一组缓冲区中，随消息传递的文件会被放入当前进程的文件描述符表。
+///
+/// + `socketfd`: 指明要操作socket的文件描述符fd;
+/// + `msg`: 指明消息头部([`MsgHdr`])的地址，返回时其中的地址长度、辅助数据长度和标志位会被更新;
+/// + `flags`: 指明接收操作的类型。
+///
+/// 如果接收成功，返回接收的字节数；否则返回错误信息。
+#[syscall_func(212)]
+pub fn recvmsg(socketfd: usize, msg: usize, flags: usize) -> AlienResult<isize> {
+    let socket_fd = common_socket_syscall(socketfd)?;
+    let task = current_task().unwrap();
+    let mut hdr = MsgHdr::default();
+    task.access_inner()
+        .copy_from_user(msg as *const MsgHdr, &mut hdr);
+    let mut iovs = Vec::new();
+    for i in 0..hdr.iov_len {
+        let mut iov = IoVec::empty();
+        let ptr = unsafe { (hdr.iov as *mut IoVec).add(i) };
+        task.access_inner().copy_from_user(ptr, &mut iov);
+        iovs.push(iov);
+    }
+    let length = iovs.iter().map(|iov| iov.len).sum::<usize>();
+    let mut tmp_buffer = vec![0u8; length];
+    let socket = socket_fd.get_socketdata()?;
+    let (recv, from, files) = socket.recv_msg(tmp_buffer.as_mut_slice(), flags)?;
+    drop(socket);
+    let mut copied = 0;
+    for iov in iovs {
+        if copied >= recv {
+            break;
+        }
+        if iov.base as usize == 0 || iov.len == 0 {
+            continue;
+        }
+        let len = iov.len.min(recv - copied);
+        task.access_inner().copy_to_user_buffer(
+            tmp_buffer[copied..].as_ptr(),
+            iov.base as *mut u8,
+            len,
+        );
+        copied += len;
+    }
+    if hdr.name != 0 {
+        let name_len = msg + core::mem::offset_of!(MsgHdr, name_len);
+        socket_addr_to_user(&from, hdr.name, name_len)?;
+    }
+    let mut control_len = 0;
+    let mut msg_flags = 0;
+    if !files.is_empty() {
+        let space = hdr
+            .control_len
+            .saturating_sub(core::mem::size_of::<CmsgHdr>())
+            / core::mem::size_of::<i32>();
+        // 与 Linux 一致，文件描述符表已满时不返回错误，已经放入的文件照常交给用户，
+        // 消息已经被取出，返回错误会让已经放入的文件描述符泄漏
+        let mut fds = Vec::new();
+        for file in files.iter().take(space) {
+            match task.add_file(file.clone()) {
+                Ok(fd) => fds.push(fd as i32),
+                Err(_) => break,
+            }
+        }
+        // the files which can't be delivered are closed
+        if fds.len() < files.len() {
+            msg_flags |= MSG_CTRUNC;
+        }
+        if !fds.is_empty() {
+            let cmsg = CmsgHdr {
+                len: core::mem::size_of::<CmsgHdr>() + fds.len() * core::mem::size_of::<i32>(),
+                level: SocketLevel::Socket as i32,
+                ty: SCM_RIGHTS,
+            };
+            task.access_inner()
+                .copy_to_user(&cmsg, hdr.control as *mut CmsgHdr);
+            task.access_inner().copy_to_user_buffer(
+                fds.as_ptr(),
+                (hdr.control + core::mem::size_of::<CmsgHdr>()) as *mut i32,
+                fds.len(),
+            );
+            control_len = cmsg_align(cmsg.len).min(hdr.control_len);
+        }
+    }
+    let hdr_new = task.access_inner().transfer_raw_ptr_mut(msg as *mut MsgHdr);
+    hdr_new.control_len = control_len;
+    hdr_new.flags = msg_flags;
+    Ok(recv as isize)
+}
+
+/// 一个系统调用，创建一对未绑定的socket套接字，该对套接字可以用于全双工通信，或者用于父子进程之间的通信。
 ///
 /// 如果向其中的一个socket写入后，再从该socket读时，就会发生阻塞。只能在另一个套接字中读。往往和shutdown()配合使用
 ///
@@ -454,7 +622,8 @@ pub fn shutdown(socketfd: usize, how: usize) -> AlienResult<()> {
 #[syscall_func(199)]
 pub fn socket_pair(domain: usize, s_type: usize, protocol: usize, sv: usize) -> AlienResult<isize> {
     let domain = Domain::try_from(domain).map_err(|_| LinuxErrno::EINVAL)?;
-    let socket_type = SocketType::try_from(s_type).map_err(|_| LinuxErrno::EINVAL)?;
+    let socket_type =
+        SocketType::try_from(s_type & SOCKET_TYPE_MASK as usize).map_err(|_| LinuxErrno::EINVAL)?;
     if domain != Domain::AF_UNIX {
         return Err(LinuxErrno::EAFNOSUPPORT);
     }
@@ -479,26 +648,16 @@ pub fn socket_pair(domain: usize, s_type: usize, protocol: usize, sv: usize) ->
         info!("socket with cloexec");
     }
 
-    let socket_guard = file1.get_socketdata()?;
-    match &socket_guard.socket {
-        Socket::Unix(unix_socket) => {
-            unix_socket.set_remote(&(file2));
-        }
-        _ => {
-            panic!("socket_pair: unsupported socket type")
-        }
-    }
-    drop(socket_guard);
-    let socket_guard = file2.get_socketdata()?;
-    match &socket_guard.socket {
-        Socket::Unix(unix_socket) => {
-            unix_socket.set_remote(&file1);
+    let socket1 = file1.get_socketdata()?;
+    let socket2 = file2.get_socketdata()?;
+    match (&socket1.socket, &socket2.socket) {
+        (Socket::Unix(unix_socket1), Socket::Unix(unix_socket2)) => {
+            unix_socket1.pair(unix_socket2);
         }
         _ => {
             panic!("socket_pair: unsupported socket type")
         }
     }
-    drop(socket_guard);
     let task = current_task().unwrap();
     let fd1 = task.add_file(file1).map_err(|_| LinuxErrno::EMFILE)?;
     let fd2 = task.add_file(file2).map_err(|_| LinuxErrno::EMFILE)?;
@@ -1,17 +1,33 @@
 //! uname系统调用实现
 
-use alloc::vec;
-use core::cmp::min;
+use alloc::{sync::Arc, vec::Vec};
+use core::{
+    cmp::min,
+    sync::atomic::{AtomicU64, AtomicU8, Ordering},
+};
 
+use bit_field::BitField;
+use config::CPU_NUM;
 use constants::{
-    sys::{Rusage, RusageFlag, Sysinfo, SyslogAction},
-    time::TimeVal,
+    sys::{Rusage, RusageFlag, Sysinfo},
+    time::{TimeSpec, TimeVal},
     AlienResult, LinuxErrno,
 };
+use platform::kmsg::{
+    console_loglevel, kmsg_first_seq, kmsg_next_seq, kmsg_read, kmsg_wait, set_console_loglevel,
+    KmsgReadError, KMSG_LINE_MAX, KMSG_RECORDS, MINIMUM_CONSOLE_LOGLEVEL,
+};
 use syscall_table::syscall_func;
 use timer::{get_time_ms, TimeFromFreq};
 
-use crate::task::current_task;
+use crate::task::{
+    current_task, do_suspend, get_task_from_tid,
+    sched::{SchedParam, SchedPolicy, RR_TIMESLICE_MS, SCHED_RESET_ON_FORK},
+    Task, GLOBAL_TASK_MANAGER,
+};
+
+/// `getrusage` 中 `who` 为 `RUSAGE_CHILDREN` 时的取值
+const RUSAGE_CHILDREN: isize = -1;
 
 /// 记录系统信息的结构，包括操作系统名、在网络中的用户名、操作系统release和version版本、硬件类型、域名等信息。
 #[repr(C)]
@@ -67,47 +83,163 @@ pub fn uname(utsname: *const u8) -> isize {
     0
 }
 
-const LOG_BUF_LEN: usize = 4096;
-const LOG: &str = r"
-[    0.000000] Linux version 5.10.0-7-riscv64 (debian-kernel@lists.debian.org) (gcc-10 (Debian 10.2.1-6) 10.2.1 20210110, GNU ld (GNU Binutils for Debian) 2.35.2) #1 SMP Debian 5.10.40-1 (2021-05-28)
-";
+/// `syslog` 中 `log_type` 的取值
+const SYSLOG_ACTION_CLOSE: u32 = 0;
+const SYSLOG_ACTION_OPEN: u32 = 1;
+const SYSLOG_ACTION_READ: u32 = 2;
+const SYSLOG_ACTION_READ_ALL: u32 = 3;
+const SYSLOG_ACTION_READ_CLEAR: u32 = 4;
+const SYSLOG_ACTION_CLEAR: u32 = 5;
+const SYSLOG_ACTION_CONSOLE_OFF: u32 = 6;
+const SYSLOG_ACTION_CONSOLE_ON: u32 = 7;
+const SYSLOG_ACTION_CONSOLE_LEVEL: u32 = 8;
+const SYSLOG_ACTION_SIZE_UNREAD: u32 = 9;
+const SYSLOG_ACTION_SIZE_BUFFER: u32 = 10;
+
+/// `SYSLOG_ACTION_READ` 下一次读取的记录序号
+static SYSLOG_SEQ: AtomicU64 = AtomicU64::new(0);
+/// `SYSLOG_ACTION_READ_ALL` 从该序号开始读取，`SYSLOG_ACTION_CLEAR` 会将其设置为下一条记录的序号
+static CLEAR_SEQ: AtomicU64 = AtomicU64::new(0);
+/// `SYSLOG_ACTION_CONSOLE_OFF` 时保存的控制台日志级别，0 表示没有保存
+static SAVED_CONSOLE_LOGLEVEL: AtomicU8 = AtomicU8::new(0);
+
+/// 从序号 `seq` 开始按照 syslog 的格式读取完整的记录追加到 `data` 中，`data` 的总长度不超过 `limit`。
+///
+/// 返回下一条没有读取的记录的序号，已经被覆盖的记录会被跳过。
+fn read_syslog_records(mut seq: u64, data: &mut Vec<u8>, limit: usize) -> u64 {
+    seq = seq.max(kmsg_first_seq());
+    while seq < kmsg_next_seq() {
+        match kmsg_read(seq) {
+            Ok(record) => {
+                let line = record.syslog_line();
+                if data.len() + line.as_bytes().len() > limit {
+                    break;
+                }
+                data.extend_from_slice(line.as_bytes());
+                seq += 1;
+            }
+            Err(KmsgReadError::Overwritten) => seq = kmsg_first_seq().max(seq + 1),
+            Err(KmsgReadError::NotYet) => break,
+        }
+    }
+    seq
+}
+
+/// 从 `CLEAR_SEQ` 开始，找到最旧的一条记录，使得从它开始的所有记录都能放入长度为 `len` 的缓冲区
+fn syslog_read_all_start(len: usize) -> u64 {
+    let start = CLEAR_SEQ.load(Ordering::Relaxed).max(kmsg_first_seq());
+    let mut seq = kmsg_next_seq();
+    let mut total = 0;
+    while seq > start {
+        let Ok(record) = kmsg_read(seq - 1) else {
+            break;
+        };
+        total += record.syslog_line().as_bytes().len();
+        if total > len {
+            break;
+        }
+        seq -= 1;
+    }
+    seq
+}
 
-/// (待完善)一个系统调用函数，用于对内核消息环状缓冲区进行操作。
+/// 一个系统调用函数，用于对内核消息环状缓冲区进行操作，缓冲区的内容见 [`platform::kmsg`]。
 ///
-/// + `log_type`: 指明操作的类型，具体值可见[`SyslogAction`]；
+/// + `log_type`: 指明操作的类型；
 /// + `buf`: 指明读取消息时，消息要保存到的位置；
-/// + `len`: 指明具体操作时，对于消息读取的长度限制。真正的读取消息的长度将取决于就传入的`len`和`LOG_BUF_LEN`的最小值。
+/// + `len`: 指明读取消息时缓冲区的长度，或者 `SYSLOG_ACTION_CONSOLE_LEVEL` 时要设置的控制台日志级别。
 ///
-/// 当`log_type`为`READ`、`ReadAll`、`ReadClear`三种flag，正确执行后返回读取消息的长度；
-/// 当`log_type`为`Unknown`时，会返回`EINVAL`；当`log_type`为`OPEN`或`CLOSE`时，函数不进行任何操作后返回0。
-/// 目前Alien仅支持上述`log_type`值，其余值都将不进行任何操作后返回0。
+/// 支持的操作如下：
+/// + `SYSLOG_ACTION_READ`: 读取尚未被该操作读取过的记录，没有记录时阻塞等待，读取的记录不能再次被该操作读取；
+/// + `SYSLOG_ACTION_READ_ALL`: 读取缓冲区中最新的不超过 `len` 字节的记录；
+/// + `SYSLOG_ACTION_READ_CLEAR`: 读取缓冲区中的记录，之后清空缓冲区；
+/// + `SYSLOG_ACTION_CLEAR`: 清空缓冲区，之后 `SYSLOG_ACTION_READ_ALL` 只能读取到新的记录；
+/// + `SYSLOG_ACTION_CONSOLE_OFF`/`SYSLOG_ACTION_CONSOLE_ON`: 关闭/恢复日志在控制台上的输出；
+/// + `SYSLOG_ACTION_CONSOLE_LEVEL`: 设置控制台日志级别为 `len`，取值范围为 1 到 8；
+/// + `SYSLOG_ACTION_SIZE_UNREAD`: 返回 `SYSLOG_ACTION_READ` 可以读取的字节数；
+/// + `SYSLOG_ACTION_SIZE_BUFFER`: 返回缓冲区的大小；
+/// + `SYSLOG_ACTION_OPEN`/`SYSLOG_ACTION_CLOSE`: 不进行任何操作。
+///
+/// 读取操作成功时返回读取的字节数，其它操作成功时返回 0。除了 `SYSLOG_ACTION_READ_ALL` 和 `SYSLOG_ACTION_SIZE_BUFFER` 外，
+/// 其它操作都需要超级用户权限，否则返回 `EPERM`。参数非法时返回 `EINVAL`，等待时被信号打断返回 `EINTR`。
 ///
 /// Reference: [syslog](https://man7.org/linux/man-pages/man2/syslog.2.html)
 #[syscall_func(116)]
-pub fn syslog(log_type: u32, buf: usize, len: usize) -> isize {
-    let log_type = SyslogAction::try_from(log_type);
-    if log_type.is_err() {
-        return LinuxErrno::EINVAL as isize;
-    }
-    match log_type.unwrap() {
-        SyslogAction::OPEN | SyslogAction::CLOSE => 0,
-        SyslogAction::READ | SyslogAction::ReadAll | SyslogAction::ReadClear => {
-            let min_len = min(len, LOG_BUF_LEN);
-            let task = current_task().unwrap();
-            // the buf may be not valid, so we need to check it -- > sbrk heap
-            let mut buf = task.transfer_buffer(buf as *mut u8, min_len);
-            let log = LOG.as_bytes();
-            let mut offset = 0;
-            buf.iter_mut().for_each(|buf| {
-                let copy_len = min(log.len() - offset, buf.len());
-                buf[..copy_len].copy_from_slice(&log[offset..offset + copy_len]);
-                offset += copy_len;
-            });
-            offset as isize
+pub fn syslog(log_type: u32, buf: usize, len: isize) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    if !matches!(log_type, SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_SIZE_BUFFER)
+        && !task.access_inner().cred.is_root()
+    {
+        return Err(LinuxErrno::EPERM);
+    }
+    let read_buf = |len: isize| -> AlienResult<usize> {
+        if buf == 0 || len < 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        Ok(len as usize)
+    };
+    let mut data = Vec::new();
+    match log_type {
+        SYSLOG_ACTION_CLOSE | SYSLOG_ACTION_OPEN => return Ok(0),
+        SYSLOG_ACTION_READ => {
+            let len = read_buf(len)?;
+            if len == 0 {
+                return Ok(0);
+            }
+            while SYSLOG_SEQ.load(Ordering::Relaxed) >= kmsg_next_seq() {
+                kmsg_wait(SYSLOG_SEQ.load(Ordering::Relaxed))?;
+            }
+            let seq = read_syslog_records(SYSLOG_SEQ.load(Ordering::Relaxed), &mut data, len);
+            SYSLOG_SEQ.store(seq, Ordering::Relaxed);
+        }
+        SYSLOG_ACTION_READ_ALL | SYSLOG_ACTION_READ_CLEAR => {
+            let len = read_buf(len)?;
+            let seq = read_syslog_records(syslog_read_all_start(len), &mut data, len);
+            if log_type == SYSLOG_ACTION_READ_CLEAR {
+                CLEAR_SEQ.store(seq, Ordering::Relaxed);
+            }
+        }
+        SYSLOG_ACTION_CLEAR => {
+            CLEAR_SEQ.store(kmsg_next_seq(), Ordering::Relaxed);
+            return Ok(0);
         }
-        SyslogAction::Unknown => LinuxErrno::EINVAL as isize,
-        _ => 0,
+        SYSLOG_ACTION_CONSOLE_OFF => {
+            let _ = SAVED_CONSOLE_LOGLEVEL.compare_exchange(
+                0,
+                console_loglevel(),
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+            );
+            set_console_loglevel(MINIMUM_CONSOLE_LOGLEVEL);
+            return Ok(0);
+        }
+        SYSLOG_ACTION_CONSOLE_ON => {
+            let saved = SAVED_CONSOLE_LOGLEVEL.swap(0, Ordering::Relaxed);
+            if saved != 0 {
+                set_console_loglevel(saved);
+            }
+            return Ok(0);
+        }
+        SYSLOG_ACTION_CONSOLE_LEVEL => {
+            if !(1..=8).contains(&len) {
+                return Err(LinuxErrno::EINVAL);
+            }
+            set_console_loglevel(len as u8);
+            SAVED_CONSOLE_LOGLEVEL.store(0, Ordering::Relaxed);
+            return Ok(0);
+        }
+        SYSLOG_ACTION_SIZE_UNREAD => {
+            read_syslog_records(SYSLOG_SEQ.load(Ordering::Relaxed), &mut data, usize::MAX);
+            return Ok(data.len() as isize);
+        }
+        SYSLOG_ACTION_SIZE_BUFFER => return Ok((KMSG_RECORDS * KMSG_LINE_MAX) as isize),
+        _ => return Err(LinuxErrno::EINVAL),
+    }
+    if !data.is_empty() {
+        task.access_inner()
+            .copy_to_user_buffer(data.as_ptr(), buf as *mut u8, data.len());
     }
+    Ok(data.len() as isize)
 }
 
 extern "C" {
@@ -149,51 +281,258 @@ pub fn sys_info(dst_info: usize) -> isize {
     0
 }
 
-/// (待实现)一个系统调用，设置进程调度的参数。目前直接返回0。
+/// 根据系统调用传入的 `pid` 获取对应的任务，`pid` 为 0 时表示当前任务
+fn find_task(pid: usize) -> AlienResult<Arc<Task>> {
+    if pid == 0 {
+        Ok(current_task().unwrap().clone())
+    } else {
+        get_task_from_tid(pid).ok_or(LinuxErrno::ESRCH)
+    }
+}
+
+/// 检查当前任务是否可以修改任务 `task` 的调度参数，返回当前任务是否为超级用户
+///
+/// 普通用户只能修改有效用户号与自己相同、或者实际用户号与自己的有效用户号相同的任务，否则返回 `EPERM`。
+fn check_sched_permission(task: &Task) -> AlienResult<bool> {
+    let cred = current_task().unwrap().credentials();
+    if cred.is_root() {
+        return Ok(true);
+    }
+    let target = task.credentials();
+    if cred.euid != target.euid && cred.euid != target.ruid {
+        return Err(LinuxErrno::EPERM);
+    }
+    Ok(false)
+}
+
+/// 一个系统调用，设置进程调度的参数。`param`所指向的[`SchedParam`]结构中保存了新的实时优先级。
+///
+/// 新的优先级需要在当前调度策略允许的范围内，否则返回`EINVAL`。普通用户只能修改自己的任务，
+/// 并且不能提高实时优先级，否则返回`EPERM`。
+///
+/// Reference: [sched_setparam](https://man7.org/linux/man-pages/man2/sched_setparam.2.html)
 #[syscall_func(118)]
-pub fn sched_setparam() -> isize {
-    0
+pub fn sched_setparam(pid: usize, param: usize) -> AlienResult<isize> {
+    if param == 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = find_task(pid)?;
+    let mut sched_param = SchedParam::default();
+    current_task()
+        .unwrap()
+        .access_inner()
+        .copy_from_user(param as *const SchedParam, &mut sched_param);
+    let privileged = check_sched_permission(&task)?;
+    GLOBAL_TASK_MANAGER.update_sched(&task, |sched| {
+        if !privileged && sched_param.sched_priority > sched.rt_priority as i32 {
+            return Err(LinuxErrno::EPERM);
+        }
+        let (policy, reset_on_fork) = (sched.policy, sched.reset_on_fork);
+        sched.set_scheduler(policy, sched_param.sched_priority, reset_on_fork)
+    })?;
+    Ok(0)
 }
 
-/// (待实现)一个系统调用，获取进程调度的参数。目前直接返回0。
+/// 一个系统调用，获取进程调度的参数。进程的实时优先级将保存到`param`所指向的[`SchedParam`]结构中。
+///
+/// Reference: [sched_getparam](https://man7.org/linux/man-pages/man2/sched_getparam.2.html)
 #[syscall_func(121)]
-pub fn sched_getparam() -> isize {
-    0
+pub fn sched_getparam(pid: usize, param: usize) -> AlienResult<isize> {
+    if param == 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = find_task(pid)?;
+    let sched_param = SchedParam {
+        sched_priority: task.access_inner().sched.rt_priority as i32,
+    };
+    current_task()
+        .unwrap()
+        .access_inner()
+        .copy_to_user(&sched_param, param as *mut SchedParam);
+    Ok(0)
 }
 
-/// (待实现)一个系统调用，设置进程CPU亲和力(位掩码)，使进程绑定在某一个或几个CPU上运行，避免在CPU之间来回切换，从而提高该进程的实时性能。目前直接返回0。
+/// 一个系统调用，设置进程的CPU亲和力(位掩码)，使进程绑定在某一个或几个CPU上运行，避免在CPU之间来回切换，从而提高该进程的实时性能。
+///
+/// `mask`指向长度为`size`字节的位掩码，超出CPU数量的位会被忽略。如果掩码中不包含任何可用的CPU，返回`EINVAL`。
+/// 普通用户只能修改自己的任务，否则返回`EPERM`。处于就绪队列中的任务如果所在的CPU不在新的掩码中，会被迁移到允许的CPU上；
+/// 如果设置的是当前进程且当前所在的CPU不在新的掩码中，当前进程会立即让出CPU并迁移到允许的CPU上。
+///
+/// Reference: [sched_setaffinity](https://man7.org/linux/man-pages/man2/sched_setaffinity.2.html)
 #[syscall_func(122)]
-pub fn sched_setaffinity() -> isize {
-    0
+pub fn sched_setaffinity(pid: usize, size: usize, mask: usize) -> AlienResult<isize> {
+    if mask == 0 || size == 0 {
+        return Err(LinuxErrno::EFAULT);
+    }
+    let task = find_task(pid)?;
+    check_sched_permission(&task)?;
+    let mut buf = [0u8; core::mem::size_of::<usize>()];
+    let len = min(size, buf.len());
+    current_task()
+        .unwrap()
+        .access_inner()
+        .copy_from_user_buffer(mask as *const u8, buf.as_mut_ptr(), len);
+    let affinity = usize::from_le_bytes(buf) & ((1 << CPU_NUM) - 1);
+    if affinity == 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    info!("sched_setaffinity: pid: {}, mask: {:#b}", pid, affinity);
+    GLOBAL_TASK_MANAGER.set_affinity(&task, affinity);
+    let current = current_task().unwrap();
+    if Arc::ptr_eq(&task, current) && !affinity.get_bit(arch::hart_id()) {
+        do_suspend();
+    }
+    Ok(0)
 }
 
-/// (待完善)一个系统调用，获取某进程对CPU的亲和力(位掩码)。当前进程的cpu亲和力将保存到`mask`所指向的位置。函数执行成功后返回8。
+/// 一个系统调用，获取某进程对CPU的亲和力(位掩码)，保存到`mask`所指向的位置。
+///
+/// `size`不能小于一个`usize`的大小，否则返回`EINVAL`。函数执行成功后返回写入的字节数。
+///
+/// Reference: [sched_getaffinity](https://man7.org/linux/man-pages/man2/sched_getaffinity.2.html)
 #[syscall_func(123)]
-pub fn sched_getaffinity(pid: usize, size: usize, mask: usize) -> isize {
-    warn!(
-        "sched_getaffinity: pid: {}, size: {}, mask: {}",
-        pid, size, mask
-    );
-    assert_eq!(pid, 0);
-    let task = current_task().unwrap();
-    let res = task.access_inner().cpu_affinity;
-    let mask = task.access_inner().transfer_raw_ptr_mut(mask as *mut usize);
-    *mask = res;
-    8
+pub fn sched_getaffinity(pid: usize, size: usize, mask: usize) -> AlienResult<isize> {
+    if size < core::mem::size_of::<usize>() {
+        return Err(LinuxErrno::EINVAL);
+    }
+    if mask == 0 {
+        return Err(LinuxErrno::EFAULT);
+    }
+    let task = find_task(pid)?;
+    let affinity = task.access_inner().cpu_affinity;
+    current_task()
+        .unwrap()
+        .access_inner()
+        .copy_to_user(&affinity, mask as *mut usize);
+    Ok(core::mem::size_of::<usize>() as isize)
 }
 
-/// (待实现)一个系统调用，用于获取当前CPU的调度策略。目前直接返回0。
+/// 一个系统调用，用于获取进程的调度策略。
+///
+/// 如果进程设置了`SCHED_RESET_ON_FORK`标志，返回值中也会包含该标志。
+///
+/// Reference: [sched_getscheduler](https://man7.org/linux/man-pages/man2/sched_getscheduler.2.html)
 #[syscall_func(120)]
-pub fn sched_getscheduler(pid: usize) -> isize {
-    assert_eq!(pid, 0);
-    // let task = current_task().unwrap();
-    0
+pub fn sched_getscheduler(pid: usize) -> AlienResult<isize> {
+    let task = find_task(pid)?;
+    let inner = task.access_inner();
+    let mut policy = inner.sched.policy as usize;
+    if inner.sched.reset_on_fork {
+        policy |= SCHED_RESET_ON_FORK;
+    }
+    Ok(policy as isize)
 }
 
-/// (待实现)一个系统调用，用于设置当前CPU的调度策略。目前直接返回0。
+/// 一个系统调用，用于设置进程的调度策略和实时优先级。
+///
+/// `policy`可以为`SCHED_OTHER`、`SCHED_FIFO`、`SCHED_RR`、`SCHED_BATCH`和`SCHED_IDLE`中的一种，
+/// 并可以与`SCHED_RESET_ON_FORK`标志按位或。`param`所指向的[`SchedParam`]结构中保存了实时优先级，
+/// 实时调度策略的优先级范围为 1 ~ 99，其它调度策略的优先级必须为 0。
+///
+/// 普通用户只能修改自己的任务，并且不能切换到实时调度策略或者提高实时优先级，否则返回`EPERM`。
+///
+/// Reference: [sched_setscheduler](https://man7.org/linux/man-pages/man2/sched_setscheduler.2.html)
 #[syscall_func(119)]
-pub fn sched_setscheduler(_pid: usize, _policy: usize, _param: usize) -> isize {
-    0
+pub fn sched_setscheduler(pid: usize, policy: usize, param: usize) -> AlienResult<isize> {
+    if param == 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let reset_on_fork = policy & SCHED_RESET_ON_FORK != 0;
+    let policy = SchedPolicy::try_from(policy & !SCHED_RESET_ON_FORK)?;
+    let task = find_task(pid)?;
+    let mut sched_param = SchedParam::default();
+    current_task()
+        .unwrap()
+        .access_inner()
+        .copy_from_user(param as *const SchedParam, &mut sched_param);
+    info!(
+        "sched_setscheduler: pid: {}, policy: {:?}, priority: {}",
+        pid, policy, sched_param.sched_priority
+    );
+    let privileged = check_sched_permission(&task)?;
+    GLOBAL_TASK_MANAGER.update_sched(&task, |sched| {
+        if !privileged
+            && policy.is_rt()
+            && (!sched.policy.is_rt() || sched_param.sched_priority > sched.rt_priority as i32)
+        {
+            return Err(LinuxErrno::EPERM);
+        }
+        sched.set_scheduler(policy, sched_param.sched_priority, reset_on_fork)
+    })?;
+    Ok(0)
+}
+
+/// 一个系统调用，返回调度策略`policy`所允许的最高优先级。
+#[syscall_func(125)]
+pub fn sched_get_priority_max(policy: usize) -> AlienResult<isize> {
+    let policy = SchedPolicy::try_from(policy)?;
+    Ok(policy.max_priority() as isize)
+}
+
+/// 一个系统调用，返回调度策略`policy`所允许的最低优先级。
+#[syscall_func(126)]
+pub fn sched_get_priority_min(policy: usize) -> AlienResult<isize> {
+    let policy = SchedPolicy::try_from(policy)?;
+    Ok(policy.min_priority() as isize)
+}
+
+/// 一个系统调用，获取`SCHED_RR`进程的时间片长度，保存到`interval`所指向的[`TimeSpec`]结构中。
+/// 对于其它调度策略的进程，返回的时间片长度为 0。
+#[syscall_func(127)]
+pub fn sched_rr_get_interval(pid: usize, interval: usize) -> AlienResult<isize> {
+    let task = find_task(pid)?;
+    let time = if task.access_inner().sched.policy == SchedPolicy::RoundRobin {
+        TimeSpec::new(0, RR_TIMESLICE_MS * 1_000_000)
+    } else {
+        TimeSpec::new(0, 0)
+    };
+    current_task()
+        .unwrap()
+        .access_inner()
+        .copy_to_user(&time, interval as *mut TimeSpec);
+    Ok(0)
+}
+
+/// `setpriority` / `getpriority` 中的 `which` 参数，表示 `who` 为一个进程
+const PRIO_PROCESS: usize = 0;
+
+/// 一个系统调用，用于设置进程的 nice 值。超出 -20 ~ 19 范围的值会被截断。
+///
+/// 目前仅支持`which`为`PRIO_PROCESS`，其余情况返回`EINVAL`。普通用户只能修改自己的任务，否则返回`EPERM`；
+/// 普通用户降低 nice 值(提高优先级)时返回`EACCES`。
+///
+/// Reference: [setpriority](https://man7.org/linux/man-pages/man2/setpriority.2.html)
+#[syscall_func(140)]
+pub fn setpriority(which: usize, who: usize, prio: isize) -> AlienResult<isize> {
+    if which != PRIO_PROCESS {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = find_task(who)?;
+    let privileged = check_sched_permission(&task)?;
+    GLOBAL_TASK_MANAGER.update_sched(&task, |sched| {
+        if !privileged && prio < sched.nice as isize {
+            return Err(LinuxErrno::EACCES);
+        }
+        sched.set_nice(prio);
+        Ok(())
+    })?;
+    Ok(0)
+}
+
+/// 一个系统调用，用于获取进程的 nice 值。
+///
+/// 与 Linux 的系统调用一致，返回值为`20 - nice`，范围为 1 ~ 40，由用户库负责转换为 nice 值。
+/// 目前仅支持`which`为`PRIO_PROCESS`，其余情况返回`EINVAL`。
+///
+/// Reference: [getpriority](https://man7.org/linux/man-pages/man2/setpriority.2.html)
+#[syscall_func(141)]
+pub fn getpriority(which: usize, who: usize) -> AlienResult<isize> {
+    if which != PRIO_PROCESS {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = find_task(who)?;
+    let nice = task.access_inner().sched.nice as isize;
+    Ok(20 - nice)
 }
 
 /// (待完善)一个系统调用，用于获取对系统资源的使用量信息。获取的信息将保存到`usage`所指向的[`Rusage`]结构中。
@@ -203,38 +542,93 @@ pub fn sched_setscheduler(_pid: usize, _policy: usize, _param: usize) -> isize {
 /// + `RUSAGE_CHILDREN`: 返回调用该函数进程所有已终止且被回收子进程的资源用量统计.
 /// + `RUSAGE_THREAD`: 返回调用该函数线程的资源用量统计。
 ///
-/// 在Alien中，目前仅支持`RUSAGE_SELF`。且返回的信息目前仅有[`Rusage`]下的`ru_utime`和`ru_stime`字段。
+/// 在Alien中，`RUSAGE_SELF`和`RUSAGE_THREAD`目前均返回调用线程的统计，`RUSAGE_CHILDREN`返回该线程通过
+/// `wait4`/`waitid`回收的子进程的统计。且返回的信息目前仅有[`Rusage`]下的`ru_utime`和`ru_stime`字段。
 ///
 /// 正确执行后返回0。
 #[syscall_func(165)]
 pub fn getrusage(who: isize, usage: usize) -> AlienResult<isize> {
-    let who = RusageFlag::try_from(who).map_err(|_| LinuxErrno::EINVAL)?;
-    info!("getrusage: who: {:?}, usage: {}", who, usage);
+    let flag = RusageFlag::try_from(who).map_err(|_| LinuxErrno::EINVAL)?;
+    info!("getrusage: who: {:?}, usage: {}", flag, usage);
     let task = current_task().unwrap();
     let static_info = task.access_inner().statistical_data().clone();
     let mut task_usage = Rusage::default();
-    task_usage.ru_utime = TimeVal::from_freq(static_info.tms_utime);
-    task_usage.ru_stime = TimeVal::from_freq(static_info.tms_stime);
+    if who == RUSAGE_CHILDREN {
+        task_usage.ru_utime = TimeVal::from_freq(static_info.tms_cutime);
+        task_usage.ru_stime = TimeVal::from_freq(static_info.tms_cstime);
+    } else {
+        task_usage.ru_utime = TimeVal::from_freq(static_info.tms_utime);
+        task_usage.ru_stime = TimeVal::from_freq(static_info.tms_stime);
+    }
     task.access_inner()
         .copy_to_user(&task_usage, usage as *mut Rusage);
     Ok(0)
 }
 
+/// `getrandom` 的标志位，CRNG 未初始化完成时不等待，返回 `EAGAIN`
+const GRND_NONBLOCK: u32 = 0x1;
+/// `getrandom` 的标志位，与读取 `/dev/random` 相同。所有随机数都来自同一个 CRNG，因此与不设置时的行为相同
+const GRND_RANDOM: u32 = 0x2;
+/// `getrandom` 的标志位，CRNG 未初始化完成时也立即返回随机数
+const GRND_INSECURE: u32 = 0x4;
+/// `getrandom` 每次生成并拷贝到用户空间的字节数
+const GETRANDOM_CHUNK: usize = 256;
+
+/// 等待 CRNG 初始化完成。`nonblock` 为 true 时不等待，返回 `EAGAIN`；等待过程中收到信号时返回 `EINTR`
+fn wait_for_crng(nonblock: bool) -> AlienResult<()> {
+    if krandom::crng_ready() {
+        return Ok(());
+    }
+    if nonblock {
+        return Err(LinuxErrno::EAGAIN);
+    }
+    krandom::wait_for_random_bytes()
+}
+
+/// 一个系统调用，向 `buf` 中写入 `len` 字节的随机数。
+///
+/// 随机数来自内核的 ChaCha20 随机数生成器(CRNG)，与 `/dev/random`、`/dev/urandom` 相同。
+/// CRNG 在熵池中积累足够的熵之前处于未初始化状态，此时:
+/// + 设置了 `GRND_INSECURE` 时立即返回随机数；
+/// + 设置了 `GRND_NONBLOCK` 时返回 `EAGAIN`；
+/// + 否则等待 CRNG 初始化完成，等待过程中收到信号时返回 `EINTR`。
+///
+/// `flags` 中包含未知的标志位，或者同时设置了 `GRND_INSECURE` 和 `GRND_RANDOM` 时返回 `EINVAL`。
+/// 生成大量随机数的过程中收到信号时，返回已经写入的字节数。
+///
+/// Reference: [getrandom](https://man7.org/linux/man-pages/man2/getrandom.2.html)
 #[syscall_func(278)]
 pub fn get_random(buf: *mut u8, len: usize, flags: u32) -> AlienResult<isize> {
     info!(
         "get_random: buf: {:x?}, len: {}, flags: {}",
         buf, len, flags
     );
+    if flags & !(GRND_NONBLOCK | GRND_RANDOM | GRND_INSECURE) != 0
+        || flags & (GRND_INSECURE | GRND_RANDOM) == GRND_INSECURE | GRND_RANDOM
+    {
+        return Err(LinuxErrno::EINVAL);
+    }
     let task = current_task().unwrap();
-    let mut rand_buf = vec![0; len];
-    for v in rand_buf.iter_mut() {
-        let time_ms = get_time_ms();
-        *v = (time_ms & 0xff) as u8;
+    if flags & GRND_INSECURE == 0 {
+        wait_for_crng(flags & GRND_NONBLOCK != 0)?;
     }
-    task.access_inner()
-        .copy_to_user_buffer(rand_buf.as_ptr(), buf, len);
-    Ok(len as isize)
+    let mut rand_buf = [0u8; GETRANDOM_CHUNK];
+    let mut copied = 0;
+    while copied < len {
+        if copied > 0 && task.access_inner().signal_receivers.lock().have_signal() {
+            break;
+        }
+        let size = min(GETRANDOM_CHUNK, len - copied);
+        krandom::get_random_bytes(&mut rand_buf[..size]);
+        task.access_inner().copy_to_user_buffer(
+            rand_buf.as_ptr(),
+            unsafe { buf.add(copied) },
+            size,
+        );
+        copied += size;
+    }
+    rand_buf.fill(0);
+    Ok(copied as isize)
 }
 
 /// 一个系统调用，通过调用 SBI_SHUTDOWN 来关闭操作系统（直接退出 QEMU）
@@ -2,31 +2,51 @@
 use alloc::{
     string::{String, ToString},
     sync::Arc,
+    vec,
     vec::Vec,
 };
-use core::cell::UnsafeCell;
+use core::{
+    cell::UnsafeCell,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
+use bit_field::BitField;
 use config::CPU_NUM;
 use constants::{
-    ipc::FutexOp,
+    io::OpenFlags,
     signal::SignalNumber,
+    sys::Rusage,
     task::{CloneFlags, WaitOptions},
-    AlienError, AlienResult, PrLimitResType, RLimit64,
+    time::TimeVal,
+    AlienError, AlienResult, LinuxErrno, RLimit64, RLimitResource, AT_FDCWD,
 };
 use ksync::Mutex;
 use log::{info, warn};
 use platform::system_shutdown;
-use smpscheduler::{FifoSmpScheduler, FifoTask, ScheduleHart};
 use spin::Lazy;
 use syscall_table::syscall_func;
+use timer::TimeFromFreq;
+use vfs::perm::{inode_owner, S_ISGID, S_ISUID};
+use vfscore::utils::VfsNodeType;
 
 use crate::{
     fs,
-    ipc::{futex, global_logoff_signals},
+    fs::{
+        perm::{inode_permission, MAY_EXEC},
+        user_path_at,
+    },
+    ipc::{
+        discard_ignored_signal,
+        futex::{self, FUTEX_BITSET_MATCH_ANY},
+        global_logoff_signals,
+        pidfd::PidFd,
+    },
     task::{
         context::Context,
+        count_user_tasks, get_process_group, get_task_from_tid, get_thread_group,
+        sched::{RunQueue, SchedEntity},
         schedule::schedule,
-        task::{Task, TaskState},
+        task::{JobStatus, Task, TaskState},
         INIT_PROCESS,
     },
     trap::{check_task_timer_expired, TrapFrame},
@@ -72,18 +92,159 @@ unsafe impl<CPU> Sync for SafeRefCell<CPU> {}
 const DEFAULT_CPU: SafeRefCell<CPU> = SafeRefCell::new(CPU::empty());
 /// 保存每个核的信息
 static CPU_MANAGER: [SafeRefCell<CPU>; CPU_NUM] = [DEFAULT_CPU; CPU_NUM];
+/// 周期性负载均衡的间隔，以时钟中断次数为单位
+const BALANCE_INTERVAL: usize = 4;
+
+/// 任务管理器，每个 CPU 拥有一个独立的运行队列
+///
+/// 任务加入时会根据其 CPU 亲和力选择负载最小的运行队列；每个 CPU 在空闲时以及每隔 [`BALANCE_INTERVAL`]
+/// 次时钟中断会从负载最大的运行队列中迁移任务，使得各个 CPU 的负载大致相同。
 #[derive(Debug)]
-pub struct ScheduleHartImpl;
+pub struct TaskManager {
+    run_queues: [Mutex<RunQueue>; CPU_NUM],
+    ticks: [AtomicUsize; CPU_NUM],
+}
+
+impl TaskManager {
+    /// 创建一个新的任务管理器
+    pub fn new() -> Self {
+        Self {
+            run_queues: core::array::from_fn(|hart| Mutex::new(RunQueue::new(hart))),
+            ticks: [const { AtomicUsize::new(0) }; CPU_NUM],
+        }
+    }
+
+    /// 将一个就绪的任务加入到运行队列中
+    pub fn add_task(&self, task: Arc<Task>) {
+        let (affinity, last) = {
+            let inner = task.access_inner();
+            (inner.cpu_affinity, inner.sched.cpu)
+        };
+        let hart = self.select_hart(affinity, last);
+        let migrate_from = if hart != last {
+            Some(self.run_queues[last].lock().min_vruntime())
+        } else {
+            None
+        };
+        self.run_queues[hart].lock().enqueue(task, migrate_from);
+    }
+
+    /// 修改任务 `task` 的调度信息，调用者不能持有任务的锁
+    ///
+    /// 任务位于运行队列中时，先将其移出，修改后再按照新的调度策略和优先级放回同一个运行队列，
+    /// 避免任务留在与其调度策略或优先级不符的就绪队列中。
+    pub fn update_sched<R>(&self, task: &Arc<Task>, f: impl FnOnce(&mut SchedEntity) -> R) -> R {
+        loop {
+            let hart = task.access_inner().sched.cpu;
+            let mut run_queue = self.run_queues[hart].lock();
+            // 任务在获取锁之前被迁移到了其它 CPU 的运行队列
+            if task.access_inner().sched.cpu != hart {
+                continue;
+            }
+            let queued = run_queue.dequeue(task);
+            let res = f(&mut task.access_inner().sched);
+            if let Some(task) = queued {
+                run_queue.enqueue(task, None);
+            }
+            return res;
+        }
+    }
+
+    /// 修改任务 `task` 的 CPU 亲和力，调用者不能持有任务的锁
+    ///
+    /// 任务位于新的亲和力不允许的 CPU 的运行队列中时，将其迁移到允许的 CPU 中负载最小的一个；
+    /// 正在运行的任务在下一次被放回运行队列时由 [`TaskManager::add_task`] 选择允许的 CPU。
+    pub fn set_affinity(&self, task: &Arc<Task>, affinity: usize) {
+        let (queued, hart, min_vruntime) = loop {
+            let hart = task.access_inner().sched.cpu;
+            let mut run_queue = self.run_queues[hart].lock();
+            // 任务在获取锁之前被迁移到了其它 CPU 的运行队列
+            if task.access_inner().sched.cpu != hart {
+                continue;
+            }
+            task.access_inner().cpu_affinity = affinity;
+            if affinity.get_bit(hart) {
+                return;
+            }
+            break (run_queue.dequeue(task), hart, run_queue.min_vruntime());
+        };
+        // 为了避免死锁，迁移时不同时持有两个运行队列的锁
+        if let Some(task) = queued {
+            let target = self.select_hart(affinity, hart);
+            self.run_queues[target]
+                .lock()
+                .enqueue(task, Some(min_vruntime));
+        }
+    }
+
+    /// 在亲和力 `affinity` 允许的 CPU 中选择负载最小的一个，负载相同时优先选择任务上一次所在的 CPU
+    fn select_hart(&self, affinity: usize, last: usize) -> usize {
+        let mut best = None;
+        for hart in (0..CPU_NUM).filter(|hart| affinity.get_bit(*hart)) {
+            let len = self.run_queues[hart].lock().len();
+            match best {
+                Some((_, best_len)) if len > best_len => {}
+                Some((_, best_len)) if len == best_len && hart != last => {}
+                _ => best = Some((hart, len)),
+            }
+        }
+        best.map_or(last, |(hart, _)| hart)
+    }
+
+    /// 从当前 CPU 的运行队列中选取下一个要运行的任务，队列为空时尝试从其它 CPU 迁移任务
+    pub fn pick_next_task(&self) -> Option<Arc<Task>> {
+        let hart = arch::hart_id();
+        let task = self.run_queues[hart].lock().pick_next();
+        task.or_else(|| {
+            self.pull_task(hart, 0);
+            self.run_queues[hart].lock().pick_next()
+        })
+    }
 
-impl ScheduleHart for ScheduleHartImpl {
-    fn hart_id() -> usize {
-        arch::hart_id()
+    /// 当前 CPU 的运行队列中是否有优先级高于 `priority` 的实时任务
+    pub fn has_higher_rt(&self, priority: u8) -> bool {
+        self.run_queues[arch::hart_id()]
+            .lock()
+            .has_higher_rt(priority)
+    }
+
+    /// 周期性的负载均衡，由时钟中断调用
+    ///
+    /// 当负载最大的运行队列比当前 CPU 的运行队列多出至少两个任务时，从中迁移一个任务到当前 CPU。
+    pub fn load_balance(&self) {
+        let hart = arch::hart_id();
+        if self.ticks[hart].fetch_add(1, Ordering::Relaxed) % BALANCE_INTERVAL != 0 {
+            return;
+        }
+        let local = self.run_queues[hart].lock().len();
+        self.pull_task(hart, local + 1);
+    }
+
+    /// 从负载最大且任务数大于 `threshold` 的运行队列中迁移一个允许在 `hart` 上运行的任务
+    ///
+    /// 为了避免死锁，任何时候都只持有一个运行队列的锁。
+    fn pull_task(&self, hart: usize, threshold: usize) {
+        let busiest = (0..CPU_NUM)
+            .filter(|other| *other != hart)
+            .map(|other| (other, self.run_queues[other].lock().len()))
+            .filter(|(_, len)| *len > threshold)
+            .max_by_key(|(_, len)| *len);
+        if let Some((busiest, _)) = busiest {
+            let (task, min_vruntime) = {
+                let mut run_queue = self.run_queues[busiest].lock();
+                (run_queue.steal(hart), run_queue.min_vruntime())
+            };
+            if let Some(task) = task {
+                self.run_queues[hart]
+                    .lock()
+                    .enqueue(task, Some(min_vruntime));
+            }
+        }
     }
 }
+
 /// 多核调度器
-pub static GLOBAL_TASK_MANAGER: Lazy<
-    FifoSmpScheduler<CPU_NUM, Arc<Task>, Mutex<()>, ScheduleHartImpl>,
-> = Lazy::new(|| FifoSmpScheduler::new());
+pub static GLOBAL_TASK_MANAGER: Lazy<TaskManager> = Lazy::new(|| TaskManager::new());
 
 /// 获取当前 cpu 的信息
 pub fn current_cpu() -> &'static mut CPU {
@@ -121,12 +282,17 @@ pub fn current_trap_frame() -> &'static mut TrapFrame {
 /// `exit_code`中的值，将会在其父进程调用[`wait4`]时，作为信息传递给父进程。
 /// 当一个具有子进程的进程终止时，其所有子进程将转交至init进程，由init进程完成其子进程相关资源的回收。
 /// 当`clear_child_tid`不为0时，会将`clear_child_tid`该处的值置为0，同时内核唤醒当前正在等待的futex。
+/// 进程通过[`set_robust_list`](crate::ipc::set_robust_list)注册的robust锁列表中仍被其持有的锁，也会在退出时被释放。
 ///
 /// 当调用该函数的进程为`pid==0`的init进程时，将直接调用`system_shutdown`使得内核终止。
 #[syscall_func(93)]
 pub fn do_exit(exit_code: i32, exit_group: u8) -> isize {
+    exit_with_status((exit_code & 0xff) << 8, exit_group)
+}
+
+/// 以 `wait` 状态 `status` 终止当前线程，`status` 的编码与 [`wait4`] 返回给父进程的相同
+fn exit_with_status(exit_code: i32, exit_group: u8) -> isize {
     let task = current_task().unwrap();
-    let exit_code = (exit_code & 0xff) << 8;
     if task.get_pid() == 1 {
         println!("Init process exit with code {}", exit_code);
         system_shutdown();
@@ -144,16 +310,17 @@ pub fn do_exit(exit_code: i32, exit_group: u8) -> isize {
     task.update_state(TaskState::Zombie);
     task.update_exit_code(exit_code);
     global_logoff_signals(task.get_tid() as usize);
+    futex::exit_robust_list(task);
     // 回收一些物理页，不然等到wait系统调用真正进行回收时，可能会出现OOM
     // 在这里还不能回收内核栈页，因为还需要用到内核栈页来执行下面的代码
     task.pre_recycle();
     info!("pre recycle done");
     let clear_child_tid = task.clear_child_tid();
     if clear_child_tid != 0 {
-        let phy_addr = task.transfer_raw_ptr(clear_child_tid as *mut usize);
+        let phy_addr = task.transfer_raw_ptr(clear_child_tid as *mut u32);
         *phy_addr = 0;
         info!("exit wake futex on {:#x}", clear_child_tid);
-        futex(clear_child_tid, FutexOp::FutexWake as u32, 1, 0, 0, 0);
+        let _ = futex::futex_wake(task, clear_child_tid, false, 1, FUTEX_BITSET_MATCH_ANY);
     } else {
         info!("exit clear_child_tid is 0");
     }
@@ -161,18 +328,42 @@ pub fn do_exit(exit_code: i32, exit_group: u8) -> isize {
     0
 }
 
-/// 一个系统调用，退出当前进程(进程组)下的所有线程(进程)。
+/// 一个系统调用，退出当前进程(线程组)下的所有线程。
 ///
-/// 目前该系统调用直接调用[`do_exit`]，有关进程组的相关功能有待实现。
+/// 线程组中的其它线程会被标记为需要退出，并在下一次从内核态返回用户态之前通过 [`check_exit_group`] 以相同的
+/// 返回值退出，当前线程则直接调用 [`do_exit`] 退出。
 #[syscall_func(94)]
 pub fn exit_group(exit_code: i32) -> isize {
-    do_exit(exit_code, 0)
+    group_exit((exit_code & 0xff) << 8)
+}
+
+/// 因信号 `sig` 终止当前进程(线程组)下的所有线程，父进程通过 [`wait4`] 将得知该进程被信号终止
+pub fn exit_group_by_signal(sig: SignalNumber) -> isize {
+    group_exit(sig as i32)
+}
+
+fn group_exit(status: i32) -> isize {
+    let task = current_task().unwrap();
+    get_thread_group(task.get_pid() as usize)
+        .into_iter()
+        .filter(|thread| thread.get_tid() != task.get_tid())
+        .for_each(|thread| {
+            let mut inner = thread.access_inner();
+            inner.exit_group = true;
+            inner.exit_code = status;
+        });
+    exit_with_status(status, 0)
 }
 
+/// 检查当前线程是否因为线程组中的其它线程调用了 [`exit_group`] 而需要退出
 pub fn check_exit_group() {
     let task = current_task().unwrap();
-    if task.access_inner().exit_group {
-        do_exit(0, 1);
+    let (exit_group, exit_code) = {
+        let inner = task.access_inner();
+        (inner.exit_group, inner.exit_code)
+    };
+    if exit_group {
+        exit_with_status(exit_code, 0);
     }
 }
 
@@ -187,22 +378,100 @@ pub fn do_suspend() -> isize {
     0
 }
 
-/// (待实现)设置进程组的id。目前直接返回0。
+/// 一个系统调用，将进程 `pid` 加入进程组 `pgid`。
+///
+/// `pid` 为 0 时表示当前进程，`pgid` 为 0 时表示使用 `pid` 作为进程组号，即创建一个新的进程组。
+/// 只能修改当前进程或者当前进程的子进程，且目标进程与当前进程必须位于同一个会话中；会话的首进程不能修改进程组，
+/// 加入一个已经存在的进程组时，该进程组必须位于同一个会话中。
+///
+/// Reference: [setpgid](https://man7.org/linux/man-pages/man2/setpgid.2.html)
 #[syscall_func(154)]
-pub fn set_pgid() -> isize {
-    0
+pub fn set_pgid(pid: isize, pgid: isize) -> AlienResult<isize> {
+    if pid < 0 || pgid < 0 {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let pid = if pid == 0 {
+        task.get_pid() as usize
+    } else {
+        pid as usize
+    };
+    let pgid = if pgid == 0 { pid } else { pgid as usize };
+    let target = get_task_from_tid(pid)
+        .filter(|target| target.get_pid() as usize == pid)
+        .ok_or(LinuxErrno::ESRCH)?;
+    let sid = task.get_sid();
+    if target.get_pid() != task.get_pid() {
+        let is_child = target
+            .access_inner()
+            .parent
+            .as_ref()
+            .and_then(|parent| parent.upgrade())
+            .map_or(false, |parent| parent.get_pid() == task.get_pid());
+        if !is_child {
+            return Err(LinuxErrno::ESRCH);
+        }
+        if target.get_sid() != sid {
+            return Err(LinuxErrno::EPERM);
+        }
+    }
+    if target.get_sid() == pid {
+        return Err(LinuxErrno::EPERM);
+    }
+    if pgid != pid
+        && !get_process_group(pgid)
+            .iter()
+            .any(|process| process.get_sid() == sid)
+    {
+        return Err(LinuxErrno::EPERM);
+    }
+    get_thread_group(pid)
+        .iter()
+        .for_each(|thread| thread.access_inner().pgid = pgid);
+    Ok(0)
 }
 
-/// (待实现)获取进程组的id。目前直接返回0。
+/// 一个系统调用，获取进程 `pid` 的进程组号，`pid` 为 0 时表示当前进程。
 #[syscall_func(155)]
-pub fn get_pgid() -> isize {
-    0
+pub fn get_pgid(pid: usize) -> AlienResult<isize> {
+    let task = find_process(pid)?;
+    Ok(task.get_pgid() as isize)
+}
+
+/// 一个系统调用，获取进程 `pid` 所在的会话号，`pid` 为 0 时表示当前进程。
+#[syscall_func(156)]
+pub fn get_sid(pid: usize) -> AlienResult<isize> {
+    let task = find_process(pid)?;
+    Ok(task.get_sid() as isize)
 }
 
-/// 创建一个新的session，并使得使用系统调用的当前task成为新session的leader，同时也是新进程组的leader(待实现)
+/// 一个系统调用，创建一个新的会话，并使当前进程成为新会话和新进程组的首进程。新的会话没有控制终端。
+///
+/// 如果当前进程已经是某个进程组的首进程，返回 `EPERM`。成功时返回新的会话号。
 #[syscall_func(157)]
-pub fn set_sid() -> isize {
-    0
+pub fn set_sid() -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let pid = task.get_pid() as usize;
+    if !get_process_group(pid).is_empty() {
+        return Err(LinuxErrno::EPERM);
+    }
+    get_thread_group(pid).iter().for_each(|thread| {
+        let mut inner = thread.access_inner();
+        inner.pgid = pid;
+        inner.sid = pid;
+    });
+    Ok(pid as isize)
+}
+
+/// 获取进程号为 `pid` 的进程，`pid` 为 0 时表示当前进程
+fn find_process(pid: usize) -> AlienResult<Arc<Task>> {
+    let task = current_task().unwrap();
+    if pid == 0 {
+        return Ok(task.clone());
+    }
+    get_task_from_tid(pid)
+        .filter(|task| task.get_pid() as usize == pid)
+        .ok_or(LinuxErrno::ESRCH)
 }
 
 /// 获取当前正在运行task的pid号。在Alien中pid作为线程组的标识符，位于同一线程组中的线程的pid相同。
@@ -224,28 +493,149 @@ pub fn get_ppid() -> isize {
     }
 }
 
-/// (待实现)获取用户 id。在实现多用户权限前默认为最高权限。目前直接返回0。
+/// 获取实际用户号。
 #[syscall_func(174)]
 pub fn getuid() -> isize {
-    0
+    current_task().unwrap().access_inner().cred.ruid as isize
 }
 
-/// (待实现)获取有效用户 id，即相当于哪个用户的权限。在实现多用户权限前默认为最高权限。目前直接返回0。
+/// 获取有效用户号，权限检查时使用有效用户号。
 #[syscall_func(175)]
 pub fn geteuid() -> isize {
-    0
+    current_task().unwrap().access_inner().cred.euid as isize
 }
 
-/// (待实现)获取用户组 id。在实现多用户权限前默认为最高权限。目前直接返回0。
+/// 获取实际用户组号。
 #[syscall_func(176)]
 pub fn getgid() -> isize {
-    0
+    current_task().unwrap().access_inner().cred.rgid as isize
 }
 
-/// (待实现)获取有效用户组 id，即相当于哪个用户组的权限。在实现多用户组权限前默认为最高权限。目前直接返回0。
+/// 获取有效用户组号，权限检查时使用有效用户组号。
 #[syscall_func(177)]
 pub fn getegid() -> isize {
-    0
+    current_task().unwrap().access_inner().cred.egid as isize
+}
+
+/// 一个系统调用，设置当前任务的用户号。
+///
+/// 超级用户会同时设置实际、有效和保存的用户号；普通用户只能将有效用户号设置为实际或保存的用户号，否则返回 `EPERM`。
+///
+/// Reference: [setuid](https://man7.org/linux/man-pages/man2/setuid.2.html)
+#[syscall_func(146)]
+pub fn setuid(uid: u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    task.access_inner().cred.set_uid(uid)?;
+    Ok(0)
+}
+
+/// 一个系统调用，设置当前任务的用户组号，规则与 [`setuid`] 相同。
+#[syscall_func(144)]
+pub fn setgid(gid: u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    task.access_inner().cred.set_gid(gid)?;
+    Ok(0)
+}
+
+/// 一个系统调用，设置当前任务的实际和有效用户号，值为 -1 时表示不修改。
+///
+/// Reference: [setreuid](https://man7.org/linux/man-pages/man2/setreuid.2.html)
+#[syscall_func(145)]
+pub fn setreuid(ruid: u32, euid: u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    task.access_inner().cred.set_reuid(ruid, euid)?;
+    Ok(0)
+}
+
+/// 一个系统调用，设置当前任务的实际和有效用户组号，值为 -1 时表示不修改。
+#[syscall_func(143)]
+pub fn setregid(rgid: u32, egid: u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    task.access_inner().cred.set_regid(rgid, egid)?;
+    Ok(0)
+}
+
+/// 一个系统调用，设置当前任务的实际、有效和保存的用户号，值为 -1 时表示不修改。
+///
+/// Reference: [setresuid](https://man7.org/linux/man-pages/man2/setresuid.2.html)
+#[syscall_func(147)]
+pub fn setresuid(ruid: u32, euid: u32, suid: u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    task.access_inner().cred.set_resuid(ruid, euid, suid)?;
+    Ok(0)
+}
+
+/// 一个系统调用，获取当前任务的实际、有效和保存的用户号。
+#[syscall_func(148)]
+pub fn getresuid(ruid: *mut u32, euid: *mut u32, suid: *mut u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let cred = task.credentials();
+    let mut inner = task.access_inner();
+    inner.copy_to_user(&cred.ruid, ruid);
+    inner.copy_to_user(&cred.euid, euid);
+    inner.copy_to_user(&cred.suid, suid);
+    Ok(0)
+}
+
+/// 一个系统调用，设置当前任务的实际、有效和保存的用户组号，值为 -1 时表示不修改。
+#[syscall_func(149)]
+pub fn setresgid(rgid: u32, egid: u32, sgid: u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    task.access_inner().cred.set_resgid(rgid, egid, sgid)?;
+    Ok(0)
+}
+
+/// 一个系统调用，获取当前任务的实际、有效和保存的用户组号。
+#[syscall_func(150)]
+pub fn getresgid(rgid: *mut u32, egid: *mut u32, sgid: *mut u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let cred = task.credentials();
+    let mut inner = task.access_inner();
+    inner.copy_to_user(&cred.rgid, rgid);
+    inner.copy_to_user(&cred.egid, egid);
+    inner.copy_to_user(&cred.sgid, sgid);
+    Ok(0)
+}
+
+/// 一个系统调用，获取当前任务的附加组列表。
+///
+/// `size` 为 0 时只返回附加组的数量；`size` 小于附加组的数量时返回 `EINVAL`。
+///
+/// Reference: [getgroups](https://man7.org/linux/man-pages/man2/getgroups.2.html)
+#[syscall_func(158)]
+pub fn getgroups(size: usize, list: *mut u32) -> AlienResult<isize> {
+    let task = current_task().unwrap();
+    let groups = task.credentials().groups;
+    if size == 0 {
+        return Ok(groups.len() as isize);
+    }
+    if size < groups.len() {
+        return Err(LinuxErrno::EINVAL);
+    }
+    if !groups.is_empty() {
+        task.access_inner()
+            .copy_to_user_buffer(groups.as_ptr(), list, groups.len());
+    }
+    Ok(groups.len() as isize)
+}
+
+/// 一个系统调用，设置当前任务的附加组列表，只有超级用户可以调用。
+#[syscall_func(159)]
+pub fn setgroups(size: usize, list: *const u32) -> AlienResult<isize> {
+    if size > NGROUPS_MAX {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    if !inner.cred.is_root() {
+        return Err(LinuxErrno::EPERM);
+    }
+    let mut groups = vec![0u32; size];
+    if size > 0 {
+        inner.copy_from_user_buffer(list, groups.as_mut_ptr(), size);
+    }
+    inner.cred.groups = groups;
+    Ok(0)
 }
 
 /// 获取当前正在运行task的tid号。在Alien中tid作为task的唯一标识符。
@@ -268,33 +658,138 @@ pub fn get_tid() -> isize {
 /// `tls`用于为子进程创建新的TLS(thread-local storage)值，在flag包含`CLONE_SETTLS`时才会实际产生效果。
 /// `ctid`用于给子进程中的[`set_child_tid`]和[`clear_child_tid`]赋值(分别在flag中包含`CLONE_CHILD_SETTID`和`CLONE_CHILD_CLEARTID`时产生效果)。
 ///
-/// 成功创建子进程后父进程会返回子进程的tid号，子进程的返回值将被设置为0；否则返回-1。
+/// 成功创建子进程后父进程会返回子进程的tid号，子进程的返回值将被设置为0。
+/// 当前实际用户拥有的任务数量达到 `RLIMIT_NPROC` 的软限制时(超级用户除外)，或者无法分配新的任务时，返回`EAGAIN`。
+///
+/// flag包含`CLONE_VFORK`时，父进程会被挂起，直到子进程执行`execve`或者退出。
+/// flag包含`CLONE_PIDFD`时，会在父进程中创建一个指向子进程的pidfd(见[`PidFd`])，并将其文件描述符写入`ptid`处。
+/// flag组合非法，或者包含尚未支持的命名空间相关的flag时，返回`EINVAL`。
 ///
 /// Reference: [clone](https://www.man7.org/linux/man-pages/man2/clone.2.html)
 #[syscall_func(220)]
-pub fn clone(flag: usize, stack: usize, ptid: usize, tls: usize, ctid: usize) -> isize {
+pub fn clone(
+    flag: usize,
+    stack: usize,
+    ptid: usize,
+    tls: usize,
+    ctid: usize,
+) -> AlienResult<isize> {
     let clone_flag = CloneFlags::from_bits_truncate(flag as u32);
+    check_clone_flags(flag, clone_flag)?;
     // check whether flag include signal
     let sig = flag & 0xff;
-    let sig = SignalNumber::try_from(sig as u8).unwrap();
-    let mut task = current_task().unwrap();
-
-    let child_num = task.access_inner().children.len();
-    if child_num >= 10 {
-        do_suspend();
-        task = current_task().unwrap();
-    }
-    let new_task = task.t_clone(clone_flag, stack, sig, ptid, tls, ctid);
-    if new_task.is_none() {
-        return -1;
+    let sig = SignalNumber::try_from(sig as u8).map_err(|_| LinuxErrno::EINVAL)?;
+    let task = current_task().unwrap();
+    let cred = task.credentials();
+    let nproc = task
+        .access_inner()
+        .get_prlimit(RLimitResource::RlimitNproc)
+        .rlim_cur;
+    if !cred.is_root() && count_user_tasks(cred.ruid) as u64 >= nproc {
+        return Err(LinuxErrno::EAGAIN);
     }
-    let new_task = new_task.unwrap();
+    // 先占用 pidfd 的文件描述符，避免创建子进程之后才发现没有可用的文件描述符
+    let pidfd = if flag & CLONE_PIDFD != 0 {
+        let fd = task
+            .add_file(Arc::new(PidFd::empty()))
+            .map_err(|_| LinuxErrno::EMFILE)?;
+        Some(fd)
+    } else {
+        None
+    };
+    let Some(new_task) = task.t_clone(clone_flag, stack, sig, tls, ctid) else {
+        if let Some(fd) = pidfd {
+            let _ = task.remove_file(fd);
+        }
+        return Err(LinuxErrno::EAGAIN);
+    };
     // update return value
     let trap_frame = new_task.trap_frame();
     trap_frame.update_res(0);
     let tid = new_task.get_tid();
-    GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(new_task)));
-    tid
+    if let Some(fd) = pidfd {
+        // 子进程不应该继承父进程中指向自己的 pidfd
+        if !clone_flag.contains(CloneFlags::CLONE_FILES) {
+            let _ = new_task.remove_file(fd);
+        }
+        let pidfd = PidFd::new(&new_task, OpenFlags::empty());
+        let _ = task.add_file_with_fd(Arc::new(pidfd), fd);
+        *task.transfer_raw_ptr(ptid as *mut i32) = fd as i32;
+    } else if clone_flag.contains(CloneFlags::CLONE_PARENT_SETTID) {
+        *task.transfer_raw_ptr(ptid as *mut i32) = tid as i32;
+    }
+    let thread_number = new_task.access_inner().thread_number;
+    let vfork_child = clone_flag
+        .contains(CloneFlags::CLONE_VFORK)
+        .then(|| new_task.clone());
+    GLOBAL_TASK_MANAGER.add_task(new_task);
+    if let Some(child) = vfork_child {
+        wait_vfork_done(&task, child, thread_number);
+    }
+    Ok(tid)
+}
+
+/// `clone` 中的 `CLONE_PIDFD` 标志位
+const CLONE_PIDFD: usize = 0x1000;
+
+/// 检查 `clone` 的 flag 组合是否合法
+fn check_clone_flags(flag: usize, clone_flag: CloneFlags) -> AlienResult<()> {
+    let namespaces = CloneFlags::CLONE_NEWNS
+        | CloneFlags::CLONE_NEWCGROUP
+        | CloneFlags::CLONE_NEWUTS
+        | CloneFlags::CLONE_NEWIPC
+        | CloneFlags::CLONE_NEWUSER
+        | CloneFlags::CLONE_NEWPID
+        | CloneFlags::CLONE_NEWNET;
+    if clone_flag.intersects(namespaces) {
+        return Err(LinuxErrno::EINVAL);
+    }
+    if clone_flag.contains(CloneFlags::CLONE_THREAD)
+        && !clone_flag.contains(CloneFlags::CLONE_SIGHAND)
+    {
+        return Err(LinuxErrno::EINVAL);
+    }
+    if clone_flag.contains(CloneFlags::CLONE_SIGHAND) && !clone_flag.contains(CloneFlags::CLONE_VM)
+    {
+        return Err(LinuxErrno::EINVAL);
+    }
+    // pidfd 和父进程中的 tid 都保存在 ptid 处，且 pidfd 只能指向进程
+    if flag & CLONE_PIDFD != 0
+        && clone_flag.intersects(CloneFlags::CLONE_THREAD | CloneFlags::CLONE_PARENT_SETTID)
+    {
+        return Err(LinuxErrno::EINVAL);
+    }
+    Ok(())
+}
+
+/// 等待以 `CLONE_VFORK` 创建的子进程执行 `execve` 或者退出。
+///
+/// 子进程与父进程共享地址空间时使用了父进程的 trap 上下文槽位 `thread_number`，此时子进程已经不再使用它，可以释放。
+///
+/// 与 Linux 一致，等待只会被 `SIGKILL` 打断，此时父进程直接退出，子进程仍然使用的槽位不会被释放；
+/// 其它信号在子进程执行 `execve` 或者退出之后再处理。
+fn wait_vfork_done(task: &Arc<Task>, child: Arc<Task>, thread_number: usize) {
+    let mut killed = false;
+    // 子进程退出时在 pre_recycle 中释放 trap 上下文，之后才会进入 Terminated 状态
+    task.child_wait.wait_event(|| {
+        let done = {
+            let inner = child.access_inner();
+            inner.vfork_done || inner.state == TaskState::Terminated
+        };
+        killed = !done
+            && task
+                .access_inner()
+                .signal_receivers
+                .lock()
+                .check_signal(SignalNumber::SIGKILL as usize);
+        done || killed
+    });
+    if killed {
+        exit_group_by_signal(SignalNumber::SIGKILL);
+    }
+    if thread_number != 0 {
+        let _ = task.access_inner().threads.remove(thread_number - 1);
+    }
 }
 
 /// 一个系统调用，用于执行一个文件。
@@ -303,6 +798,9 @@ pub fn clone(flag: usize, stack: usize, ptid: usize, tls: usize, ctid: usize) ->
 /// `args_ptr`用于指明保存启动可执行文件时要传入的参数的地址。
 /// `env`用于指明保存相关环境变量的地址。
 ///
+/// 执行文件需要对文件有执行权限，否则返回 `EACCES`。如果文件设置了 set-user-ID/set-group-ID 位，
+/// 当前任务的有效用户号/有效用户组号会被设置为文件的属主/属组。
+///
 /// 成功执行文件后会返回0；否则会返回-1或错误类型。
 #[syscall_func(221)]
 pub fn do_exec(path: *const u8, args_ptr: usize, env: usize) -> AlienResult<isize> {
@@ -325,11 +823,21 @@ pub fn do_exec(path: *const u8, args_ptr: usize, env: usize) -> AlienResult<isiz
     if path_str.contains("libc-bench") {
         path_str = "libc-bench2".to_string();
     }
+    let inode = user_path_at(AT_FDCWD, &path_str)?.open(None)?.inode()?;
+    if inode.inode_type() != VfsNodeType::File {
+        return Err(AlienError::EACCES);
+    }
+    inode_permission(&inode, MAY_EXEC)?;
+    let owner = inode_owner(&inode)?;
     if fs::read_all(&path_str, &mut data) {
         let res = task.exec(&path_str, data.as_slice(), args, envs);
         if res.is_err() {
             return Err(AlienError::ENOEXEC);
         }
+        // set-group-ID 位只有在属组有执行权限时才生效
+        let setuid = (owner.mode & S_ISUID != 0).then_some(owner.uid);
+        let setgid = (owner.mode & S_ISGID != 0 && owner.mode & 0o010 != 0).then_some(owner.gid);
+        task.access_inner().cred.exec(setuid, setgid);
         Ok(0)
     } else {
         info!("exec {} failed", path_str);
@@ -337,54 +845,341 @@ pub fn do_exec(path: *const u8, args_ptr: usize, env: usize) -> AlienResult<isiz
     }
 }
 
-/// 一个系统调用，用于父进程等待某子进程退出。
+/// `wait4`/`waitid` 中目前被忽略的 `__WNOTHREAD`、`__WALL` 和 `__WCLONE` 选项
+const WAIT_THREAD_OPTIONS: u32 = 0xe000_0000;
+
+/// `waitid` 中 `idtype` 的取值
+const P_ALL: usize = 0;
+const P_PID: usize = 1;
+const P_PGID: usize = 2;
+const P_PIDFD: usize = 3;
+
+/// `siginfo_t` 中 `si_code` 的取值，说明子进程状态变化的原因
+const CLD_EXITED: i32 = 1;
+const CLD_KILLED: i32 = 2;
+const CLD_STOPPED: i32 = 5;
+const CLD_CONTINUED: i32 = 6;
+
+/// `waitid` 返回给用户的 `siginfo_t`，只包含与 `SIGCHLD` 相关的字段，大小与 `linux` 中的相同
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+struct ChildSigInfo {
+    si_signo: i32,
+    si_errno: i32,
+    si_code: i32,
+    _pad: i32,
+    si_pid: i32,
+    si_uid: u32,
+    si_status: i32,
+    _rest: [u8; 100],
+}
+
+impl ChildSigInfo {
+    /// 没有子进程状态发生变化时返回全 0 的结构
+    fn empty() -> Self {
+        Self {
+            si_signo: 0,
+            si_errno: 0,
+            si_code: 0,
+            _pad: 0,
+            si_pid: 0,
+            si_uid: 0,
+            si_status: 0,
+            _rest: [0; 100],
+        }
+    }
+}
+
+/// 父进程等待的子进程范围
+#[derive(Debug, Clone, Copy)]
+enum WaitTarget {
+    /// 任意子进程
+    Any,
+    /// 进程号为 `pid` 的子进程
+    Pid(usize),
+    /// 进程组号为 `pgid` 的子进程
+    Group(usize),
+}
+
+impl WaitTarget {
+    fn matches(&self, child: &Task) -> bool {
+        match *self {
+            WaitTarget::Any => true,
+            WaitTarget::Pid(pid) => child.get_pid() as usize == pid,
+            WaitTarget::Group(pgid) => child.get_pgid() == pgid,
+        }
+    }
+}
+
+/// 子进程的状态变化
+#[derive(Debug, Clone, Copy)]
+enum WaitEvent {
+    /// 子进程退出，记录其退出状态
+    Exited(i32),
+    /// 子进程因信号停止
+    Stopped(SignalNumber),
+    /// 子进程因 `SIGCONT` 恢复执行
+    Continued,
+}
+
+impl WaitEvent {
+    /// `wait4` 返回给用户的状态，编码与 `linux` 中的 `WIFEXITED`/`WIFSIGNALED`/`WIFSTOPPED`/`WIFCONTINUED` 对应
+    fn status(&self) -> i32 {
+        match *self {
+            WaitEvent::Exited(status) => status,
+            WaitEvent::Stopped(sig) => ((sig as i32) << 8) | 0x7f,
+            WaitEvent::Continued => 0xffff,
+        }
+    }
+
+    /// `waitid` 返回给用户的 `si_code` 和 `si_status`
+    fn code_and_status(&self) -> (i32, i32) {
+        match *self {
+            WaitEvent::Exited(status) if status & 0x7f == 0 => (CLD_EXITED, (status >> 8) & 0xff),
+            WaitEvent::Exited(status) => (CLD_KILLED, status & 0x7f),
+            WaitEvent::Stopped(sig) => (CLD_STOPPED, sig as i32),
+            WaitEvent::Continued => (CLD_CONTINUED, SignalNumber::SIGCONT as i32),
+        }
+    }
+}
+
+/// 等待到的子进程状态变化
+struct WaitResult {
+    pid: isize,
+    /// 子进程的实际用户号
+    uid: u32,
+    event: WaitEvent,
+    /// 子进程以及其已经回收的子进程在用户态下的运行时间
+    utime: usize,
+    /// 子进程以及其已经回收的子进程在内核态下的运行时间
+    stime: usize,
+}
+
+impl WaitResult {
+    fn rusage(&self) -> Rusage {
+        let mut usage = Rusage::default();
+        usage.ru_utime = TimeVal::from_freq(self.utime);
+        usage.ru_stime = TimeVal::from_freq(self.stime);
+        usage
+    }
+
+    fn siginfo(&self) -> ChildSigInfo {
+        let (code, status) = self.event.code_and_status();
+        ChildSigInfo {
+            si_signo: SignalNumber::SIGCHLD as i32,
+            si_code: code,
+            si_pid: self.pid as i32,
+            si_uid: self.uid,
+            si_status: status,
+            ..ChildSigInfo::empty()
+        }
+    }
+}
+
+/// 检查子进程 `child` 是否有 `options` 关心的状态变化。没有设置 `WNOWAIT` 时，停止和继续执行事件会被取走
+fn poll_child(child: &Arc<Task>, options: WaitOptions) -> Option<WaitResult> {
+    let event = if child.state() == TaskState::Terminated {
+        if !options.contains(WaitOptions::WEXITED) {
+            return None;
+        }
+        WaitEvent::Exited(child.exit_code())
+    } else {
+        let mut inner = child.access_inner();
+        let event = match inner.job_status? {
+            JobStatus::Stopped(sig) if options.contains(WaitOptions::WUNTRACED) => {
+                WaitEvent::Stopped(sig)
+            }
+            JobStatus::Continued if options.contains(WaitOptions::WCONTINUED) => {
+                WaitEvent::Continued
+            }
+            _ => return None,
+        };
+        if !options.contains(WaitOptions::WNOWAIT) {
+            inner.job_status = None;
+        }
+        event
+    };
+    let inner = child.access_inner();
+    let data = inner.statistical_data();
+    Some(WaitResult {
+        pid: child.get_pid(),
+        uid: inner.cred.ruid,
+        event,
+        utime: data.tms_utime + data.tms_cutime,
+        stime: data.tms_stime + data.tms_cstime,
+    })
+}
+
+/// [`wait4`] 和 [`waitid`] 的公共部分，等待 `target` 范围内的子进程发生 `options` 关心的状态变化。
+///
+/// 没有满足条件的子进程时返回 `ECHILD`；设置了 `WNOHANG` 且没有子进程发生状态变化时返回 `None`；
+/// 阻塞期间收到不会被忽略的信号时返回 `EINTR`。
+///
+/// 子进程退出且没有设置 `WNOWAIT` 时，子进程会被回收，其运行时间累加到当前任务的 `tms_cutime`/`tms_cstime` 中。
+fn do_wait(target: WaitTarget, options: WaitOptions) -> AlienResult<Option<WaitResult>> {
+    let task = current_task().unwrap().clone();
+    loop {
+        // 在检查子进程之前记录状态变化的计数，检查之后发生的变化会使等待立即结束
+        let event = task.child_event.load(Ordering::Acquire);
+        let found = {
+            let children = task
+                .children()
+                .into_iter()
+                .filter(|child| child.get_tid() == child.get_pid() && target.matches(child))
+                .collect::<Vec<_>>();
+            if children.is_empty() {
+                return Err(LinuxErrno::ECHILD);
+            }
+            children.iter().find_map(|child| poll_child(child, options))
+        };
+        if let Some(result) = found {
+            if let WaitEvent::Exited(_) = result.event {
+                if !options.contains(WaitOptions::WNOWAIT) {
+                    let child = task.remove_child_by_tid(result.pid).unwrap();
+                    assert_eq!(
+                        Arc::strong_count(&child),
+                        1,
+                        "Father is [{}-{}], wait task is [{}-{}]",
+                        task.get_pid(),
+                        task.get_tid(),
+                        child.get_pid(),
+                        child.get_tid()
+                    );
+                    let mut inner = task.access_inner();
+                    inner.statistical_data.tms_cutime += result.utime;
+                    inner.statistical_data.tms_cstime += result.stime;
+                }
+            }
+            return Ok(Some(result));
+        }
+        if options.contains(WaitOptions::WNOHANG) {
+            return Ok(None);
+        }
+        task.child_wait.wait_event_interruptible(|| {
+            // 被忽略的 SIGCHLD 不应该打断等待
+            discard_ignored_signal(&task, SignalNumber::SIGCHLD);
+            task.child_event.load(Ordering::Acquire) != event
+        })?;
+    }
+}
+
+/// 一个系统调用，用于父进程等待某子进程的状态发生变化。
 ///
-/// `pid`用于指明等待的子进程pid号。`pid == -1`表示父进程等待任意子进程返回。
-/// 当`exit_code`非空时，将会把退出的子程序的退出值赋给`exit_code`所指向的位置。
-/// `options`主要用于控制`wait4`的执行逻辑，例如当`wait_options`包含`WNOHANG`时，即使未发现子程序返回，函数也将直接返回0。
+/// `pid`用于指明等待的子进程范围：
+/// + `pid > 0`: 等待进程号为 `pid` 的子进程；
+/// + `pid == -1`: 等待任意子进程；
+/// + `pid == 0`: 等待与当前进程位于同一进程组的任意子进程；
+/// + `pid < -1`: 等待进程组号为 `-pid` 的任意子进程。
 ///
-/// 一般`wait4`会使得父进程阻塞，直到子进程退出，返回退出的子进程pid。但当`wait_options`包含`WNOHANG`时，即使未发现子程序返回，函数也将直接返回0。
-/// 当父进程的所有子进程中不包含进程号为pid的子进程，将返回-1。
+/// 当`exit_code`非空时，将会把子进程的状态赋给`exit_code`所指向的位置；当`rusage`非空时，将会把子进程
+/// (包括其已经回收的子进程)的资源使用情况保存到`rusage`所指向的[`Rusage`]结构中。
+///
+/// `options`可以包含以下选项：
+/// + `WNOHANG`: 没有子进程发生状态变化时直接返回0；
+/// + `WUNTRACED`: 同时等待因信号停止的子进程；
+/// + `WCONTINUED`: 同时等待因 `SIGCONT` 恢复执行的子进程。
+///
+/// 成功时返回状态发生变化的子进程pid。当没有满足条件的子进程时返回`ECHILD`，`options`非法时返回`EINVAL`。
 ///
 /// Reference:[wait](https://man7.org/linux/man-pages/man2/wait.2.html)
 #[syscall_func(260)]
-pub fn wait4(pid: isize, exit_code: *mut i32, options: u32, _rusage: *const u8) -> isize {
-    loop {
-        let task = current_task().unwrap();
-        if task
-            .children()
-            .iter()
-            .find(|child| child.get_pid() == pid || pid == -1)
-            .is_none()
-        {
-            return -1;
-        }
-        let res = task.check_child(pid);
-        if let Some(index) = res {
-            let child = task.remove_child(index);
-            assert_eq!(
-                Arc::strong_count(&child),
-                1,
-                "Father is [{}-{}], wait task is [{}-{}]",
-                task.get_pid(),
-                task.get_tid(),
-                child.get_pid(),
-                child.get_tid()
-            );
-            if !exit_code.is_null() {
-                let exit_code_ref = task.transfer_raw_ptr(exit_code);
-                *exit_code_ref = child.exit_code();
-            }
-            return child.get_tid();
-        } else {
-            let wait_options = WaitOptions::from_bits(options).unwrap();
-            if wait_options.contains(WaitOptions::WNOHANG) {
-                return 0;
-            } else {
-                do_suspend();
+pub fn wait4(pid: isize, exit_code: *mut i32, options: u32, rusage: *mut u8) -> AlienResult<isize> {
+    let options =
+        WaitOptions::from_bits(options & !WAIT_THREAD_OPTIONS).ok_or(LinuxErrno::EINVAL)?;
+    if options.intersects(WaitOptions::WEXITED | WaitOptions::WNOWAIT) {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let target = match pid {
+        -1 => WaitTarget::Any,
+        0 => WaitTarget::Group(current_task().unwrap().get_pgid()),
+        pid if pid < 0 => WaitTarget::Group(pid.unsigned_abs()),
+        pid => WaitTarget::Pid(pid as usize),
+    };
+    let result = match do_wait(target, options | WaitOptions::WEXITED)? {
+        Some(result) => result,
+        None => return Ok(0),
+    };
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    if !exit_code.is_null() {
+        inner.copy_to_user(&result.event.status(), exit_code);
+    }
+    if !rusage.is_null() {
+        inner.copy_to_user(&result.rusage(), rusage as *mut Rusage);
+    }
+    Ok(result.pid)
+}
+
+/// 一个系统调用，用于父进程等待某子进程的状态发生变化，与 [`wait4`] 相比可以更精细地选择关心的状态变化。
+///
+/// `idtype`和`id`用于指明等待的子进程范围：`P_ALL`表示任意子进程，`P_PID`表示进程号为`id`的子进程，
+/// `P_PGID`表示进程组号为`id`的子进程，`id`为0时表示当前进程所在的进程组，`P_PIDFD`表示文件描述符`id`所指向的子进程。
+/// 指向子进程的 pidfd 设置了`PIDFD_NONBLOCK`时，子进程还没有发生状态变化会返回`EAGAIN`。
+///
+/// `options`中必须包含`WEXITED`、`WSTOPPED`(与`WUNTRACED`相同)和`WCONTINUED`中的至少一个，
+/// 另外可以包含`WNOHANG`和`WNOWAIT`，后者表示不取走子进程的状态，之后仍然可以再次等待到该状态。
+///
+/// 子进程的状态保存在`infop`所指向的`siginfo_t`结构中，设置了`WNOHANG`且没有子进程发生状态变化时该结构被清零。
+/// 当`rusage`非空时，将会把子进程的资源使用情况保存到`rusage`所指向的[`Rusage`]结构中。
+///
+/// 成功时返回0。当没有满足条件的子进程时返回`ECHILD`，参数非法时返回`EINVAL`。
+///
+/// Reference:[waitid](https://man7.org/linux/man-pages/man2/waitid.2.html)
+#[syscall_func(95)]
+pub fn waitid(
+    idtype: usize,
+    id: usize,
+    infop: *mut u8,
+    options: u32,
+    rusage: *mut u8,
+) -> AlienResult<isize> {
+    let mut options =
+        WaitOptions::from_bits(options & !WAIT_THREAD_OPTIONS).ok_or(LinuxErrno::EINVAL)?;
+    if !options.intersects(WaitOptions::WEXITED | WaitOptions::WUNTRACED | WaitOptions::WCONTINUED)
+    {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let mut nonblock_pidfd = false;
+    let target = match idtype {
+        P_ALL => WaitTarget::Any,
+        P_PID if id > 0 => WaitTarget::Pid(id),
+        P_PGID if id == 0 => WaitTarget::Group(current_task().unwrap().get_pgid()),
+        P_PGID => WaitTarget::Group(id),
+        P_PIDFD => {
+            let file = current_task()
+                .unwrap()
+                .get_file(id)
+                .ok_or(LinuxErrno::EBADF)?;
+            let pidfd = file
+                .downcast_arc::<PidFd>()
+                .map_err(|_| LinuxErrno::EINVAL)?;
+            if pidfd.is_nonblock() && !options.contains(WaitOptions::WNOHANG) {
+                options |= WaitOptions::WNOHANG;
+                nonblock_pidfd = true;
             }
+            WaitTarget::Pid(pidfd.pid())
         }
+        _ => return Err(LinuxErrno::EINVAL),
+    };
+    let result = do_wait(target, options)?;
+    if nonblock_pidfd && result.is_none() {
+        return Err(LinuxErrno::EAGAIN);
     }
+    let task = current_task().unwrap();
+    let mut inner = task.access_inner();
+    if !infop.is_null() {
+        let info = result
+            .as_ref()
+            .map_or(ChildSigInfo::empty(), |result| result.siginfo());
+        inner.copy_to_user(&info, infop as *mut ChildSigInfo);
+    }
+    if !rusage.is_null() {
+        let usage = result
+            .as_ref()
+            .map_or(Rusage::default(), |result| result.rusage());
+        inner.copy_to_user(&usage, rusage as *mut Rusage);
+    }
+    Ok(0)
 }
 
 /// 一个系统调用，用于改变堆区的大小(目前仅可以增加堆区大小)
@@ -392,7 +1187,7 @@ pub fn wait4(pid: isize, exit_code: *mut i32, options: u32, _rusage: *const u8)
 /// `addr`用于指明扩充堆区后，堆区的末尾位置。
 /// 当`addr`所标识的位置在当前堆起始位置的前方，或者堆当前已使用的末尾位置的前方时，将会导致增加堆区大小失败。
 ///
-/// 成功增加堆区大小时，函数返回堆当前已使用的末尾位置；否则返回-1。
+/// 成功增加堆区大小时，函数返回堆当前已使用的末尾位置；因为超过 `RLIMIT_DATA` 或 `RLIMIT_AS` 而失败时返回原来的末尾位置；否则返回-1。
 #[syscall_func(214)]
 pub fn do_brk(addr: usize) -> isize {
     let process = current_task().unwrap();
@@ -407,7 +1202,8 @@ pub fn do_brk(addr: usize) -> isize {
     }
     let res = inner.extend_heap(addr);
     if res.is_err() {
-        return -1;
+        // 超过资源限制等原因导致失败时，返回原来的堆末尾位置
+        return heap_info.current as isize;
     }
     res.unwrap() as isize
 }
@@ -420,42 +1216,81 @@ pub fn set_tid_address(tidptr: usize) -> isize {
     task.get_tid()
 }
 
-/// 一个系统调用，用于修改进程的资源限制。
+/// 一个系统调用，用于获取或修改进程的资源限制。
 ///
 /// 进程对其拥有的资源，包括用户栈大小、可以打开的文件描述符数、用户地址空间大小等都有所上限。
 ///
-/// `prlimit64`则可以根据资源的种类对不同的资源进行大小的限制。针对每一具体限制都包括软上限和硬上限，具体可见[`PrLimit`]。
-/// `pid`用于指明需要修改资源限制的进程的pid号。
-/// `resource`用于指明需要修改的资源类型，可选的值包括`RLIMIT_STACK`、`RLIMIT_NOFILE`、`RLIMIT_AS`等，详情可见[`PrLimitRes`]。
+/// `prlimit64`则可以根据资源的种类对不同的资源进行大小的限制。针对每一具体限制都包括软上限和硬上限，具体可见[`RLimit64`]。
+/// `pid`用于指明需要修改资源限制的进程的pid号，为0时表示当前进程。
+/// `resource`用于指明需要修改的资源类型，可选的值包括`RLIMIT_STACK`、`RLIMIT_NOFILE`、`RLIMIT_AS`等，详情可见[`RLimitResource`]。
 /// `new_limit`用于指明新限制的指针，如果为空指针则不进行新限制的赋值。
 /// `old_limit`用于指明存放旧限制的指针，如果为空则不进行旧限制的保存。
 ///
-/// 正确执行后会返回0；如果输入的pid为0或者为当前正在运行的进程号，则会直接终止。
+/// 正确执行后会返回0。资源类型非法或者新限制的软上限大于硬上限时返回`EINVAL`；进程不存在时返回`ESRCH`；
+/// 普通用户修改其它用户的进程或者提高硬上限时返回`EPERM`。
+///
+/// Reference: [prlimit](https://man7.org/linux/man-pages/man2/prlimit.2.html)
 #[syscall_func(261)]
-pub fn prlimit64(pid: usize, resource: usize, new_limit: *const u8, old_limit: *mut u8) -> isize {
-    assert!(pid == 0 || pid == current_task().unwrap().get_pid() as usize);
+pub fn prlimit64(
+    pid: usize,
+    resource: usize,
+    new_limit: *const u8,
+    old_limit: *mut u8,
+) -> AlienResult<isize> {
+    let resource = RLimitResource::try_from(resource).map_err(|_| LinuxErrno::EINVAL)?;
     let task = current_task().unwrap();
-    let mut inner = task.access_inner();
-    if let Ok(resource) = PrLimitResType::try_from(resource) {
-        if !old_limit.is_null() {
-            let limit = inner.get_prlimit(resource);
-            warn!("get rlimit nofile to {:?}", limit);
-            inner.copy_to_user(&limit, old_limit as *mut RLimit64);
+    let target = find_process(pid)?;
+    let cred = task.credentials();
+    if !cred.is_root() && target.get_pid() != task.get_pid() {
+        let target_cred = target.credentials();
+        if target_cred.ruid != cred.ruid || target_cred.euid != cred.euid {
+            return Err(LinuxErrno::EPERM);
         }
-        match resource {
-            PrLimitResType::RlimitStack => {}
-            PrLimitResType::RlimitNofile => {
-                if !new_limit.is_null() {
-                    let mut limit = RLimit64::new(0, 0);
-                    inner.copy_from_user(new_limit as *const RLimit64, &mut limit);
-                    warn!("set rlimit nofile to {:?}", limit);
-                    inner.set_prlimit(resource, limit);
-                }
-            }
-            PrLimitResType::RlimitAs => {}
+    }
+    let new_limit = if new_limit.is_null() {
+        None
+    } else {
+        let mut limit = RLimit64::new(0, 0);
+        task.access_inner()
+            .copy_from_user(new_limit as *const RLimit64, &mut limit);
+        Some(limit)
+    };
+    let old = target.access_inner().get_prlimit(resource);
+    if let Some(limit) = new_limit {
+        if limit.rlim_cur > limit.rlim_max {
+            return Err(LinuxErrno::EINVAL);
+        }
+        if limit.rlim_max > old.rlim_max && !cred.is_root() {
+            return Err(LinuxErrno::EPERM);
         }
     }
-    0
+    if !old_limit.is_null() {
+        task.access_inner()
+            .copy_to_user(&old, old_limit as *mut RLimit64);
+    }
+    if let Some(limit) = new_limit {
+        info!(
+            "set rlimit {:?} of {} to {:#x}/{:#x}",
+            resource,
+            target.get_pid(),
+            limit.rlim_cur,
+            limit.rlim_max
+        );
+        target.access_inner().set_prlimit(resource, limit);
+    }
+    Ok(0)
+}
+
+/// 一个系统调用，用于获取当前进程的资源限制，等价于 `prlimit64(0, resource, NULL, rlim)`
+#[syscall_func(163)]
+pub fn getrlimit(resource: usize, rlim: *mut u8) -> AlienResult<isize> {
+    prlimit64(0, resource, core::ptr::null(), rlim)
+}
+
+/// 一个系统调用，用于修改当前进程的资源限制，等价于 `prlimit64(0, resource, rlim, NULL)`
+#[syscall_func(164)]
+pub fn setrlimit(resource: usize, rlim: *const u8) -> AlienResult<isize> {
+    prlimit64(0, resource, rlim, core::ptr::null_mut())
 }
 
 /// 用于exec可执行文件时，分别在args_ptr和env_ptr所指向的地址处取出参数和环境变量
@@ -0,0 +1,178 @@
+//! 任务的用户和用户组凭证
+//!
+//! 与 Linux 相同，每个任务拥有实际、有效和保存的用户号与用户组号，以及附加组列表。
+//! 凭证在 `clone` 时被复制，在 `exec` 时保留，执行设置了 set-user-ID/set-group-ID 位的文件时有效用户号/用户组号会被修改。
+use alloc::vec::Vec;
+
+use constants::{AlienResult, LinuxErrno};
+
+/// 附加组的最大数量，对应 `linux` 中的 `NGROUPS_MAX`
+pub const NGROUPS_MAX: usize = 65536;
+
+/// 用户号或用户组号为 -1 时表示不修改
+const ID_UNCHANGED: u32 = u32::MAX;
+
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub ruid: u32,
+    pub euid: u32,
+    pub suid: u32,
+    pub rgid: u32,
+    pub egid: u32,
+    pub sgid: u32,
+    /// 附加组列表
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    /// 超级用户的凭证，初始进程和内核线程使用
+    pub fn root() -> Self {
+        Self {
+            ruid: 0,
+            euid: 0,
+            suid: 0,
+            rgid: 0,
+            egid: 0,
+            sgid: 0,
+            groups: Vec::new(),
+        }
+    }
+
+    /// 有效用户号是否为超级用户
+    pub fn is_root(&self) -> bool {
+        self.euid == 0
+    }
+
+    /// `gid` 是否为有效用户组号或者附加组之一
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.egid == gid || self.groups.contains(&gid)
+    }
+
+    /// `setuid` 的语义：超级用户同时修改实际、有效和保存的用户号，普通用户只能将有效用户号修改为实际或保存的用户号
+    pub fn set_uid(&mut self, uid: u32) -> AlienResult<()> {
+        if self.is_root() {
+            self.ruid = uid;
+            self.suid = uid;
+        } else if uid != self.ruid && uid != self.suid {
+            return Err(LinuxErrno::EPERM);
+        }
+        self.euid = uid;
+        Ok(())
+    }
+
+    /// `setgid` 的语义，与 [`Credentials::set_uid`] 相同
+    pub fn set_gid(&mut self, gid: u32) -> AlienResult<()> {
+        if self.is_root() {
+            self.rgid = gid;
+            self.sgid = gid;
+        } else if gid != self.rgid && gid != self.sgid {
+            return Err(LinuxErrno::EPERM);
+        }
+        self.egid = gid;
+        Ok(())
+    }
+
+    /// `setreuid` 的语义：普通用户只能将实际用户号设置为实际或有效用户号，将有效用户号设置为实际、有效或保存的用户号。
+    ///
+    /// 如果设置了实际用户号，或者有效用户号被设置为与原实际用户号不同的值，保存的用户号被设置为新的有效用户号。
+    pub fn set_reuid(&mut self, ruid: u32, euid: u32) -> AlienResult<()> {
+        if !self.is_root() {
+            if ruid != ID_UNCHANGED && ruid != self.ruid && ruid != self.euid {
+                return Err(LinuxErrno::EPERM);
+            }
+            if euid != ID_UNCHANGED && euid != self.ruid && euid != self.euid && euid != self.suid {
+                return Err(LinuxErrno::EPERM);
+            }
+        }
+        let old_ruid = self.ruid;
+        if ruid != ID_UNCHANGED {
+            self.ruid = ruid;
+        }
+        if euid != ID_UNCHANGED {
+            self.euid = euid;
+        }
+        if ruid != ID_UNCHANGED || (euid != ID_UNCHANGED && euid != old_ruid) {
+            self.suid = self.euid;
+        }
+        Ok(())
+    }
+
+    /// `setregid` 的语义，与 [`Credentials::set_reuid`] 相同
+    pub fn set_regid(&mut self, rgid: u32, egid: u32) -> AlienResult<()> {
+        if !self.is_root() {
+            if rgid != ID_UNCHANGED && rgid != self.rgid && rgid != self.egid {
+                return Err(LinuxErrno::EPERM);
+            }
+            if egid != ID_UNCHANGED && egid != self.rgid && egid != self.egid && egid != self.sgid {
+                return Err(LinuxErrno::EPERM);
+            }
+        }
+        let old_rgid = self.rgid;
+        if rgid != ID_UNCHANGED {
+            self.rgid = rgid;
+        }
+        if egid != ID_UNCHANGED {
+            self.egid = egid;
+        }
+        if rgid != ID_UNCHANGED || (egid != ID_UNCHANGED && egid != old_rgid) {
+            self.sgid = self.egid;
+        }
+        Ok(())
+    }
+
+    /// `setresuid` 的语义：普通用户只能将三个用户号设置为当前实际、有效或保存的用户号之一
+    pub fn set_resuid(&mut self, ruid: u32, euid: u32, suid: u32) -> AlienResult<()> {
+        let ids = [self.ruid, self.euid, self.suid];
+        if !self.is_root()
+            && [ruid, euid, suid]
+                .iter()
+                .any(|id| *id != ID_UNCHANGED && !ids.contains(id))
+        {
+            return Err(LinuxErrno::EPERM);
+        }
+        if ruid != ID_UNCHANGED {
+            self.ruid = ruid;
+        }
+        if euid != ID_UNCHANGED {
+            self.euid = euid;
+        }
+        if suid != ID_UNCHANGED {
+            self.suid = suid;
+        }
+        Ok(())
+    }
+
+    /// `setresgid` 的语义，与 [`Credentials::set_resuid`] 相同
+    pub fn set_resgid(&mut self, rgid: u32, egid: u32, sgid: u32) -> AlienResult<()> {
+        let ids = [self.rgid, self.egid, self.sgid];
+        if !self.is_root()
+            && [rgid, egid, sgid]
+                .iter()
+                .any(|id| *id != ID_UNCHANGED && !ids.contains(id))
+        {
+            return Err(LinuxErrno::EPERM);
+        }
+        if rgid != ID_UNCHANGED {
+            self.rgid = rgid;
+        }
+        if egid != ID_UNCHANGED {
+            self.egid = egid;
+        }
+        if sgid != ID_UNCHANGED {
+            self.sgid = sgid;
+        }
+        Ok(())
+    }
+
+    /// 执行文件时更新凭证，`setuid`/`setgid` 为文件设置了 set-user-ID/set-group-ID 位时的属主，保存的用户号被设置为有效用户号
+    pub fn exec(&mut self, setuid: Option<u32>, setgid: Option<u32>) {
+        if let Some(uid) = setuid {
+            self.euid = uid;
+        }
+        if let Some(gid) = setgid {
+            self.egid = gid;
+        }
+        self.suid = self.euid;
+        self.sgid = self.egid;
+    }
+}
@@ -1,4 +1,5 @@
 use alloc::{collections::BTreeMap, string::ToString, sync::Arc, vec::Vec};
+use core::sync::atomic::AtomicUsize;
 
 use bit_field::BitField;
 use config::{CPU_NUM, FRAME_SIZE, MAX_FD_NUM, MAX_THREAD_NUM, USER_KERNEL_STACK_SIZE};
@@ -8,9 +9,8 @@ use constants::{
     *,
 };
 use gmanager::MinimalManager;
-use ksync::Mutex;
+use ksync::{wait::WaitQueue, Mutex};
 use mem::kernel_space;
-use smpscheduler::FifoTask;
 use vfs::kfile::File;
 
 use crate::{
@@ -18,7 +18,10 @@ use crate::{
     mm::map::MMapInfo,
     task::{
         context::Context,
-        resource::{HeapInfo, TidHandle},
+        cred::Credentials,
+        global_register_task,
+        resource::{HeapInfo, ResourceLimits, TidHandle},
+        sched::SchedEntity,
         stack::Stack,
         task::{TaskInner, TaskTimer},
         FsContext, StatisticalData, Task, TaskState, GLOBAL_TASK_MANAGER,
@@ -39,6 +42,8 @@ pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
         tid,
         kernel_stack: k_stack,
         pid,
+        child_wait: WaitQueue::new(),
+        child_event: AtomicUsize::new(0),
         inner: Mutex::new(TaskInner {
             name: name.to_string(),
             threads: MinimalManager::new(MAX_THREAD_NUM),
@@ -60,6 +65,7 @@ pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
             timer: TaskTimer::default(),
             exit_code: 0,
             heap: Arc::new(Mutex::new(HeapInfo::new(0, 0))),
+            resource_limits: Arc::new(Mutex::new(ResourceLimits::new())),
             mmap: MMapInfo::new(),
             signal_handlers: Arc::new(Mutex::new(SignalHandlers::new())),
             signal_receivers: Arc::new(Mutex::new(SignalReceivers::new())),
@@ -71,7 +77,7 @@ pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
             shm: BTreeMap::new(),
             cpu_affinity: {
                 let mut affinity = 0;
-                affinity.set_bits(0..CPU_NUM, 1 << CPU_NUM - 1);
+                affinity.set_bits(0..CPU_NUM, (1 << CPU_NUM) - 1);
                 affinity
             },
             unmask: 0o022,
@@ -84,11 +90,20 @@ pub fn ktread_create(func: fn(), name: &str) -> AlienResult<()> {
                 ss_size: 0,
             },
             exit_group: false,
+            pgid: pid,
+            sid: pid,
+            cred: Credentials::root(),
+            stopped: false,
+            job_status: None,
+            vfork_done: false,
+            sched: SchedEntity::new(),
+            cmdline: Vec::new(),
+            environ: Vec::new(),
         }),
         send_sigchld_when_exit: false,
     };
     let task = Arc::new(task);
-    let task = Arc::new(FifoTask::new(task));
+    global_register_task(&task);
     GLOBAL_TASK_MANAGER.add_task(task);
     Ok(())
 }
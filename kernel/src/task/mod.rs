@@ -2,27 +2,42 @@
 //!
 //! [`context`] 子模块定义了 Alien 中线程上下文的相关结构.
 //! [`cpu`] 子模块中指明了 Alien 中有关进程的系统调用 和 多核的相关支持。
+//! [`cred`] 子模块定义了 Alien 中任务的用户和用户组凭证。
 //! [`heap`] 子模块定义了 Alien 记录进程堆空间的相关信息的结构。
+//! [`sched`] 子模块定义了 Alien 中的调度类，包括实时调度类和公平调度类。
 //! [`schedule`] 子模块指明了 Alien 中有关 CPU 调度的相关机制
 //! [`stack`] 子模块定义了 Alien 中有关内核栈的相关结构。
 //! [`task`] 子模块定义了 Alien 中有关进程控制块的定义。
-use alloc::{sync::Arc, vec::Vec};
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 
 pub use cpu::*;
+pub use cred::{Credentials, NGROUPS_MAX};
+use ksync::Mutex;
+use platform::config::CLOCK_FREQ;
 use shim::{KTask, KTaskShim};
-use smpscheduler::FifoTask;
 use spin::Lazy;
-pub use task::{StatisticalData, Task, TaskState};
-use timer::get_time_ms;
+pub use task::{JobStatus, StatisticalData, Task, TaskState};
+use timer::read_timer;
 
 pub use crate::task::task::FsContext;
-use crate::{fs::read_all, task::schedule::schedule_now};
+use crate::{
+    fs::{proc, read_all},
+    ipc::{send_process_signal, signal_blocked_or_ignored},
+    task::schedule::{finish_wait, schedule_now, wake_up_task},
+    time::{sleep_until, wait_timeout},
+};
 
 mod context;
 mod control;
 mod cpu;
+mod cred;
 mod kthread;
 mod resource;
+pub mod sched;
 pub mod schedule;
 mod stack;
 mod task;
@@ -36,24 +51,95 @@ pub static INIT_PROCESS: Lazy<Arc<Task>> = Lazy::new(|| {
     Arc::new(task)
 });
 
+/// 记录所有存活的任务，从 tid 获取任务控制块
+static TID2TASK: Lazy<Mutex<BTreeMap<usize, Weak<Task>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// 所有任务创建时均需要加入表
+///
+/// 线程组的第一个线程 (即进程) 还会在 procfs 中创建对应的 `/proc/<pid>` 目录。
+pub fn global_register_task(task: &Arc<Task>) {
+    TID2TASK
+        .lock()
+        .insert(task.get_tid() as usize, Arc::downgrade(task));
+    if task.get_tid() == task.get_pid() {
+        proc::proc_register_process(task);
+    }
+}
+
+/// 任务控制块被释放时从表中删除
+pub fn global_logoff_task(tid: usize) {
+    TID2TASK.lock().remove(&tid);
+    proc::proc_unregister_process(tid);
+}
+
+/// 根据 tid 获取任务控制块
+pub fn get_task_from_tid(tid: usize) -> Option<Arc<Task>> {
+    TID2TASK.lock().get(&tid).and_then(|task| task.upgrade())
+}
+
+/// 获取线程组 `pid` 中所有存活的线程
+pub fn get_thread_group(pid: usize) -> Vec<Arc<Task>> {
+    // 先复制出所有的弱引用再升级，避免在持有锁时释放最后一个强引用
+    let tasks = TID2TASK.lock().values().cloned().collect::<Vec<_>>();
+    tasks
+        .into_iter()
+        .filter_map(|task| task.upgrade())
+        .filter(|task| task.get_pid() as usize == pid)
+        .collect()
+}
+
+/// 获取所有存活的进程，即各个线程组中 tid 与 pid 相同的线程
+pub fn get_all_processes() -> Vec<Arc<Task>> {
+    let tasks = TID2TASK.lock().values().cloned().collect::<Vec<_>>();
+    tasks
+        .into_iter()
+        .filter_map(|task| task.upgrade())
+        .filter(|task| task.get_tid() == task.get_pid())
+        .collect()
+}
+
+/// 统计实际用户号为 `uid` 的任务(包括线程)数量，用于检查 `RLIMIT_NPROC`
+pub fn count_user_tasks(uid: u32) -> usize {
+    let tasks = TID2TASK.lock().values().cloned().collect::<Vec<_>>();
+    tasks
+        .into_iter()
+        .filter_map(|task| task.upgrade())
+        .filter(|task| task.state() != TaskState::Terminated && task.credentials().ruid == uid)
+        .count()
+}
+
+/// 获取进程组 `pgid` 中所有存活的进程
+pub fn get_process_group(pgid: usize) -> Vec<Arc<Task>> {
+    get_all_processes()
+        .into_iter()
+        .filter(|task| task.get_pgid() == pgid)
+        .collect()
+}
+
 /// 将初始进程加入进程池中进行调度
 pub fn init_task() {
     kthread::ktread_create(kthread_init, "kthread_test").unwrap();
+    kthread::ktread_create(crate::fs::writeback_kthread, "writeback").unwrap();
+    kthread::ktread_create(crate::net::net_poll_kthread, "net_poll").unwrap();
+    if devices::GPU_DEVICE.get().is_some() {
+        kthread::ktread_create(crate::gui::fb_refresh_kthread, "fb_refresh").unwrap();
+    }
     let task = INIT_PROCESS.clone();
-    GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
+    // init 进程的标准输入输出是内核打开的串口终端，串口终端成为 init 所在会话的控制终端
+    if let Some(tty) = devices::UART_TTY.get() {
+        tty.set_session(task.get_sid(), task.get_pgid());
+    }
+    global_register_task(&task);
+    GLOBAL_TASK_MANAGER.add_task(task);
     println!("Init task success");
 }
 
 fn kthread_init() {
     println!("kthread_init start...");
-    let mut time = get_time_ms();
     loop {
-        let now = get_time_ms();
-        if now - time > 1000 {
-            // println!("kthread_init tick at {}", now);
-            time = now;
-        }
-        do_suspend();
+        // println!("kthread_init tick at {}", get_time_ms());
+        let _ = sleep_until(read_timer() + CLOCK_FREQ);
     }
 }
 
@@ -67,6 +153,24 @@ impl KTask for Task {
     fn have_signal(&self) -> bool {
         self.access_inner().signal_receivers.lock().have_signal()
     }
+    fn pid(&self) -> usize {
+        self.get_pid() as usize
+    }
+    fn pgid(&self) -> usize {
+        self.get_pgid()
+    }
+    fn sid(&self) -> usize {
+        self.get_sid()
+    }
+    fn signal_blocked_or_ignored(&self, sig: usize) -> bool {
+        signal_blocked_or_ignored(self, sig)
+    }
+    fn euid(&self) -> u32 {
+        self.access_inner().cred.euid
+    }
+    fn egid(&self) -> u32 {
+        self.access_inner().cred.egid
+    }
 }
 pub struct DriverTaskImpl;
 impl KTaskShim for DriverTaskImpl {
@@ -80,15 +184,27 @@ impl KTaskShim for DriverTaskImpl {
     }
     fn put_task(&self, task: Arc<dyn KTask>) {
         let task = task.downcast_arc::<Task>().map_err(|_| ()).unwrap();
-        GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
+        GLOBAL_TASK_MANAGER.add_task(task);
     }
     fn suspend(&self) {
         do_suspend();
     }
+    fn sleep_until(&self, end_time: usize) {
+        let _ = sleep_until(end_time);
+    }
 
     fn schedule_now(&self, task: Arc<dyn KTask>) {
         schedule_now(task.downcast_arc::<Task>().map_err(|_| ()).unwrap());
     }
+    fn wake_up(&self, task: Arc<dyn KTask>) {
+        wake_up_task(task.downcast_arc::<Task>().map_err(|_| ()).unwrap());
+    }
+    fn wait_timeout(&self, deadline: Option<usize>, interruptible: bool) {
+        wait_timeout(deadline, interruptible);
+    }
+    fn finish_wait(&self) {
+        finish_wait();
+    }
     fn transfer_ptr_raw(&self, ptr: usize) -> usize {
         let task = current_task().unwrap();
         task.transfer_raw(ptr)
@@ -97,6 +213,16 @@ impl KTaskShim for DriverTaskImpl {
         let task = current_task().unwrap();
         task.transfer_buffer(src as *const u8, size)
     }
+    fn kill_pgrp(&self, pgid: usize, sig: usize) {
+        get_process_group(pgid)
+            .iter()
+            .for_each(|task| send_process_signal(task.get_pid() as usize, sig));
+    }
+    fn pgrp_in_session(&self, pgid: usize, sid: usize) -> bool {
+        get_process_group(pgid)
+            .iter()
+            .any(|task| task.get_sid() == sid)
+    }
 }
 
 // online test has no sort.src
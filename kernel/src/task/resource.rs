@@ -1,8 +1,11 @@
-use config::MAX_THREAD_NUM;
+use config::{MAX_FD_NUM, MAX_THREAD_NUM, USER_STACK_SIZE};
+use constants::{RLimit64, RLimitResource, RLIM_INFINITY, RLIM_NLIMITS};
 use ksync::Mutex;
 use small_index::IndexAllocator;
 use spin::Lazy;
 
+use crate::task::global_logoff_task;
+
 /// 这里把MinimalManager复用为tid分配器，通常，MinimalManager会将数据插入到最小可用位置并返回位置，
 /// 但tid的分配并不需要实际存储信息，因此可以插入任意的数据，这里为了节省空间，将数据定义为u8
 pub static TID_MANAGER: Lazy<Mutex<IndexAllocator<MAX_THREAD_NUM>>> =
@@ -25,6 +28,7 @@ impl TidHandle {
 
 impl Drop for TidHandle {
     fn drop(&mut self) {
+        global_logoff_task(self.0);
         TID_MANAGER.lock().deallocate(self.0).unwrap();
     }
 }
@@ -53,3 +57,40 @@ impl HeapInfo {
         addr >= self.start && addr < self.end
     }
 }
+
+/// 进程的资源限制，同一线程组中的线程共享，`fork` 时被复制
+#[derive(Debug, Clone)]
+pub struct ResourceLimits {
+    limits: [RLimit64; RLIM_NLIMITS],
+    /// 下一次因为超过 `RLIMIT_CPU` 的软限制而发送 `SIGXCPU` 时的 CPU 时间，单位为秒
+    pub next_xcpu: u64,
+}
+
+impl ResourceLimits {
+    /// 默认的资源限制。用户栈在 `exec` 时按照固定大小分配，因此栈的软硬限制均为 `USER_STACK_SIZE`
+    pub fn new() -> Self {
+        let mut limits = [RLimit64::new(RLIM_INFINITY, RLIM_INFINITY); RLIM_NLIMITS];
+        limits[RLimitResource::RlimitStack as usize] =
+            RLimit64::new(USER_STACK_SIZE as u64, USER_STACK_SIZE as u64);
+        limits[RLimitResource::RlimitNproc as usize] =
+            RLimit64::new(MAX_THREAD_NUM as u64, MAX_THREAD_NUM as u64);
+        limits[RLimitResource::RlimitNofile as usize] =
+            RLimit64::new(MAX_FD_NUM as u64, MAX_FD_NUM as u64);
+        limits[RLimitResource::RlimitCore as usize] = RLimit64::new(0, RLIM_INFINITY);
+        Self {
+            limits,
+            next_xcpu: 0,
+        }
+    }
+
+    pub fn get(&self, resource: RLimitResource) -> RLimit64 {
+        self.limits[resource as usize]
+    }
+
+    pub fn set(&mut self, resource: RLimitResource, limit: RLimit64) {
+        self.limits[resource as usize] = limit;
+        if resource == RLimitResource::RlimitCpu {
+            self.next_xcpu = 0;
+        }
+    }
+}
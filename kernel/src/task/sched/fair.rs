@@ -0,0 +1,120 @@
+//! 基于虚拟运行时间的公平调度类
+//!
+//! 每个任务的虚拟运行时间按照其权重增长，权重越大 (nice 值越小) 的任务虚拟运行时间增长得越慢。
+//! 调度时总是选择虚拟运行时间最小的任务，从而使得各个任务按照权重比例分享 CPU。
+use alloc::{collections::BTreeMap, sync::Arc};
+
+use bit_field::BitField;
+use platform::config::CLOCK_FREQ;
+
+use crate::task::{
+    sched::{SchedEntity, SchedPolicy, MIN_NICE},
+    Task,
+};
+
+/// nice 值为 0 的任务的权重
+const NICE_0_LOAD: usize = 1024;
+/// `SCHED_IDLE` 任务的权重
+const WEIGHT_IDLEPRIO: usize = 3;
+/// 调度周期，以 ms 为单位。唤醒的任务最多获得半个调度周期的补偿
+const SCHED_LATENCY_MS: usize = 6;
+
+/// nice 值 -20 ~ 19 对应的权重，相邻的 nice 值之间权重相差约 1.25 倍
+const SCHED_PRIO_TO_WEIGHT: [usize; 40] = [
+    88761, 71755, 56483, 46273, 36291, // -20 ~ -16
+    29154, 23254, 18705, 14949, 11916, // -15 ~ -11
+    9548, 7620, 6100, 4904, 3906, // -10 ~ -6
+    3121, 2501, 1991, 1586, 1277, // -5 ~ -1
+    1024, 820, 655, 526, 423, // 0 ~ 4
+    335, 272, 215, 172, 137, // 5 ~ 9
+    110, 87, 70, 56, 45, // 10 ~ 14
+    36, 29, 23, 18, 15, // 15 ~ 19
+];
+
+/// 根据调度策略和 nice 值获取任务的权重
+pub fn weight_of(policy: SchedPolicy, nice: i8) -> usize {
+    if policy == SchedPolicy::Idle {
+        return WEIGHT_IDLEPRIO;
+    }
+    SCHED_PRIO_TO_WEIGHT[(nice - MIN_NICE) as usize]
+}
+
+/// 将实际运行的时间 `delta` 换算为虚拟运行时间
+pub fn calc_delta_fair(delta: usize, weight: usize) -> usize {
+    if weight == NICE_0_LOAD {
+        delta
+    } else {
+        delta * NICE_0_LOAD / weight
+    }
+}
+
+/// 公平调度类的就绪队列，按照 (虚拟运行时间, tid) 排序
+#[derive(Debug)]
+pub struct FairRunQueue {
+    tasks: BTreeMap<(usize, usize), Arc<Task>>,
+    /// 队列中单调递增的最小虚拟运行时间
+    min_vruntime: usize,
+}
+
+impl FairRunQueue {
+    pub fn new() -> Self {
+        Self {
+            tasks: BTreeMap::new(),
+            min_vruntime: 0,
+        }
+    }
+
+    /// 调整即将入队的任务的虚拟运行时间
+    ///
+    /// 长时间睡眠的任务的虚拟运行时间会远小于其它任务，为了避免其唤醒后长时间独占 CPU，
+    /// 这里将其虚拟运行时间调整为不小于 `min_vruntime` 减去半个调度周期。
+    pub fn place_entity(&self, entity: &mut SchedEntity) {
+        let thresh = SCHED_LATENCY_MS * CLOCK_FREQ / 1000 / 2;
+        let min = self.min_vruntime.saturating_sub(thresh);
+        if entity.vruntime < min {
+            entity.vruntime = min;
+        }
+    }
+
+    pub fn enqueue(&mut self, task: Arc<Task>, vruntime: usize) {
+        let tid = task.get_tid() as usize;
+        self.tasks.insert((vruntime, tid), task);
+    }
+
+    /// 取出虚拟运行时间最小的任务
+    pub fn pick_next(&mut self) -> Option<Arc<Task>> {
+        let ((vruntime, _), task) = self.tasks.pop_first()?;
+        if vruntime > self.min_vruntime {
+            self.min_vruntime = vruntime;
+        }
+        Some(task)
+    }
+
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    /// 移出虚拟运行时间为 `vruntime` 的任务 `task`，任务不在队列中时返回 `None`
+    pub fn remove(&mut self, task: &Arc<Task>, vruntime: usize) -> Option<Arc<Task>> {
+        self.tasks.remove(&(vruntime, task.get_tid() as usize))
+    }
+
+    /// 当前队列的最小虚拟运行时间，任务在不同的队列之间迁移时需要以此为基准调整虚拟运行时间
+    pub fn min_vruntime(&self) -> usize {
+        self.min_vruntime
+    }
+
+    /// 从队列中取出一个允许在 `hart` 上运行的任务
+    ///
+    /// 优先选择虚拟运行时间最大的任务。这样的任务已经运行得最多，在当前队列中会最晚被调度，
+    /// 迁移它对当前队列中其它任务的影响最小。
+    pub fn steal(&mut self, hart: usize) -> Option<Arc<Task>> {
+        let key = self
+            .tasks
+            .iter()
+            .rev()
+            .find(|(_, task)| task.access_inner().cpu_affinity.get_bit(hart))
+            .map(|(key, _)| *key)?;
+        self.tasks.remove(&key)
+    }
+}
@@ -0,0 +1,328 @@
+//! Alien 中的调度类
+//!
+//! 每个任务都属于一个调度类，调度时总是优先从实时调度类中选取任务，只有当实时调度类中没有就绪的任务时，
+//! 才会从公平调度类中选取任务。
+//!
+//! [`rt`] 子模块实现了 `SCHED_FIFO` 和 `SCHED_RR` 两种实时调度策略。
+//! [`fair`] 子模块实现了基于虚拟运行时间 (vruntime) 的公平调度策略，对应 `SCHED_OTHER`、`SCHED_BATCH` 和 `SCHED_IDLE`。
+use alloc::sync::Arc;
+
+use constants::{AlienError, AlienResult, LinuxErrno};
+use platform::config::CLOCK_FREQ;
+use timer::read_timer;
+
+use crate::task::{
+    sched::{fair::FairRunQueue, rt::RtRunQueue},
+    Task,
+};
+
+mod fair;
+mod rt;
+
+/// `sched_setscheduler` 中与调度策略一起传入的标志，表示子进程不继承实时调度策略和负的 nice 值
+pub const SCHED_RESET_ON_FORK: usize = 0x4000_0000;
+/// 实时任务的最高优先级
+pub const MAX_RT_PRIO: u8 = 99;
+/// 实时任务的最低优先级
+pub const MIN_RT_PRIO: u8 = 1;
+/// 最小的 nice 值
+pub const MIN_NICE: i8 = -20;
+/// 最大的 nice 值
+pub const MAX_NICE: i8 = 19;
+/// `SCHED_RR` 任务的时间片长度，以 ms 为单位
+pub const RR_TIMESLICE_MS: usize = 100;
+
+/// 任务的调度策略
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum SchedPolicy {
+    /// 默认的分时调度策略
+    Normal = 0,
+    /// 先进先出的实时调度策略
+    Fifo = 1,
+    /// 时间片轮转的实时调度策略
+    RoundRobin = 2,
+    /// 批处理任务，按照分时策略调度
+    Batch = 3,
+    /// 优先级极低的任务
+    Idle = 5,
+}
+
+impl TryFrom<usize> for SchedPolicy {
+    type Error = AlienError;
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(SchedPolicy::Normal),
+            1 => Ok(SchedPolicy::Fifo),
+            2 => Ok(SchedPolicy::RoundRobin),
+            3 => Ok(SchedPolicy::Batch),
+            5 => Ok(SchedPolicy::Idle),
+            _ => Err(LinuxErrno::EINVAL),
+        }
+    }
+}
+
+impl SchedPolicy {
+    /// 是否为实时调度策略
+    pub fn is_rt(&self) -> bool {
+        matches!(self, SchedPolicy::Fifo | SchedPolicy::RoundRobin)
+    }
+
+    /// 该策略下允许的最高优先级
+    pub fn max_priority(&self) -> u8 {
+        if self.is_rt() {
+            MAX_RT_PRIO
+        } else {
+            0
+        }
+    }
+
+    /// 该策略下允许的最低优先级
+    pub fn min_priority(&self) -> u8 {
+        if self.is_rt() {
+            MIN_RT_PRIO
+        } else {
+            0
+        }
+    }
+}
+
+/// 系统调用 `sched_setparam` / `sched_getparam` 使用的参数结构
+#[repr(C)]
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SchedParam {
+    pub sched_priority: i32,
+}
+
+/// 任务控制块中记录的与调度相关的信息
+#[derive(Debug, Clone)]
+pub struct SchedEntity {
+    /// 调度策略
+    pub policy: SchedPolicy,
+    /// 实时优先级，只对实时调度策略有效
+    pub rt_priority: u8,
+    /// nice 值，只对分时调度策略有效
+    pub nice: i8,
+    /// 虚拟运行时间，以 cpu 时钟为单位
+    pub vruntime: usize,
+    /// 本次被调度到 CPU 上的时间
+    pub exec_start: usize,
+    /// 总的运行时间
+    pub sum_exec_runtime: usize,
+    /// 子进程是否重置调度策略
+    pub reset_on_fork: bool,
+    /// 任务最近一次所在的运行队列对应的 CPU
+    pub cpu: usize,
+    /// 因为 PI futex 的优先级继承而被提升时，提升之前的调度策略和实时优先级
+    pub pi_saved: Option<(SchedPolicy, u8)>,
+}
+
+impl SchedEntity {
+    /// 创建一个使用默认分时调度策略的调度实体
+    pub fn new() -> Self {
+        Self {
+            policy: SchedPolicy::Normal,
+            rt_priority: 0,
+            nice: 0,
+            vruntime: 0,
+            exec_start: 0,
+            sum_exec_runtime: 0,
+            reset_on_fork: false,
+            cpu: 0,
+            pi_saved: None,
+        }
+    }
+
+    /// 创建子任务时，根据父任务的调度信息生成子任务的调度实体
+    pub fn fork(&self) -> Self {
+        let mut entity = self.clone();
+        entity.sum_exec_runtime = 0;
+        // 子任务不继承因为优先级继承而得到的优先级
+        if let Some((policy, priority)) = entity.pi_saved.take() {
+            entity.policy = policy;
+            entity.rt_priority = priority;
+        }
+        if self.reset_on_fork {
+            if self.policy.is_rt() {
+                entity.policy = SchedPolicy::Normal;
+                entity.rt_priority = 0;
+            }
+            if entity.nice < 0 {
+                entity.nice = 0;
+            }
+            entity.reset_on_fork = false;
+        }
+        entity
+    }
+
+    /// 设置调度策略以及实时优先级
+    pub fn set_scheduler(
+        &mut self,
+        policy: SchedPolicy,
+        priority: i32,
+        reset_on_fork: bool,
+    ) -> AlienResult<()> {
+        if priority < policy.min_priority() as i32 || priority > policy.max_priority() as i32 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        // 显式设置的调度策略覆盖优先级继承的提升，直到下一次重新计算继承的优先级
+        self.pi_saved = None;
+        self.policy = policy;
+        self.rt_priority = priority as u8;
+        self.reset_on_fork = reset_on_fork;
+        Ok(())
+    }
+
+    /// 不考虑优先级继承时任务的实时优先级，分时任务为 0
+    pub fn base_rt_priority(&self) -> u8 {
+        let (policy, priority) = self.pi_saved.unwrap_or((self.policy, self.rt_priority));
+        if policy.is_rt() {
+            priority
+        } else {
+            0
+        }
+    }
+
+    /// 优先级继承：持有 PI futex 的任务被提升到等待者中最高的实时优先级 `priority`
+    ///
+    /// `priority` 为 `None` 或者不高于任务本身的优先级时，恢复任务原来的调度策略和优先级。
+    pub fn set_pi_boost(&mut self, priority: Option<u8>) {
+        if let Some((policy, rt_priority)) = self.pi_saved.take() {
+            self.policy = policy;
+            self.rt_priority = rt_priority;
+        }
+        let base = self.base_rt_priority();
+        if let Some(priority) = priority.filter(|&priority| priority > base) {
+            self.pi_saved = Some((self.policy, self.rt_priority));
+            self.policy = SchedPolicy::Fifo;
+            self.rt_priority = priority;
+        }
+    }
+
+    /// 设置 nice 值，超出范围的值会被截断到 [`MIN_NICE`, `MAX_NICE`]
+    pub fn set_nice(&mut self, nice: isize) {
+        self.nice = nice.clamp(MIN_NICE as isize, MAX_NICE as isize) as i8;
+    }
+
+    /// 任务的权重，用于计算虚拟运行时间
+    pub fn weight(&self) -> usize {
+        fair::weight_of(self.policy, self.nice)
+    }
+
+    /// 任务被调度到 CPU 上时调用，记录开始运行的时间
+    pub fn start_exec(&mut self) {
+        self.exec_start = read_timer();
+    }
+
+    /// 任务让出 CPU 时调用，统计本次运行的时间并更新虚拟运行时间
+    pub fn stop_exec(&mut self) {
+        let now = read_timer();
+        let delta = now.saturating_sub(self.exec_start);
+        self.exec_start = now;
+        self.sum_exec_runtime += delta;
+        if !self.policy.is_rt() {
+            self.vruntime += fair::calc_delta_fair(delta, self.weight());
+        }
+    }
+
+    /// 在时钟中断中检查当前任务是否用完了时间片
+    ///
+    /// `SCHED_FIFO` 任务没有时间片，只会被更高优先级的实时任务抢占。
+    pub fn timeslice_expired(&self) -> bool {
+        match self.policy {
+            SchedPolicy::Fifo => false,
+            SchedPolicy::RoundRobin => {
+                read_timer().saturating_sub(self.exec_start) >= RR_TIMESLICE_MS * CLOCK_FREQ / 1000
+            }
+            _ => true,
+        }
+    }
+}
+
+/// 每个 CPU 的运行队列，由实时调度类和公平调度类的就绪队列组成
+#[derive(Debug)]
+pub struct RunQueue {
+    hart: usize,
+    rt: RtRunQueue,
+    fair: FairRunQueue,
+}
+
+impl RunQueue {
+    /// 为 CPU `hart` 创建一个空的运行队列
+    pub fn new(hart: usize) -> Self {
+        Self {
+            hart,
+            rt: RtRunQueue::new(),
+            fair: FairRunQueue::new(),
+        }
+    }
+
+    /// 将一个就绪的任务按照其调度策略放入对应的就绪队列中
+    ///
+    /// 如果任务是从其它 CPU 的运行队列迁移过来的，`migrate_from` 为原队列的最小虚拟运行时间，
+    /// 任务的虚拟运行时间会被调整为相对于当前队列的值，避免因各队列之间虚拟运行时间的差异导致任务饥饿或长期占用 CPU。
+    pub fn enqueue(&mut self, task: Arc<Task>, migrate_from: Option<usize>) {
+        let mut inner = task.access_inner();
+        let entity = &mut inner.sched;
+        entity.cpu = self.hart;
+        if entity.policy.is_rt() {
+            let priority = entity.rt_priority;
+            drop(inner);
+            self.rt.enqueue(task, priority);
+        } else {
+            if let Some(min_vruntime) = migrate_from {
+                entity.vruntime =
+                    entity.vruntime.saturating_sub(min_vruntime) + self.fair.min_vruntime();
+            }
+            self.fair.place_entity(entity);
+            let vruntime = entity.vruntime;
+            drop(inner);
+            self.fair.enqueue(task, vruntime);
+        }
+    }
+
+    /// 将任务 `task` 从就绪队列中移出，任务不在队列中时返回 `None`
+    ///
+    /// 需要在修改任务的调度策略、实时优先级之前调用，这样才能找到任务所在的就绪队列。
+    pub fn dequeue(&mut self, task: &Arc<Task>) -> Option<Arc<Task>> {
+        let (policy, priority, vruntime) = {
+            let inner = task.access_inner();
+            (
+                inner.sched.policy,
+                inner.sched.rt_priority,
+                inner.sched.vruntime,
+            )
+        };
+        if policy.is_rt() {
+            self.rt.remove(task, priority)
+        } else {
+            self.fair.remove(task, vruntime)
+        }
+    }
+
+    /// 选取下一个要运行的任务，实时任务优先
+    pub fn pick_next(&mut self) -> Option<Arc<Task>> {
+        self.rt.pick_next().or_else(|| self.fair.pick_next())
+    }
+
+    /// 队列中是否有优先级高于 `priority` 的实时任务
+    pub fn has_higher_rt(&self, priority: u8) -> bool {
+        self.rt
+            .highest_priority()
+            .map_or(false, |highest| highest > priority)
+    }
+
+    /// 队列中就绪任务的数量
+    pub fn len(&self) -> usize {
+        self.rt.len() + self.fair.len()
+    }
+
+    /// 公平调度类的最小虚拟运行时间
+    pub fn min_vruntime(&self) -> usize {
+        self.fair.min_vruntime()
+    }
+
+    /// 从队列中取出一个允许在 `hart` 上运行的任务，用于 CPU 之间的负载均衡
+    pub fn steal(&mut self, hart: usize) -> Option<Arc<Task>> {
+        self.rt.steal(hart).or_else(|| self.fair.steal(hart))
+    }
+}
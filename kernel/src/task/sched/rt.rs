@@ -0,0 +1,84 @@
+//! 实时调度类
+//!
+//! 实时任务按照优先级 (1 ~ 99) 组织为多个先进先出队列，调度时总是选择优先级最高的队列中的第一个任务。
+//! `SCHED_FIFO` 任务会一直运行直到主动让出 CPU，`SCHED_RR` 任务在用完时间片后会被放到同优先级队列的末尾。
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::Arc,
+};
+
+use bit_field::BitField;
+
+use crate::task::Task;
+
+/// 实时调度类的就绪队列
+#[derive(Debug)]
+pub struct RtRunQueue {
+    queues: BTreeMap<u8, VecDeque<Arc<Task>>>,
+    len: usize,
+}
+
+impl RtRunQueue {
+    pub fn new() -> Self {
+        Self {
+            queues: BTreeMap::new(),
+            len: 0,
+        }
+    }
+
+    pub fn enqueue(&mut self, task: Arc<Task>, priority: u8) {
+        self.queues.entry(priority).or_default().push_back(task);
+        self.len += 1;
+    }
+
+    /// 取出优先级最高的任务
+    pub fn pick_next(&mut self) -> Option<Arc<Task>> {
+        let mut entry = self.queues.last_entry()?;
+        let task = entry.get_mut().pop_front();
+        if entry.get().is_empty() {
+            entry.remove();
+        }
+        if task.is_some() {
+            self.len -= 1;
+        }
+        task
+    }
+
+    /// 队列中最高的优先级
+    pub fn highest_priority(&self) -> Option<u8> {
+        self.queues.last_key_value().map(|(priority, _)| *priority)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    /// 从优先级为 `priority` 的队列中移出任务 `task`，任务不在队列中时返回 `None`
+    pub fn remove(&mut self, task: &Arc<Task>, priority: u8) -> Option<Arc<Task>> {
+        let queue = self.queues.get_mut(&priority)?;
+        let index = queue.iter().position(|other| Arc::ptr_eq(other, task))?;
+        let task = queue.remove(index);
+        if queue.is_empty() {
+            self.queues.remove(&priority);
+        }
+        self.len -= 1;
+        task
+    }
+
+    /// 从队列中取出一个允许在 `hart` 上运行的任务，优先迁移优先级最高的任务
+    pub fn steal(&mut self, hart: usize) -> Option<Arc<Task>> {
+        let (priority, index) = self.queues.iter().rev().find_map(|(priority, queue)| {
+            queue
+                .iter()
+                .position(|task| task.access_inner().cpu_affinity.get_bit(hart))
+                .map(|index| (*priority, index))
+        })?;
+        let queue = self.queues.get_mut(&priority).unwrap();
+        let task = queue.remove(index);
+        if queue.is_empty() {
+            self.queues.remove(&priority);
+        }
+        self.len -= 1;
+        task
+    }
+}
@@ -2,13 +2,13 @@
 use alloc::sync::Arc;
 use core::hint::spin_loop;
 
+use arch::{interrupt_disable, interrupt_enable, is_interrupt_enable};
 use constants::signal::SignalNumber;
-use smpscheduler::FifoTask;
 
 use crate::{
     ipc::send_signal,
     task::{
-        context::switch, cpu::current_cpu, take_current_task, task::TaskState, Task,
+        context::switch, cpu::current_cpu, current_task, take_current_task, task::TaskState, Task,
         GLOBAL_TASK_MANAGER,
     },
 };
@@ -27,21 +27,82 @@ pub fn run_task() -> ! {
         let cpu = current_cpu();
         if let Some(task) = GLOBAL_TASK_MANAGER.pick_next_task() {
             // update state to running
-            task.inner().update_state(TaskState::Running);
+            task.update_state(TaskState::Running);
+            task.access_inner().sched.start_exec();
             // get the process context
-            let context = task.inner().get_context_raw_ptr();
-            cpu.task = Some(task.inner().clone());
+            let context = task.get_context_raw_ptr();
+            cpu.task = Some(task.clone());
             // switch to the process context
             let cpu_context = cpu.get_context_mut_raw_ptr();
             // println!("hart {} switch to task {}", hart_id(),task.get_tid());
             drop(task);
             switch(cpu_context, context);
         } else {
+            // 空闲时打开中断，使得时钟中断能够及时唤醒睡眠的任务
+            interrupt_enable();
             spin_loop();
+            interrupt_disable();
         }
     }
 }
 
+/// 唤醒一个处于等待状态的任务，将其重新放入调度队列中
+///
+/// 如果任务已经被其它事件唤醒，则什么也不做，避免同一个任务被重复放入调度队列。
+pub fn wake_up_task(task: Arc<Task>) {
+    {
+        let mut inner = task.access_inner();
+        if inner.state != TaskState::Waiting {
+            return;
+        }
+        inner.state = TaskState::Ready;
+    }
+    GLOBAL_TASK_MANAGER.add_task(task);
+}
+
+/// 当前任务结束在等待队列上的等待，恢复为运行状态
+///
+/// 如果任务在结束等待前已经被唤醒并放入了调度队列，则让出 CPU，由调度队列中的那一份继续执行，
+/// 避免同一个任务同时出现在 CPU 和调度队列中。
+pub fn finish_wait() {
+    let task = current_task().unwrap().clone();
+    let enable = is_interrupt_enable();
+    interrupt_disable();
+    let woken = {
+        let mut inner = task.access_inner();
+        let woken = inner.state == TaskState::Ready;
+        inner.state = if woken {
+            TaskState::Waiting
+        } else {
+            TaskState::Running
+        };
+        woken
+    };
+    if woken {
+        schedule();
+    }
+    if enable {
+        interrupt_enable();
+    }
+}
+
+/// 时钟中断时调用，检查当前在 CPU 上执行的任务是否需要让出 CPU
+///
+/// 分时任务每个时间片都会让出 CPU，由公平调度类重新选择虚拟运行时间最小的任务；
+/// 实时任务只有在用完时间片 (`SCHED_RR`) 或者有更高优先级的实时任务就绪时才会让出 CPU。
+/// 同时也会周期性地在各个 CPU 之间进行负载均衡。
+pub fn scheduler_tick() -> bool {
+    GLOBAL_TASK_MANAGER.load_balance();
+    let Some(task) = current_task() else {
+        return false;
+    };
+    let (expired, priority) = {
+        let inner = task.access_inner();
+        (inner.sched.timeslice_expired(), inner.sched.rt_priority)
+    };
+    expired || GLOBAL_TASK_MANAGER.has_higher_rt(priority)
+}
+
 /// 切换线程上下文，调度当前在 CPU 上执行的线程 让渡出 CPU
 pub fn schedule() {
     let task = take_current_task().unwrap();
@@ -51,6 +112,7 @@ pub fn schedule() {
 // todo!(fix bugs)
 pub fn schedule_now(task: Arc<Task>) {
     let context = task.get_context_mut_raw_ptr();
+    task.access_inner().sched.stop_exec();
     match task.state() {
         TaskState::Waiting => {
             drop(task);
@@ -70,7 +132,7 @@ pub fn schedule_now(task: Arc<Task>) {
             task.terminate(); // release some resources
         }
         _ => {
-            GLOBAL_TASK_MANAGER.add_task(Arc::new(FifoTask::new(task)));
+            GLOBAL_TASK_MANAGER.add_task(task);
         }
     }
     let cpu = current_cpu();
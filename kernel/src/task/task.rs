@@ -14,19 +14,21 @@ use alloc::{
 use core::{
     fmt::{Debug, Formatter},
     ops::Range,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use bit_field::BitField;
 use config::*;
 use constants::{aux::*, io::MMapFlags, ipc::RobustList, signal::*, task::CloneFlags, time::*, *};
 use gmanager::MinimalManager;
-use ksync::{Mutex, MutexGuard};
+use ksync::{wait::WaitQueue, Mutex, MutexGuard};
 use mem::{kernel_satp, VmmPageAllocator, FRAME_REF_MANAGER};
 use page_table::{
-    addr::{align_down_4k, align_up_4k, VirtAddr},
+    addr::{align_down_4k, align_up_4k, PhysAddr, VirtAddr},
     pte::MappingFlags,
     table::Sv39PageTable,
 };
+use platform::config::CLOCK_FREQ;
 use timer::{read_timer, TimeNow, ToClock};
 use vfs::kfile::File;
 use vfscore::dentry::VfsDentry;
@@ -38,11 +40,14 @@ use crate::{
         loader::{
             build_cow_address_space, build_elf_address_space, build_thread_address_space, UserStack,
         },
-        map::{MMapInfo, MMapRegion, ProtFlags},
+        map::{DeviceMapping, MMapInfo, MMapRegion, ProtFlags},
     },
     task::{
         context::Context,
-        resource::{HeapInfo, TidHandle},
+        cred::Credentials,
+        global_register_task,
+        resource::{HeapInfo, ResourceLimits, TidHandle},
+        sched::SchedEntity,
         stack::Stack,
     },
     trap::{trap_common_read_file, trap_return, user_trap_vector, TrapFrame},
@@ -62,6 +67,10 @@ pub struct Task {
     pub send_sigchld_when_exit: bool,
     /// 内核栈
     pub kernel_stack: Stack,
+    /// 在 `wait4`/`waitid` 或者 vfork 中等待子任务状态变化的任务
+    pub child_wait: WaitQueue,
+    /// 子任务状态变化的计数，每次变化时加一
+    pub child_event: AtomicUsize,
     /// 更详细的信息
     pub inner: Mutex<TaskInner>,
 }
@@ -96,6 +105,8 @@ pub struct TaskInner {
     pub exit_code: i32,
     /// 堆空间
     pub heap: Arc<Mutex<HeapInfo>>,
+    /// 资源限制，同一线程组中的线程共享
+    pub resource_limits: Arc<Mutex<ResourceLimits>>,
     /// 地址空间中的映射信息
     pub mmap: MMapInfo,
     /// 信号量对应的一组处理函数。
@@ -135,6 +146,24 @@ pub struct TaskInner {
     pub ss_stack: SignalStack,
 
     pub exit_group: bool,
+    /// 进程组号，同一线程组中的线程相同
+    pub pgid: usize,
+    /// 会话号，同一线程组中的线程相同
+    pub sid: usize,
+    /// 用户和用户组凭证
+    pub cred: Credentials,
+    /// 是否因为作业控制信号 (`SIGSTOP`、`SIGTSTP`、`SIGTTIN`、`SIGTTOU`) 而停止，收到 `SIGCONT` 后恢复执行
+    pub stopped: bool,
+    /// 尚未被父进程通过 `wait4`/`waitid` 获取的停止或继续执行事件，只记录在线程组的主线程中
+    pub job_status: Option<JobStatus>,
+    /// 是否已经执行过 `execve`，以 `CLONE_VFORK` 创建子进程的父进程会等待子进程执行 `execve` 或者退出
+    pub vfork_done: bool,
+    /// 调度相关的信息，包括调度策略、优先级以及虚拟运行时间等
+    pub sched: SchedEntity,
+    /// 启动参数，每个参数以 '\0' 结尾，用于 `/proc/<pid>/cmdline`
+    pub cmdline: Vec<u8>,
+    /// 环境变量，每个环境变量以 '\0' 结尾，用于 `/proc/<pid>/environ`
+    pub environ: Vec<u8>,
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -262,6 +291,15 @@ pub enum TaskState {
     Terminated,
 }
 
+/// 进程因作业控制信号停止或者恢复执行的事件，父进程可以通过 `WUNTRACED`/`WCONTINUED` 等待
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum JobStatus {
+    /// 因信号停止
+    Stopped(SignalNumber),
+    /// 因 `SIGCONT` 恢复执行
+    Continued,
+}
+
 impl Task {
     pub fn set_exit_group(&self) {
         self.access_inner().exit_group = true;
@@ -271,7 +309,9 @@ impl Task {
     pub fn terminate(self: Arc<Self>) {
         // recycle kernel stack
         self.kernel_stack.release();
-        if self.access_inner().thread_number != 0 {
+        // 线程退出后不会被父进程等待，直接从父进程的子任务列表中删除。以 `CLONE_VM` 创建的子进程(如 vfork)
+        // 虽然使用了线程的 trap 上下文槽位，但仍然需要由父进程回收
+        if self.pid != self.tid.0 {
             let parent = self.inner.lock().parent.clone();
             if let Some(parent) = parent {
                 let parent = parent.upgrade();
@@ -280,9 +320,24 @@ impl Task {
                     assert_eq!(Arc::strong_count(&self), 1);
                 }
             }
-        } else {
         }
-        self.access_inner().state = TaskState::Terminated;
+        let parent = {
+            let mut inner = self.access_inner();
+            inner.state = TaskState::Terminated;
+            inner.parent.clone()
+        };
+        // 释放任务的控制块之后再唤醒父进程，父进程回收子进程时要求只有它持有子进程的控制块
+        drop(self);
+        if let Some(parent) = parent.and_then(|parent| parent.upgrade()) {
+            parent.child_changed();
+        }
+    }
+
+    /// 子任务退出、停止、继续执行或者以 `CLONE_VFORK` 创建的子进程执行了 `execve` 时调用，
+    /// 唤醒在 `wait4`/`waitid` 或者 vfork 中等待子任务的任务
+    pub fn child_changed(&self) {
+        self.child_event.fetch_add(1, Ordering::AcqRel);
+        self.child_wait.wake_all();
     }
 
     /// 获取进程的 pid 号
@@ -291,6 +346,21 @@ impl Task {
         self.pid as isize
     }
 
+    /// 获取进程组号
+    pub fn get_pgid(&self) -> usize {
+        self.inner.lock().pgid
+    }
+
+    /// 获取会话号
+    pub fn get_sid(&self) -> usize {
+        self.inner.lock().sid
+    }
+
+    /// 获取用户和用户组凭证的一份拷贝
+    pub fn credentials(&self) -> Credentials {
+        self.inner.lock().cred.clone()
+    }
+
     /// 获取进程的 tid 号
     #[inline]
     pub fn get_tid(&self) -> isize {
@@ -367,21 +437,6 @@ impl Task {
         inner.children.clone()
     }
 
-    /// 查找进程号为pid的子进程是否已经退出。当pid为-1时，任意子进程退出都将返回子进程的pid号。
-    pub fn check_child(&self, pid: isize) -> Option<usize> {
-        let res = self
-            .inner
-            .lock()
-            .children
-            .iter()
-            .enumerate()
-            .find(|(_, child)| {
-                child.state() == TaskState::Terminated && (child.get_pid() == pid || pid == -1)
-            })
-            .map(|(index, _)| index);
-        res
-    }
-
     /// 取走当前进程的子进程控制块列表的所有权
     pub fn take_children(&self) -> Vec<Arc<Task>> {
         let children = self.children();
@@ -553,29 +608,16 @@ impl TaskInner {
     }
 
     /// 获取当前进程对于资源的限制
-    pub fn get_prlimit(&self, resource: PrLimitResType) -> RLimit64 {
-        match resource {
-            PrLimitResType::RlimitStack => {
-                RLimit64::new(USER_STACK_SIZE as u64, USER_STACK_SIZE as u64)
-            }
-            PrLimitResType::RlimitNofile => {
-                let max_fd = self.fd_table.lock().max();
-                RLimit64::new(max_fd as u64, max_fd as u64)
-            }
-            PrLimitResType::RlimitAs => RLimit64::new(u64::MAX, u64::MAX),
-        }
+    pub fn get_prlimit(&self, resource: RLimitResource) -> RLimit64 {
+        self.resource_limits.lock().get(resource)
     }
 
-    /// 设置当前进程对于资源的限制
-    pub fn set_prlimit(&mut self, resource: PrLimitResType, value: RLimit64) {
-        match resource {
-            PrLimitResType::RlimitStack => {}
-            PrLimitResType::RlimitNofile => {
-                let new_max_fd = value.rlim_cur;
-                self.fd_table.lock().set_max(new_max_fd as usize);
-            }
-            PrLimitResType::RlimitAs => {}
+    /// 设置当前进程对于资源的限制，文件描述符数量的限制会同步到文件描述符表中
+    pub fn set_prlimit(&mut self, resource: RLimitResource, value: RLimit64) {
+        if resource == RLimitResource::RlimitNofile {
+            self.fd_table.lock().set_max(value.rlim_cur as usize);
         }
+        self.resource_limits.lock().set(resource, value);
     }
 
     /// 返回 trap 上下文的一个可变指针
@@ -941,6 +983,27 @@ impl TaskInner {
         }
     }
 
+    /// 检查任务使用的 CPU 时间是否超过 `RLIMIT_CPU`，目前按照线程使用的 CPU 时间统计。
+    ///
+    /// 达到软限制时返回 `SIGXCPU`，之后每多使用一秒再返回一次；达到硬限制时返回 `SIGKILL`。
+    pub fn check_cpu_limit(&self) -> Option<SignalNumber> {
+        let limit = self.get_prlimit(RLimitResource::RlimitCpu);
+        if limit.rlim_cur == RLIM_INFINITY {
+            return None;
+        }
+        let data = &self.statistical_data;
+        let seconds = ((data.tms_utime + data.tms_stime) / CLOCK_FREQ) as u64;
+        if seconds >= limit.rlim_max {
+            return Some(SignalNumber::SIGKILL);
+        }
+        let mut limits = self.resource_limits.lock();
+        if seconds >= limits.next_xcpu.max(limit.rlim_cur) {
+            limits.next_xcpu = seconds + 1;
+            return Some(SignalNumber::SIGXCPU);
+        }
+        None
+    }
+
     /// 返回进程的统计信息
     pub fn statistical_data(&self) -> &StatisticalData {
         &self.statistical_data
@@ -957,8 +1020,43 @@ impl TaskInner {
         todo!()
     }
 
-    /// 拓展堆空间
+    /// 进程虚拟地址空间的大小，包括用户栈、堆以及 mmap 映射区，用于检查 `RLIMIT_AS`
+    pub fn address_space_size(&self) -> usize {
+        let heap_size = {
+            let heap = self.heap.lock();
+            heap.end - heap.start
+        };
+        let mmap_size = self
+            .mmap
+            .regions()
+            .iter()
+            .map(|region| region.map_len)
+            .sum::<usize>();
+        (self.stack.end - self.stack.start) + heap_size + mmap_size
+    }
+
+    /// 检查地址空间再增加 `len` 字节后是否会超过 `RLIMIT_AS`，超过时返回 `ENOMEM`
+    pub fn check_address_space_limit(&self, len: usize) -> AlienResult<()> {
+        let limit = self.get_prlimit(RLimitResource::RlimitAs).rlim_cur;
+        if limit != RLIM_INFINITY && (self.address_space_size() + len) as u64 > limit {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        Ok(())
+    }
+
+    /// 拓展堆空间。堆的长度受 `RLIMIT_DATA` 限制，新增的空间受 `RLIMIT_AS` 限制，超过限制时返回 `ENOMEM`
     pub fn extend_heap(&mut self, addr: usize) -> Result<usize, AlienError> {
+        let (start, end) = {
+            let heap = self.heap.lock();
+            (heap.start, heap.end)
+        };
+        let data_limit = self.get_prlimit(RLimitResource::RlimitData).rlim_cur;
+        if data_limit != RLIM_INFINITY && (addr - start) as u64 > data_limit {
+            return Err(LinuxErrno::ENOMEM);
+        }
+        if addr > end {
+            self.check_address_space_limit(align_up_4k(addr - end))?;
+        }
         let mut heap = self.heap.lock();
         heap.current = addr;
         if addr < heap.end {
@@ -1012,6 +1110,7 @@ impl TaskInner {
         if self.heap.lock().contains(start) && self.heap.lock().contains(start + len) {
             return Ok(start);
         }
+        self.check_address_space_limit(align_up_4k(len))?;
 
         // not map to file
         let fd = if flags.contains(MMapFlags::MAP_ANONYMOUS) {
@@ -1093,7 +1192,7 @@ impl TaskInner {
             v_range
         };
 
-        let region = MMapRegion::new(
+        let mut region = MMapRegion::new(
             v_range.start,
             len,
             v_range.end - v_range.start,
@@ -1103,8 +1202,32 @@ impl TaskInner {
             offset,
         );
         // warn!("add mmap region:{:#x?}",region);
-        self.mmap.add_region(region);
         let start = v_range.start;
+        if flags.contains(MMapFlags::MAP_SHARED) {
+            if let Some(file) = fd.as_ref() {
+                match file.mmap_phys(offset, v_range.end - start) {
+                    Ok(phys) => {
+                        // device memory such as the framebuffer, map it directly
+                        region.device = Some(Arc::new(DeviceMapping::new(
+                            file.clone(),
+                            offset,
+                            v_range.end - start,
+                        )));
+                        self.map_device_memory(v_range, phys, prot);
+                        self.mmap.add_region(region);
+                        return Ok(start);
+                    }
+                    Err(LinuxErrno::ENODEV) => {}
+                    Err(e) => return Err(e),
+                }
+            }
+        }
+        if region.page_cache().is_some() {
+            // shared file mapping, the pages of the page cache are mapped on first access
+            self.mmap.add_region(region);
+            return Ok(start);
+        }
+        self.mmap.add_region(region);
         let mut map_flags: MappingFlags = prot.into(); // no V  flag
         map_flags |= "AD".into();
         let mut lazy_alloc = true;
@@ -1136,45 +1259,245 @@ impl TaskInner {
         Ok(start)
     }
 
-    /// 用于在进程的虚拟内存空间中消除一段内存映射。传入的`start`需要是某段内存映射的首地址，`len`需要是该段内存映射的长度。
+    /// 用于在进程的虚拟内存空间中消除`[start, start + len)`范围内的内存映射。
+    ///
+    /// 部分位于范围内的内存映射区会被切分，只有范围内的部分会被消除。
     pub fn unmap(&mut self, start: usize, len: usize) -> Result<(), isize> {
-        // check whether the start is in mmap
-        let x = self.mmap.get_region(start);
-        if x.is_none() {
+        if start % FRAME_SIZE != 0 || len == 0 {
             return Err(LinuxErrno::EINVAL.into());
         }
-        // now we need make sure the start is equal to the start of the region, and the len is equal to the len of the region
-        let region = x.unwrap();
-        if region.start != start || len != region.len {
-            return Err(LinuxErrno::EINVAL.into());
+        let end = start + align_up_4k(len);
+        let removed = self.mmap.remove_range(start, end);
+        for region in removed.iter() {
+            if let Err(e) = self.write_back_shared(region) {
+                warn!(
+                    "write back shared mapping {:#x} failed: {:?}",
+                    region.start, e
+                );
+            }
+        }
+        let mut address_space = self.address_space.lock();
+        for region in removed {
+            if region.page_cache().is_some() {
+                // 共享文件映射中只有被访问过的页建立了页表项
+                for addr in region.pages.keys() {
+                    address_space
+                        .unmap_region(VirtAddr::from(*addr), FRAME_SIZE)
+                        .unwrap();
+                }
+                continue;
+            }
+            address_space
+                .unmap_region(VirtAddr::from(region.start), region.map_len)
+                .unwrap();
         }
+        Ok(())
+    }
+
+    /// 在第一次访问共享文件映射中`addr`所在的页时，将页缓存中对应的物理页映射到该页
+    ///
+    /// 如果`addr`不在共享文件映射中或者所在的页已经被映射，返回`false`。
+    pub fn map_shared_page(&mut self, addr: usize) -> AlienResult<bool> {
+        let addr = align_down_4k(addr);
+        let Some(region) = self.mmap.get_region(addr) else {
+            return Ok(false);
+        };
+        if region.pages.contains_key(&addr) {
+            return Ok(false);
+        }
+        let Some(cache) = region.page_cache() else {
+            return Ok(false);
+        };
+        let page = cache.get_page((region.offset + addr - region.start) / FRAME_SIZE)?;
+        let mut map_flags: MappingFlags = if region.prot == ProtFlags::PROT_NONE {
+            MappingFlags::R
+        } else {
+            region.prot.into()
+        };
+        map_flags |= "VAD".into();
         self.address_space
             .lock()
-            .unmap_region(VirtAddr::from(start), region.map_len)
+            .map_region(
+                VirtAddr::from(addr),
+                PhysAddr::from(page.phys_addr()),
+                FRAME_SIZE,
+                map_flags,
+                false,
+            )
             .unwrap();
-        self.mmap.remove_region(start);
+        self.mmap
+            .get_region_mut(addr)
+            .unwrap()
+            .pages
+            .insert(addr, page);
+        Ok(true)
+    }
+
+    /// 将虚拟地址范围`range`直接映射到从`phys`开始的设备内存
+    fn map_device_memory(&mut self, range: Range<usize>, phys: usize, prot: ProtFlags) {
+        let mut map_flags: MappingFlags = if prot == ProtFlags::PROT_NONE {
+            MappingFlags::R
+        } else {
+            prot.into()
+        };
+        map_flags |= "VAD".into();
+        let mut address_space = self.address_space.lock();
+        for (i, addr) in range.step_by(FRAME_SIZE).enumerate() {
+            address_space
+                .map_region(
+                    VirtAddr::from(addr),
+                    PhysAddr::from(phys + i * FRAME_SIZE),
+                    FRAME_SIZE,
+                    map_flags,
+                    false,
+                )
+                .unwrap();
+        }
+    }
+
+    /// 将共享文件映射`region`中被修改的页写回文件
+    ///
+    /// 用户态和内核都可能直接写入页表项可写的页，因此这些页都被视为脏页。
+    fn write_back_shared(&self, region: &MMapRegion) -> AlienResult<()> {
+        let Some(cache) = region.page_cache() else {
+            return Ok(());
+        };
+        for (&addr, page) in region.pages.iter() {
+            let res = self.address_space.lock().query(VirtAddr::from(addr));
+            if let Ok((_, flags, _)) = res {
+                if flags.contains(MappingFlags::W) {
+                    page.mark_dirty();
+                }
+            }
+            cache.write_back((region.offset + addr - region.start) / FRAME_SIZE)?;
+        }
+        Ok(())
+    }
+
+    /// 将`[start, end)`范围内的共享文件映射写回文件
+    ///
+    /// 如果范围内存在未被映射的地址，返回`ENOMEM`。
+    pub fn sync_shared_mmap(&mut self, start: usize, end: usize) -> AlienResult<()> {
+        for addr in (start..end).step_by(FRAME_SIZE) {
+            if self.mmap.get_region(addr).is_none()
+                && self
+                    .address_space
+                    .lock()
+                    .query(VirtAddr::from(addr))
+                    .is_err()
+            {
+                return Err(LinuxErrno::ENOMEM);
+            }
+        }
+        let regions = self
+            .mmap
+            .regions()
+            .iter()
+            .filter(|region| region.start < end && region.start + region.map_len > start)
+            .cloned()
+            .collect::<Vec<_>>();
+        for region in regions {
+            let region_end = region.start + region.map_len;
+            let mut region = region;
+            if region.start < start {
+                region = region.split(start).1;
+            }
+            if region_end > end {
+                region = region.split(end).0;
+            }
+            self.write_back_shared(&region)?;
+        }
         Ok(())
     }
 
-    /// 设置内存映射的保护位，函数会检查传入的`start`和`len`所指示的内存映射区是否已经处于被映射状态，如果是，则将对应内存映射区的保护位与`prot`做或运算。
+    /// 将所有共享文件映射写回文件，在进程退出或执行新程序前调用
+    fn sync_all_shared_mmap(&self) {
+        for region in self.mmap.regions() {
+            if let Err(e) = self.write_back_shared(region) {
+                warn!(
+                    "write back shared mapping {:#x} failed: {:?}",
+                    region.start, e
+                );
+            }
+        }
+    }
+
+    /// 设置`[start, start + len)`范围内内存映射的保护位，同时更新对应页表项的权限位。
+    ///
+    /// 不在内存映射区中的页需要已经被映射(如 elf 文件的段和动态链接器的 RELRO 段)，只修改它们的页表项；
+    /// 如果范围内存在既不在内存映射区中也没有被映射的页，返回`ENOMEM`。
     pub fn map_protect(&mut self, start: usize, len: usize, prot: ProtFlags) -> AlienResult<()> {
-        // check whether the start is in mmap
-        let x = self.mmap.get_region_mut(start);
-        if x.is_none() {
-            let res = self.address_space.lock().query(VirtAddr::from(start));
-            return if res.is_err() {
-                Err(LinuxErrno::EINVAL)
+        if start % FRAME_SIZE != 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let end = start + align_up_4k(len);
+        {
+            let address_space = self.address_space.lock();
+            for addr in (start..end).step_by(FRAME_SIZE) {
+                if self.mmap.get_region(addr).is_none()
+                    && address_space.query(VirtAddr::from(addr)).is_err()
+                {
+                    return Err(LinuxErrno::ENOMEM);
+                }
+            }
+        }
+        self.mmap.protect_range(start, end, prot);
+        self.update_pte_prot(start, end, prot);
+        Ok(())
+    }
+
+    /// 将`[start, end)`范围内已经建立的页表项的权限位修改为`prot`
+    ///
+    /// 写时复制的页 (带有 RSD 标志) 不会直接获得写权限，而是在写入时由 [`TaskInner::do_store_page_fault`] 复制。
+    /// fork 时只读的私有页没有 RSD 标志，但它的页帧仍与其它进程共享，赋予写权限时同样改为设置 RSD 标志。
+    /// 由于 RISC-V 中 R/W/X 均为 0 的页表项表示下一级页表，`PROT_NONE` 的页通过去掉 U 标志使得用户态无法访问。
+    fn update_pte_prot(&mut self, start: usize, end: usize, prot: ProtFlags) {
+        let mut address_space = self.address_space.lock();
+        let mut addr = start;
+        while addr < end {
+            let Ok((phy, flags, page_size)) = address_space.query(VirtAddr::from(addr)) else {
+                addr += FRAME_SIZE;
+                continue;
+            };
+            let mut new_flags: MappingFlags = if prot == ProtFlags::PROT_NONE {
+                MappingFlags::R
             } else {
-                Ok(())
+                prot.into()
             };
+            new_flags |= flags & (MappingFlags::from("VAD") | MappingFlags::RSD);
+            if new_flags.contains(MappingFlags::W)
+                && flags.contains(MappingFlags::V)
+                && !flags.contains(MappingFlags::RSD)
+            {
+                let frame_ref_manager = FRAME_REF_MANAGER.lock();
+                let shared = (0..usize::from(page_size) / FRAME_SIZE).any(|i| {
+                    frame_ref_manager.ref_count((phy + i * FRAME_SIZE).as_usize() >> FRAME_BITS) > 1
+                });
+                if shared {
+                    new_flags |= MappingFlags::RSD;
+                }
+            }
+            if new_flags.contains(MappingFlags::RSD) {
+                new_flags -= MappingFlags::W;
+            }
+            address_space
+                .modify_pte_flags(VirtAddr::from(addr), new_flags, false)
+                .unwrap();
+            addr += usize::from(page_size);
         }
-        // now we need make sure the start is equal to the start of the region, and the len is equal to the len of the region
-        let region = x.unwrap();
-        if start + len > region.start + region.len {
-            error!("start+len > region.start + region.len");
-            return Err(LinuxErrno::EINVAL);
+    }
+
+    /// 检查对内存映射区中地址`addr`的访问是否被其保护位所允许，不在内存映射区中的地址不做检查
+    fn check_mmap_access(&self, addr: usize, access: ProtFlags) -> AlienResult<()> {
+        if let Some(region) = self.mmap.get_region(addr) {
+            if !region.prot.intersects(access) {
+                warn!(
+                    "access {:#x} with {:?} but region prot is {:?}",
+                    addr, access, region.prot
+                );
+                return Err(AlienError::EINVAL);
+            }
         }
-        region.prot |= prot;
         Ok(())
     }
 
@@ -1183,6 +1506,10 @@ impl TaskInner {
         &mut self,
         addr: usize,
     ) -> AlienResult<Option<(Option<Arc<dyn File>>, &'static mut [u8], u64)>> {
+        self.check_mmap_access(addr, ProtFlags::PROT_READ | ProtFlags::PROT_WRITE)?;
+        if self.map_shared_page(addr)? {
+            return Ok(None);
+        }
         // check whether the addr is in mmap
         let addr = align_down_4k(addr);
         let (_phy, flags, page_size) = self
@@ -1199,28 +1526,9 @@ impl TaskInner {
         if !flags.contains(MappingFlags::V) {
             return self.invalid_page_solver(addr);
         }
-        assert!(!flags.contains(MappingFlags::RSD));
-
-        let region = self.mmap.get_region(addr).ok_or(AlienError::EINVAL)?;
-        // now we need make sure the start is equal to the start of the region, and the len is equal to the len of the region
-        // update page table
-        let mut map_flags: MappingFlags = region.prot.into();
-        map_flags |= "V".into();
-
-        let mut address_space = self.address_space.lock();
-
-        let (_, flags, _) = address_space.query(VirtAddr::from(addr)).unwrap();
-        assert!(!flags.contains(MappingFlags::V));
-        address_space
-            .validate(VirtAddr::from(addr), map_flags)
-            .unwrap();
-        let (phy, _, size) = address_space.query(VirtAddr::from(addr)).unwrap();
-        let buf =
-            unsafe { core::slice::from_raw_parts_mut(phy.as_usize() as *mut u8, size.into()) };
-        let file = &region.fd;
-
-        let read_offset = region.offset + (addr - region.start);
-        Ok(Some((file.clone(), buf, read_offset as u64)))
+        // 页表项有效但不允许读，例如被 mprotect 设置为 PROT_NONE 的 elf 段
+        warn!("load page fault at {:#x} with flags {:?}", addr, flags);
+        Err(AlienError::EINVAL)
     }
 
     /// 用于处理无效页错误
@@ -1272,6 +1580,12 @@ impl TaskInner {
             return Ok(Some((file.clone(), buf, read_offset as u64)));
         } else {
             warn!("invalid page fault in stack, addr: {:#x}", addr);
+            // 栈向下增长到超过 RLIMIT_STACK 时视为非法访问
+            let limit = self.get_prlimit(RLimitResource::RlimitStack).rlim_cur;
+            if (self.stack.end - align_down_4k(addr)) as u64 > limit {
+                warn!("stack overflow at {:#x}, limit: {:#x}", addr, limit);
+                return Err(AlienError::ENOMEM);
+            }
             let map_flags = "RWUVAD".into();
             self.address_space
                 .lock()
@@ -1286,6 +1600,10 @@ impl TaskInner {
         &mut self,
         addr: usize,
     ) -> AlienResult<Option<(Option<Arc<dyn File>>, &'static mut [u8], u64)>> {
+        self.check_mmap_access(addr, ProtFlags::PROT_EXEC)?;
+        if self.map_shared_page(addr)? {
+            return Ok(None);
+        }
         let addr = align_down_4k(addr);
         let (_phy, flags, page_size) = self
             .address_space
@@ -1310,6 +1628,10 @@ impl TaskInner {
         &mut self,
         o_addr: usize,
     ) -> AlienResult<Option<(Option<Arc<dyn File>>, &'static mut [u8], u64)>> {
+        self.check_mmap_access(o_addr, ProtFlags::PROT_WRITE)?;
+        if self.map_shared_page(o_addr)? {
+            return Ok(None);
+        }
         let addr = align_down_4k(o_addr);
         let (phy, flags, page_size) = self
             .address_space
@@ -1334,15 +1656,11 @@ impl TaskInner {
         if !flags.contains(MappingFlags::V) {
             return self.invalid_page_solver(addr);
         }
-        // if !flags.contains(MappingFlags::RSD) {
-        //     return Ok(None);
-        // }
-        assert!(
-            flags.contains(MappingFlags::RSD),
-            "addr:{:#x} flags:{:?}",
-            o_addr,
-            flags
-        );
+        if !flags.contains(MappingFlags::RSD) {
+            // 页表项有效但不允许写，例如被 mprotect 设置为只读的 elf 段
+            warn!("store page fault at {:#x} with flags {:?}", o_addr, flags);
+            return Err(AlienError::EINVAL);
+        }
         // decrease the reference count
         let mut flags = flags | "W".into();
         flags -= MappingFlags::RSD;
@@ -1382,8 +1700,14 @@ impl Task {
         inner.children.clear();
         let thread_number = inner.thread_number;
         if thread_number == 0 {
+            inner.sync_all_shared_mmap();
+            // 释放映射区持有的缓存页和设备内存映射
+            inner.mmap = MMapInfo::new();
             let _ = inner.fd_table.lock().clear();
             drop(inner);
+        } else if self.pid == self.tid.0 && Arc::strong_count(&inner.fd_table) == 1 {
+            // 与父进程共享地址空间的子进程没有执行 execve 就退出时，也需要关闭只属于它的文件
+            let _ = inner.fd_table.lock().clear();
         }
     }
 
@@ -1413,6 +1737,8 @@ impl Task {
             tid,
             kernel_stack: k_stack,
             pid,
+            child_wait: WaitQueue::new(),
+            child_event: AtomicUsize::new(0),
             inner: Mutex::new(TaskInner {
                 name: name.to_string(),
                 threads: MinimalManager::new(MAX_THREAD_NUM),
@@ -1437,6 +1763,7 @@ impl Task {
                     elf_info.heap_bottom,
                     elf_info.heap_bottom,
                 ))),
+                resource_limits: Arc::new(Mutex::new(ResourceLimits::new())),
                 mmap: MMapInfo::new(),
                 signal_handlers: Arc::new(Mutex::new(SignalHandlers::new())),
                 signal_receivers: Arc::new(Mutex::new(SignalReceivers::new())),
@@ -1448,7 +1775,7 @@ impl Task {
                 shm: BTreeMap::new(),
                 cpu_affinity: {
                     let mut affinity = 0;
-                    affinity.set_bits(0..CPU_NUM, 1 << CPU_NUM - 1);
+                    affinity.set_bits(0..CPU_NUM, (1 << CPU_NUM) - 1);
                     affinity
                 },
                 unmask: 0o022,
@@ -1460,6 +1787,15 @@ impl Task {
                     ss_size: 0,
                 },
                 exit_group: false,
+                pgid: pid,
+                sid: pid,
+                cred: Credentials::root(),
+                stopped: false,
+                job_status: None,
+                vfork_done: false,
+                sched: SchedEntity::new(),
+                cmdline: join_with_nul([name]),
+                environ: Vec::new(),
             }),
             send_sigchld_when_exit: false,
         };
@@ -1486,25 +1822,21 @@ impl Task {
     /// `flag`用于控制父子进程之间资源的共享程度，有关flag值及其相关含义设置可见[`CloneFlags`]。
     /// `stack`用于控制子进程的用户栈。由于clone产生的子进程有可能和父进程共享内存，所以它不能使用父进程的栈。
     /// `sig`用于控制子进程退出时传递给父进程的相关信号。目前Alien中的设计为当其值为`SIGCHLD`时，在子进程退出时会向父程序发送`SIGCHLD`信号。会其它有关值的设置可见[`SignalNumber`]。
-    /// `ptid`是一个在父进程地址空间中的地址，用于在创建子进程成功后向该位置写入子进程的tid号。在flag包含`CLONE_PARENT_SETTID`时才会发挥效果。
     /// `tls`用于为子进程创建新的TLS(thread-local storage)值，在flag包含`CLONE_SETTLS`时才会实际产生效果。
     /// `ctid`用于给子进程中的[`set_child_tid`]和[`clear_child_tid`]赋值(分别在flag中包含`CLONE_CHILD_SETTID`和`CLONE_CHILD_CLEARTID`时产生效果)。
     ///
-    /// 成功创建子进程后父进程会返回子进程的TCB。
-    ///
-    /// Note: 当传入的ptid未在父进程地址空间中被分配时，会引发panic。
+    /// 成功创建子进程后父进程会返回子进程的TCB。`CLONE_PARENT_SETTID` 由调用者在父进程地址空间中处理。
     pub fn t_clone(
         self: &Arc<Self>,
         flag: CloneFlags,
         stack: usize,
         sig: SignalNumber,
-        ptid: usize,
         tls: usize,
         ctid: usize,
     ) -> Option<Arc<Task>> {
         warn!(
-            "clone: flag:{:?}, sig:{:?}, stack:{:#x}, ptid:{:#x}, tls:{:#x}, ctid:{:#x}",
-            flag, sig, stack, ptid, tls, ctid
+            "clone: flag:{:?}, sig:{:?}, stack:{:#x}, tls:{:#x}, ctid:{:#x}",
+            flag, sig, stack, tls, ctid
         );
         let tid = TidHandle::new()?;
         let mut inner = self.inner.lock();
@@ -1513,8 +1845,21 @@ impl Task {
             inner.address_space.clone()
         } else {
             // to create process
-            let address_space =
-                build_cow_address_space(&mut inner.address_space.lock(), inner.shm.clone());
+            // shm, shared file mappings and device memory should be shared with the child instead of cow
+            let shared = inner
+                .shm
+                .values()
+                .map(|shm| shm.start_va..shm.end_va)
+                .chain(
+                    inner
+                        .mmap
+                        .regions()
+                        .iter()
+                        .filter(|region| region.page_cache().is_some() || region.is_device_memory())
+                        .map(|region| region.start..region.start + region.map_len),
+                )
+                .collect::<Vec<_>>();
+            let address_space = build_cow_address_space(&mut inner.address_space.lock(), shared);
             Arc::new(Mutex::new(address_space))
         };
 
@@ -1530,6 +1875,12 @@ impl Task {
             Arc::new(Mutex::new(inner.signal_handlers.lock().clone()))
         };
 
+        let resource_limits = if flag.contains(CloneFlags::CLONE_THREAD) {
+            inner.resource_limits.clone()
+        } else {
+            Arc::new(Mutex::new(inner.resource_limits.lock().clone()))
+        };
+
         let parent = if flag.contains(CloneFlags::CLONE_PARENT) {
             inner.parent.clone()
         } else {
@@ -1576,20 +1927,6 @@ impl Task {
             trap_context.update_tp(tls);
         }
 
-        // 检查是否在父任务地址中写入 tid
-        if flag.contains(CloneFlags::CLONE_PARENT_SETTID) {
-            // 有可能这个地址是 lazy alloc 的，需要先检查
-            let res = inner.address_space.lock().query(VirtAddr::from(ptid));
-            if res.is_ok() {
-                let (physical, _, _) = res.unwrap();
-                unsafe {
-                    *(physical.as_usize() as *mut i32) = tid.0 as i32;
-                }
-            } else {
-                panic!("clone: ptid is not mapped")
-            }
-        }
-
         let ctid_value = if flag.contains(CloneFlags::CLONE_CHILD_SETTID)
             || flag.contains(CloneFlags::CLONE_CHILD_CLEARTID)
         {
@@ -1608,7 +1945,6 @@ impl Task {
             }
         }
         if stack != 0 {
-            assert!(flag.contains(CloneFlags::CLONE_VM));
             // set the sp of the new process
             trap_context.regs()[2] = stack;
         }
@@ -1618,6 +1954,8 @@ impl Task {
             tid,
             kernel_stack: k_stack,
             pid,
+            child_wait: WaitQueue::new(),
+            child_event: AtomicUsize::new(0),
             inner: Mutex::new(TaskInner {
                 name: inner.name.clone(),
                 threads: MinimalManager::new(MAX_THREAD_NUM),
@@ -1633,6 +1971,7 @@ impl Task {
                 timer: TaskTimer::default(),
                 exit_code: 0,
                 heap,
+                resource_limits,
                 mmap: inner.mmap.clone(),
                 signal_handlers,
                 signal_receivers,
@@ -1650,11 +1989,7 @@ impl Task {
                 signal_set_siginfo: false,
                 robust: RobustList::default(),
                 shm: inner.shm.clone(),
-                cpu_affinity: {
-                    let mut affinity = 0;
-                    affinity.set_bits(0..CPU_NUM, 1 << CPU_NUM - 1);
-                    affinity
-                },
+                cpu_affinity: inner.cpu_affinity,
                 unmask: 0o022,
                 stack: inner.stack.clone(),
                 need_wait: 0,
@@ -1664,12 +1999,29 @@ impl Task {
                     ss_size: 0,
                 },
                 exit_group: false,
+                pgid: inner.pgid,
+                sid: inner.sid,
+                cred: inner.cred.clone(),
+                stopped: false,
+                job_status: None,
+                vfork_done: false,
+                sched: inner.sched.fork(),
+                cmdline: inner.cmdline.clone(),
+                environ: inner.environ.clone(),
             }),
             send_sigchld_when_exit: sig == SignalNumber::SIGCHLD,
         };
         let task = Arc::new(task);
+        global_register_task(&task);
         if !flag.contains(CloneFlags::CLONE_PARENT) {
             inner.children.push(task.clone());
+        } else {
+            // 子任务的父进程是当前任务的父进程
+            drop(inner);
+            let parent = task.access_inner().parent.clone();
+            if let Some(parent) = parent.and_then(|parent| parent.upgrade()) {
+                parent.insert_child(task.clone());
+            }
         }
         info!("create a task success");
         Some(task)
@@ -1697,9 +2049,20 @@ impl Task {
         }
         let elf_info = elf_info.unwrap();
         let mut inner = self.inner.lock();
-        assert_eq!(inner.thread_number, 0);
+        if inner.thread_number != 0 {
+            // 以 `CLONE_VM` 创建的子进程(如 vfork)执行 execve 时，释放其在共享地址空间中的 trap 上下文，
+            // 新的地址空间中 trap 上下文位于 TRAP_CONTEXT_BASE
+            let trap_frame_ptr = inner.trap_frame_ptr() as usize;
+            inner
+                .address_space
+                .lock()
+                .unmap_region(VirtAddr::from(trap_frame_ptr), FRAME_SIZE)
+                .unwrap();
+            inner.thread_number = 0;
+        }
         let name = elf_info.name;
         let address_space = elf_info.address_space;
+        inner.sync_all_shared_mmap();
         // reset the address space
         inner.address_space = Arc::new(Mutex::new(address_space));
         // reset the heap
@@ -1807,6 +2170,8 @@ impl Task {
         let argc_ptr = user_stack.push(argc).unwrap();
         let user_sp = argc_ptr;
         warn!("args:{:?}, env:{:?}, user_sp: {:#x}", args, env, user_sp);
+        inner.cmdline = join_with_nul(&args);
+        inner.environ = join_with_nul(&env);
         let (physical, _, _) = inner
             .address_space
             .lock()
@@ -1821,6 +2186,22 @@ impl Task {
             user_trap_vector as usize,
         );
         trap_frame.regs()[4] = elf_info.tls; // tp --> tls
+        inner.vfork_done = true;
+        let parent = inner.parent.clone();
+        drop(inner);
+        if let Some(parent) = parent.and_then(|parent| parent.upgrade()) {
+            parent.child_changed();
+        }
         Ok(())
     }
 }
+
+/// 将一组字符串拼接为以 '\0' 分隔的字节序列，每个字符串都以 '\0' 结尾
+fn join_with_nul<S: AsRef<str>>(strs: impl IntoIterator<Item = S>) -> Vec<u8> {
+    let mut res = Vec::new();
+    for s in strs {
+        res.extend_from_slice(s.as_ref().trim_end_matches('\0').as_bytes());
+        res.push(0);
+    }
+    res
+}
@@ -11,20 +11,33 @@
 //!
 //! 对于时间片 (每次引发时钟中断的时间间隔) 大小的设计：目前 Alien 中用户态和内核态下采用相同的时间片间隔，1s 内触发 10 次时钟中断。
 
-use alloc::sync::Arc;
+use alloc::{collections::BinaryHeap, sync::Arc, vec::Vec};
+use core::{
+    cmp::Ordering,
+    sync::atomic::{AtomicUsize, Ordering as AtomicOrdering},
+};
 
+use arch::{hart_id, interrupt_disable, interrupt_enable, is_interrupt_enable};
+use config::CPU_NUM;
 use constants::{
     io::OpenFlags,
     time::{ClockId, ITimeSpec, ITimerVal, TimeSpec, TimeVal, TimerFdFlags, TimerType},
-    AlienResult, FromUsize, LinuxErrno,
+    AlienError, AlienResult, FromUsize, LinuxErrno,
 };
+use ksync::Mutex;
 use log::{info, warn};
 use platform::{config::CLOCK_FREQ, set_timer};
+use shim::KTask;
+use spin::Lazy;
 use syscall_table::syscall_func;
-use timer::{get_time_ms, read_timer, TimeNow, Times, ToClock};
-use vfs::timerfd::TimerFile;
+use timer::{get_time_ms, read_timer, TimeFromFreq, TimeNow, Times, ToClock};
+use vfs::timerfd::{check_timerfds, register_timerfd, TimerFile};
 
-use crate::task::{current_task, do_suspend, StatisticalData};
+use crate::task::{
+    current_task,
+    schedule::{schedule, wake_up_task},
+    StatisticalData, Task, TaskState,
+};
 
 #[inline]
 #[allow(unused)]
@@ -49,11 +62,22 @@ pub fn read_time_us() -> u64 {
 const TICKS_PER_SEC: usize = 10;
 // const TICKS_PER_SEC_IN_KERNEL: usize = 1000;
 
+/// 每个核上已经设置的下一次时钟中断的时间
+static NEXT_TRIGGER: [AtomicUsize; CPU_NUM] = [const { AtomicUsize::new(0) }; CPU_NUM];
+
 /// 设置下一次时钟的中断
+///
+/// 如果当前核的计时器队列中有更早到期的计时器，则以该计时器的结束时间作为下一次中断的时间，
+/// 保证睡眠的任务能够在到期时被准时唤醒。
 #[inline]
 pub fn set_next_trigger() {
     let next = read_timer() + CLOCK_FREQ / TICKS_PER_SEC;
     assert!(next > read_timer());
+    let next = match next_timer_deadline() {
+        Some(deadline) if deadline < next => deadline,
+        _ => next,
+    };
+    NEXT_TRIGGER[hart_id()].store(next, AtomicOrdering::Relaxed);
     set_timer(next);
 }
 
@@ -62,9 +86,7 @@ pub fn set_next_trigger() {
 /// 原设计为内核态下的时间片设置的更短一些，以免一个进程在进入内核态前后占用过多的时间片。但目前修改为 内核态和用户态下的时间片大小相同。
 #[inline]
 pub fn set_next_trigger_in_kernel() {
-    let next = read_timer() + CLOCK_FREQ / TICKS_PER_SEC;
-    assert!(next > read_timer());
-    set_timer(next);
+    set_next_trigger();
 }
 
 /// 一个系统调用函数，获取当前的时间，获取的时间将存储在`tv`所指向的[`TimeVal`]结构处。
@@ -104,32 +126,27 @@ pub fn times_from_process_data(data: &StatisticalData) -> Times {
 }
 
 /// 一个系统调用函数，暂停本进程直到一段时间后结束，要暂停的时间将保存在`req`所指向的[`TimeSpec`]结构处。
-/// 但在`nanosleep`执行过程中，本进程有可能被其他信号唤醒。
+/// 但在`nanosleep`执行过程中，本进程有可能被其他信号唤醒，此时若`rem`不为空，剩余的睡眠时间将保存在`rem`所指向的位置。
 /// 函数若正常停止`req`时间则返回0；如果由于因为其他信号而被唤醒，此时函数返回-1(EINTR)。
 ///
 /// Reference: [nanosleep](https://man7.org/linux/man-pages/man2/nanosleep.2.html)
 #[syscall_func(101)]
-pub fn nanosleep(req: *mut u8, _: *mut u8) -> isize {
+pub fn nanosleep(req: *mut u8, rem: *mut u8) -> AlienResult<isize> {
     let task = current_task().unwrap().clone();
     let mut time = TimeSpec::new(0, 0);
     task.access_inner()
         .copy_from_user(req as *const TimeSpec, &mut time);
     warn!("nanosleep: {:?}", time);
     let end_time = read_timer() + time.to_clock();
-    loop {
-        if read_timer() >= end_time {
-            break;
-        }
-        do_suspend();
-        let task = current_task().unwrap();
-        // interrupt by signal
-        let task_inner = task.access_inner();
-        let receiver = task_inner.signal_receivers.lock();
-        if receiver.have_signal() {
-            return LinuxErrno::EINTR as isize;
+    if let Err(err) = sleep_until(end_time) {
+        if !rem.is_null() {
+            let remain = TimeSpec::from_freq(end_time.saturating_sub(read_timer()));
+            task.access_inner()
+                .copy_to_user(&remain, rem as *mut TimeSpec);
         }
+        return Err(err);
     }
-    0
+    Ok(0)
 }
 
 /// 一个系统调用函数，可以根据输入的时钟类型`clock_id`来获取当前的时间，获取的时间将存储在`tp`所指向的[`TimeSpec`]结构处。
@@ -154,11 +171,161 @@ pub fn clock_get_time(clock_id: usize, tp: *mut u8) -> isize {
     0
 }
 
-/// 当发生时钟中断时，`trap_handler` 会调用该函数检查所有计时器队列中的计时器，并唤醒等待在这些计时器上的进程
+/// 放入计时器队列中的计时器，到期时唤醒等待在其上的任务
+#[derive(Debug)]
+pub struct Timer {
+    /// 计时器的结束时间，以 cpu 时钟为单位
+    pub end_time: usize,
+    /// 等待该计时器的任务
+    pub task: Arc<Task>,
+}
+
+impl Timer {
+    /// 创建一个新的计时器
+    pub fn new(end_time: usize, task: Arc<Task>) -> Self {
+        Self { end_time, task }
+    }
+}
+
+impl PartialEq for Timer {
+    fn eq(&self, other: &Self) -> bool {
+        self.end_time == other.end_time
+    }
+}
+
+impl Eq for Timer {}
+
+impl PartialOrd for Timer {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Timer {
+    /// [`BinaryHeap`] 为大根堆，这里反转比较的结果，使得最早到期的计时器位于堆顶
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.end_time.cmp(&self.end_time)
+    }
+}
+
+/// 每个核上的计时器队列，按照计时器的结束时间组织为小根堆
+pub static TIMER_QUEUE: Lazy<[Mutex<BinaryHeap<Timer>>; CPU_NUM]> =
+    Lazy::new(|| core::array::from_fn(|_| Mutex::new(BinaryHeap::new())));
+
+/// 获取当前核的计时器队列中最早到期的时间
+fn next_timer_deadline() -> Option<usize> {
+    TIMER_QUEUE[hart_id()]
+        .lock()
+        .peek()
+        .map(|timer| timer.end_time)
+}
+
+/// 将一个计时器加入当前核的计时器队列中
+///
+/// 如果该计时器比已经设置的下一次时钟中断更早到期，则重新设置时钟中断的时间。
+pub fn push_to_timer_queue(end_time: usize, task: Arc<Task>) {
+    let hart = hart_id();
+    TIMER_QUEUE[hart].lock().push(Timer::new(end_time, task));
+    if end_time < NEXT_TRIGGER[hart].load(AtomicOrdering::Relaxed) {
+        NEXT_TRIGGER[hart].store(end_time, AtomicOrdering::Relaxed);
+        set_timer(end_time);
+    }
+}
+
+/// 从所有核的计时器队列中移除任务号为 `tid` 的任务的计时器
 ///
-/// 遍历所有计时器队列 [`TIMER_QUEUE`] 中的计时器，若计时器的超时时间在当前时间之前(即已超时)，那么将该等待的进程加入
-/// 线程池的首位，马上对其进行调度。
-pub fn check_timer_queue() {}
+/// 任务因为其它事件提前被唤醒时，需要调用该函数撤销尚未到期的计时器，避免之后被错误地唤醒。
+pub fn remove_from_timer_queue(tid: usize) {
+    TIMER_QUEUE.iter().for_each(|queue| {
+        queue
+            .lock()
+            .retain(|timer| timer.task.get_tid() as usize != tid);
+    });
+}
+
+/// 使当前任务进入等待状态，直到 cpu 时钟到达 `end_time`
+///
+/// 睡眠期间任务不会被调度，直到计时器到期或者收到信号时才会被唤醒。
+/// 如果因为信号而被唤醒，返回 `EINTR`。
+pub fn sleep_until(end_time: usize) -> AlienResult<()> {
+    let task = current_task().unwrap().clone();
+    let tid = task.get_tid() as usize;
+    loop {
+        if read_timer() >= end_time {
+            return Ok(());
+        }
+        if task.have_signal() {
+            remove_from_timer_queue(tid);
+            return Err(AlienError::EINTR);
+        }
+        // 在切换到其它任务前关闭中断，避免时钟中断在任务真正让出 CPU 前将其唤醒
+        let enable = is_interrupt_enable();
+        interrupt_disable();
+        task.update_state(TaskState::Waiting);
+        push_to_timer_queue(end_time, task.clone());
+        schedule();
+        if enable {
+            interrupt_enable();
+        }
+    }
+}
+
+/// 在等待队列上睡眠的任务让出 CPU，直到被唤醒、cpu 时钟到达 `deadline`，或者在 `interruptible` 时收到信号
+///
+/// 调用前任务需要已经进入等待状态。如果任务在让出 CPU 之前已经被唤醒并放入了调度队列，则由调度队列中的那一份继续执行。
+pub fn wait_timeout(deadline: Option<usize>, interruptible: bool) {
+    let task = current_task().unwrap().clone();
+    // 计时器队列中的任务收到信号时也会被唤醒
+    let wake_time = deadline.or(interruptible.then_some(usize::MAX));
+    let enable = is_interrupt_enable();
+    interrupt_disable();
+    let woken = {
+        let mut inner = task.access_inner();
+        let woken = inner.state == TaskState::Ready;
+        inner.state = TaskState::Waiting;
+        woken
+    };
+    if !woken {
+        if let Some(wake_time) = wake_time {
+            push_to_timer_queue(wake_time, task.clone());
+        }
+    }
+    schedule();
+    if enable {
+        interrupt_enable();
+    }
+    if !woken && wake_time.is_some() {
+        remove_from_timer_queue(task.get_tid() as usize);
+    }
+}
+
+/// 当发生时钟中断时，`trap_handler` 会调用该函数检查当前核计时器队列中的计时器，并唤醒等待在这些计时器上的进程
+///
+/// 遍历当前核的计时器队列 [`TIMER_QUEUE`] 中的计时器，若计时器的超时时间在当前时间之前(即已超时)，那么将该等待的进程
+/// 重新放入调度队列中。同时，收到信号的等待进程也会被提前唤醒，到期的 timerfd 会通知监听它的 epoll 实例。
+pub fn check_timer_queue() {
+    let now = read_timer();
+    let mut wake_list = Vec::new();
+    {
+        let mut queue = TIMER_QUEUE[hart_id()].lock();
+        while let Some(timer) = queue.peek() {
+            if timer.end_time > now {
+                break;
+            }
+            wake_list.push(queue.pop().unwrap().task);
+        }
+        queue.retain(|timer| {
+            if timer.task.have_signal() {
+                wake_list.push(timer.task.clone());
+                false
+            } else {
+                true
+            }
+        });
+    }
+    wake_list.into_iter().for_each(wake_up_task);
+    check_timerfds();
+}
 
 /// 一个系统调用函数，用于获取当前进程的计时器，保存在`current_value`指向的[`ITimerVal`]结构处。
 /// 由于Alien目前每个进程只支持一个计时器，原定于分辨计时器种类的`_which`在此处并没有派上用场。
@@ -238,7 +405,8 @@ pub fn clock_getres(id: usize, res: usize) -> isize {
 
 /// 一个系统调用函数，如`nanosleep`一样，暂停本进程直到一段时间后结束，但`clock_nanosleep`可以根据传入的`clock_id`来指定使用的时钟类型。
 ///
-/// 要暂停的时间将保存在`req`所指向的[`TimeSpec`]结构处。目前仅支持`Monotonic`，输入其它时钟类型将会返回使得进程panic。
+/// 要暂停的时间将保存在`req`所指向的[`TimeSpec`]结构处。目前仅支持`Monotonic`和`Realtime`，输入其它时钟类型将会使得进程panic。
+/// 当`flags`包含`TIMER_ABSTIME`时，`req`表示睡眠结束的绝对时间，否则表示相对时间。
 /// 如`nanosleep`一样，在`clock_nanosleep`执行过程中，本进程也有可能被其他信号唤醒。
 ///
 /// 函数若正常停止`req`时间则返回0；如果由于因为其他信号而被唤醒，此时函数返回-1(EINTR)。
@@ -253,27 +421,24 @@ pub fn clock_nanosleep(clock_id: usize, flags: usize, req: usize, remain: usize)
         id, flags, req, remain
     );
     match id {
-        ClockId::Monotonic => {
-            assert_eq!(flags, TIMER_ABSTIME);
+        ClockId::Monotonic | ClockId::Realtime => {
             let mut target_time = TimeSpec::new(0, 0);
             let task = current_task().unwrap().clone();
             task.access_inner()
                 .copy_from_user(req as *const TimeSpec, &mut target_time);
-            let end_time = target_time.to_clock();
-
-            loop {
-                let now = read_timer();
-                if now >= end_time {
-                    break;
-                }
-                do_suspend();
-                let task = current_task().unwrap();
-                // check signal
-                let task_inner = task.access_inner();
-                let receiver = task_inner.signal_receivers.lock();
-                if receiver.have_signal() {
-                    return LinuxErrno::EINTR.into();
+            let end_time = if flags & TIMER_ABSTIME != 0 {
+                target_time.to_clock()
+            } else {
+                read_timer() + target_time.to_clock()
+            };
+            if let Err(err) = sleep_until(end_time) {
+                // 绝对时间的睡眠不需要返回剩余时间
+                if flags & TIMER_ABSTIME == 0 && remain != 0 {
+                    let rem = TimeSpec::from_freq(end_time.saturating_sub(read_timer()));
+                    task.access_inner()
+                        .copy_to_user(&rem, remain as *mut TimeSpec);
                 }
+                return err.into();
             }
         }
         _ => {
@@ -292,10 +457,9 @@ pub fn timerfd_create(clock_id: usize, flags: u32) -> AlienResult<isize> {
     let flags = OpenFlags::from_bits_truncate(flags as usize);
     let task = current_task().unwrap();
     // println_color!(32, "timerfd_create: Id: {:?} ,flags: {:?}", id, flags);
-    let timer_file = TimerFile::new(flags, ITimeSpec::default(), id);
-    let fd = task
-        .add_file(Arc::new(timer_file))
-        .map_err(|_| LinuxErrno::EMFILE)?;
+    let timer_file = Arc::new(TimerFile::new(flags, ITimeSpec::default(), id));
+    register_timerfd(&timer_file);
+    let fd = task.add_file(timer_file).map_err(|_| LinuxErrno::EMFILE)?;
     Ok(fd as isize)
 }
 
@@ -4,16 +4,19 @@
 use interrupt::record_irq;
 
 use crate::{
-    ipc::solve_futex_wait,
-    task::do_suspend,
+    task::{do_suspend, schedule::scheduler_tick},
     time::{check_timer_queue, set_next_trigger},
 };
 
 /// 时钟中断处理函数
+///
+/// 只有当前任务需要被抢占时才会让出 CPU，具体可见 [`scheduler_tick`]。
 pub fn timer_interrupt_handler() {
     record_irq(1);
+    krandom::add_timer_randomness();
     check_timer_queue();
-    solve_futex_wait();
     set_next_trigger();
-    do_suspend();
+    if scheduler_tick() {
+        do_suspend();
+    }
 }
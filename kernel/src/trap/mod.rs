@@ -23,7 +23,7 @@ use riscv::register::{
 };
 
 use crate::{
-    ipc::{send_signal, signal_handler, signal_return, solve_futex_wait},
+    ipc::{send_signal, signal_handler, signal_return},
     task::{current_task, current_trap_frame, current_user_token, do_exit, do_suspend},
     time::{check_timer_queue, set_next_trigger_in_kernel},
     trap::context::KTrapFrame,
@@ -203,8 +203,8 @@ impl TrapHandler for Trap {
             Trap::Interrupt(Interrupt::SupervisorTimer) => {
                 trace!("[kernel] timer interrupt");
                 record_irq(1);
+                krandom::add_timer_randomness();
                 check_timer_queue();
-                solve_futex_wait();
                 set_next_trigger_in_kernel();
             }
             Trap::Exception(Exception::StorePageFault) => {
@@ -269,10 +269,17 @@ pub fn user_trap_vector() {
 }
 
 /// 用于检查进程的计时器是否超时。如果超时则会重置计时器，并按照计时器类型向进程发送信号。
+///
+/// 同时检查进程使用的 CPU 时间是否超过 `RLIMIT_CPU`，超过时发送 `SIGXCPU` 或 `SIGKILL`。
 pub fn check_task_timer_expired() {
     let task = current_task().unwrap();
     let timer_expired = task.access_inner().check_timer_expired();
     let tid = task.get_tid() as usize;
+    let cpu_limit = task.access_inner().check_cpu_limit();
+    if let Some(sig) = cpu_limit {
+        warn!("task {} exceeds RLIMIT_CPU, send {:?}", tid, sig);
+        send_signal(tid, sig as usize);
+    }
     if timer_expired.is_some() {
         error!("timer expired: {:?}", timer_expired);
         let timer_type = timer_expired.unwrap();
@@ -53,3 +53,64 @@ impl FromUsize for TimeVal {
         }
     }
 }
+
+/// 资源限制的数量，对应 `linux` 中的 `RLIM_NLIMITS`
+pub const RLIM_NLIMITS: usize = 16;
+/// 表示不限制资源使用量
+pub const RLIM_INFINITY: u64 = u64::MAX;
+
+/// `getrlimit`/`setrlimit`/`prlimit64` 中可以限制的资源类型，比 [`PrLimitResType`] 更完整
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(usize)]
+pub enum RLimitResource {
+    /// 进程可以使用的 CPU 时间，单位为秒
+    RlimitCpu = 0,
+    /// 进程可以创建的文件的最大长度
+    RlimitFsize = 1,
+    /// 进程数据段(堆)的最大长度
+    RlimitData = 2,
+    /// 进程栈的最大长度
+    RlimitStack = 3,
+    RlimitCore = 4,
+    RlimitRss = 5,
+    /// 实际用户可以拥有的最大进程(线程)数量
+    RlimitNproc = 6,
+    /// 进程可以打开的最大文件描述符加一
+    RlimitNofile = 7,
+    RlimitMemlock = 8,
+    /// 进程虚拟地址空间的最大长度
+    RlimitAs = 9,
+    RlimitLocks = 10,
+    RlimitSigpending = 11,
+    RlimitMsgqueue = 12,
+    RlimitNice = 13,
+    RlimitRtprio = 14,
+    RlimitRttime = 15,
+}
+
+impl TryFrom<usize> for RLimitResource {
+    type Error = ();
+
+    fn try_from(value: usize) -> Result<Self, Self::Error> {
+        let resource = match value {
+            0 => Self::RlimitCpu,
+            1 => Self::RlimitFsize,
+            2 => Self::RlimitData,
+            3 => Self::RlimitStack,
+            4 => Self::RlimitCore,
+            5 => Self::RlimitRss,
+            6 => Self::RlimitNproc,
+            7 => Self::RlimitNofile,
+            8 => Self::RlimitMemlock,
+            9 => Self::RlimitAs,
+            10 => Self::RlimitLocks,
+            11 => Self::RlimitSigpending,
+            12 => Self::RlimitMsgqueue,
+            13 => Self::RlimitNice,
+            14 => Self::RlimitRtprio,
+            15 => Self::RlimitRttime,
+            _ => return Err(()),
+        };
+        Ok(resource)
+    }
+}
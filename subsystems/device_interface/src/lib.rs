@@ -1,8 +1,12 @@
 #![no_std]
 
+extern crate alloc;
+
+use alloc::{string::String, sync::Arc};
 use core::any::Any;
 
 use constants::{io::RtcTime, AlienResult};
+use ksync::poll::PollQueue;
 
 pub trait DeviceBase: Sync + Send {
     fn handle_irq(&self);
@@ -35,6 +39,57 @@ pub trait InputDevice: DeviceBase {
     fn is_empty(&self) -> bool;
     fn read_event_async(&self) -> u64;
     fn read_event_without_block(&self) -> Option<u64>;
+    /// Hand every event to `handler` in the interrupt handler, the events are still buffered
+    /// for `read_event_*`
+    fn set_handler(&self, _handler: Arc<dyn InputHandler>) {}
+    /// The name of the device
+    fn name(&self) -> String {
+        String::new()
+    }
+    /// The bus type, vendor, product and version of the device
+    fn input_id(&self) -> InputId {
+        InputId::default()
+    }
+    /// Fill `bits` with the bitmap of the codes supported for the event type `event_type`,
+    /// return the size of the bitmap, 0 if the event type is not supported
+    fn event_bits(&self, _event_type: u16, _bits: &mut [u8]) -> usize {
+        0
+    }
+    /// Fill `bits` with the bitmap of the device properties, return the size of the bitmap
+    fn prop_bits(&self, _bits: &mut [u8]) -> usize {
+        0
+    }
+    /// The range and resolution of the absolute axis `axis`, `None` if the axis is not supported
+    fn abs_info(&self, _axis: u16) -> Option<InputAbsInfo> {
+        None
+    }
+}
+
+/// The upper layer (usually an evdev) fed by an input device from its interrupt handler
+pub trait InputHandler: Send + Sync {
+    fn handle_event(&self, event_type: u16, code: u16, value: u32);
+}
+
+/// The identity of an input device, the same as `struct input_id` in Linux
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InputId {
+    pub bustype: u16,
+    pub vendor: u16,
+    pub product: u16,
+    pub version: u16,
+}
+
+/// The state of an absolute axis, the same as `struct input_absinfo` in Linux
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InputAbsInfo {
+    pub value: i32,
+    pub minimum: i32,
+    pub maximum: i32,
+    pub fuzz: i32,
+    pub flat: i32,
+    pub resolution: i32,
 }
 
 pub trait RtcDevice: DeviceBase {
@@ -47,6 +102,23 @@ pub trait UartDevice: DeviceBase {
     fn put_bytes(&self, bytes: &[u8]);
     fn have_data_to_get(&self) -> bool;
     fn have_space_to_put(&self) -> bool;
+    /// The queue notified when new data arrives, `None` if the device does not support it
+    fn poll_queue(&self) -> Option<&PollQueue> {
+        None
+    }
+    /// Hand the received data to `receiver` in the interrupt handler instead of buffering it,
+    /// data buffered before the call is delivered immediately
+    fn set_receiver(&self, _receiver: Arc<dyn UartReceiver>) {}
+}
+
+/// The upper layer (usually a tty) fed by a uart from its interrupt handler
+pub trait UartReceiver: Send + Sync {
+    fn receive(&self, bytes: &[u8]);
 }
 
 pub trait NetDevice: DeviceBase {}
+
+pub trait RngDevice: DeviceBase {
+    /// Fill `buf` with random bytes, return the number of bytes filled
+    fn read_entropy(&self, buf: &mut [u8]) -> AlienResult<usize>;
+}
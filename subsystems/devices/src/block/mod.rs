@@ -0,0 +1,257 @@
+//! 块设备
+//!
+//! 探测到的每个磁盘都通过 [`register_disk`] 登记，并按照种类命名为 `vda`、`mmcblk0` 或者 `ram0`。
+//! 磁盘上的 MBR 或 GPT 分区表会被解析，每个分区作为一个独立的块设备登记，例如 `vda1`、`mmcblk0p1`。
+mod partition;
+
+use alloc::{format, string::String, sync::Arc, vec::Vec};
+
+use constants::{AlienResult, DeviceId};
+use device_interface::BlockDevice;
+use drivers::block_device::GenericBlockDevice;
+use ksync::Mutex;
+use log::{info, warn};
+pub use partition::Partition;
+use vfscore::{
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    utils::{VfsFileStat, VfsNodeType, VfsPollEvents},
+    VfsResult,
+};
+
+const SECTOR_SIZE: usize = 512;
+
+const BLKGETSIZE: u32 = 0x1260;
+const BLKFLSBUF: u32 = 0x1261;
+const BLKSSZGET: u32 = 0x1268;
+const BLKGETSIZE64: u32 = 0x80081272;
+
+/// 磁盘的种类，决定了磁盘及其分区的命名方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiskKind {
+    /// virtio-blk 磁盘，依次命名为 `vda`、`vdb`、...
+    Virtio,
+    /// SD 卡，依次命名为 `mmcblk0`、`mmcblk1`、...
+    Mmc,
+    /// 内存中的磁盘镜像，依次命名为 `ram0`、`ram1`、...
+    Ram,
+}
+
+/// 一个登记的块设备，可能是整个磁盘，也可能是磁盘上的一个分区
+#[derive(Clone)]
+pub struct BlockDeviceEntry {
+    /// 设备名，同时也是 `/dev` 中设备文件的名字
+    pub name: String,
+    pub kind: DiskKind,
+    pub device: Arc<dyn BlockDevice>,
+    /// 分区所在磁盘的设备名，整个磁盘为 `None`
+    pub disk: Option<String>,
+    /// 磁盘上的分区数量，分区的该值为 0
+    pub partitions: usize,
+}
+
+impl BlockDeviceEntry {
+    pub fn is_partition(&self) -> bool {
+        self.disk.is_some()
+    }
+}
+
+/// 所有的块设备，每个磁盘之后紧跟着它的分区
+static BLOCK_DEVICES: Mutex<Vec<BlockDeviceEntry>> = Mutex::new(Vec::new());
+
+/// 磁盘的设备名，`index` 为同一种类的磁盘中的序号
+fn disk_name(kind: DiskKind, index: usize) -> String {
+    match kind {
+        DiskKind::Virtio => {
+            // a ~ z, aa ~ zz, ...
+            let mut suffix = Vec::new();
+            let mut n = index + 1;
+            while n > 0 {
+                n -= 1;
+                suffix.push(b'a' + (n % 26) as u8);
+                n /= 26;
+            }
+            suffix.reverse();
+            format!("vd{}", core::str::from_utf8(&suffix).unwrap())
+        }
+        DiskKind::Mmc => format!("mmcblk{}", index),
+        DiskKind::Ram => format!("ram{}", index),
+    }
+}
+
+/// 分区的设备名，磁盘名以数字结尾时在分区号之前加上 `p`
+fn partition_name(disk: &str, number: usize) -> String {
+    if disk.ends_with(|c: char| c.is_ascii_digit()) {
+        format!("{}p{}", disk, number)
+    } else {
+        format!("{}{}", disk, number)
+    }
+}
+
+/// 登记一个磁盘并解析它的分区表，返回磁盘的设备名
+pub fn register_disk(kind: DiskKind, disk: Arc<GenericBlockDevice>) -> String {
+    let disk: Arc<dyn BlockDevice> = disk;
+    let partitions = partition::scan_partitions(disk.as_ref()).unwrap_or_else(|e| {
+        warn!("read partition table failed: {:?}", e);
+        Vec::new()
+    });
+    let mut devices = BLOCK_DEVICES.lock();
+    let index = devices
+        .iter()
+        .filter(|entry| entry.kind == kind && !entry.is_partition())
+        .count();
+    let name = disk_name(kind, index);
+    info!(
+        "block device {}: {}MB, {} partitions",
+        name,
+        disk.size() / 1024 / 1024,
+        partitions.len()
+    );
+    devices.push(BlockDeviceEntry {
+        name: name.clone(),
+        kind,
+        device: disk.clone(),
+        disk: None,
+        partitions: partitions.len(),
+    });
+    for info in partitions.iter() {
+        let part_name = partition_name(&name, info.number);
+        info!(
+            "partition {}: start sector {}, {} sectors",
+            part_name, info.start_sector, info.sectors
+        );
+        devices.push(BlockDeviceEntry {
+            name: part_name,
+            kind,
+            device: Arc::new(Partition::new(disk.clone(), info)),
+            disk: Some(name.clone()),
+            partitions: 0,
+        });
+    }
+    name
+}
+
+/// 所有登记的块设备，包括磁盘和分区
+pub fn block_devices() -> Vec<BlockDeviceEntry> {
+    BLOCK_DEVICES.lock().clone()
+}
+
+/// 按照设备名查找块设备
+pub fn find_block_device(name: &str) -> Option<BlockDeviceEntry> {
+    BLOCK_DEVICES
+        .lock()
+        .iter()
+        .find(|entry| entry.name == name)
+        .cloned()
+}
+
+/// 将所有块设备缓存中的脏页写回设备
+pub fn flush_block_device() -> AlienResult<()> {
+    let disks = BLOCK_DEVICES
+        .lock()
+        .iter()
+        .filter(|entry| !entry.is_partition())
+        .map(|entry| entry.device.clone())
+        .collect::<Vec<_>>();
+    let mut res = Ok(());
+    for disk in disks {
+        if let Err(e) = disk.flush() {
+            res = Err(e);
+        }
+    }
+    res
+}
+
+pub struct BLKDevice {
+    device_id: DeviceId,
+    device: Arc<dyn BlockDevice>,
+}
+
+impl BLKDevice {
+    pub fn new(device_id: DeviceId, device: Arc<dyn BlockDevice>) -> Self {
+        Self { device_id, device }
+    }
+    pub fn device_id(&self) -> DeviceId {
+        self.device_id
+    }
+}
+
+impl VfsFile for BLKDevice {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
+        let len = buf
+            .len()
+            .min(self.device.size().saturating_sub(offset as usize));
+        if len == 0 {
+            return Ok(0);
+        }
+        self.device
+            .read(&mut buf[..len], offset as usize)
+            .map_err(|_| VfsError::IoError)
+    }
+    fn write_at(&self, offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        let len = buf
+            .len()
+            .min(self.device.size().saturating_sub(offset as usize));
+        if len == 0 {
+            return if buf.is_empty() {
+                Ok(0)
+            } else {
+                Err(VfsError::ENOSPC)
+            };
+        }
+        self.device
+            .write(&buf[..len], offset as usize)
+            .map_err(|_| VfsError::IoError)
+    }
+    fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        // 块设备总是可以读写
+        Ok(event & (VfsPollEvents::IN | VfsPollEvents::OUT))
+    }
+    fn ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
+        match cmd {
+            BLKGETSIZE => {
+                let sectors = self.device.size() / SECTOR_SIZE;
+                shim::copy_data_to_task(&sectors, arg as *mut usize);
+                Ok(0)
+            }
+            BLKGETSIZE64 => {
+                let size = self.device.size() as u64;
+                shim::copy_data_to_task(&size, arg as *mut u64);
+                Ok(0)
+            }
+            BLKSSZGET => {
+                shim::copy_data_to_task(&(SECTOR_SIZE as u32), arg as *mut u32);
+                Ok(0)
+            }
+            BLKFLSBUF => {
+                self.device.flush().map_err(|_| VfsError::IoError)?;
+                Ok(0)
+            }
+            _ => Err(VfsError::ENOTTY),
+        }
+    }
+    fn flush(&self) -> VfsResult<()> {
+        self.device.flush().map_err(|_| VfsError::IoError)
+    }
+    fn fsync(&self) -> VfsResult<()> {
+        self.device.flush().map_err(|_| VfsError::IoError)
+    }
+}
+
+impl VfsInode for BLKDevice {
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_rdev: self.device_id.id(),
+            st_size: self.device.size() as u64,
+            st_blksize: 512,
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::BlockDevice
+    }
+}
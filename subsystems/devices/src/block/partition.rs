@@ -0,0 +1,290 @@
+//! 分区表解析
+//!
+//! 支持 MBR(包括扩展分区中的逻辑分区)和 GPT。分区的编号与 Linux 相同：MBR 的主分区按照表项的位置编号为 1~4，
+//! 逻辑分区从 5 开始编号；GPT 分区按照表项的位置从 1 开始编号。
+use alloc::{sync::Arc, vec, vec::Vec};
+
+use constants::{AlienResult, LinuxErrno};
+use device_interface::{BlockDevice, DeviceBase};
+use log::warn;
+
+const SECTOR_SIZE: usize = 512;
+
+const MBR_SIGNATURE: [u8; 2] = [0x55, 0xaa];
+const MBR_TABLE_OFFSET: usize = 446;
+const MBR_ENTRY_SIZE: usize = 16;
+const MBR_TYPE_EMPTY: u8 = 0x00;
+const MBR_TYPE_GPT_PROTECTIVE: u8 = 0xee;
+const MBR_TYPE_EXTENDED: [u8; 3] = [0x05, 0x0f, 0x85];
+/// 扩展分区中最多解析的逻辑分区数量，避免损坏的分区表形成环
+const MAX_LOGICAL_PARTITIONS: usize = 128;
+
+const GPT_SIGNATURE: &[u8; 8] = b"EFI PART";
+const GPT_MIN_HEADER_SIZE: usize = 92;
+const GPT_MIN_ENTRY_SIZE: usize = 128;
+const GPT_MAX_ENTRY_SIZE: usize = 4096;
+const GPT_MAX_ENTRIES: usize = 256;
+
+/// 分区表中的一个分区
+#[derive(Debug, Clone, Copy)]
+pub struct PartitionInfo {
+    /// 分区编号，从 1 开始
+    pub number: usize,
+    /// 分区的起始扇区
+    pub start_sector: usize,
+    /// 分区的扇区数量
+    pub sectors: usize,
+}
+
+struct MbrEntry {
+    boot_indicator: u8,
+    partition_type: u8,
+    start_sector: usize,
+    sectors: usize,
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+fn read_u64(buf: &[u8], offset: usize) -> u64 {
+    u64::from_le_bytes(buf[offset..offset + 8].try_into().unwrap())
+}
+
+/// GPT 使用的 CRC32 (IEEE 802.3)
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn read_sectors(disk: &dyn BlockDevice, sector: usize, buf: &mut [u8]) -> AlienResult<()> {
+    disk.read(buf, sector * SECTOR_SIZE)?;
+    Ok(())
+}
+
+fn mbr_entries(sector: &[u8]) -> [MbrEntry; 4] {
+    core::array::from_fn(|i| {
+        let entry = &sector[MBR_TABLE_OFFSET + i * MBR_ENTRY_SIZE..][..MBR_ENTRY_SIZE];
+        MbrEntry {
+            boot_indicator: entry[0],
+            partition_type: entry[4],
+            start_sector: read_u32(entry, 8) as usize,
+            sectors: read_u32(entry, 12) as usize,
+        }
+    })
+}
+
+/// FAT 文件系统的引导扇区同样以 `0x55aa` 结尾，没有分区表的磁盘不能被当作 MBR
+fn is_fat_boot_sector(sector: &[u8]) -> bool {
+    &sector[0x36..0x39] == b"FAT" || &sector[0x52..0x57] == b"FAT32"
+}
+
+/// 读取磁盘的分区表，没有分区表时返回空的列表
+pub fn scan_partitions(disk: &dyn BlockDevice) -> AlienResult<Vec<PartitionInfo>> {
+    let disk_sectors = disk.size() / SECTOR_SIZE;
+    let mut mbr = [0u8; SECTOR_SIZE];
+    read_sectors(disk, 0, &mut mbr)?;
+    if mbr[510..512] != MBR_SIGNATURE || is_fat_boot_sector(&mbr) {
+        return Ok(Vec::new());
+    }
+    let entries = mbr_entries(&mbr);
+    if entries
+        .iter()
+        .any(|entry| entry.boot_indicator != 0 && entry.boot_indicator != 0x80)
+    {
+        return Ok(Vec::new());
+    }
+    if entries
+        .iter()
+        .any(|entry| entry.partition_type == MBR_TYPE_GPT_PROTECTIVE)
+    {
+        return scan_gpt(disk, disk_sectors);
+    }
+    let mut partitions = Vec::new();
+    for (i, entry) in entries.iter().enumerate() {
+        if entry.partition_type == MBR_TYPE_EMPTY || entry.sectors == 0 {
+            continue;
+        }
+        if entry.start_sector + entry.sectors > disk_sectors {
+            warn!("mbr partition {} is beyond the end of the disk", i + 1);
+            continue;
+        }
+        if MBR_TYPE_EXTENDED.contains(&entry.partition_type) {
+            scan_extended(disk, disk_sectors, entry.start_sector, &mut partitions)?;
+        } else {
+            partitions.push(PartitionInfo {
+                number: i + 1,
+                start_sector: entry.start_sector,
+                sectors: entry.sectors,
+            });
+        }
+    }
+    Ok(partitions)
+}
+
+/// 沿着扩展引导记录(EBR)的链表解析扩展分区中的逻辑分区
+///
+/// 每个 EBR 的第一项是逻辑分区，起始扇区相对于该 EBR；第二项指向下一个 EBR，起始扇区相对于扩展分区。
+fn scan_extended(
+    disk: &dyn BlockDevice,
+    disk_sectors: usize,
+    extended_start: usize,
+    partitions: &mut Vec<PartitionInfo>,
+) -> AlienResult<()> {
+    let mut ebr_sector = extended_start;
+    let mut number = 5;
+    for _ in 0..MAX_LOGICAL_PARTITIONS {
+        let mut ebr = [0u8; SECTOR_SIZE];
+        read_sectors(disk, ebr_sector, &mut ebr)?;
+        if ebr[510..512] != MBR_SIGNATURE {
+            break;
+        }
+        let [logical, next, ..] = mbr_entries(&ebr);
+        if logical.partition_type != MBR_TYPE_EMPTY && logical.sectors != 0 {
+            let start_sector = ebr_sector + logical.start_sector;
+            if start_sector + logical.sectors <= disk_sectors {
+                partitions.push(PartitionInfo {
+                    number,
+                    start_sector,
+                    sectors: logical.sectors,
+                });
+            } else {
+                warn!("logical partition {} is beyond the end of the disk", number);
+            }
+            number += 1;
+        }
+        if !MBR_TYPE_EXTENDED.contains(&next.partition_type) || next.start_sector == 0 {
+            break;
+        }
+        ebr_sector = extended_start + next.start_sector;
+        if ebr_sector >= disk_sectors {
+            break;
+        }
+    }
+    Ok(())
+}
+
+/// 解析 GPT，分区表头位于 1 号扇区
+fn scan_gpt(disk: &dyn BlockDevice, disk_sectors: usize) -> AlienResult<Vec<PartitionInfo>> {
+    let mut header = [0u8; SECTOR_SIZE];
+    read_sectors(disk, 1, &mut header)?;
+    if &header[0..8] != GPT_SIGNATURE {
+        warn!("protective mbr found but there is no gpt header");
+        return Ok(Vec::new());
+    }
+    // 计算分区表头的校验和时，校验和字段视为 0
+    let header_size = read_u32(&header, 12) as usize;
+    if !(GPT_MIN_HEADER_SIZE..=SECTOR_SIZE).contains(&header_size) {
+        warn!("invalid gpt header size {}", header_size);
+        return Err(LinuxErrno::EINVAL);
+    }
+    let header_crc = read_u32(&header, 16);
+    header[16..20].fill(0);
+    if crc32(&header[..header_size]) != header_crc {
+        warn!("gpt header checksum mismatch");
+        return Err(LinuxErrno::EINVAL);
+    }
+    let entries_lba = read_u64(&header, 72) as usize;
+    let entry_count = read_u32(&header, 80) as usize;
+    let entry_size = read_u32(&header, 84) as usize;
+    if !(GPT_MIN_ENTRY_SIZE..=GPT_MAX_ENTRY_SIZE).contains(&entry_size) || entry_size % 8 != 0 {
+        warn!("invalid gpt entry size {}", entry_size);
+        return Err(LinuxErrno::EINVAL);
+    }
+    let entry_count = entry_count.min(GPT_MAX_ENTRIES);
+    let table_size = (entry_count * entry_size).div_ceil(SECTOR_SIZE) * SECTOR_SIZE;
+    if entries_lba
+        .checked_add(table_size / SECTOR_SIZE)
+        .is_none_or(|end| end > disk_sectors)
+    {
+        warn!("gpt partition entries are beyond the end of the disk");
+        return Err(LinuxErrno::EINVAL);
+    }
+    let mut table = vec![0u8; table_size];
+    read_sectors(disk, entries_lba, &mut table)?;
+    let mut partitions = Vec::new();
+    for (i, entry) in table.chunks_exact(entry_size).take(entry_count).enumerate() {
+        // 分区类型为全 0 的表项没有被使用
+        if entry[0..16].iter().all(|&b| b == 0) {
+            continue;
+        }
+        let first = read_u64(entry, 32) as usize;
+        let last = read_u64(entry, 40) as usize;
+        if last < first || last >= disk_sectors {
+            warn!("gpt partition {} is beyond the end of the disk", i + 1);
+            continue;
+        }
+        partitions.push(PartitionInfo {
+            number: i + 1,
+            start_sector: first,
+            sectors: last - first + 1,
+        });
+    }
+    Ok(partitions)
+}
+
+/// 磁盘上的一个分区，读写的偏移相对于分区的起始位置，并且不能超过分区的末尾
+pub struct Partition {
+    disk: Arc<dyn BlockDevice>,
+    /// 分区在磁盘中的起始偏移，单位为字节
+    start: usize,
+    /// 分区的大小，单位为字节
+    size: usize,
+}
+
+impl Partition {
+    pub fn new(disk: Arc<dyn BlockDevice>, info: &PartitionInfo) -> Self {
+        Self {
+            disk,
+            start: info.start_sector * SECTOR_SIZE,
+            size: info.sectors * SECTOR_SIZE,
+        }
+    }
+
+    /// 将访问的范围限制在分区之内，返回实际可以访问的长度
+    fn clamp(&self, offset: usize, len: usize) -> usize {
+        len.min(self.size.saturating_sub(offset))
+    }
+}
+
+impl DeviceBase for Partition {
+    fn handle_irq(&self) {
+        // 中断由分区所在的磁盘处理
+    }
+}
+
+impl BlockDevice for Partition {
+    fn read(&self, buf: &mut [u8], offset: usize) -> AlienResult<usize> {
+        let len = self.clamp(offset, buf.len());
+        if len == 0 {
+            return Ok(0);
+        }
+        self.disk.read(&mut buf[..len], self.start + offset)
+    }
+
+    fn write(&self, buf: &[u8], offset: usize) -> AlienResult<usize> {
+        let len = self.clamp(offset, buf.len());
+        if len == 0 && !buf.is_empty() {
+            return Err(LinuxErrno::ENOSPC);
+        }
+        self.disk.write(&buf[..len], self.start + offset)
+    }
+
+    fn size(&self) -> usize {
+        self.size
+    }
+
+    fn flush(&self) -> AlienResult<()> {
+        self.disk.flush()
+    }
+}
@@ -0,0 +1,492 @@
+//! evdev 输入设备接口
+//!
+//! 每个输入设备对应一个 [`EvDev`]，设备的中断处理程序通过 [`InputHandler`] 将事件交给它，
+//! [`EvDev`] 为事件加上时间戳后分发给所有打开了该设备的 [`EvDevClient`]，每个客户端对应用户态打开的一个
+//! `/dev/input/eventN`，读取得到的是 `struct input_event`。
+//!
+//! 客户端可以通过 `EVIOCGRAB` 独占设备，此时事件只会发送给该客户端。
+//! 设备的名字、支持的事件以及绝对坐标轴的范围等信息通过 `EVIOCG*` 获取，均由底层驱动提供。
+use alloc::{
+    collections::VecDeque,
+    sync::{Arc, Weak},
+    vec,
+    vec::Vec,
+};
+use core::mem::size_of;
+
+use constants::{io::PollEvents, AlienResult, LinuxErrno};
+use device_interface::{InputAbsInfo, InputDevice, InputHandler, InputId};
+use ksync::{poll::PollQueue, wait::WaitQueue, Mutex};
+use platform::config::CLOCK_FREQ;
+use spin::Once;
+
+pub const EV_SYN: u16 = 0x00;
+pub const EV_KEY: u16 = 0x01;
+pub const EV_REL: u16 = 0x02;
+pub const EV_ABS: u16 = 0x03;
+pub const EV_MSC: u16 = 0x04;
+pub const EV_SW: u16 = 0x05;
+pub const EV_LED: u16 = 0x11;
+pub const EV_SND: u16 = 0x12;
+pub const EV_REP: u16 = 0x14;
+pub const EV_FF: u16 = 0x15;
+
+/// 客户端的缓冲区已满，之前的事件被丢弃
+const SYN_DROPPED: u16 = 3;
+
+/// 各类事件的编码数量
+const EV_CNT: usize = 0x20;
+const PROP_CNT: usize = 0x20;
+const KEY_CNT: usize = 0x300;
+const REL_CNT: usize = 0x10;
+const ABS_CNT: usize = 0x40;
+const MSC_CNT: usize = 0x08;
+const SW_CNT: usize = 0x11;
+const LED_CNT: usize = 0x10;
+const SND_CNT: usize = 0x08;
+const REP_CNT: usize = 0x02;
+const FF_CNT: usize = 0x80;
+
+/// evdev 协议的版本
+const EV_VERSION: i32 = 0x010001;
+
+const EVIOCGVERSION: u32 = 0x80044501;
+const EVIOCGID: u32 = 0x80084502;
+/// 独占或者释放设备
+const EVIOCGRAB: u32 = 0x40044590;
+/// 撤销客户端对设备的访问，之后的读取返回 `ENODEV`
+const EVIOCREVOKE: u32 = 0x40044591;
+/// 设置事件时间戳使用的时钟
+const EVIOCSCLOCKID: u32 = 0x400445a0;
+
+/// 长度可变的 `EVIOCG*` 的编号，长度编码在命令中
+const EVIOCGNAME_NR: u32 = 0x06;
+const EVIOCGPHYS_NR: u32 = 0x07;
+const EVIOCGUNIQ_NR: u32 = 0x08;
+const EVIOCGPROP_NR: u32 = 0x09;
+const EVIOCGKEY_NR: u32 = 0x18;
+const EVIOCGLED_NR: u32 = 0x19;
+const EVIOCGSND_NR: u32 = 0x1a;
+const EVIOCGSW_NR: u32 = 0x1b;
+/// `EVIOCGBIT(ev, len)` 的编号为该值加上事件类型
+const EVIOCGBIT_NR: u32 = 0x20;
+/// `EVIOCGABS(abs)` 的编号为该值加上坐标轴
+const EVIOCGABS_NR: u32 = 0x40;
+
+const IOC_READ: u32 = 2;
+
+const CLOCK_REALTIME: i32 = 0;
+const CLOCK_MONOTONIC: i32 = 1;
+const CLOCK_BOOTTIME: i32 = 7;
+
+/// 每个客户端最多缓存的事件数量
+const EVDEV_BUF_SIZE: usize = 64;
+
+/// 输入事件，与 Linux 中的 `struct input_event` 相同
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct InputEvent {
+    pub sec: usize,
+    pub usec: usize,
+    pub event_type: u16,
+    pub code: u16,
+    pub value: i32,
+}
+
+impl InputEvent {
+    fn now(event_type: u16, code: u16, value: i32) -> Self {
+        let time = arch::read_timer();
+        Self {
+            sec: time / CLOCK_FREQ,
+            usec: (time % CLOCK_FREQ) * 1_000_000 / CLOCK_FREQ,
+            event_type,
+            code,
+            value,
+        }
+    }
+}
+
+/// 编码数量为 `count` 的位图的长度，与 Linux 一样按照 `long` 对齐
+const fn bitmap_len(count: usize) -> usize {
+    count.div_ceil(usize::BITS as usize) * size_of::<usize>()
+}
+
+/// 事件类型 `event_type` 的编码位图的长度，类型 0 表示事件类型本身的位图
+fn event_bitmap_len(event_type: u16) -> Option<usize> {
+    let count = match event_type {
+        EV_SYN => EV_CNT,
+        EV_KEY => KEY_CNT,
+        EV_REL => REL_CNT,
+        EV_ABS => ABS_CNT,
+        EV_MSC => MSC_CNT,
+        EV_SW => SW_CNT,
+        EV_LED => LED_CNT,
+        EV_SND => SND_CNT,
+        EV_REP => REP_CNT,
+        EV_FF => FF_CNT,
+        _ => return None,
+    };
+    Some(bitmap_len(count))
+}
+
+/// 将 `data` 复制到长度为 `len` 的用户缓冲区中，返回复制的长度
+fn copy_to_user(data: &[u8], arg: usize, len: usize) -> usize {
+    let len = len.min(data.len());
+    shim::copy_bytes_to_task(&data[..len], arg as *mut u8);
+    len
+}
+
+/// 系统中所有的输入设备，下标即 `/dev/input/eventN` 中的 N
+static INPUT_DEVICES: Mutex<Vec<Arc<EvDev>>> = Mutex::new(Vec::new());
+
+/// 出现新的输入设备时调用的函数，由文件系统注册，用于创建设备文件
+static INPUT_LISTENER: Once<fn(Arc<EvDev>)> = Once::new();
+
+/// 注册一个输入设备，设备随后的事件都会交给它的 [`EvDev`]
+///
+/// 设备可以在任何时候注册，例如热插拔的设备，文件系统会通过 [`set_input_listener`] 注册的函数得知新的设备。
+pub fn register_input_device(device: Arc<dyn InputDevice>) -> Arc<EvDev> {
+    let (evdev, listener) = {
+        let mut devices = INPUT_DEVICES.lock();
+        let evdev = Arc::new(EvDev::new(devices.len(), device.clone()));
+        devices.push(evdev.clone());
+        (evdev, INPUT_LISTENER.get().copied())
+    };
+    device.set_handler(evdev.clone());
+    if let Some(listener) = listener {
+        listener(evdev.clone());
+    }
+    evdev
+}
+
+/// 设置出现新的输入设备时调用的函数，已经注册的设备会立即以该函数通知
+pub fn set_input_listener(listener: fn(Arc<EvDev>)) {
+    let devices = {
+        let devices = INPUT_DEVICES.lock();
+        INPUT_LISTENER.call_once(|| listener);
+        devices.clone()
+    };
+    devices.into_iter().for_each(listener);
+}
+
+struct EvDevInner {
+    clients: Vec<Weak<EvDevClient>>,
+    /// 独占设备的客户端
+    grab: Option<Weak<EvDevClient>>,
+    /// 按键的状态，按下的键对应的位为 1
+    key_state: [u8; bitmap_len(KEY_CNT)],
+    /// 绝对坐标轴的当前值
+    abs_value: [i32; ABS_CNT],
+}
+
+impl EvDevInner {
+    fn grabber(&self) -> Option<Arc<EvDevClient>> {
+        self.grab.as_ref().and_then(Weak::upgrade)
+    }
+}
+
+/// 一个输入设备的 evdev
+pub struct EvDev {
+    index: usize,
+    device: Arc<dyn InputDevice>,
+    inner: Mutex<EvDevInner>,
+}
+
+impl EvDev {
+    fn new(index: usize, device: Arc<dyn InputDevice>) -> Self {
+        Self {
+            index,
+            device,
+            inner: Mutex::new(EvDevInner {
+                clients: Vec::new(),
+                grab: None,
+                key_state: [0; bitmap_len(KEY_CNT)],
+                abs_value: [0; ABS_CNT],
+            }),
+        }
+    }
+
+    /// 设备的编号，即 `/dev/input/eventN` 中的 N
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// 设备是否会产生 `event_type` 类型的事件
+    pub fn has_event_type(&self, event_type: u16) -> bool {
+        let mut bits = [0u8; 128];
+        self.device.event_bits(event_type, &mut bits) > 0
+    }
+
+    /// 打开设备，创建一个新的客户端
+    pub fn open(self: &Arc<Self>) -> Arc<EvDevClient> {
+        let client = Arc::new(EvDevClient {
+            evdev: self.clone(),
+            inner: Mutex::new(ClientInner {
+                events: VecDeque::with_capacity(EVDEV_BUF_SIZE),
+                revoked: false,
+            }),
+            read_queue: WaitQueue::new(),
+            poll_queue: PollQueue::new(),
+        });
+        let mut inner = self.inner.lock();
+        inner.clients.retain(|client| client.strong_count() > 0);
+        inner.clients.push(Arc::downgrade(&client));
+        client
+    }
+
+    /// 事件类型 `event_type` 的编码位图，类型 0 时为设备支持的事件类型的位图
+    fn event_bits(&self, event_type: u16) -> AlienResult<Vec<u8>> {
+        let len = event_bitmap_len(event_type).ok_or(LinuxErrno::EINVAL)?;
+        let mut bits = vec![0u8; len.max(128)];
+        if event_type == EV_SYN {
+            bits[0] = 1;
+            (1..EV_CNT as u16)
+                .filter(|&ty| self.has_event_type(ty))
+                .for_each(|ty| bits[ty as usize / 8] |= 1 << (ty % 8));
+        } else {
+            self.device.event_bits(event_type, &mut bits);
+        }
+        bits.truncate(len);
+        Ok(bits)
+    }
+
+    fn grab(&self, client: &Arc<EvDevClient>, grab: bool) -> AlienResult<usize> {
+        let mut inner = self.inner.lock();
+        let grabber = inner.grabber();
+        if grab && grabber.is_some() {
+            return Err(LinuxErrno::EBUSY);
+        }
+        if !grab && !grabber.is_some_and(|grabber| Arc::ptr_eq(&grabber, client)) {
+            return Err(LinuxErrno::EINVAL);
+        }
+        inner.grab = grab.then(|| Arc::downgrade(client));
+        Ok(0)
+    }
+}
+
+impl InputHandler for EvDev {
+    fn handle_event(&self, event_type: u16, code: u16, value: u32) {
+        let event = InputEvent::now(event_type, code, value as i32);
+        let clients = {
+            let mut inner = self.inner.lock();
+            match event_type {
+                EV_KEY if (code as usize) < KEY_CNT => {
+                    let (byte, bit) = (code as usize / 8, code % 8);
+                    if value == 0 {
+                        inner.key_state[byte] &= !(1 << bit);
+                    } else {
+                        inner.key_state[byte] |= 1 << bit;
+                    }
+                }
+                EV_ABS if (code as usize) < ABS_CNT => inner.abs_value[code as usize] = event.value,
+                _ => {}
+            }
+            match inner.grabber() {
+                Some(grabber) => vec![grabber],
+                None => inner
+                    .clients
+                    .iter()
+                    .filter_map(Weak::upgrade)
+                    .collect::<Vec<_>>(),
+            }
+        };
+        clients.iter().for_each(|client| client.push(event));
+    }
+}
+
+struct ClientInner {
+    events: VecDeque<InputEvent>,
+    /// 客户端的访问已经被 `EVIOCREVOKE` 撤销
+    revoked: bool,
+}
+
+/// 打开的 evdev 设备
+pub struct EvDevClient {
+    evdev: Arc<EvDev>,
+    inner: Mutex<ClientInner>,
+    read_queue: WaitQueue,
+    poll_queue: PollQueue,
+}
+
+impl Drop for EvDevClient {
+    fn drop(&mut self) {
+        let mut inner = self.evdev.inner.lock();
+        inner.clients.retain(|client| client.strong_count() > 0);
+        // 独占设备的客户端被关闭时释放设备
+        if inner.grab.is_some() && inner.grabber().is_none() {
+            inner.grab = None;
+        }
+    }
+}
+
+impl EvDevClient {
+    /// 客户端的就绪事件通知队列
+    pub fn poll_queue(&self) -> &PollQueue {
+        &self.poll_queue
+    }
+
+    /// 缓冲区已满时丢弃缓冲区中所有的事件，并用一个 `SYN_DROPPED` 事件告诉读者有事件丢失
+    fn push(&self, event: InputEvent) {
+        {
+            let mut inner = self.inner.lock();
+            if inner.revoked {
+                return;
+            }
+            if inner.events.len() >= EVDEV_BUF_SIZE {
+                inner.events.clear();
+                inner.events.push_back(InputEvent {
+                    event_type: EV_SYN,
+                    code: SYN_DROPPED,
+                    value: 0,
+                    ..event
+                });
+            }
+            inner.events.push_back(event);
+        }
+        self.read_queue.wake_all();
+        self.poll_queue.notify(PollEvents::EPOLLIN);
+    }
+
+    /// 读取事件，`buf` 的长度至少为一个事件的大小，每次只返回完整的事件
+    pub fn read(&self, buf: &mut [u8], nonblock: bool) -> AlienResult<usize> {
+        let size = size_of::<InputEvent>();
+        if buf.len() < size {
+            return Err(LinuxErrno::EINVAL);
+        }
+        loop {
+            {
+                let mut inner = self.inner.lock();
+                if inner.revoked {
+                    return Err(LinuxErrno::ENODEV);
+                }
+                let count = (buf.len() / size).min(inner.events.len());
+                if count > 0 {
+                    inner
+                        .events
+                        .drain(..count)
+                        .zip(buf.chunks_exact_mut(size))
+                        .for_each(|(event, dst)| {
+                            let src = unsafe {
+                                core::slice::from_raw_parts(
+                                    &event as *const InputEvent as *const u8,
+                                    size,
+                                )
+                            };
+                            dst.copy_from_slice(src);
+                        });
+                    return Ok(count * size);
+                }
+            }
+            if nonblock {
+                return Err(LinuxErrno::EAGAIN);
+            }
+            self.read_queue.wait_event_interruptible(|| {
+                let inner = self.inner.lock();
+                inner.revoked || !inner.events.is_empty()
+            })?;
+        }
+    }
+
+    pub fn poll(&self, event: PollEvents) -> PollEvents {
+        let inner = self.inner.lock();
+        if inner.revoked {
+            return PollEvents::EPOLLERR | PollEvents::EPOLLHUP;
+        }
+        let mut res = PollEvents::empty();
+        if event.contains(PollEvents::EPOLLIN) && !inner.events.is_empty() {
+            res |= PollEvents::EPOLLIN;
+        }
+        res
+    }
+
+    fn revoke(self: &Arc<Self>) {
+        self.inner.lock().revoked = true;
+        let _ = self.evdev.grab(self, false);
+        self.read_queue.wake_all();
+        self.poll_queue
+            .notify(PollEvents::EPOLLERR | PollEvents::EPOLLHUP);
+    }
+
+    /// evdev 的 ioctl，不支持的命令返回 `EINVAL`
+    pub fn ioctl(self: &Arc<Self>, cmd: u32, arg: usize) -> AlienResult<usize> {
+        if self.inner.lock().revoked {
+            return Err(LinuxErrno::ENODEV);
+        }
+        let evdev = &self.evdev;
+        match cmd {
+            EVIOCGVERSION => {
+                shim::copy_data_to_task(&EV_VERSION, arg as *mut i32);
+                return Ok(0);
+            }
+            EVIOCGID => {
+                let id = evdev.device.input_id();
+                shim::copy_data_to_task(&id, arg as *mut InputId);
+                return Ok(0);
+            }
+            EVIOCGRAB => return evdev.grab(self, arg != 0),
+            EVIOCREVOKE => {
+                if arg != 0 {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                self.revoke();
+                return Ok(0);
+            }
+            EVIOCSCLOCKID => {
+                // 内核中的实时时钟和单调时钟相同，时间戳总是取自 cpu 时钟
+                let clock = *shim::transfer_ptr(arg as *const i32);
+                return match clock {
+                    CLOCK_REALTIME | CLOCK_MONOTONIC | CLOCK_BOOTTIME => Ok(0),
+                    _ => Err(LinuxErrno::EINVAL),
+                };
+            }
+            _ => {}
+        }
+        // 其余的命令都是读取设备信息，长度编码在命令中
+        let (dir, ty, nr, len) = (
+            cmd >> 30,
+            (cmd >> 8) & 0xff,
+            cmd & 0xff,
+            (cmd >> 16) & 0x3fff,
+        );
+        if dir != IOC_READ || ty != b'E' as u32 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let len = len as usize;
+        match nr {
+            EVIOCGNAME_NR => {
+                let mut name = evdev.device.name().into_bytes();
+                name.push(0);
+                Ok(copy_to_user(&name, arg, len))
+            }
+            // 没有物理位置和唯一标识
+            EVIOCGPHYS_NR | EVIOCGUNIQ_NR => Err(LinuxErrno::ENOENT),
+            EVIOCGPROP_NR => {
+                let mut bits = [0u8; 128];
+                evdev.device.prop_bits(&mut bits);
+                Ok(copy_to_user(&bits[..bitmap_len(PROP_CNT)], arg, len))
+            }
+            EVIOCGKEY_NR => {
+                let state = evdev.inner.lock().key_state;
+                Ok(copy_to_user(&state, arg, len))
+            }
+            // 不支持指示灯、声音和开关，它们的状态总是为 0
+            EVIOCGLED_NR => Ok(copy_to_user(&[0; bitmap_len(LED_CNT)], arg, len)),
+            EVIOCGSND_NR => Ok(copy_to_user(&[0; bitmap_len(SND_CNT)], arg, len)),
+            EVIOCGSW_NR => Ok(copy_to_user(&[0; bitmap_len(SW_CNT)], arg, len)),
+            nr if (EVIOCGBIT_NR..EVIOCGBIT_NR + EV_CNT as u32).contains(&nr) => {
+                let bits = evdev.event_bits((nr - EVIOCGBIT_NR) as u16)?;
+                Ok(copy_to_user(&bits, arg, len))
+            }
+            nr if (EVIOCGABS_NR..EVIOCGABS_NR + ABS_CNT as u32).contains(&nr)
+                && len == size_of::<InputAbsInfo>() =>
+            {
+                let axis = (nr - EVIOCGABS_NR) as u16;
+                let mut info = evdev.device.abs_info(axis).ok_or(LinuxErrno::EINVAL)?;
+                info.value = evdev.inner.lock().abs_value[axis as usize];
+                shim::copy_data_to_task(&info, arg as *mut InputAbsInfo);
+                Ok(0)
+            }
+            _ => Err(LinuxErrno::EINVAL),
+        }
+    }
+}
@@ -1,11 +1,14 @@
 #![no_std]
 
 mod block;
+mod evdev;
 mod gpu;
 mod input;
 mod net;
 mod prob;
+mod rng;
 mod rtc;
+mod tty;
 mod uart;
 
 extern crate alloc;
@@ -13,22 +16,27 @@ extern crate alloc;
 use alloc::{boxed::Box, sync::Arc, vec::Vec};
 use core::ptr::NonNull;
 
-pub use block::{BLKDevice, BLOCK_DEVICE};
+pub use block::{
+    block_devices, find_block_device, flush_block_device, BLKDevice, BlockDeviceEntry, DiskKind,
+};
 use config::MAX_INPUT_EVENT_NUM;
-use device_interface::{DeviceBase, GpuDevice, LowBlockDevice};
+use device_interface::{DeviceBase, GpuDevice, InputDevice, LowBlockDevice};
 use drivers::{
     block_device::GenericBlockDevice,
     rtc::GoldFishRtc,
     uart::{Uart, Uart16550, Uart8250},
 };
+pub use evdev::{register_input_device, set_input_listener, EvDev, EvDevClient, InputEvent};
 use fdt::Fdt;
 pub use gpu::{GPUDevice, GPU_DEVICE};
 pub use input::{INPUTDevice, KEYBOARD_INPUT_DEVICE, MOUSE_INPUT_DEVICE};
 use interrupt::register_device_to_plic;
 use log::info;
 use platform::println;
+pub use rng::RNG_DEVICE;
 pub use rtc::{RTCDevice, RTC_DEVICE};
-pub use uart::{UARTDevice, UART_DEVICE};
+pub use tty::{controlling_tty, Tty, TtyDriver};
+pub use uart::{UARTDevice, UART_DEVICE, UART_TTY};
 use virtio_drivers::transport::{
     mmio::{MmioTransport, VirtIOHeader},
     DeviceType, Transport,
@@ -142,11 +150,6 @@ fn init_uart(uart: prob::DeviceInfo) {
     println!("Init uart success");
 }
 
-// keyboard
-const VIRTIO5: usize = 0x10005000;
-// mouse
-const VIRTIO6: usize = 0x10006000;
-
 pub fn init_virtio_mmio(devices: Vec<prob::DeviceInfo>) {
     for device in devices {
         let paddr = device.base_addr;
@@ -163,16 +166,11 @@ pub fn init_virtio_mmio(devices: Vec<prob::DeviceInfo>) {
                 );
                 info!("Probe virtio device: {:?}", transport.device_type());
                 match transport.device_type() {
-                    DeviceType::Input => {
-                        if paddr == VIRTIO5 {
-                            init_input_device(device, "keyboard", Some(transport));
-                        } else if paddr == VIRTIO6 {
-                            init_input_device(device, "mouse", Some(transport));
-                        }
-                    }
+                    DeviceType::Input => init_input_device(device, Some(transport)),
                     DeviceType::Block => init_block_device(device, Some(transport)),
                     DeviceType::GPU => init_gpu(device, Some(transport)),
                     DeviceType::Network => init_net(Some(device)),
+                    DeviceType::EntropySource => init_rng(device, Some(transport)),
                     ty => {
                         println!("Don't support virtio device type: {:?}", ty);
                     }
@@ -204,9 +202,9 @@ fn init_block_device(blk: prob::DeviceInfo, mmio_transport: Option<MmioTransport
             let size = block_device.capacity();
             println!("Block device size is {}MB", size * 512 / 1024 / 1024);
             let block_device = Arc::new(GenericBlockDevice::new(Box::new(block_device)));
-            block::init_block_device(block_device);
+            let name = block::register_disk(DiskKind::Virtio, block_device);
             // register_device_to_plic(irq, block_device);
-            println!("Init block device success");
+            println!("Init block device {} success", name);
         }
         "starfive,jh7110-sdio" => {
             // starfive2
@@ -218,9 +216,9 @@ fn init_block_device(blk: prob::DeviceInfo, mmio_transport: Option<MmioTransport
                 let size = block_device.capacity();
                 println!("Block device size is {}MB", size * 512 / 1024 / 1024);
                 let block_device = Arc::new(GenericBlockDevice::new(Box::new(block_device)));
-                block::init_block_device(block_device);
+                let name = block::register_disk(DiskKind::Mmc, block_device);
                 // register_device_to_plic(irq, block_device);
-                println!("Init SDIO block device success");
+                println!("Init SDIO block device {} success", name);
             }
             #[cfg(feature = "ramdisk")]
             {
@@ -247,8 +245,8 @@ fn init_ramdisk() {
         unsafe { core::slice::from_raw_parts_mut(RAMDISK.as_ptr() as *mut u8, RAMDISK.len()) };
     let block_device = GenericBlockDevice::new(Box::new(MemoryFat32Img::new(data)));
     let block_device = Arc::new(block_device);
-    block::init_block_device(block_device);
-    println!("Init fake block device success");
+    let name = block::register_disk(DiskKind::Ram, block_device);
+    println!("Init fake block device {} success", name);
 }
 
 fn init_gpu(gpu: prob::DeviceInfo, mmio_transport: Option<MmioTransport>) {
@@ -272,12 +270,12 @@ fn init_gpu(gpu: prob::DeviceInfo, mmio_transport: Option<MmioTransport>) {
     }
 }
 
-fn init_input_device(input: prob::DeviceInfo, name: &str, mmio_transport: Option<MmioTransport>) {
+/// 初始化一个输入设备，每个输入设备都会得到一个 evdev。
+///
+/// 第一个能够产生相对或者绝对坐标事件的设备同时作为鼠标，第一个其它的设备作为键盘，供 `/dev/mouse` 和 `/dev/keyboard` 使用。
+fn init_input_device(input: prob::DeviceInfo, mmio_transport: Option<MmioTransport>) {
     let (base_addr, irq) = (input.base_addr, input.irq);
-    println!(
-        "Init {} input device, base_addr:{:#x},irq:{}",
-        name, base_addr, irq
-    );
+    println!("Init input device, base_addr:{:#x},irq:{}", base_addr, irq);
     match input.compatible.as_str() {
         "virtio,mmio" => {
             // qemu
@@ -285,16 +283,39 @@ fn init_input_device(input: prob::DeviceInfo, name: &str, mmio_transport: Option
             let input =
                 VirtIOInputDriver::from_mmio(mmio_transport.unwrap(), MAX_INPUT_EVENT_NUM as u32);
             let input = Arc::new(input);
-            match name {
-                "mouse" => input::init_mouse_input_device(input.clone()),
-                "keyboard" => input::init_keyboard_input_device(input.clone()),
-                _ => panic!("Don't support {} input device", name),
+            let name = input.name();
+            let evdev = register_input_device(input.clone());
+            if evdev.has_event_type(evdev::EV_REL) || evdev.has_event_type(evdev::EV_ABS) {
+                input::init_mouse_input_device(input.clone());
+            } else {
+                input::init_keyboard_input_device(input.clone());
             }
             let _ = register_device_to_plic(irq, input);
-            println!("Init keyboard input device success");
+            println!(
+                "Init input device {:?} as event{} success",
+                name,
+                evdev.index()
+            );
+        }
+        name => {
+            println!("Don't support input device: {}", name);
+        }
+    }
+}
+
+fn init_rng(rng: prob::DeviceInfo, mmio_transport: Option<MmioTransport>) {
+    let (base_addr, irq) = (rng.base_addr, rng.irq);
+    println!("Init rng device, base_addr:{:#x},irq:{}", base_addr, irq);
+    match rng.compatible.as_str() {
+        "virtio,mmio" => {
+            // qemu
+            use drivers::rng::VirtIORngDriver;
+            let rng = Arc::new(VirtIORngDriver::from_mmio(mmio_transport.unwrap()));
+            rng::init_rng(rng);
+            println!("Init rng device success");
         }
         name => {
-            println!("Don't support keyboard input device: {}", name);
+            println!("Don't support rng device: {}", name);
         }
     }
 }
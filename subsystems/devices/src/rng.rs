@@ -0,0 +1,19 @@
+use alloc::sync::Arc;
+
+use device_interface::RngDevice;
+use spin::Once;
+
+pub static RNG_DEVICE: Once<Arc<dyn RngDevice>> = Once::new();
+
+pub fn init_rng(rng: Arc<dyn RngDevice>) {
+    RNG_DEVICE.call_once(|| rng);
+    krandom::register_hwrng(read_hwrng);
+}
+
+/// 从硬件随机数发生器中读取随机数，供内核的熵池使用
+fn read_hwrng(buf: &mut [u8]) -> usize {
+    RNG_DEVICE
+        .get()
+        .and_then(|rng| rng.read_entropy(buf).ok())
+        .unwrap_or(0)
+}
@@ -0,0 +1,572 @@
+//! 终端(TTY)核心
+//!
+//! [`Tty`] 保存终端的模式([`Termios`])、窗口大小以及作业控制相关的信息(会话和前台进程组)。
+//! 底层驱动(例如串口的中断处理程序)收到的输入通过 [`Tty::receive`] 交给 [`n_tty`] 行规程处理后供进程读取，
+//! 进程写入的数据经过行规程的输出处理后再交给 [`TtyDriver`] 输出。
+//!
+//! 没有控制终端的会话首进程打开终端(没有指定 `O_NOCTTY`)或者执行 `TIOCSCTTY` 时，终端成为该会话的控制终端，
+//! 读写终端和其它 ioctl 不会改变控制终端。
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use constants::{
+    io::{LocalModes, PollEvents, TeletypeCommand, Termios, WinSize},
+    signal::SignalNumber,
+    LinuxErrno,
+};
+use ksync::{poll::PollQueue, wait::WaitQueue, Mutex};
+use platform::config::CLOCK_FREQ;
+use vfscore::{error::VfsError, utils::VfsPollEvents, VfsResult};
+
+use self::n_tty::{default_termios, is_canonical, NTty, VMIN, VSTART, VSTOP, VTIME};
+
+pub mod n_tty;
+
+/// 使当前进程成为终端的控制进程
+const TIOCSCTTY: u32 = 0x540E;
+/// 放弃控制终端
+const TIOCNOTTY: u32 = 0x5422;
+/// 获取以终端为控制终端的会话号
+const TIOCGSID: u32 = 0x5429;
+/// 等待输出完成并发送 break
+const TCSBRK: u32 = 0x5409;
+/// 暂停或者恢复输入输出
+const TCXONC: u32 = 0x540A;
+/// 丢弃输入或者输出
+const TCFLSH: u32 = 0x540B;
+/// 获取输出队列中的字节数
+const TIOCOUTQ: u32 = 0x5411;
+/// 获取可以读取的字节数
+const FIONREAD: u32 = 0x541B;
+
+/// `TCXONC` 的参数
+const TCOOFF: usize = 0;
+const TCOON: usize = 1;
+const TCIOFF: usize = 2;
+const TCION: usize = 3;
+
+/// `TCFLSH` 的参数
+const TCIFLUSH: usize = 0;
+const TCOFLUSH: usize = 1;
+const TCIOFLUSH: usize = 2;
+
+/// 每次进行输出处理的最大字节数，避免长时间持有终端的锁
+const WRITE_CHUNK_SIZE: usize = 256;
+
+/// 终端的底层驱动，负责输出经过行规程处理的数据
+pub trait TtyDriver: Send + Sync {
+    /// 输出数据
+    ///
+    /// 终端只在 [`TtyDriver::write_room`] 不为 0 时输出，每次输出的数据不超过一次输出处理的结果，
+    /// 回显的数据会被截断为剩余的空间。
+    fn write(&self, buf: &[u8]);
+    /// 驱动的输出缓冲区中剩余的空间，为 0 时写终端的任务需要等待驱动调用 [`Tty::write_wakeup`]
+    fn write_room(&self) -> usize {
+        usize::MAX
+    }
+}
+
+/// 各个会话的控制终端，以会话号为键
+static CONTROLLING_TTYS: Mutex<BTreeMap<usize, Weak<Tty>>> = Mutex::new(BTreeMap::new());
+
+/// 会话 `sid` 的控制终端
+pub fn controlling_tty(sid: usize) -> Option<Arc<Tty>> {
+    CONTROLLING_TTYS.lock().get(&sid).and_then(Weak::upgrade)
+}
+
+#[derive(Debug)]
+struct IoData {
+    foreground_pgid: u32,
+    /// 以终端为控制终端的会话，为 0 时表示终端不是任何会话的控制终端
+    session: usize,
+    winsize: WinSize,
+    termios: Termios,
+}
+
+impl IoData {
+    /// 终端不再是任何会话的控制终端，返回原来的前台进程组
+    fn detach(&mut self) -> usize {
+        if self.session != 0 {
+            CONTROLLING_TTYS.lock().remove(&self.session);
+        }
+        let pgid = self.foreground_pgid as usize;
+        self.session = 0;
+        self.foreground_pgid = 0;
+        pgid
+    }
+}
+
+struct TtyInner {
+    io: IoData,
+    ldisc: NTty,
+    /// 终端已经被挂断，读取返回文件结束，写入返回 `EIO`
+    hung_up: bool,
+}
+
+/// 一个终端
+pub struct Tty {
+    this: Weak<Tty>,
+    driver: Arc<dyn TtyDriver>,
+    inner: Mutex<TtyInner>,
+    /// 等待输入的任务
+    read_queue: WaitQueue,
+    /// 等待被 `VSTOP` 暂停的输出恢复，或者等待驱动的输出缓冲区有空闲空间的任务
+    write_queue: WaitQueue,
+    poll_queue: PollQueue,
+}
+
+impl Tty {
+    pub fn new(driver: Arc<dyn TtyDriver>) -> Arc<Self> {
+        Arc::new_cyclic(|this| Self {
+            this: this.clone(),
+            driver,
+            inner: Mutex::new(TtyInner {
+                io: IoData {
+                    foreground_pgid: 0,
+                    session: 0,
+                    winsize: WinSize::default(),
+                    termios: default_termios(),
+                },
+                ldisc: NTty::new(),
+                hung_up: false,
+            }),
+            read_queue: WaitQueue::new(),
+            write_queue: WaitQueue::new(),
+            poll_queue: PollQueue::new(),
+        })
+    }
+
+    /// 终端的就绪事件通知队列
+    pub fn poll_queue(&self) -> &PollQueue {
+        &self.poll_queue
+    }
+
+    /// 底层驱动收到了输入，可以在中断处理程序中调用
+    pub fn receive(&self, bytes: &[u8]) {
+        let (actions, pgid) = {
+            let mut inner = self.inner.lock();
+            let inner = &mut *inner;
+            let actions = inner.ldisc.receive(&inner.io.termios, bytes);
+            (actions, inner.io.foreground_pgid as usize)
+        };
+        if !actions.echo.is_empty() {
+            // 输出缓冲区已满时丢弃多余的回显，与 `linux` 相同
+            let room = self.driver.write_room().min(actions.echo.len());
+            self.driver.write(&actions.echo[..room]);
+        }
+        if pgid != 0 {
+            actions
+                .signals
+                .iter()
+                .for_each(|sig| shim::kill_pgrp(pgid, *sig as usize));
+        }
+        if actions.readable {
+            self.read_queue.wake_all();
+            self.poll_queue.notify(PollEvents::EPOLLIN);
+        }
+        if actions.restarted {
+            self.write_queue.wake_all();
+            self.poll_queue.notify(PollEvents::EPOLLOUT);
+        }
+    }
+
+    /// 成为会话 `sid` 的控制终端，`pgid` 成为前台进程组
+    fn attach(&self, io: &mut IoData, sid: usize, pgid: usize) {
+        io.session = sid;
+        io.foreground_pgid = pgid as u32;
+        CONTROLLING_TTYS.lock().insert(sid, self.this.clone());
+    }
+
+    /// 打开终端
+    ///
+    /// 调用者是会话首进程、所在的会话没有控制终端并且 `noctty` 为假时，终端成为该会话的控制终端。
+    pub fn open(&self, noctty: bool) {
+        let task = match shim::current_task() {
+            Some(task) => task,
+            None => return,
+        };
+        if noctty || task.sid() != task.pid() || controlling_tty(task.sid()).is_some() {
+            return;
+        }
+        let mut inner = self.inner.lock();
+        if !inner.hung_up && inner.io.session == 0 {
+            self.attach(&mut inner.io, task.sid(), task.pgid());
+        }
+    }
+
+    /// 使终端成为会话 `sid` 的控制终端，用于 init 进程继承内核打开的终端
+    pub fn set_session(&self, sid: usize, pgid: usize) {
+        let mut inner = self.inner.lock();
+        if inner.io.session == 0 {
+            self.attach(&mut inner.io, sid, pgid);
+        }
+    }
+
+    /// 前台进程组，终端不是任何会话的控制终端时为 0
+    pub fn foreground_pgid(&self) -> usize {
+        self.inner.lock().io.foreground_pgid as usize
+    }
+
+    /// 驱动的输出缓冲区有了空闲空间，唤醒等待写终端的任务
+    pub fn write_wakeup(&self) {
+        self.write_queue.wake_all();
+        self.poll_queue.notify(PollEvents::EPOLLOUT);
+    }
+
+    /// 挂断终端，例如 pty 的主设备被关闭时挂断从设备
+    ///
+    /// 终端不再是任何会话的控制终端，原来的前台进程组会收到 `SIGHUP` 和 `SIGCONT`。
+    pub fn hangup(&self) {
+        let pgid = {
+            let mut inner = self.inner.lock();
+            if inner.hung_up {
+                return;
+            }
+            inner.hung_up = true;
+            inner.io.detach()
+        };
+        self.read_queue.wake_all();
+        self.write_queue.wake_all();
+        self.poll_queue
+            .notify(PollEvents::EPOLLIN | PollEvents::EPOLLHUP);
+        if pgid != 0 {
+            shim::kill_pgrp(pgid, SignalNumber::SIGHUP as usize);
+            shim::kill_pgrp(pgid, SignalNumber::SIGCONT as usize);
+        }
+    }
+
+    /// 终端是否已经被挂断
+    pub fn is_hung_up(&self) -> bool {
+        self.inner.lock().hung_up
+    }
+
+    /// 作业控制检查，只对以终端为控制终端的会话中的后台进程组生效。
+    ///
+    /// 后台进程组读终端，或者在设置了 `TOSTOP` 时写终端，会向该进程组发送 `sig` (`SIGTTIN` 或 `SIGTTOU`)
+    /// 并返回 `EINTR`；如果信号被屏蔽或者忽略，读操作返回 `EIO`，写操作则可以继续进行。
+    fn check_background(&self, sig: SignalNumber) -> VfsResult<()> {
+        let task = match shim::current_task() {
+            Some(task) => task,
+            None => return Ok(()),
+        };
+        {
+            let inner = self.inner.lock();
+            let io = &inner.io;
+            if io.session != task.sid() || io.foreground_pgid as usize == task.pgid() {
+                return Ok(());
+            }
+            if sig == SignalNumber::SIGTTOU
+                && !LocalModes::from_bits_truncate(io.termios.lflag).contains(LocalModes::TOSTOP)
+            {
+                return Ok(());
+            }
+        }
+        if task.signal_blocked_or_ignored(sig as usize) {
+            return match sig {
+                SignalNumber::SIGTTIN => Err(VfsError::EIO),
+                _ => Ok(()),
+            };
+        }
+        shim::kill_pgrp(task.pgid(), sig as usize);
+        Err(VfsError::EINTR)
+    }
+
+    fn readable(&self) -> bool {
+        let inner = self.inner.lock();
+        inner.hung_up || inner.ldisc.readable(&inner.io.termios)
+    }
+
+    /// 从终端读取数据
+    ///
+    /// 规范模式下等待完整的一行，每次最多返回一行；非规范模式下按照 `VMIN` 和 `VTIME` 决定何时返回：
+    /// `VMIN` 大于 0 时至少等待 `VMIN` 个字节，此时 `VTIME` 为读到第一个字节之后字节之间的超时时间；
+    /// `VMIN` 为 0 时 `VTIME` 为整个读操作的超时时间，二者都为 0 时不等待。
+    pub fn read(&self, buf: &mut [u8]) -> VfsResult<usize> {
+        self.check_background(SignalNumber::SIGTTIN)?;
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let mut count = 0;
+        let mut deadline = None;
+        loop {
+            {
+                let mut inner = self.inner.lock();
+                let inner = &mut *inner;
+                if inner.hung_up {
+                    return Ok(count);
+                }
+                let termios = &inner.io.termios;
+                if is_canonical(termios) {
+                    if let Some(count) = inner.ldisc.read_canonical(buf) {
+                        return Ok(count);
+                    }
+                } else {
+                    let read = inner.ldisc.read_raw(&mut buf[count..]);
+                    count += read;
+                    let min = termios.cc[VMIN] as usize;
+                    let time = termios.cc[VTIME] as usize * CLOCK_FREQ / 10;
+                    if count == buf.len()
+                        || (min > 0 && count >= min)
+                        || (min == 0 && (time == 0 || count > 0))
+                    {
+                        return Ok(count);
+                    }
+                    if time > 0 && ((min > 0 && read > 0) || (min == 0 && deadline.is_none())) {
+                        deadline = Some(arch::read_timer() + time);
+                    }
+                }
+            }
+            match self
+                .read_queue
+                .wait_event_interruptible_timeout(|| self.readable(), deadline)
+            {
+                Ok(()) => {}
+                Err(LinuxErrno::ETIMEDOUT) => return Ok(count),
+                Err(_) if count > 0 => return Ok(count),
+                Err(_) => return Err(VfsError::EINTR),
+            }
+        }
+    }
+
+    /// 输出没有被暂停，并且驱动的输出缓冲区有空闲空间
+    fn writable(&self) -> bool {
+        let stopped = {
+            let inner = self.inner.lock();
+            if inner.hung_up {
+                return true;
+            }
+            inner.ldisc.is_stopped()
+        };
+        !stopped && self.driver.write_room() > 0
+    }
+
+    /// 向终端写入数据，输出被 `VSTOP` 暂停或者驱动的输出缓冲区已满时等待
+    ///
+    /// `nonblock` 为真时不等待，已经写入了部分数据时返回写入的字节数，否则返回 `EAGAIN`。
+    pub fn write(&self, buf: &[u8], nonblock: bool) -> VfsResult<usize> {
+        self.check_background(SignalNumber::SIGTTOU)?;
+        let mut written = 0;
+        for chunk in buf.chunks(WRITE_CHUNK_SIZE) {
+            let res = if nonblock {
+                if self.writable() {
+                    Ok(())
+                } else {
+                    Err(VfsError::EAGAIN)
+                }
+            } else {
+                self.write_queue
+                    .wait_event_interruptible(|| self.writable())
+                    .map_err(|_| VfsError::EINTR)
+            };
+            if let Err(e) = res {
+                return if written > 0 { Ok(written) } else { Err(e) };
+            }
+            let mut out = Vec::with_capacity(chunk.len());
+            {
+                let mut inner = self.inner.lock();
+                if inner.hung_up {
+                    return Err(VfsError::EIO);
+                }
+                let inner = &mut *inner;
+                inner
+                    .ldisc
+                    .process_output(&inner.io.termios, chunk, &mut out);
+            }
+            self.driver.write(&out);
+            written += chunk.len();
+        }
+        Ok(written)
+    }
+
+    pub fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
+        let mut res = VfsPollEvents::empty();
+        // 在持有终端的锁之前查询驱动，驱动可能需要获取自己的锁
+        let has_room = self.driver.write_room() > 0;
+        let inner = self.inner.lock();
+        if inner.hung_up {
+            return Ok(VfsPollEvents::HUP | (event & VfsPollEvents::IN));
+        }
+        if event.contains(VfsPollEvents::IN) && inner.ldisc.readable(&inner.io.termios) {
+            res |= VfsPollEvents::IN;
+        }
+        if event.contains(VfsPollEvents::OUT) && !inner.ldisc.is_stopped() && has_room {
+            res |= VfsPollEvents::OUT;
+        }
+        Ok(res)
+    }
+
+    /// 终端的 ioctl，不支持的命令返回 `ENOTTY`
+    pub fn ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
+        self.do_ioctl(cmd, arg)
+    }
+
+    /// 通过 pty 的主设备对从设备的终端执行 ioctl
+    ///
+    /// 与 [`Tty::ioctl`] 不同，不能通过主设备设置或者放弃控制终端。
+    pub fn link_ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
+        match cmd {
+            TIOCSCTTY | TIOCNOTTY => Err(VfsError::ENOTTY),
+            _ => self.do_ioctl(cmd, arg),
+        }
+    }
+
+    fn do_ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
+        let task = shim::current_task();
+        let mut inner = self.inner.lock();
+        match cmd {
+            TIOCSCTTY => {
+                let task = task.ok_or(VfsError::EPERM)?;
+                let sid = task.sid();
+                // 只有会话的首进程可以获取控制终端
+                if sid != task.pid() {
+                    return Err(VfsError::EPERM);
+                }
+                if inner.io.session == sid {
+                    return Ok(0);
+                }
+                if controlling_tty(sid).is_some() {
+                    return Err(VfsError::EPERM);
+                }
+                // 终端已经属于其它会话时，只有 root 可以通过 arg 为 1 抢占，原来的会话失去控制终端
+                let old_pgid = if inner.io.session != 0 {
+                    if arg != 1 || task.euid() != 0 {
+                        return Err(VfsError::EPERM);
+                    }
+                    inner.io.detach()
+                } else {
+                    0
+                };
+                self.attach(&mut inner.io, sid, task.pgid());
+                drop(inner);
+                if old_pgid != 0 {
+                    shim::kill_pgrp(old_pgid, SignalNumber::SIGHUP as usize);
+                    shim::kill_pgrp(old_pgid, SignalNumber::SIGCONT as usize);
+                }
+                return Ok(0);
+            }
+            TIOCNOTTY => {
+                let task = task.ok_or(VfsError::ENOTTY)?;
+                if inner.io.session != task.sid() {
+                    return Err(VfsError::ENOTTY);
+                }
+                if task.sid() == task.pgid() {
+                    // 会话首进程放弃控制终端时，前台进程组会收到 SIGHUP 和 SIGCONT
+                    let pgid = inner.io.detach();
+                    drop(inner);
+                    shim::kill_pgrp(pgid, SignalNumber::SIGHUP as usize);
+                    shim::kill_pgrp(pgid, SignalNumber::SIGCONT as usize);
+                }
+                return Ok(0);
+            }
+            TIOCGSID => {
+                if inner.io.session == 0 {
+                    return Err(VfsError::ENOTTY);
+                }
+                let word = shim::transfer_ptr_mut(arg as *mut u32);
+                *word = inner.io.session as u32;
+                return Ok(0);
+            }
+            // 输出没有缓冲，不需要等待输出完成
+            TCSBRK => return Ok(0),
+            TIOCOUTQ => {
+                *shim::transfer_ptr_mut(arg as *mut u32) = 0;
+                return Ok(0);
+            }
+            FIONREAD => {
+                *shim::transfer_ptr_mut(arg as *mut u32) = inner.ldisc.available() as u32;
+                return Ok(0);
+            }
+            TCFLSH => {
+                match arg {
+                    TCIFLUSH | TCIOFLUSH => inner.ldisc.flush_input(),
+                    TCOFLUSH => {}
+                    _ => return Err(VfsError::Invalid),
+                }
+                return Ok(0);
+            }
+            TCXONC => {
+                let termios = inner.io.termios;
+                match arg {
+                    TCOOFF => inner.ldisc.set_stopped(true),
+                    TCOON => {
+                        inner.ldisc.set_stopped(false);
+                        drop(inner);
+                        self.write_queue.wake_all();
+                    }
+                    TCIOFF => {
+                        drop(inner);
+                        self.driver.write(&[termios.cc[VSTOP]]);
+                    }
+                    TCION => {
+                        drop(inner);
+                        self.driver.write(&[termios.cc[VSTART]]);
+                    }
+                    _ => return Err(VfsError::Invalid),
+                }
+                return Ok(0);
+            }
+            _ => {}
+        }
+        let cmd = TeletypeCommand::try_from(cmd).map_err(|_| VfsError::ENOTTY)?;
+        match cmd {
+            TeletypeCommand::TCGETS | TeletypeCommand::TCGETA => {
+                shim::copy_data_to_task(&inner.io.termios, arg as *mut Termios);
+                Ok(0)
+            }
+            TeletypeCommand::TCSETS | TeletypeCommand::TCSETSW | TeletypeCommand::TCSETSF => {
+                let mut termios = inner.io.termios;
+                shim::copy_data_from_task(arg as *const Termios, &mut termios);
+                {
+                    let inner = &mut *inner;
+                    if matches!(cmd, TeletypeCommand::TCSETSF) {
+                        inner.ldisc.flush_input();
+                    }
+                    inner.ldisc.set_termios(&inner.io.termios, &termios);
+                    inner.io.termios = termios;
+                }
+                drop(inner);
+                // 模式改变后可能有数据可以读取，或者输出被恢复
+                self.read_queue.wake_all();
+                self.write_queue.wake_all();
+                self.poll_queue.notify(PollEvents::empty());
+                Ok(0)
+            }
+            TeletypeCommand::TIOCGPGRP => {
+                let word = shim::transfer_ptr_mut(arg as *mut u32);
+                *word = inner.io.foreground_pgid;
+                Ok(0)
+            }
+            TeletypeCommand::TIOCSPGRP => {
+                // 只能由会话中的进程将本会话中的进程组设置为前台进程组
+                let sid = task.map_or(0, |task| task.sid());
+                if inner.io.session != sid {
+                    return Err(VfsError::ENOTTY);
+                }
+                let pgid = *shim::transfer_ptr(arg as *const u32);
+                if !shim::pgrp_in_session(pgid as usize, sid) {
+                    return Err(VfsError::EPERM);
+                }
+                inner.io.foreground_pgid = pgid;
+                Ok(0)
+            }
+            TeletypeCommand::TIOCGWINSZ => {
+                shim::copy_data_to_task(&inner.io.winsize, arg as *mut WinSize);
+                Ok(0)
+            }
+            TeletypeCommand::TIOCSWINSZ => {
+                shim::copy_data_from_task(arg as *const WinSize, &mut inner.io.winsize);
+                // 窗口大小改变时通知前台进程组
+                let pgid = inner.io.foreground_pgid as usize;
+                drop(inner);
+                if pgid != 0 {
+                    shim::kill_pgrp(pgid, SignalNumber::SIGWINCH as usize);
+                }
+                Ok(0)
+            }
+            _ => Err(VfsError::ENOTTY),
+        }
+    }
+}
@@ -0,0 +1,521 @@
+//! N_TTY 行规程
+//!
+//! 行规程位于终端驱动和读写终端的进程之间，按照终端的 [`Termios`] 处理输入和输出：
+//! + 规范模式(`ICANON`)下按行缓冲输入，支持 `VERASE`、`VWERASE`、`VKILL`、`VLNEXT`、`VREPRINT` 等行编辑功能，
+//! 读操作每次最多返回一行；非规范模式下输入直接可以被读取，读操作按照 `VMIN` 和 `VTIME` 决定何时返回。
+//! + 开启 `ISIG` 时，`VINTR`、`VQUIT`、`VSUSP` 被转换为发送给前台进程组的信号。
+//! + 开启 `IXON` 时，`VSTOP` 和 `VSTART` 用于暂停和恢复输出。
+//! + 开启 `ECHO` 时回显输入，开启 `OPOST` 时对输出进行 `ONLCR` 等处理。
+use alloc::{collections::VecDeque, vec::Vec};
+
+use constants::{
+    io::{LocalModes, Termios},
+    signal::SignalNumber,
+};
+
+/// 控制字符在 `Termios::cc` 中的下标
+pub const VINTR: usize = 0;
+pub const VQUIT: usize = 1;
+pub const VERASE: usize = 2;
+pub const VKILL: usize = 3;
+pub const VEOF: usize = 4;
+pub const VTIME: usize = 5;
+pub const VMIN: usize = 6;
+pub const VSTART: usize = 8;
+pub const VSTOP: usize = 9;
+pub const VSUSP: usize = 10;
+pub const VEOL: usize = 11;
+pub const VREPRINT: usize = 12;
+pub const VDISCARD: usize = 13;
+pub const VWERASE: usize = 14;
+pub const VLNEXT: usize = 15;
+pub const VEOL2: usize = 16;
+
+/// 输入模式 `Termios::iflag`
+pub const ISTRIP: u32 = 0o000040;
+pub const INLCR: u32 = 0o000100;
+pub const IGNCR: u32 = 0o000200;
+pub const ICRNL: u32 = 0o000400;
+pub const IXON: u32 = 0o002000;
+pub const IXANY: u32 = 0o004000;
+pub const IUTF8: u32 = 0o040000;
+
+/// 输出模式 `Termios::oflag`
+pub const OPOST: u32 = 0o000001;
+pub const ONLCR: u32 = 0o000004;
+pub const OCRNL: u32 = 0o000010;
+pub const ONOCR: u32 = 0o000020;
+pub const ONLRET: u32 = 0o000040;
+pub const TABDLY: u32 = 0o014000;
+/// 将制表符展开为空格
+pub const XTABS: u32 = 0o014000;
+
+/// 行规程缓冲区的大小，规范模式下一行最多为 `N_TTY_BUF_SIZE - 1` 个字符
+const N_TTY_BUF_SIZE: usize = 4096;
+
+/// 终端的默认模式，与 Linux 中新打开的终端相同
+pub fn default_termios() -> Termios {
+    let mut termios = Termios {
+        iflag: ICRNL | IXON | IUTF8,
+        oflag: OPOST | ONLCR,
+        lflag: (LocalModes::ISIG
+            | LocalModes::ICANON
+            | LocalModes::ECHO
+            | LocalModes::ECHOE
+            | LocalModes::ECHOK
+            | LocalModes::ECHOCTL
+            | LocalModes::ECHOKE
+            | LocalModes::IEXTEN)
+            .bits(),
+        ..Termios::default()
+    };
+    termios.cc[VINTR] = 0x03;
+    termios.cc[VQUIT] = 0x1c;
+    termios.cc[VERASE] = 0x7f;
+    termios.cc[VKILL] = 0x15;
+    termios.cc[VEOF] = 0x04;
+    termios.cc[VTIME] = 0;
+    termios.cc[VMIN] = 1;
+    termios.cc[VSTART] = 0x11;
+    termios.cc[VSTOP] = 0x13;
+    termios.cc[VSUSP] = 0x1a;
+    termios.cc[VEOL] = 0;
+    termios.cc[VREPRINT] = 0x12;
+    termios.cc[VDISCARD] = 0x0f;
+    termios.cc[VWERASE] = 0x17;
+    termios.cc[VLNEXT] = 0x16;
+    termios.cc[VEOL2] = 0;
+    termios
+}
+
+/// 终端是否处于规范模式
+pub fn is_canonical(termios: &Termios) -> bool {
+    LocalModes::from_bits_truncate(termios.lflag).contains(LocalModes::ICANON)
+}
+
+/// `ch` 是否为控制字符 `index`，值为 0 的控制字符被禁用
+fn is_cc(termios: &Termios, index: usize, ch: u8) -> bool {
+    termios.cc[index] != 0 && termios.cc[index] == ch
+}
+
+fn is_ctrl(ch: u8) -> bool {
+    ch < 0x20 || ch == 0x7f
+}
+
+/// 行编辑时擦除的范围
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum EraseKind {
+    Char,
+    Word,
+    Line,
+}
+
+/// 处理输入后需要终端完成的动作
+#[derive(Debug, Default)]
+pub struct InputActions {
+    /// 需要回显的内容
+    pub echo: Vec<u8>,
+    /// 需要发送给前台进程组的信号
+    pub signals: Vec<SignalNumber>,
+    /// 是否有新的数据可以读取
+    pub readable: bool,
+    /// 被暂停的输出是否重新开始
+    pub restarted: bool,
+}
+
+/// N_TTY 行规程的状态
+#[derive(Debug, Default)]
+pub struct NTty {
+    /// 可以被读取的数据，规范模式下只包含已经完成的行
+    read_buf: VecDeque<u8>,
+    /// 规范模式下 `read_buf` 中每一行的长度，`VEOF` 结束的行不包含 `VEOF` 字符，长度可以为 0
+    lines: VecDeque<usize>,
+    /// 规范模式下正在编辑的行
+    line: Vec<u8>,
+    /// 下一个字符按照字面输入(`VLNEXT`)
+    lnext: bool,
+    /// 输出被 `VSTOP` 暂停
+    stopped: bool,
+    /// 输出的光标所在的列，用于擦除制表符
+    column: usize,
+    /// 正在编辑的行开始时光标所在的列
+    canon_column: usize,
+}
+
+impl NTty {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 输出是否被暂停
+    pub fn is_stopped(&self) -> bool {
+        self.stopped
+    }
+
+    /// 暂停或者恢复输出
+    pub fn set_stopped(&mut self, stopped: bool) {
+        self.stopped = stopped;
+    }
+
+    /// 可以被读取的字节数
+    pub fn available(&self) -> usize {
+        self.read_buf.len()
+    }
+
+    /// 是否有数据可以读取，规范模式下需要有完整的一行
+    pub fn readable(&self, termios: &Termios) -> bool {
+        if is_canonical(termios) {
+            !self.lines.is_empty()
+        } else {
+            !self.read_buf.is_empty()
+        }
+    }
+
+    /// 丢弃所有的输入
+    pub fn flush_input(&mut self) {
+        self.read_buf.clear();
+        self.lines.clear();
+        self.line.clear();
+        self.lnext = false;
+    }
+
+    /// 终端的模式从 `old` 改变为 `new`
+    ///
+    /// 离开规范模式时，正在编辑的行直接变为可以读取的数据；进入规范模式时，已经输入的数据作为完整的一行。
+    pub fn set_termios(&mut self, old: &Termios, new: &Termios) {
+        match (is_canonical(old), is_canonical(new)) {
+            (true, false) => {
+                self.read_buf.extend(self.line.drain(..));
+                self.lines.clear();
+            }
+            (false, true) if !self.read_buf.is_empty() => {
+                self.lines.clear();
+                self.lines.push_back(self.read_buf.len());
+            }
+            _ => {}
+        }
+        if new.iflag & IXON == 0 {
+            self.stopped = false;
+        }
+    }
+
+    /// 规范模式下读取一行，一行比 `buf` 长时剩下的部分留给下一次读取，没有完整的行时返回 `None`
+    pub fn read_canonical(&mut self, buf: &mut [u8]) -> Option<usize> {
+        let len = *self.lines.front()?;
+        let count = len.min(buf.len());
+        buf[..count]
+            .iter_mut()
+            .zip(self.read_buf.drain(..count))
+            .for_each(|(dst, src)| *dst = src);
+        if count == len {
+            self.lines.pop_front();
+        } else {
+            self.lines[0] -= count;
+        }
+        Some(count)
+    }
+
+    /// 非规范模式下读取尽可能多的数据
+    pub fn read_raw(&mut self, buf: &mut [u8]) -> usize {
+        let count = self.read_buf.len().min(buf.len());
+        buf[..count]
+            .iter_mut()
+            .zip(self.read_buf.drain(..count))
+            .for_each(|(dst, src)| *dst = src);
+        count
+    }
+
+    /// 处理终端驱动收到的输入
+    pub fn receive(&mut self, termios: &Termios, bytes: &[u8]) -> InputActions {
+        let mut actions = InputActions::default();
+        bytes
+            .iter()
+            .for_each(|&ch| self.receive_char(termios, ch, &mut actions));
+        actions
+    }
+
+    fn receive_char(&mut self, termios: &Termios, mut ch: u8, actions: &mut InputActions) {
+        let lflag = LocalModes::from_bits_truncate(termios.lflag);
+        let iflag = termios.iflag;
+        if iflag & ISTRIP != 0 {
+            ch &= 0x7f;
+        }
+        if self.lnext {
+            self.lnext = false;
+            self.insert(termios, ch, actions);
+            return;
+        }
+        if ch == b'\r' {
+            if iflag & IGNCR != 0 {
+                return;
+            }
+            if iflag & ICRNL != 0 {
+                ch = b'\n';
+            }
+        } else if ch == b'\n' && iflag & INLCR != 0 {
+            ch = b'\r';
+        }
+        if iflag & IXON != 0 {
+            if is_cc(termios, VSTOP, ch) {
+                self.stopped = true;
+                return;
+            }
+            if is_cc(termios, VSTART, ch) {
+                actions.restarted |= self.stopped;
+                self.stopped = false;
+                return;
+            }
+            if self.stopped && iflag & IXANY != 0 {
+                self.stopped = false;
+                actions.restarted = true;
+            }
+        }
+        if lflag.contains(LocalModes::ISIG) {
+            let sig = if is_cc(termios, VINTR, ch) {
+                Some(SignalNumber::SIGINT)
+            } else if is_cc(termios, VQUIT, ch) {
+                Some(SignalNumber::SIGQUIT)
+            } else if is_cc(termios, VSUSP, ch) {
+                Some(SignalNumber::SIGTSTP)
+            } else {
+                None
+            };
+            if let Some(sig) = sig {
+                if !lflag.contains(LocalModes::NOFLSH) {
+                    self.flush_input();
+                }
+                if lflag.contains(LocalModes::ECHO) {
+                    self.echo_char(termios, ch, &mut actions.echo);
+                }
+                actions.signals.push(sig);
+                return;
+            }
+        }
+        if lflag.contains(LocalModes::ICANON) {
+            let iexten = lflag.contains(LocalModes::IEXTEN);
+            let echo = lflag.contains(LocalModes::ECHO);
+            if is_cc(termios, VERASE, ch) {
+                self.erase(termios, ch, EraseKind::Char, &mut actions.echo);
+                return;
+            }
+            if iexten && is_cc(termios, VWERASE, ch) {
+                self.erase(termios, ch, EraseKind::Word, &mut actions.echo);
+                return;
+            }
+            if is_cc(termios, VKILL, ch) {
+                self.erase(termios, ch, EraseKind::Line, &mut actions.echo);
+                return;
+            }
+            if iexten && is_cc(termios, VLNEXT, ch) {
+                self.lnext = true;
+                if echo && lflag.contains(LocalModes::ECHOCTL) {
+                    // 先显示 "^"，再由下一个字符覆盖
+                    actions.echo.extend_from_slice(b"^\x08");
+                }
+                return;
+            }
+            if iexten && is_cc(termios, VREPRINT, ch) {
+                if echo {
+                    self.echo_char(termios, ch, &mut actions.echo);
+                    self.output_char(termios, b'\n', &mut actions.echo);
+                    self.canon_column = self.column;
+                    let line = core::mem::take(&mut self.line);
+                    line.iter()
+                        .for_each(|&c| self.echo_char(termios, c, &mut actions.echo));
+                    self.line = line;
+                }
+                return;
+            }
+            if is_cc(termios, VEOF, ch) {
+                // VEOF 不放入缓冲区，也不回显
+                self.finish_line();
+                actions.readable = true;
+                return;
+            }
+            if ch == b'\n' || is_cc(termios, VEOL, ch) || is_cc(termios, VEOL2, ch) {
+                if echo || (ch == b'\n' && lflag.contains(LocalModes::ECHONL)) {
+                    self.echo_char(termios, ch, &mut actions.echo);
+                }
+                self.line.push(ch);
+                self.finish_line();
+                actions.readable = true;
+                return;
+            }
+        }
+        self.insert(termios, ch, actions);
+    }
+
+    /// 将普通字符放入缓冲区并回显
+    fn insert(&mut self, termios: &Termios, ch: u8, actions: &mut InputActions) {
+        let lflag = LocalModes::from_bits_truncate(termios.lflag);
+        if lflag.contains(LocalModes::ICANON) {
+            // 行已满时丢弃多余的字符
+            if self.line.len() >= N_TTY_BUF_SIZE - 1 {
+                return;
+            }
+            if self.line.is_empty() {
+                self.canon_column = self.column;
+            }
+            self.line.push(ch);
+        } else {
+            if self.read_buf.len() >= N_TTY_BUF_SIZE {
+                return;
+            }
+            self.read_buf.push_back(ch);
+            actions.readable = true;
+        }
+        if lflag.contains(LocalModes::ECHO) {
+            self.echo_char(termios, ch, &mut actions.echo);
+        }
+    }
+
+    /// 结束正在编辑的行，使其可以被读取
+    fn finish_line(&mut self) {
+        self.lines.push_back(self.line.len());
+        self.read_buf.extend(self.line.drain(..));
+    }
+
+    /// 擦除正在编辑的行中的一个字符、一个单词或者整行，并在屏幕上擦除对应的回显
+    fn erase(&mut self, termios: &Termios, ch: u8, kind: EraseKind, out: &mut Vec<u8>) {
+        if self.line.is_empty() {
+            return;
+        }
+        let lflag = LocalModes::from_bits_truncate(termios.lflag);
+        let echo = lflag.contains(LocalModes::ECHO);
+        if kind == EraseKind::Line
+            && !(echo
+                && lflag.contains(LocalModes::ECHOK)
+                && lflag.contains(LocalModes::ECHOKE)
+                && lflag.contains(LocalModes::ECHOE))
+        {
+            self.line.clear();
+            if echo {
+                self.echo_char(termios, ch, out);
+                if lflag.contains(LocalModes::ECHOK) {
+                    self.output_char(termios, b'\n', out);
+                }
+            }
+            return;
+        }
+        let iutf8 = termios.iflag & IUTF8 != 0;
+        let mut seen_alnums = 0;
+        while let Some(c) = self.line.pop() {
+            // UTF-8 字符的后续字节与首字节一起擦除
+            if iutf8 && c & 0xc0 == 0x80 {
+                continue;
+            }
+            if kind == EraseKind::Word {
+                if c.is_ascii_alphanumeric() || c == b'_' || c >= 0x80 {
+                    seen_alnums += 1;
+                } else if seen_alnums > 0 {
+                    self.line.push(c);
+                    break;
+                }
+            }
+            if echo {
+                if kind == EraseKind::Char && !lflag.contains(LocalModes::ECHOE) {
+                    self.echo_char(termios, ch, out);
+                } else if c == b'\t' {
+                    // 制表符的宽度取决于它前面的内容，退回到删除制表符之后行末所在的列
+                    let column = self.line_column(termios);
+                    out.resize(out.len() + self.column.saturating_sub(column), 0x08);
+                    self.column = column;
+                } else {
+                    let width = match (is_ctrl(c), lflag.contains(LocalModes::ECHOCTL)) {
+                        (true, true) => 2,
+                        (true, false) => 0,
+                        _ => 1,
+                    };
+                    (0..width).for_each(|_| out.extend_from_slice(b"\x08 \x08"));
+                    self.column = self.column.saturating_sub(width);
+                }
+            }
+            if kind == EraseKind::Char {
+                break;
+            }
+        }
+    }
+
+    /// 正在编辑的行回显完之后光标所在的列
+    fn line_column(&self, termios: &Termios) -> usize {
+        let echoctl = LocalModes::from_bits_truncate(termios.lflag).contains(LocalModes::ECHOCTL);
+        let iutf8 = termios.iflag & IUTF8 != 0;
+        self.line
+            .iter()
+            .fold(self.canon_column, |column, &c| match c {
+                b'\t' => (column | 7) + 1,
+                c if is_ctrl(c) => column + if echoctl { 2 } else { 0 },
+                c if iutf8 && c & 0xc0 == 0x80 => column,
+                _ => column + 1,
+            })
+    }
+
+    /// 回显一个输入的字符，开启 `ECHOCTL` 时控制字符显示为 `^X` 的形式
+    fn echo_char(&mut self, termios: &Termios, ch: u8, out: &mut Vec<u8>) {
+        let echoctl = LocalModes::from_bits_truncate(termios.lflag).contains(LocalModes::ECHOCTL);
+        if echoctl && is_ctrl(ch) && ch != b'\t' && ch != b'\n' {
+            out.push(b'^');
+            out.push(ch ^ 0x40);
+            self.column += 2;
+        } else {
+            self.output_char(termios, ch, out);
+        }
+    }
+
+    /// 对进程写入终端的数据进行输出处理
+    pub fn process_output(&mut self, termios: &Termios, buf: &[u8], out: &mut Vec<u8>) {
+        buf.iter()
+            .for_each(|&ch| self.output_char(termios, ch, out));
+    }
+
+    fn output_char(&mut self, termios: &Termios, ch: u8, out: &mut Vec<u8>) {
+        let oflag = termios.oflag;
+        if oflag & OPOST == 0 {
+            out.push(ch);
+            return;
+        }
+        match ch {
+            b'\n' if oflag & ONLCR != 0 => {
+                out.extend_from_slice(b"\r\n");
+                self.column = 0;
+            }
+            b'\n' => {
+                if oflag & ONLRET != 0 {
+                    self.column = 0;
+                }
+                out.push(ch);
+            }
+            b'\r' if oflag & ONOCR != 0 && self.column == 0 => {}
+            b'\r' if oflag & OCRNL != 0 => {
+                if oflag & ONLRET != 0 {
+                    self.column = 0;
+                }
+                out.push(b'\n');
+            }
+            b'\r' => {
+                self.column = 0;
+                out.push(ch);
+            }
+            b'\t' => {
+                let spaces = 8 - self.column % 8;
+                self.column += spaces;
+                if oflag & TABDLY == XTABS {
+                    out.resize(out.len() + spaces, b' ');
+                } else {
+                    out.push(ch);
+                }
+            }
+            0x08 => {
+                self.column = self.column.saturating_sub(1);
+                out.push(ch);
+            }
+            _ => {
+                let continuation = termios.iflag & IUTF8 != 0 && ch & 0xc0 == 0x80;
+                if !is_ctrl(ch) && !continuation {
+                    self.column += 1;
+                }
+                out.push(ch);
+            }
+        }
+    }
+}
@@ -1,11 +1,7 @@
 use alloc::sync::Arc;
 
-use constants::{
-    io::{LocalModes, TeletypeCommand, Termios, WinSize},
-    DeviceId,
-};
-use device_interface::UartDevice;
-use ksync::Mutex;
+use constants::DeviceId;
+use device_interface::{UartDevice, UartReceiver};
 use spin::Once;
 use vfscore::{
     error::VfsError,
@@ -16,32 +12,44 @@ use vfscore::{
     VfsResult,
 };
 
+use crate::tty::{Tty, TtyDriver};
+
 pub static UART_DEVICE: Once<Arc<dyn UartDevice>> = Once::new();
 
+/// 串口对应的终端
+pub static UART_TTY: Once<Arc<Tty>> = Once::new();
+
 pub fn init_uart(uart: Arc<dyn UartDevice>) {
-    UART_DEVICE.call_once(|| uart);
+    UART_DEVICE.call_once(|| uart.clone());
+    let tty = UART_TTY.call_once(|| Tty::new(Arc::new(UartTtyDriver(uart.clone()))));
+    // 串口的中断处理程序直接将收到的数据交给终端的行规程
+    uart.set_receiver(tty.clone());
+}
+
+/// 将终端的输出写入串口
+struct UartTtyDriver(Arc<dyn UartDevice>);
+
+impl TtyDriver for UartTtyDriver {
+    fn write(&self, buf: &[u8]) {
+        // 行规程已经完成了换行的转换，不能再使用会转换换行的 put_bytes
+        buf.iter().for_each(|&c| self.0.put(c));
+    }
 }
 
-#[derive(Debug, Default)]
-pub struct IoData {
-    foreground_pgid: u32,
-    winsize: WinSize,
-    termios: Termios,
+impl UartReceiver for Tty {
+    fn receive(&self, bytes: &[u8]) {
+        Tty::receive(self, bytes)
+    }
 }
 
 pub struct UARTDevice {
     device_id: DeviceId,
-    device: Arc<dyn UartDevice>,
-    io: Mutex<IoData>,
+    tty: Arc<Tty>,
 }
 
 impl UARTDevice {
-    pub fn new(device_id: DeviceId, device: Arc<dyn UartDevice>) -> Self {
-        Self {
-            device_id,
-            device,
-            io: Mutex::new(IoData::default()),
-        }
+    pub fn new(device_id: DeviceId, tty: Arc<Tty>) -> Self {
+        Self { device_id, tty }
     }
     pub fn device_id(&self) -> DeviceId {
         self.device_id
@@ -50,86 +58,16 @@ impl UARTDevice {
 
 impl VfsFile for UARTDevice {
     fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
-        // read util \r and transform to \n
-        let mut read_count = 0;
-        loop {
-            let ch = self.device.get();
-            assert!(ch.is_some());
-            let ch = ch.unwrap();
-            buf[read_count] = ch;
-            read_count += 1;
-            if ch == b'\r' {
-                buf[read_count - 1] = b'\n';
-                if LocalModes::from_bits_truncate(self.io.lock().termios.lflag)
-                    .contains(LocalModes::ECHO)
-                {
-                    self.device.put(b'\n');
-                }
-                break;
-            }
-            if LocalModes::from_bits_truncate(self.io.lock().termios.lflag)
-                .contains(LocalModes::ECHO)
-            {
-                self.device.put(ch);
-            }
-            if read_count >= buf.len() {
-                break;
-            }
-        }
-        Ok(read_count)
+        self.tty.read(buf)
     }
     fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
-        self.device.put_bytes(buf);
-        Ok(buf.len())
+        self.tty.write(buf, false)
     }
     fn poll(&self, event: VfsPollEvents) -> VfsResult<VfsPollEvents> {
-        let mut res = VfsPollEvents::empty();
-        if event.contains(VfsPollEvents::IN) {
-            if self.device.have_data_to_get() {
-                res |= VfsPollEvents::IN;
-            }
-        }
-        if event.contains(VfsPollEvents::OUT) {
-            if self.device.have_space_to_put() {
-                res |= VfsPollEvents::OUT
-            }
-        }
-        Ok(res)
+        self.tty.poll(event)
     }
     fn ioctl(&self, cmd: u32, arg: usize) -> VfsResult<usize> {
-        let mut io = self.io.lock();
-        let cmd = TeletypeCommand::try_from(cmd).unwrap();
-        return match cmd {
-            TeletypeCommand::TCGETS | TeletypeCommand::TCGETA => {
-                shim::copy_data_to_task(&io.termios, arg as *mut Termios);
-                Ok(0)
-            }
-            TeletypeCommand::TCSETS | TeletypeCommand::TCSETSW | TeletypeCommand::TCSETSF => {
-                shim::copy_data_from_task(arg as *const Termios, &mut io.termios);
-                Ok(0)
-            }
-            TeletypeCommand::TIOCGPGRP => {
-                let word = shim::transfer_ptr_mut(arg as *mut u32);
-                *word = io.foreground_pgid;
-                Ok(0)
-            }
-            TeletypeCommand::TIOCSPGRP => {
-                let word = shim::transfer_ptr(arg as *const u32);
-                io.foreground_pgid = *word;
-                Ok(0)
-            }
-            TeletypeCommand::TIOCGWINSZ => {
-                shim::copy_data_to_task(&io.winsize, arg as *mut WinSize);
-                Ok(0)
-            }
-            TeletypeCommand::TIOCSWINSZ => {
-                shim::copy_data_from_task(arg as *const WinSize, &mut io.winsize);
-                Ok(0)
-            }
-            _ => {
-                unimplemented!("ioctl cmd: {:?}", cmd)
-            }
-        };
+        self.tty.ioctl(cmd, arg)
     }
     fn flush(&self) -> VfsResult<()> {
         Ok(())
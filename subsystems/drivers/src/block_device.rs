@@ -1,4 +1,8 @@
-use alloc::{boxed::Box, collections::BTreeMap, sync::Arc, vec::Vec};
+use alloc::{
+    boxed::Box,
+    collections::{BTreeMap, BTreeSet},
+    sync::Arc,
+};
 use core::{
     cmp::min,
     fmt::{Debug, Formatter},
@@ -29,7 +33,8 @@ const PAGE_CACHE_SIZE: usize = FRAME_SIZE;
 pub struct GenericBlockDevice {
     device: Box<dyn LowBlockDevice>,
     cache: Mutex<LruCache<usize, FrameTracker>>,
-    dirty: Mutex<Vec<usize>>,
+    /// 被修改过、还未写回设备的缓存页
+    dirty: Mutex<BTreeSet<usize>>,
 }
 
 #[derive(Debug)]
@@ -74,8 +79,54 @@ impl GenericBlockDevice {
             cache: Mutex::new(LruCache::new(
                 NonZeroUsize::new(BLOCK_CACHE_FRAMES).unwrap(),
             )),
-            dirty: Mutex::new(Vec::new()),
+            dirty: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// 将缓存页 `page_id` 写回设备
+    fn write_page(&self, page_id: usize, cache: &FrameTracker) -> AlienResult<()> {
+        let start_block = page_id * PAGE_CACHE_SIZE / 512;
+        let end_block = start_block + PAGE_CACHE_SIZE / 512;
+        for i in start_block..end_block {
+            let target_buf = &cache[(i - start_block) * 512..(i - start_block + 1) * 512];
+            self.device.write_block(i, target_buf)?;
         }
+        Ok(())
+    }
+
+    /// 获取缓存页 `page_id`，如果不在缓存中则从设备读入，被换出的脏页会先写回设备
+    fn get_page<'a>(
+        &self,
+        cache_lock: &'a mut LruCache<usize, FrameTracker>,
+        page_id: usize,
+    ) -> AlienResult<&'a mut FrameTracker> {
+        if !cache_lock.contains(&page_id) {
+            // 缓存已满时先写回将被换出的脏页，写回失败时不换出该页，避免丢失其中的数据
+            if cache_lock.len() == cache_lock.cap().get() {
+                if let Some((&id, old_cache)) = cache_lock.peek_lru() {
+                    if self.dirty.lock().contains(&id) {
+                        self.write_page(id, old_cache)?;
+                        self.dirty.lock().remove(&id);
+                    }
+                }
+            }
+            let cache = alloc_frames(1);
+            let mut cache = FrameTracker::new(cache as usize);
+            let start_block = page_id * PAGE_CACHE_SIZE / 512;
+            let end_block = start_block + PAGE_CACHE_SIZE / 512;
+            for i in start_block..end_block {
+                let target_buf = &mut cache[(i - start_block) * 512..(i - start_block + 1) * 512];
+                self.device.read_block(i, target_buf)?;
+            }
+            // the evicted page has been written back above
+            cache_lock.push(page_id, cache);
+        }
+        Ok(cache_lock.get_mut(&page_id).unwrap())
+    }
+
+    /// 脏页的数量
+    pub fn dirty_pages(&self) -> usize {
+        self.dirty.lock().len()
     }
 }
 
@@ -101,30 +152,7 @@ impl BlockDevice for GenericBlockDevice {
         let mut count = 0;
 
         while count < len {
-            if !cache_lock.contains(&page_id) {
-                let device = &self.device;
-                let cache = alloc_frames(1);
-                let mut cache = FrameTracker::new(cache as usize);
-                let start_block = page_id * PAGE_CACHE_SIZE / 512;
-                let end_block = start_block + PAGE_CACHE_SIZE / 512;
-                for i in start_block..end_block {
-                    let target_buf =
-                        &mut cache[(i - start_block) * 512..(i - start_block + 1) * 512];
-                    device.read_block(i, target_buf).unwrap();
-                }
-                let old_cache = cache_lock.push(page_id, cache);
-                if let Some((id, old_cache)) = old_cache {
-                    let start_block = id * PAGE_CACHE_SIZE / 512;
-                    let end_block = start_block + PAGE_CACHE_SIZE / 512;
-                    for i in start_block..end_block {
-                        let target_buf =
-                            &old_cache[(i - start_block) * 512..(i - start_block + 1) * 512];
-                        device.write_block(i, target_buf).unwrap();
-                        self.dirty.lock().retain(|&x| x != id);
-                    }
-                }
-            }
-            let cache = cache_lock.get(&page_id).unwrap();
+            let cache = self.get_page(&mut cache_lock, page_id)?;
             let copy_len = min(PAGE_CACHE_SIZE - offset, len - count);
             buf[count..count + copy_len].copy_from_slice(&cache[offset..offset + copy_len]);
             count += copy_len;
@@ -140,32 +168,10 @@ impl BlockDevice for GenericBlockDevice {
         let len = buf.len();
         let mut count = 0;
         while count < len {
-            if !cache_lock.contains(&page_id) {
-                let device = &self.device;
-                let cache = alloc_frames(1);
-                let mut cache = FrameTracker::new(cache as usize);
-                let start_block = page_id * PAGE_CACHE_SIZE / 512;
-                let end_block = start_block + PAGE_CACHE_SIZE / 512;
-                for i in start_block..end_block {
-                    let target_buf =
-                        &mut cache[(i - start_block) * 512..(i - start_block + 1) * 512];
-                    device.read_block(i, target_buf).unwrap();
-                }
-                let old_cache = cache_lock.push(page_id, cache);
-                if let Some((id, old_cache)) = old_cache {
-                    let start_block = id * PAGE_CACHE_SIZE / 512;
-                    let end_block = start_block + PAGE_CACHE_SIZE / 512;
-                    for i in start_block..end_block {
-                        let target_buf =
-                            &old_cache[(i - start_block) * 512..(i - start_block + 1) * 512];
-                        device.write_block(i, target_buf).unwrap();
-                        self.dirty.lock().retain(|&x| x != id);
-                    }
-                }
-            }
-            let cache = cache_lock.get_mut(&page_id).unwrap();
+            let cache = self.get_page(&mut cache_lock, page_id)?;
             let copy_len = min(PAGE_CACHE_SIZE - offset, len - count);
             cache[offset..offset + copy_len].copy_from_slice(&buf[count..count + copy_len]);
+            self.dirty.lock().insert(page_id);
             count += copy_len;
             offset = (offset + copy_len) % PAGE_CACHE_SIZE;
             page_id += 1;
@@ -175,21 +181,22 @@ impl BlockDevice for GenericBlockDevice {
     fn size(&self) -> usize {
         self.device.capacity() * 512
     }
+    /// 将所有脏页写回设备，返回时数据已经写入设备
     fn flush(&self) -> AlienResult<()> {
-        // let mut device = self.device.lock();
-        // let mut lru = self.cache.lock();
-        // self.dirty.lock().iter().for_each(|id|{
-        //     let start = id * PAGE_CACHE_SIZE;
-        //     let start_block = start / 512;
-        //     let end_block = (start + PAGE_CACHE_SIZE) / 512;
-        //     let cache = lru.get(id).unwrap();
-        //     for i in start_block..end_block {
-        //         let target_buf = &cache[(i - start_block) * 512..(i - start_block + 1) * 512];
-        //         device.write_block(i, target_buf).unwrap();
-        //     }
-        // });
-        // self.dirty.lock().clear();
-        Ok(())
+        let mut cache_lock = self.cache.lock();
+        let dirty = core::mem::take(&mut *self.dirty.lock());
+        let mut res = Ok(());
+        for id in dirty {
+            // dirty pages are always in the cache, evicted pages are written back and removed
+            let cache = cache_lock.peek(&id).unwrap();
+            if let Err(e) = self.write_page(id, cache) {
+                // keep the page dirty so that it can be written back later
+                self.dirty.lock().insert(id);
+                res = Err(e);
+            }
+        }
+        drop(cache_lock);
+        res
     }
 }
 
@@ -1,12 +1,17 @@
-use alloc::{collections::VecDeque, sync::Arc};
+use alloc::{
+    collections::VecDeque,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
 use core::ptr::NonNull;
 
-use device_interface::{DeviceBase, InputDevice};
-use ksync::Mutex;
+use device_interface::{DeviceBase, InputAbsInfo, InputDevice, InputHandler, InputId};
+use ksync::{wait::WaitQueue, Mutex};
 use log::info;
-use shim::KTask;
+use spin::Once;
 use virtio_drivers::{
-    device::input::VirtIOInput,
+    device::input::{InputConfigSelect, VirtIOInput},
     transport::mmio::{MmioTransport, VirtIOHeader},
 };
 
@@ -14,6 +19,10 @@ use crate::hal::HalImpl;
 
 pub struct VirtIOInputDriver {
     inner: Mutex<InputDriverInner>,
+    /// 等待输入事件的任务
+    wait_queue: WaitQueue,
+    /// 中断处理程序中接收事件的上层(evdev)
+    handler: Once<Arc<dyn InputHandler>>,
 }
 
 unsafe impl Send for VirtIOInputDriver {}
@@ -24,7 +33,6 @@ struct InputDriverInner {
     max_events: u32,
     driver: VirtIOInput<HalImpl, MmioTransport>,
     events: VecDeque<u64>,
-    wait_queue: VecDeque<Arc<dyn KTask>>,
 }
 
 impl VirtIOInputDriver {
@@ -34,12 +42,20 @@ impl VirtIOInputDriver {
                 max_events,
                 driver,
                 events: VecDeque::with_capacity(max_events as usize),
-                wait_queue: VecDeque::new(),
             }),
+            wait_queue: WaitQueue::new(),
+            handler: Once::new(),
         };
         driver
     }
 
+    /// 读取设备的配置空间，返回 `select` 和 `subsel` 对应的数据的长度，数据最多 128 字节
+    fn query_config(&self, select: InputConfigSelect, subsel: u8, out: &mut [u8]) -> usize {
+        let mut inner = self.inner.lock();
+        let size = inner.driver.query_config_select(select, subsel, out) as usize;
+        size.min(out.len())
+    }
+
     pub fn from_addr(addr: usize, max_events: u32) -> Self {
         let header = NonNull::new(addr as *mut VirtIOHeader).unwrap();
         let transport = unsafe { MmioTransport::new(header) }.unwrap();
@@ -63,17 +79,10 @@ impl InputDevice for VirtIOInputDriver {
 
     fn read_event_async(&self) -> u64 {
         loop {
-            let task = {
-                let mut inner = self.inner.lock();
-                if let Some(event) = inner.events.pop_front() {
-                    return event;
-                }
-                let task = shim::take_current_task().unwrap();
-                task.to_wait();
-                inner.wait_queue.push_back(task.clone());
-                task
-            }; // drop the lock
-            shim::schedule_now(task); // yield current task
+            if let Some(event) = self.inner.lock().events.pop_front() {
+                return event;
+            }
+            self.wait_queue.wait_event(|| !self.is_empty());
         }
     }
 
@@ -81,13 +90,73 @@ impl InputDevice for VirtIOInputDriver {
         let mut inner = self.inner.lock();
         inner.events.pop_front()
     }
+
+    fn set_handler(&self, handler: Arc<dyn InputHandler>) {
+        self.handler.call_once(|| handler);
+    }
+
+    fn name(&self) -> String {
+        let mut name = [0u8; 128];
+        let size = self.query_config(InputConfigSelect::IdName, 0, &mut name);
+        String::from_utf8_lossy(&name[..size]).to_string()
+    }
+
+    fn input_id(&self) -> InputId {
+        let mut ids = [0u8; 8];
+        if self.query_config(InputConfigSelect::IdDevids, 0, &mut ids) < ids.len() {
+            return InputId::default();
+        }
+        let id = |i: usize| u16::from_le_bytes([ids[i * 2], ids[i * 2 + 1]]);
+        InputId {
+            bustype: id(0),
+            vendor: id(1),
+            product: id(2),
+            version: id(3),
+        }
+    }
+
+    fn event_bits(&self, event_type: u16, bits: &mut [u8]) -> usize {
+        match u8::try_from(event_type) {
+            Ok(event_type) => self.query_config(InputConfigSelect::EvBits, event_type, bits),
+            Err(_) => 0,
+        }
+    }
+
+    fn prop_bits(&self, bits: &mut [u8]) -> usize {
+        self.query_config(InputConfigSelect::PropBits, 0, bits)
+    }
+
+    fn abs_info(&self, axis: u16) -> Option<InputAbsInfo> {
+        // virtio 的 absinfo 依次为 min、max、fuzz、flat 和 res
+        let mut info = [0u8; 20];
+        let axis = u8::try_from(axis).ok()?;
+        if self.query_config(InputConfigSelect::AbsInfo, axis, &mut info) < info.len() {
+            return None;
+        }
+        let field = |i: usize| {
+            i32::from_le_bytes([
+                info[i * 4],
+                info[i * 4 + 1],
+                info[i * 4 + 2],
+                info[i * 4 + 3],
+            ])
+        };
+        Some(InputAbsInfo {
+            value: 0,
+            minimum: field(0),
+            maximum: field(1),
+            fuzz: field(2),
+            flat: field(3),
+            resolution: field(4),
+        })
+    }
 }
 
 impl DeviceBase for VirtIOInputDriver {
     fn handle_irq(&self) {
         let mut inner = self.inner.lock();
         inner.driver.ack_interrupt();
-        let mut count = 0;
+        let mut events = Vec::new();
         while let Some(event) = inner.driver.pop_pending_event() {
             let result =
                 (event.event_type as u64) << 48 | (event.code as u64) << 32 | (event.value) as u64;
@@ -97,14 +166,19 @@ impl DeviceBase for VirtIOInputDriver {
                 inner.events.pop_front();
             }
             inner.events.push_back(result);
-            count += 1;
-        }
-        while !inner.wait_queue.is_empty() && count > 0 {
-            let task = inner.wait_queue.pop_front().unwrap();
-            task.to_wakeup();
-            shim::put_task(task);
-            count -= 1;
+            events.push(event);
         }
+        drop(inner);
+        let count = events.len();
         info!("read {} events", count);
+        if count > 0 {
+            self.wait_queue.wake_n(count);
+        }
+        // 在驱动的锁之外将事件交给上层
+        if let Some(handler) = self.handler.get() {
+            events
+                .iter()
+                .for_each(|event| handler.handle_event(event.event_type, event.code, event.value));
+        }
     }
 }
@@ -6,5 +6,6 @@ pub mod gpu;
 pub mod hal;
 pub mod input;
 pub mod net;
+pub mod rng;
 pub mod rtc;
 pub mod uart;
@@ -1,6 +1,10 @@
-use core::ptr::NonNull;
+use core::{
+    ptr::NonNull,
+    sync::atomic::{AtomicUsize, Ordering},
+};
 
 use constants::time::TimeSpec;
+use ksync::wait::WaitQueue;
 pub use loopback::LoopbackDev;
 use netcore::{KernelNetFunc, NetInstant};
 use timer::TimeNow;
@@ -12,6 +16,17 @@ use crate::hal::HalImpl;
 pub const NET_BUFFER_LEN: usize = 4096;
 pub const NET_QUEUE_SIZE: usize = 128;
 
+/// 在 [`NetNeedFunc::yield_now`] 中等待网络协议栈推进的任务
+static NET_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+/// 网卡被轮询的次数
+static NET_POLL_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// 网卡被轮询之后调用，唤醒在 [`NetNeedFunc::yield_now`] 中等待的任务
+pub fn net_wakeup() {
+    NET_POLL_SEQ.fetch_add(1, Ordering::AcqRel);
+    NET_WAIT_QUEUE.wake_all();
+}
+
 pub struct VirtIONetDriver;
 
 impl VirtIONetDriver {
@@ -50,10 +65,11 @@ impl KernelNetFunc for NetNeedFunc {
             micros: time_spec.tv_sec as i64 * 1000_000 + time_spec.tv_nsec as i64 / 1000,
         }
     }
+    /// 睡眠直到网卡下一次被轮询，被信号打断时返回 `true`
     fn yield_now(&self) -> bool {
-        shim::suspend();
-        // interrupt by signal ?
-        let task = shim::current_task().unwrap();
-        task.have_signal()
+        let seq = NET_POLL_SEQ.load(Ordering::Acquire);
+        NET_WAIT_QUEUE
+            .wait_event_interruptible(|| NET_POLL_SEQ.load(Ordering::Acquire) != seq)
+            .is_err()
     }
 }
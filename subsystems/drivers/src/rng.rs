@@ -0,0 +1,69 @@
+use bitflags::bitflags;
+use constants::{AlienResult, LinuxErrno};
+use device_interface::{DeviceBase, RngDevice};
+use ksync::Mutex;
+use virtio_drivers::{
+    queue::VirtQueue,
+    transport::{mmio::MmioTransport, Transport},
+};
+
+use crate::hal::HalImpl;
+
+/// virtio-rng 只有一个请求队列
+const QUEUE_IDX: u16 = 0;
+const QUEUE_SIZE: usize = 8;
+
+bitflags! {
+    /// virtio-rng 没有设备特有的特性，这里只列出通用的特性
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    struct RngFeature: u64 {
+        const RING_INDIRECT_DESC = 1 << 28;
+        const RING_EVENT_IDX = 1 << 29;
+    }
+}
+
+/// virtio 熵源设备(virtio-rng)
+///
+/// 设备只有一个队列，驱动放入一个可写的缓冲区，设备将随机数写入其中后返回。
+pub struct VirtIORngDriver {
+    inner: Mutex<RngDriverInner>,
+}
+
+struct RngDriverInner {
+    transport: MmioTransport,
+    queue: VirtQueue<HalImpl, QUEUE_SIZE>,
+}
+
+unsafe impl Send for VirtIORngDriver {}
+
+unsafe impl Sync for VirtIORngDriver {}
+
+impl VirtIORngDriver {
+    pub fn from_mmio(mut transport: MmioTransport) -> Self {
+        transport.begin_init(RngFeature::empty());
+        let queue = VirtQueue::new(&mut transport, QUEUE_IDX, false, false)
+            .expect("failed to create virtio-rng queue");
+        transport.finish_init();
+        Self {
+            inner: Mutex::new(RngDriverInner { transport, queue }),
+        }
+    }
+}
+
+impl DeviceBase for VirtIORngDriver {
+    fn handle_irq(&self) {
+        self.inner.lock().transport.ack_interrupt();
+    }
+}
+
+impl RngDevice for VirtIORngDriver {
+    fn read_entropy(&self, buf: &mut [u8]) -> AlienResult<usize> {
+        let mut inner = self.inner.lock();
+        let inner = &mut *inner;
+        inner
+            .queue
+            .add_notify_wait_pop(&[], &mut [buf], &mut inner.transport)
+            .map(|len| len as usize)
+            .map_err(|_| LinuxErrno::EIO)
+    }
+}
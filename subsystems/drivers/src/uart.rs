@@ -1,8 +1,9 @@
-use alloc::{boxed::Box, collections::VecDeque, sync::Arc};
+use alloc::{boxed::Box, collections::VecDeque, sync::Arc, vec::Vec};
 
-use device_interface::{DeviceBase, UartDevice};
-use ksync::Mutex;
-use shim::KTask;
+use constants::io::PollEvents;
+use device_interface::{DeviceBase, UartDevice, UartReceiver};
+use ksync::{poll::PollQueue, wait::WaitQueue, Mutex};
+use spin::Once;
 
 pub use self::{uart16550::Uart16550, uart8250::Uart8250};
 
@@ -93,11 +94,15 @@ mod uart16550 {
 
 pub struct Uart {
     inner: Mutex<(Box<dyn LowUartDriver>, UartInner)>,
+    /// 等待接收数据的任务
+    wait_queue: WaitQueue,
+    poll_queue: PollQueue,
+    /// 设置后，中断处理程序收到的数据直接交给上层(终端)处理
+    receiver: Once<Arc<dyn UartReceiver>>,
 }
 
 struct UartInner {
     rx_buf: VecDeque<u8>,
-    wait_queue: VecDeque<Arc<dyn KTask>>,
 }
 
 impl Uart {
@@ -106,10 +111,12 @@ impl Uart {
         uart_raw._init();
         let inner = UartInner {
             rx_buf: VecDeque::new(),
-            wait_queue: VecDeque::new(),
         };
         Uart {
             inner: Mutex::new((uart_raw, inner)),
+            wait_queue: WaitQueue::new(),
+            poll_queue: PollQueue::new(),
+            receiver: Once::new(),
         }
     }
 }
@@ -123,11 +130,8 @@ impl UartDevice for Uart {
         loop {
             let mut inner = self.inner.lock();
             if inner.1.rx_buf.is_empty() {
-                let task = shim::take_current_task().unwrap();
-                task.to_wait();
-                inner.1.wait_queue.push_back(task.clone());
                 drop(inner);
-                shim::schedule_now(task);
+                self.wait_queue.wait_event(|| self.have_data_to_get());
             } else {
                 return inner.1.rx_buf.pop_front();
             }
@@ -150,22 +154,39 @@ impl UartDevice for Uart {
     fn have_space_to_put(&self) -> bool {
         true
     }
+
+    fn poll_queue(&self) -> Option<&PollQueue> {
+        Some(&self.poll_queue)
+    }
+
+    fn set_receiver(&self, receiver: Arc<dyn UartReceiver>) {
+        let receiver = self.receiver.call_once(|| receiver);
+        let buffered = self.inner.lock().1.rx_buf.drain(..).collect::<Vec<_>>();
+        if !buffered.is_empty() {
+            receiver.receive(&buffered);
+        }
+    }
 }
 
 impl DeviceBase for Uart {
     fn handle_irq(&self) {
-        loop {
+        let mut received = Vec::new();
+        {
             let mut inner = self.inner.lock();
-            if let Some(c) = inner.0._read() {
-                inner.1.rx_buf.push_back(c);
-                if !inner.1.wait_queue.is_empty() {
-                    let task = inner.1.wait_queue.pop_front().unwrap();
-                    task.to_wakeup();
-                    shim::put_task(task);
-                }
-            } else {
-                break;
+            while let Some(c) = inner.0._read() {
+                received.push(c);
             }
         }
+        if received.is_empty() {
+            return;
+        }
+        // 在锁之外交给上层处理，上层在处理时可能需要回显
+        if let Some(receiver) = self.receiver.get() {
+            receiver.receive(&received);
+            return;
+        }
+        self.inner.lock().1.rx_buf.extend(received);
+        self.wait_queue.wake_all();
+        self.poll_queue.notify(PollEvents::EPOLLIN);
     }
 }
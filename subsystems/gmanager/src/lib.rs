@@ -118,6 +118,14 @@ impl<T: Clone> MinimalManager<T> {
         Ok(val.clone())
     }
 
+    /// iterate over all values with their index
+    pub fn iter(&self) -> impl Iterator<Item = (usize, &T)> {
+        self.data
+            .iter()
+            .enumerate()
+            .filter_map(|(index, val)| val.as_ref().map(|val| (index, val)))
+    }
+
     /// User should ensure that the index is valid
     pub fn insert_with_index(&mut self, index: usize, val: T) -> Result<(), ManagerError> {
         if index >= self.max {
@@ -63,6 +63,7 @@ pub fn external_interrupt_handler() {
         .unwrap();
     device.handle_irq();
     plic.complete(hart_id as u32, Mode::Supervisor, irq);
+    krandom::add_interrupt_randomness(irq as usize);
     let mut interrupts = INTERRUPT_RECORD.lock();
     let value = interrupts.entry(irq as usize).or_insert(0);
     *value += 1;
@@ -1,6 +1,6 @@
 //! 在 Alien 内核中使用的 socket 套接字地址结构。
 //!
-//! Alien 中目前能够接收的套接字地址种类包括本地路径地址和网络套接字地址。
+//! Alien 中目前能够接收的套接字地址种类包括 Unix 协议族的本地地址和网络套接字地址。
 //! 对于从用户端传来的套接字地址，类似于 `linux` 中 `socket.h` 的套接字地址。
 //! 大致结构如下:
 //! + 2字节表明该套接字使用的地址协议族
@@ -10,7 +10,7 @@
 //! Alien 将会首先对传入的套接字的协议族进行解析，然后根据不同的地址协议族将其解析成 [`SocketAddrExt`] 结构，
 //! 向下层的具体套接字中传递相应地址时，传递的也是 [`SocketAddrExt`] 结构。
 //!
-use alloc::string::String;
+use alloc::{string::String, vec::Vec};
 use core::{
     fmt::Debug,
     net::{IpAddr, SocketAddr},
@@ -18,13 +18,71 @@ use core::{
 
 use constants::net::Domain;
 
-/// 用于存储套接字通信地址的结构，分为本地路径地址和网络套接字地址。
+/// 用于存储套接字通信地址的结构，分为 Unix 协议族的本地地址和网络套接字地址。
 #[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
 pub enum SocketAddrExt {
-    LocalPath(String),
+    Unix(UnixAddr),
     SocketAddr(SocketAddr),
 }
 
+/// Unix 协议族的套接字地址，对应 `linux` 中的 `sockaddr_un` 结构。
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub enum UnixAddr {
+    /// 未绑定的地址
+    Unnamed,
+    /// 文件系统中的路径，保存的是绝对路径
+    Path(String),
+    /// 抽象命名空间中的名字，不包括开头的 `\0`
+    Abstract(Vec<u8>),
+}
+
+/// 路径地址对应的套接字文件，由文件所在的设备号和 inode 号确定。
+///
+/// 绑定到路径地址时会在文件系统中创建套接字文件，地址表中以该文件而不是路径字符串区分路径地址。
+#[derive(Clone, Copy, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct UnixInode {
+    pub dev: u64,
+    pub ino: u64,
+}
+
+/// `sockaddr_un` 中路径的最大长度
+pub const UNIX_PATH_MAX: usize = 108;
+
+/// 对应 `linux` 中 `un.h` 的 `sockaddr_un` 结构。
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RawUnixAddr {
+    /// 地址协议族
+    pub family: u16,
+    /// 路径或者抽象命名空间中的名字
+    pub path: [u8; UNIX_PATH_MAX],
+}
+
+impl UnixAddr {
+    /// 将地址转换为 `sockaddr_un` 结构，同时返回地址的有效长度
+    pub fn to_raw(&self) -> (RawUnixAddr, usize) {
+        let mut raw = RawUnixAddr {
+            family: Domain::AF_UNIX as u16,
+            path: [0; UNIX_PATH_MAX],
+        };
+        let family_len = core::mem::size_of::<u16>();
+        let len = match self {
+            UnixAddr::Unnamed => family_len,
+            UnixAddr::Path(path) => {
+                let len = path.len().min(UNIX_PATH_MAX - 1);
+                raw.path[..len].copy_from_slice(&path.as_bytes()[..len]);
+                family_len + len + 1
+            }
+            UnixAddr::Abstract(name) => {
+                let len = name.len().min(UNIX_PATH_MAX - 1);
+                raw.path[1..len + 1].copy_from_slice(&name[..len]);
+                family_len + len + 1
+            }
+        };
+        (raw, len)
+    }
+}
+
 /// 用于存储一个Ipv4套接字相关信息的结构。对应 `linux` 中 `socket.h` 的 `sockaddr_in` 结构。
 ///
 /// 在 socket 相关系统调用中，一般都先分析出套接字采用的地址协议族，如果是 `IPV4` 则会将传入的套接字相关信息解析成 `RawIpV4Addr`。
@@ -46,19 +104,19 @@ impl SocketAddrExt {
     /// 获取网络套接字地址。当本结构中存储的是本地路径地址时，将导致 panic。
     pub fn get_socketaddr(&self) -> SocketAddr {
         match self {
-            SocketAddrExt::LocalPath(_) => {
-                panic!("Can't get socketaddr from local path")
+            SocketAddrExt::Unix(_) => {
+                panic!("Can't get socketaddr from unix addr")
             }
             SocketAddrExt::SocketAddr(addr) => *addr,
         }
     }
 
-    /// 获取本地路径地址。当本结构中存储的是网络套接字地址时，将导致 panic。
-    pub fn get_local_path(&self) -> String {
+    /// 获取 Unix 协议族的地址。当本结构中存储的是网络套接字地址时，将导致 panic。
+    pub fn get_unix_addr(&self) -> UnixAddr {
         match self {
-            SocketAddrExt::LocalPath(path) => path.clone(),
+            SocketAddrExt::Unix(addr) => addr.clone(),
             SocketAddrExt::SocketAddr(_) => {
-                panic!("Can't get local path from socketaddr")
+                panic!("Can't get unix addr from socketaddr")
             }
         }
     }
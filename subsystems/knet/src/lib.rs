@@ -5,6 +5,7 @@ extern crate alloc;
 extern crate log;
 
 pub mod addr;
+pub mod option;
 pub mod port;
 pub mod socket;
 pub mod unix;
@@ -0,0 +1,154 @@
+//! 套接字选项。
+//!
+//! 每个套接字在 [`SocketData`](crate::socket::SocketData) 中保存一份 [`SocketOptions`]，`setsockopt`/`getsockopt`
+//! 系统调用通过 `SocketData::set_option` 和 `SocketData::get_option` 以字节的形式读写选项的值。
+//!
+//! 接收和发送超时、缓冲区大小 (对于 Unix 套接字) 和 `SO_ERROR` 会影响套接字的行为。`netcore` 不支持设置
+//! 地址重用、保活、`linger`、`TCP_NODELAY` 和 TTL，这些选项只接受与协议栈的固定行为相同的值，见 [`check_fixed`]，
+//! 其它的值返回 `ENOPROTOOPT`，读取时总是返回固定的值。
+use alloc::vec::Vec;
+
+use constants::{time::TimeVal, AlienResult, LinuxErrno};
+use timer::ToClock;
+
+/// `SOL_SOCKET` 级别的选项
+pub const SO_REUSEADDR: usize = 2;
+pub const SO_TYPE: usize = 3;
+pub const SO_ERROR: usize = 4;
+pub const SO_SNDBUF: usize = 7;
+pub const SO_RCVBUF: usize = 8;
+pub const SO_KEEPALIVE: usize = 9;
+pub const SO_LINGER: usize = 13;
+pub const SO_REUSEPORT: usize = 15;
+pub const SO_RCVTIMEO: usize = 20;
+pub const SO_SNDTIMEO: usize = 21;
+
+/// `IPPROTO_TCP` 级别的选项
+pub const TCP_NODELAY: usize = 1;
+pub const TCP_MAXSEG: usize = 2;
+
+/// `IPPROTO_IP` 级别的选项
+pub const IP_TTL: usize = 2;
+
+/// 选项值的最大长度，目前最长的选项为 `timeval`
+pub const MAX_OPTION_LEN: usize = 64;
+
+/// 用户可以设置的缓冲区大小的上限，对应 `linux` 中的 `net.core.rmem_max`/`net.core.wmem_max`
+const SOCK_BUF_MAX: usize = 212992;
+/// 缓冲区大小的下限
+const SOCK_BUF_MIN: usize = 2304;
+/// 协议栈使用的 IP 报文生存时间
+pub const FIXED_TTL: i32 = 64;
+
+/// 对应 `linux` 中 `socket.h` 的 `linger` 结构
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Linger {
+    pub onoff: i32,
+    /// 关闭套接字时等待未发送数据的时间(秒)
+    pub linger: i32,
+}
+
+/// 一个套接字的选项
+#[derive(Debug, Clone)]
+pub struct SocketOptions {
+    pub nonblock: bool,
+    pub recv_buf: usize,
+    pub send_buf: usize,
+    /// 接收超时时间，为 0 时表示不会超时
+    pub recv_timeout: TimeVal,
+    /// 发送超时时间，为 0 时表示不会超时
+    pub send_timeout: TimeVal,
+    /// 尚未通过 `SO_ERROR` 报告的错误
+    pub error: Option<LinuxErrno>,
+}
+
+impl SocketOptions {
+    pub fn new(recv_buf: usize, send_buf: usize) -> Self {
+        Self {
+            nonblock: false,
+            recv_buf,
+            send_buf,
+            recv_timeout: TimeVal {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            send_timeout: TimeVal {
+                tv_sec: 0,
+                tv_usec: 0,
+            },
+            error: None,
+        }
+    }
+
+    /// 接收超时时间对应的 cpu 时钟周期数，为 0 时表示不会超时
+    pub fn recv_timeout_clock(&self) -> usize {
+        self.recv_timeout.to_clock()
+    }
+
+    /// 发送超时时间对应的 cpu 时钟周期数，为 0 时表示不会超时
+    pub fn send_timeout_clock(&self) -> usize {
+        self.send_timeout.to_clock()
+    }
+}
+
+/// 与 `linux` 相同，用户设置的缓冲区大小会被加倍，用于容纳内核的管理开销
+pub fn buffer_size(value: usize) -> usize {
+    (value.min(SOCK_BUF_MAX) * 2).max(SOCK_BUF_MIN)
+}
+
+/// 将选项的值解析为整数
+pub fn parse_int(value: &[u8]) -> AlienResult<i32> {
+    let bytes = value.get(..4).ok_or(LinuxErrno::EINVAL)?;
+    Ok(i32::from_ne_bytes(bytes.try_into().unwrap()))
+}
+
+/// 将选项的值解析为 `timeval`
+pub fn parse_timeval(value: &[u8]) -> AlienResult<TimeVal> {
+    let size = core::mem::size_of::<usize>();
+    if value.len() < 2 * size {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let tv_sec = usize::from_ne_bytes(value[..size].try_into().unwrap());
+    let tv_usec = usize::from_ne_bytes(value[size..2 * size].try_into().unwrap());
+    if tv_usec >= 1000_000 {
+        return Err(LinuxErrno::EDOM);
+    }
+    Ok(TimeVal { tv_sec, tv_usec })
+}
+
+/// 将选项的值解析为 [`Linger`]
+pub fn parse_linger(value: &[u8]) -> AlienResult<Linger> {
+    if value.len() < core::mem::size_of::<Linger>() {
+        return Err(LinuxErrno::EINVAL);
+    }
+    Ok(Linger {
+        onoff: parse_int(&value[..4])?,
+        linger: parse_int(&value[4..8])?,
+    })
+}
+
+/// 协议栈不支持设置的选项只接受与固定行为相同的值 `fixed`，其它的值返回 `ENOPROTOOPT`
+pub fn check_fixed<T: PartialEq>(value: T, fixed: T) -> AlienResult<()> {
+    if value == fixed {
+        Ok(())
+    } else {
+        Err(LinuxErrno::ENOPROTOOPT)
+    }
+}
+
+pub fn int_bytes(value: i32) -> Vec<u8> {
+    value.to_ne_bytes().to_vec()
+}
+
+pub fn timeval_bytes(value: &TimeVal) -> Vec<u8> {
+    let mut bytes = value.tv_sec.to_ne_bytes().to_vec();
+    bytes.extend_from_slice(&value.tv_usec.to_ne_bytes());
+    bytes
+}
+
+pub fn linger_bytes(value: &Linger) -> Vec<u8> {
+    let mut bytes = value.onoff.to_ne_bytes().to_vec();
+    bytes.extend_from_slice(&value.linger.to_ne_bytes());
+    bytes
+}
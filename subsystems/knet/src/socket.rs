@@ -7,31 +7,133 @@
 //! 的规定，我们只需为套接字文件规定好 [`socket_file_release`]、[`socket_file_write`]、[`socket_file_read`]、
 //! [`socket_ready_to_read`]、[`socket_ready_to_write`] 几个操作函数，即可快速的创建套接字文件，并将其放入进程的文件描述
 //! 符表中，具体有关套接字文件的创建，可见 [`SocketData::new`] 的实现。
-use alloc::{boxed::Box, sync::Arc};
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 use core::{
     fmt::{Debug, Formatter},
-    net::{Ipv4Addr, SocketAddr, SocketAddrV4},
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
 use constants::{
     io::{OpenFlags, PollEvents, SeekFrom},
-    net::{Domain, ShutdownFlag, SocketType},
+    net::{Domain, ShutdownFlag, SocketLevel, SocketType},
     AlienResult, LinuxErrno,
 };
-use ksync::{Mutex, MutexGuard};
-use netcore::{tcp::TcpSocket, udp::UdpSocket};
+use ksync::{poll::PollQueue, wait::WaitQueue, Mutex};
+use netcore::{
+    common::{MAX_SEGMENT_SIZE, SOCKET_RECV_BUFFER_SIZE, SOCKET_SEND_BUFFER_SIZE},
+    tcp::TcpSocket,
+    udp::UdpSocket,
+};
+use timer::read_timer;
 use vfs::kfile::File;
 use vfscore::{dentry::VfsDentry, inode::VfsInode, utils::VfsFileStat};
 
-use crate::{addr::SocketAddrExt, port::neterror2alien, unix::UnixSocket};
+use crate::{
+    addr::{SocketAddrExt, UnixInode},
+    option::*,
+    port::neterror2alien,
+    unix::{UnixSocket, UNIX_BUF_SIZE},
+};
 
 pub trait SocketFileExt {
-    fn get_socketdata(&self) -> AlienResult<MutexGuard<Box<SocketData>>>;
+    fn get_socketdata(&self) -> AlienResult<Arc<SocketData>>;
 }
 
 pub struct SocketFile {
     open_flag: Mutex<OpenFlags>,
-    node: Mutex<Box<SocketData>>,
+    /// 套接字的数据。[`SocketData`] 的各个部分自己处理同步，这里不加锁，因此阻塞的收发不会妨碍其它线程使用同一个套接字
+    node: Arc<SocketData>,
+    /// 套接字的就绪事件通知队列。Tcp/Udp 套接字的状态取决于网络协议栈的轮询，由 [`poll_net`] 检查并通知
+    poll_queue: Arc<PollQueue>,
+}
+
+/// 被监视就绪状态的 Tcp/Udp 套接字
+enum InetSocket {
+    Tcp(Weak<TcpSocket>),
+    Udp(Weak<UdpSocket>),
+}
+
+struct InetWatch {
+    socket: InetSocket,
+    queue: Weak<PollQueue>,
+    /// 上一次检查时套接字的就绪事件
+    events: PollEvents,
+}
+
+impl InetWatch {
+    /// 套接字当前的就绪事件，套接字已经被释放时返回 `None`
+    fn current_events(&self) -> Option<PollEvents> {
+        let (readable, writable) = match &self.socket {
+            InetSocket::Tcp(tcp) => tcp
+                .upgrade()?
+                .poll()
+                .map_or((false, false), |state| (state.readable, state.writable)),
+            InetSocket::Udp(udp) => udp
+                .upgrade()?
+                .poll()
+                .map_or((false, false), |state| (state.readable, state.writable)),
+        };
+        let mut events = PollEvents::empty();
+        if readable {
+            events |= PollEvents::EPOLLIN;
+        }
+        if writable {
+            events |= PollEvents::EPOLLOUT;
+        }
+        Some(events)
+    }
+}
+
+/// 所有存活的 Tcp/Udp 套接字
+static INET_WATCHES: Mutex<Vec<InetWatch>> = Mutex::new(Vec::new());
+
+/// 阻塞在 Tcp/Udp 套接字上的任务，[`poll_net`] 发现套接字的就绪状态发生变化时唤醒
+static INET_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+/// [`poll_net`] 发现套接字的就绪状态发生变化的次数
+static INET_EVENT_SEQ: AtomicUsize = AtomicUsize::new(0);
+
+/// 为 Tcp/Udp 套接字创建就绪事件通知队列
+fn watch_inet(socket: InetSocket) -> Arc<PollQueue> {
+    let queue = Arc::new(PollQueue::new());
+    INET_WATCHES.lock().push(InetWatch {
+        socket,
+        queue: Arc::downgrade(&queue),
+        events: PollEvents::empty(),
+    });
+    queue
+}
+
+/// 轮询网络协议栈，并通知就绪状态发生变化的 Tcp/Udp 套接字
+///
+/// `netcore` 不会报告具体哪个套接字的状态发生了变化，因此比较每个套接字在两次轮询之间的就绪事件，只通知新产生的事件。
+pub fn poll_net() {
+    if INET_WATCHES.lock().is_empty() {
+        return;
+    }
+    netcore::poll_interfaces();
+    let mut changed = Vec::new();
+    INET_WATCHES.lock().retain_mut(|watch| {
+        let (Some(events), Some(queue)) = (watch.current_events(), watch.queue.upgrade()) else {
+            return false;
+        };
+        let new = events - watch.events;
+        watch.events = events;
+        if !new.is_empty() {
+            changed.push((queue, new));
+        }
+        true
+    });
+    if changed.is_empty() {
+        return;
+    }
+    INET_EVENT_SEQ.fetch_add(1, Ordering::AcqRel);
+    INET_WAIT_QUEUE.wake_all();
+    changed
+        .into_iter()
+        .for_each(|(queue, events)| queue.notify(events));
 }
 
 impl Debug for SocketFile {
@@ -45,9 +147,16 @@ impl Debug for SocketFile {
 
 impl SocketFile {
     pub fn new(socket_data: SocketData) -> Self {
+        let poll_queue = match &socket_data.socket {
+            Socket::Unix(unix) => unix.poll_queue(),
+            Socket::Tcp(tcp) => watch_inet(InetSocket::Tcp(Arc::downgrade(tcp))),
+            Socket::Udp(udp) => watch_inet(InetSocket::Udp(Arc::downgrade(udp))),
+            Socket::None => Arc::new(PollQueue::new()),
+        };
         Self {
             open_flag: Mutex::new(OpenFlags::O_RDWR),
-            node: Mutex::new(Box::new(socket_data)),
+            node: Arc::new(socket_data),
+            poll_queue,
         }
     }
 
@@ -57,9 +166,8 @@ impl SocketFile {
 }
 
 impl SocketFileExt for SocketFile {
-    fn get_socketdata(&self) -> AlienResult<MutexGuard<Box<SocketData>>> {
-        let r = self.node.lock();
-        Ok(r)
+    fn get_socketdata(&self) -> AlienResult<Arc<SocketData>> {
+        Ok(self.node.clone())
     }
 }
 
@@ -68,7 +176,7 @@ impl File for SocketFile {
         if buf.len() == 0 {
             return Ok(0);
         }
-        netcore::poll_interfaces();
+        poll_net();
         let socket = self.get_socketdata().unwrap();
         let res = socket.recvfrom(buf, 0).map(|x| x.0).map_err(|x| {
             info!("socket_file_read: {:?}", x);
@@ -83,9 +191,9 @@ impl File for SocketFile {
             return Ok(0);
         }
         info!("socket_file_write: buf_len:{:?}", buf.len());
-        netcore::poll_interfaces();
+        poll_net();
         let socket = self.get_socketdata().unwrap();
-        let res = socket.send_to(buf, 0, None).map_err(|x| {
+        let res = socket.send_to(buf, 0, None, None).map_err(|x| {
             info!("socket_file_write: {:?}", x);
             x
         });
@@ -129,7 +237,7 @@ impl File for SocketFile {
     }
     fn poll(&self, _event: PollEvents) -> AlienResult<PollEvents> {
         let mut res = PollEvents::empty();
-        netcore::poll_interfaces();
+        poll_net();
         let socket = self.get_socketdata().unwrap();
         if _event.contains(PollEvents::EPOLLIN) {
             if socket.ready_read() {
@@ -143,6 +251,10 @@ impl File for SocketFile {
         }
         Ok(res)
     }
+
+    fn poll_queue(&self) -> Option<&PollQueue> {
+        Some(&self.poll_queue)
+    }
 }
 
 /// Alien 内核中对于每一个套接字所存储的相关信息。所有系统调用最后都要归到该结构的操作。
@@ -156,15 +268,19 @@ pub struct SocketData {
     pub protocol: usize,
     /// 具体的套接字数据，具体可见 [`Socket`]
     pub socket: Socket,
+    /// 套接字选项，具体可见 [`SocketOptions`]
+    options: Mutex<SocketOptions>,
+    /// 正在 [`SocketData::with_timeout`] 中等待的操作数量，不为 0 时 Tcp/Udp 套接字被临时设置为非阻塞的
+    timed_ops: AtomicUsize,
 }
 
 /// 用于记录一个套接字的具体数据。
 ///
-/// 针对套接字类型，`Tcp` 和 `Udp` 类型中存储的具体数据是 `simple_net` 中的 [`TcpSocket`] 和 [`UdpSocket`] 类型；
-/// `Unix` 类型中存储的数据是 [`UnixSocket`]。
+/// 针对套接字类型，`Tcp` 和 `Udp` 类型中存储的具体数据是 `simple_net` 中的 [`TcpSocket`] 和 [`UdpSocket`] 类型，
+/// [`poll_net`] 通过弱引用检查它们的就绪状态；`Unix` 类型中存储的数据是 [`UnixSocket`]。
 pub enum Socket {
-    Tcp(TcpSocket),
-    Udp(UdpSocket),
+    Tcp(Arc<TcpSocket>),
+    Udp(Arc<UdpSocket>),
     Unix(UnixSocket),
     None,
 }
@@ -201,34 +317,60 @@ impl SocketData {
         protocol: usize,
     ) -> AlienResult<Arc<SocketFile>> {
         let raw_socket = match domain {
-            Domain::AF_UNIX => {
-                log::warn!("AF_UNIX is not supported");
-                Socket::Unix(UnixSocket::new())
-            }
+            Domain::AF_UNIX => match s_type {
+                SocketType::SOCK_STREAM | SocketType::SOCK_DGRAM | SocketType::SOCK_SEQPACKET => {
+                    Socket::Unix(UnixSocket::new(s_type))
+                }
+                _ => {
+                    error!("unsupported socket type: {:?}", s_type);
+                    return Err(LinuxErrno::ESOCKTNOSUPPORT.into());
+                }
+            },
             Domain::AF_INET => match s_type {
-                SocketType::SOCK_STREAM => Socket::Tcp(TcpSocket::new()),
-                SocketType::SOCK_DGRAM => Socket::Udp(UdpSocket::new()),
+                SocketType::SOCK_STREAM => Socket::Tcp(Arc::new(TcpSocket::new())),
+                SocketType::SOCK_DGRAM => Socket::Udp(Arc::new(UdpSocket::new())),
                 _ => {
                     error!("unsupported socket type: {:?}", s_type);
                     return Err(LinuxErrno::EPROTONOSUPPORT.into());
                 }
             },
         };
+        let options = match domain {
+            Domain::AF_UNIX => SocketOptions::new(UNIX_BUF_SIZE, UNIX_BUF_SIZE),
+            Domain::AF_INET => SocketOptions::new(SOCKET_RECV_BUFFER_SIZE, SOCKET_SEND_BUFFER_SIZE),
+        };
         let socket_data = Self {
             domain,
             s_type,
             protocol,
             socket: raw_socket,
+            options: Mutex::new(options),
+            timed_ops: AtomicUsize::new(0),
         };
         Ok(Arc::new(SocketFile::new(socket_data)))
     }
-    /// 用于对一个已经存在的 tcp_socket 创建对应的套接字文件。一般在 accept 成功接受一个 client 后被调用。
-    fn new_connected(&self, tcp_socket: TcpSocket) -> Arc<SocketFile> {
+    /// 用于对一个已经建立连接的套接字创建对应的套接字文件。一般在 accept 成功接受一个 client 后被调用。
+    ///
+    /// 新的套接字继承监听套接字的选项，但总是阻塞的。
+    fn new_connected(&self, socket: Socket) -> Arc<SocketFile> {
+        let options = SocketOptions {
+            nonblock: false,
+            error: None,
+            ..self.options.lock().clone()
+        };
+        if let Socket::Unix(unix) = &socket {
+            unix.set_recv_buffer_size(options.recv_buf);
+            unix.set_send_buffer_size(options.send_buf);
+            unix.set_recv_timeout(options.recv_timeout_clock());
+            unix.set_send_timeout(options.send_timeout_clock());
+        }
         let socket_data = Self {
             domain: self.domain,
             s_type: self.s_type,
             protocol: self.protocol,
-            socket: Socket::Tcp(tcp_socket),
+            socket,
+            options: Mutex::new(options),
+            timed_ops: AtomicUsize::new(0),
         };
         Arc::new(SocketFile::new(socket_data))
     }
@@ -240,6 +382,14 @@ impl SocketData {
 
     /// 设置套接字的阻塞状态。用于传入 SOCK_NONBLOCK 标志位的套接字创建过程中。
     pub fn set_socket_nonblock(&self, blocking: bool) {
+        self.options.lock().nonblock = blocking;
+        // 有操作在 `with_timeout` 中等待时由最后一个离开的操作恢复阻塞状态
+        if blocking || self.timed_ops.load(Ordering::Acquire) == 0 {
+            self.set_nonblocking_inner(blocking);
+        }
+    }
+
+    fn set_nonblocking_inner(&self, blocking: bool) {
         match &self.socket {
             Socket::Tcp(tcp) => {
                 tcp.set_nonblocking(blocking);
@@ -247,15 +397,19 @@ impl SocketData {
             Socket::Udp(udp) => {
                 udp.set_nonblocking(blocking);
             }
-            Socket::Unix(_) => {}
+            Socket::Unix(unix) => {
+                unix.set_nonblocking(blocking);
+            }
             _ => {
                 panic!("set_socket_nonblock is not supported")
             }
         }
     }
 
-    /// 用于绑定套接字端口，tcp 和 udp 可用。被系统调用 [`bind`] 调用。
-    pub fn bind(&self, socket_addr: SocketAddrExt) -> AlienResult<()> {
+    /// 用于绑定套接字的地址。被系统调用 [`bind`] 调用。
+    ///
+    /// `inode` 为 Unix 套接字绑定到路径地址时创建的套接字文件。
+    pub fn bind(&self, socket_addr: SocketAddrExt, inode: Option<UnixInode>) -> AlienResult<()> {
         match &self.socket {
             Socket::Tcp(tcp) => {
                 tcp.bind(socket_addr.get_socketaddr())
@@ -265,6 +419,7 @@ impl SocketData {
                 udp.bind(socket_addr.get_socketaddr())
                     .map_err(neterror2alien)?;
             }
+            Socket::Unix(unix) => unix.bind(socket_addr.get_unix_addr(), inode)?,
             _ => {
                 panic!("bind is not supported socket addr: {:?}", socket_addr);
             }
@@ -272,31 +427,38 @@ impl SocketData {
         Ok(())
     }
 
-    /// 用于处理一个 client 的连接请求，仅限于 Tcp 套接字。被系统调用 [`accept`] 调用。
+    /// 用于处理一个 client 的连接请求，仅限于 Tcp 和 Unix 套接字。被系统调用 [`accept`] 调用。
     ///
-    /// 如果该套接字不是 Tcp 套接字，将直接返回 Err。
+    /// 如果该套接字不是 Tcp 或 Unix 套接字，将直接返回 Err。
     pub fn accept(&self) -> AlienResult<Arc<SocketFile>> {
+        let timeout = self.options.lock().recv_timeout_clock();
         match &self.socket {
-            Socket::Tcp(tcp) => tcp
-                .accept()
-                .map(|socket| Ok(self.new_connected(socket)))
-                .map_err(neterror2alien)?,
+            Socket::Tcp(tcp) => self.with_timeout(timeout, || {
+                tcp.accept()
+                    .map(|socket| self.new_connected(Socket::Tcp(Arc::new(socket))))
+                    .map_err(neterror2alien)
+            }),
+            Socket::Unix(unix) => Ok(self.new_connected(Socket::Unix(unix.accept()?))),
             _ => Err(LinuxErrno::EOPNOTSUPP.into()),
         }
     }
 
-    /// 用于监听一个端口，仅限于 Tcp 套接字。被系统调用 [`listening`] 调用。
+    /// 用于监听一个端口，仅限于 Tcp 和 Unix 套接字。被系统调用 [`listening`] 调用。
     ///
-    /// 如果该套接字不是 Tcp 套接字，将直接返回 Err。
-    pub fn listening(&self, _back_log: usize) -> AlienResult<()> {
+    /// 如果该套接字不是 Tcp 或 Unix 套接字，将直接返回 Err。
+    pub fn listening(&self, back_log: usize) -> AlienResult<()> {
         match &self.socket {
             Socket::Tcp(tcp) => tcp.listen().map_err(neterror2alien),
+            Socket::Unix(unix) => unix.listen(back_log),
             _ => Err(LinuxErrno::EOPNOTSUPP.into()),
         }
     }
 
     /// 用于连接一个套接字。被系统调用 [`connect`] 调用。
-    pub fn connect(&self, ip: SocketAddrExt) -> AlienResult<()> {
+    ///
+    /// `inode` 为 Unix 套接字的路径地址对应的套接字文件。
+    pub fn connect(&self, ip: SocketAddrExt, inode: Option<UnixInode>) -> AlienResult<()> {
+        self.options.lock().error = None;
         match &self.socket {
             Socket::Tcp(tcp) => {
                 tcp.connect(ip.get_socketaddr()).map_err(neterror2alien)?;
@@ -304,7 +466,7 @@ impl SocketData {
             Socket::Udp(udp) => {
                 udp.connect(ip.get_socketaddr()).map_err(neterror2alien)?;
             }
-            Socket::Unix(unix) => unix.connect(ip.get_local_path())?,
+            Socket::Unix(unix) => unix.connect(ip.get_unix_addr(), inode)?,
             _ => {
                 panic!("bind is not supported")
             }
@@ -316,55 +478,239 @@ impl SocketData {
     pub fn send_to(
         &self,
         message: &[u8],
+        flags: usize,
+        dest_addr: Option<SocketAddrExt>,
+        inode: Option<UnixInode>,
+    ) -> AlienResult<usize> {
+        self.send_msg(message, Vec::new(), flags, dest_addr, inode)
+    }
+
+    /// 用于向一个套接字中发送消息，消息可以携带文件，仅限于 Unix 套接字。被系统调用 [`sendmsg`] 调用。
+    pub fn send_msg(
+        &self,
+        message: &[u8],
+        files: Vec<Arc<dyn File>>,
         _flags: usize,
         dest_addr: Option<SocketAddrExt>,
+        inode: Option<UnixInode>,
     ) -> AlienResult<usize> {
-        match &self.socket {
-            Socket::Tcp(tcp) => tcp.send(message).map_err(|x| neterror2alien(x)),
-            Socket::Udp(udp) => {
-                if let Some(dest_addr) = dest_addr {
+        if !files.is_empty() && !matches!(self.socket, Socket::Unix(_)) {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let timeout = self.options.lock().send_timeout_clock();
+        let res = match &self.socket {
+            Socket::Tcp(tcp) => {
+                self.with_timeout(timeout, || tcp.send(message).map_err(neterror2alien))
+            }
+            Socket::Udp(udp) => self.with_timeout(timeout, || {
+                if let Some(dest_addr) = &dest_addr {
                     udp.send_to(message, dest_addr.get_socketaddr())
                         .map_err(neterror2alien)
                 } else {
                     udp.send(message).map_err(neterror2alien)
                 }
-            }
-            Socket::Unix(unix) => unix.send_to(message),
+            }),
+            Socket::Unix(unix) => unix.send(
+                message,
+                files,
+                dest_addr.map(|addr| addr.get_unix_addr()),
+                inode,
+            ),
             _ => {
                 panic!("send_to is not supported")
             }
-        }
+        };
+        self.record_error(res)
     }
 
     /// 用于从一个套接字中接收消息，接收成功则返回接受的消息长度。被系统调用 [`recvfrom`] 调用。
-    pub fn recvfrom(&self, message: &mut [u8], _flags: usize) -> AlienResult<(usize, SocketAddr)> {
-        match &self.socket {
-            Socket::Tcp(tcp) => {
+    pub fn recvfrom(
+        &self,
+        message: &mut [u8],
+        flags: usize,
+    ) -> AlienResult<(usize, SocketAddrExt)> {
+        self.recv_msg(message, flags)
+            .map(|(len, addr, _)| (len, addr))
+    }
+
+    /// 用于从一个套接字中接收消息，同时返回随消息传递的文件。被系统调用 [`recvmsg`] 调用。
+    pub fn recv_msg(
+        &self,
+        message: &mut [u8],
+        _flags: usize,
+    ) -> AlienResult<(usize, SocketAddrExt, Vec<Arc<dyn File>>)> {
+        let timeout = self.options.lock().recv_timeout_clock();
+        let res = match &self.socket {
+            Socket::Tcp(tcp) => self.with_timeout(timeout, || {
                 let recv = tcp.recv(message).map_err(neterror2alien)?;
                 let peer_addr = tcp.peer_addr().map_err(neterror2alien)?;
-                Ok((recv, peer_addr))
-            }
-            Socket::Udp(udp) => {
+                Ok((recv, SocketAddrExt::SocketAddr(peer_addr), Vec::new()))
+            }),
+            Socket::Udp(udp) => self.with_timeout(timeout, || {
                 let recv = udp.recv_from(message).map_err(neterror2alien)?;
                 // let peer_addr = udp.peer_addr().map_err(neterror2linux)?;
-                Ok((recv.0, recv.1))
-            }
+                Ok((recv.0, SocketAddrExt::SocketAddr(recv.1), Vec::new()))
+            }),
             Socket::Unix(unix) => {
-                let socket_addr = SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(0, 0, 0, 0), 0));
-                let len = unix.recvfrom(message)?;
-                Ok((len, socket_addr))
+                let (len, files, from) = unix.recv(message)?;
+                Ok((len, SocketAddrExt::Unix(from), files))
             }
             _ => {
                 panic!("bind is not supported")
             }
+        };
+        self.record_error(res)
+    }
+
+    /// 在设置了超时时间的阻塞 Tcp/Udp 套接字上执行 `op`，超时后返回 `EAGAIN`
+    ///
+    /// 等待期间套接字被临时设置为非阻塞的，由这里睡眠等待套接字的就绪状态发生变化。Unix 套接字自己处理超时。
+    fn with_timeout<T>(
+        &self,
+        timeout: usize,
+        mut op: impl FnMut() -> AlienResult<T>,
+    ) -> AlienResult<T> {
+        if timeout == 0 || self.options.lock().nonblock {
+            return op();
+        }
+        let deadline = read_timer() + timeout;
+        // 同一个套接字上可能有多个线程同时等待，由第一个进入的设置非阻塞，最后一个离开的恢复
+        if self.timed_ops.fetch_add(1, Ordering::AcqRel) == 0 {
+            self.set_nonblocking_inner(true);
+        }
+        let res = loop {
+            let seq = INET_EVENT_SEQ.load(Ordering::Acquire);
+            poll_net();
+            match op() {
+                Err(LinuxErrno::EAGAIN) => {}
+                res => break res,
+            }
+            // 睡眠直到某个套接字的就绪状态发生变化
+            let res = INET_WAIT_QUEUE.wait_event_interruptible_timeout(
+                || INET_EVENT_SEQ.load(Ordering::Acquire) != seq,
+                Some(deadline),
+            );
+            match res {
+                Ok(()) => {}
+                Err(LinuxErrno::ETIMEDOUT) => break Err(LinuxErrno::EAGAIN),
+                Err(e) => break Err(e),
+            }
+        };
+        if self.timed_ops.fetch_sub(1, Ordering::AcqRel) == 1 && !self.options.lock().nonblock {
+            self.set_nonblocking_inner(false);
+        }
+        res
+    }
+
+    /// 记录连接被拒绝或者被重置等异步错误，这些错误也会通过 `SO_ERROR` 报告
+    fn record_error<T>(&self, res: AlienResult<T>) -> AlienResult<T> {
+        if let Err(
+            e @ (LinuxErrno::ECONNREFUSED
+            | LinuxErrno::ECONNRESET
+            | LinuxErrno::EPIPE
+            | LinuxErrno::ETIMEDOUT),
+        ) = res
+        {
+            self.options.lock().error = Some(e);
+        }
+        res
+    }
+
+    /// 设置套接字选项，`value` 为用户传入的选项值。被系统调用 [`setsockopt`] 调用。
+    pub fn set_option(&self, level: SocketLevel, name: usize, value: &[u8]) -> AlienResult<()> {
+        let mut options = self.options.lock();
+        match (level, name) {
+            // netcore 不支持地址重用、保活和关闭时等待数据发送完成
+            (SocketLevel::Socket, SO_REUSEADDR | SO_REUSEPORT | SO_KEEPALIVE) => {
+                check_fixed(parse_int(value)? != 0, false)?
+            }
+            (SocketLevel::Socket, SO_LINGER) => {
+                check_fixed(parse_linger(value)?.onoff != 0, false)?
+            }
+            (SocketLevel::Socket, SO_RCVBUF) => {
+                options.recv_buf = buffer_size(parse_int(value)?.max(0) as usize);
+                if let Socket::Unix(unix) = &self.socket {
+                    unix.set_recv_buffer_size(options.recv_buf);
+                }
+            }
+            (SocketLevel::Socket, SO_SNDBUF) => {
+                options.send_buf = buffer_size(parse_int(value)?.max(0) as usize);
+                if let Socket::Unix(unix) = &self.socket {
+                    unix.set_send_buffer_size(options.send_buf);
+                }
+            }
+            (SocketLevel::Socket, SO_RCVTIMEO) => {
+                options.recv_timeout = parse_timeval(value)?;
+                if let Socket::Unix(unix) = &self.socket {
+                    unix.set_recv_timeout(options.recv_timeout_clock());
+                }
+            }
+            (SocketLevel::Socket, SO_SNDTIMEO) => {
+                options.send_timeout = parse_timeval(value)?;
+                if let Socket::Unix(unix) = &self.socket {
+                    unix.set_send_timeout(options.send_timeout_clock());
+                }
+            }
+            // netcore 总是启用 Nagle 算法
+            (SocketLevel::Tcp, TCP_NODELAY) if self.domain == Domain::AF_INET => {
+                check_fixed(parse_int(value)? != 0, false)?
+            }
+            (SocketLevel::Tcp, TCP_MAXSEG) if self.domain == Domain::AF_INET => {}
+            (SocketLevel::Ip, IP_TTL) if self.domain == Domain::AF_INET => {
+                match parse_int(value)? {
+                    // -1 表示使用默认值
+                    -1 => {}
+                    ttl @ 1..=255 => check_fixed(ttl, FIXED_TTL)?,
+                    _ => return Err(LinuxErrno::EINVAL),
+                }
+            }
+            _ => {
+                warn!("[setsockopt] unsupported option: {:?} {}", level, name);
+                return Err(LinuxErrno::ENOPROTOOPT);
+            }
         }
+        Ok(())
+    }
+
+    /// 获取套接字选项的值。被系统调用 [`getsockopt`] 调用。
+    ///
+    /// 读取 `SO_ERROR` 会清除记录的错误。
+    pub fn get_option(&self, level: SocketLevel, name: usize) -> AlienResult<Vec<u8>> {
+        let mut options = self.options.lock();
+        let value = match (level, name) {
+            (SocketLevel::Socket, SO_REUSEADDR | SO_REUSEPORT | SO_KEEPALIVE) => int_bytes(0),
+            (SocketLevel::Socket, SO_LINGER) => linger_bytes(&Linger::default()),
+            (SocketLevel::Socket, SO_RCVBUF) => int_bytes(options.recv_buf as i32),
+            (SocketLevel::Socket, SO_SNDBUF) => int_bytes(options.send_buf as i32),
+            (SocketLevel::Socket, SO_RCVTIMEO) => timeval_bytes(&options.recv_timeout),
+            (SocketLevel::Socket, SO_SNDTIMEO) => timeval_bytes(&options.send_timeout),
+            (SocketLevel::Socket, SO_TYPE) => int_bytes(self.s_type as i32),
+            (SocketLevel::Socket, SO_ERROR) => {
+                int_bytes(options.error.take().map_or(0, |e| -(e as isize) as i32))
+            }
+            (SocketLevel::Tcp, TCP_NODELAY) if self.domain == Domain::AF_INET => int_bytes(0),
+            (SocketLevel::Tcp, TCP_MAXSEG) if self.domain == Domain::AF_INET => {
+                int_bytes(MAX_SEGMENT_SIZE as i32)
+            }
+            (SocketLevel::Ip, IP_TTL) if self.domain == Domain::AF_INET => int_bytes(FIXED_TTL),
+            _ => {
+                warn!("[getsockopt] unsupported option: {:?} {}", level, name);
+                return Err(LinuxErrno::ENOPROTOOPT);
+            }
+        };
+        Ok(value)
     }
 
     /// 用于关闭套接字的读功能或写功能。被系统调用 [`shutdown`] 调用。
-    pub fn shutdown(&self, _sdflag: ShutdownFlag) -> AlienResult<()> {
+    pub fn shutdown(&self, sdflag: ShutdownFlag) -> AlienResult<()> {
         match &self.socket {
             Socket::Tcp(tcp) => tcp.shutdown().map_err(neterror2alien),
             Socket::Udp(udp) => udp.shutdown().map_err(neterror2alien),
+            Socket::Unix(unix) => {
+                // SHUT_RD = 0, SHUT_WR = 1, SHUT_RDWR = 2
+                let how = sdflag as usize;
+                unix.shutdown(how != 1, how != 0)
+            }
             _ => {
                 panic!("bind is not supported")
             }
@@ -372,12 +718,12 @@ impl SocketData {
     }
 
     /// 用于获取当前套接字绑定的本地套接字地址信息。
-    pub fn local_addr(&self) -> Option<SocketAddr> {
+    pub fn local_addr(&self) -> Option<SocketAddrExt> {
         match &self.socket {
             Socket::Tcp(tcp) => {
                 let local_addr = tcp.local_addr();
                 if let Ok(addr) = local_addr {
-                    Some(addr)
+                    Some(SocketAddrExt::SocketAddr(addr))
                 } else {
                     None
                 }
@@ -385,22 +731,23 @@ impl SocketData {
             Socket::Udp(udp) => {
                 let local_addr = udp.local_addr();
                 if let Ok(addr) = local_addr {
-                    Some(addr)
+                    Some(SocketAddrExt::SocketAddr(addr))
                 } else {
                     None
                 }
             }
+            Socket::Unix(unix) => Some(SocketAddrExt::Unix(unix.local_addr())),
             _ => None,
         }
     }
 
     /// 用于获取当前套接字连接的远程服务器的套接字地址信息。
-    pub fn peer_addr(&self) -> Option<SocketAddr> {
+    pub fn peer_addr(&self) -> Option<SocketAddrExt> {
         match &self.socket {
             Socket::Tcp(tcp) => {
                 let peer_addr = tcp.peer_addr();
                 if let Ok(addr) = peer_addr {
-                    Some(addr)
+                    Some(SocketAddrExt::SocketAddr(addr))
                 } else {
                     None
                 }
@@ -408,11 +755,12 @@ impl SocketData {
             Socket::Udp(udp) => {
                 let peer_addr = udp.peer_addr();
                 if let Ok(addr) = peer_addr {
-                    Some(addr)
+                    Some(SocketAddrExt::SocketAddr(addr))
                 } else {
                     None
                 }
             }
+            Socket::Unix(unix) => unix.peer_addr().map(SocketAddrExt::Unix),
             _ty => {
                 // log::error!("peer_addr is not supported for socket type: {:?}", ty);
                 None
@@ -467,6 +815,7 @@ impl SocketData {
                     false
                 }
             }
+            Socket::Unix(unix) => unix.ready_write(),
             _ => {
                 panic!("bind is not supported")
             }
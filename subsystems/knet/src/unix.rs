@@ -1,74 +1,676 @@
-//! 有关 Unix 协议族下的套接字结构。(目前有关的功能有待支持)
+//! 有关 Unix 协议族下的套接字结构。
+//!
+//! 支持流式 (`SOCK_STREAM`)、数据报 (`SOCK_DGRAM`) 和有序分组 (`SOCK_SEQPACKET`) 三种类型的套接字。套接字可以绑定到
+//! 文件系统中的路径或者抽象命名空间中的名字，绑定的地址记录在全局的地址表中，路径地址以绑定时创建的套接字文件为键，`connect`/`sendto` 通过地址表找到对端。
+//!
+//! 每个套接字拥有一个 [`UnixEndpoint`]，保存发往该套接字的消息。连接建立后，两端分别持有对方的 `UnixEndpoint`，
+//! 发送时直接将消息放入对端的接收队列，因此收发双方不需要同时持有两个套接字的锁。接收队列的大小有上限，
+//! 队列已满时发送方会阻塞，队列为空时接收方会阻塞。
+//!
+//! 消息可以携带文件 (`SCM_RIGHTS`)，接收方取出消息时一并取出这些文件。
+//!
+//! 接收队列中有新的消息或连接、对端关闭时会通知接收端的 [`PollQueue`]；接收方取走消息后会通知对端，
+//! 对端的发送队列因此有了空间。
+//!
+//! 阻塞的操作都在相关的 `UnixEndpoint` 的 [`WaitQueue`] 上等待：接收方和 `accept` 等待自己的接收端，
+//! 发送方和 `connect` 等待对端的接收端。接收端的状态发生任何变化时都会唤醒其等待队列。
 use alloc::{
-    string::String,
+    collections::{BTreeMap, VecDeque},
+    format,
     sync::{Arc, Weak},
     vec::Vec,
 };
+use core::{
+    cmp::min,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use constants::{io::PollEvents, net::SocketType, AlienResult, LinuxErrno};
+use ksync::{poll::PollQueue, wait::WaitQueue, Mutex};
+use spin::Lazy;
+use timer::read_timer;
+use vfs::kfile::File;
+
+use crate::addr::{UnixAddr, UnixInode};
+
+/// 每个套接字接收队列默认的最大字节数
+pub const UNIX_BUF_SIZE: usize = 64 * 1024;
+/// 等待 accept 的连接数的上限
+const UNIX_MAX_BACKLOG: usize = 128;
+
+/// 已绑定的地址表
+static UNIX_ADDRS: Lazy<Mutex<BTreeMap<AddrKey, Weak<UnixEndpoint>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// 地址表的键
+///
+/// 路径地址以绑定时创建的套接字文件为键：通过不同的路径 (相对路径、符号链接或者硬链接) 访问同一个套接字文件时
+/// 能找到同一个套接字，而套接字文件被删除后在同一路径上新建的文件不会找到原来的套接字。
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd)]
+enum AddrKey {
+    Inode(UnixInode),
+    Abstract(Vec<u8>),
+}
+
+impl AddrKey {
+    /// 路径地址需要同时给出套接字文件 `inode`，未命名的地址没有对应的键
+    fn new(addr: &UnixAddr, inode: Option<UnixInode>) -> AlienResult<Self> {
+        match (addr, inode) {
+            (UnixAddr::Path(_), Some(inode)) => Ok(AddrKey::Inode(inode)),
+            (UnixAddr::Abstract(name), _) => Ok(AddrKey::Abstract(name.clone())),
+            _ => Err(LinuxErrno::EINVAL),
+        }
+    }
+}
+
+/// 自动绑定时用于生成抽象地址的计数器
+static AUTOBIND_ID: AtomicUsize = AtomicUsize::new(0);
 
-use constants::{AlienResult, LinuxErrno};
-use ksync::Mutex;
+/// 套接字之间传递的一条消息
+struct UnixMessage {
+    data: Vec<u8>,
+    /// 随消息传递的文件 (`SCM_RIGHTS`)
+    files: Vec<Arc<dyn File>>,
+    /// 发送方的地址
+    from: UnixAddr,
+}
+
+/// 套接字的接收端
+struct UnixEndpoint {
+    s_type: SocketType,
+    inner: Mutex<EndpointInner>,
+    /// 等待接收端状态变化的任务
+    wait_queue: WaitQueue,
+    /// 拥有该接收端的套接字的就绪事件通知队列
+    poll_queue: Arc<PollQueue>,
+}
 
-use crate::socket::{Socket, SocketFile, SocketFileExt};
+struct EndpointInner {
+    /// 套接字的本地地址
+    local: UnixAddr,
+    messages: VecDeque<UnixMessage>,
+    /// 接收队列中的字节数
+    len: usize,
+    /// 接收队列的最大字节数
+    capacity: usize,
+    listening: bool,
+    backlog: usize,
+    /// 等待 accept 的连接
+    pending: VecDeque<UnixSocket>,
+    /// 对端不再发送数据，读完队列中的数据后返回 0
+    eof: bool,
+    /// 套接字已关闭或者不再接收数据，发送方会得到错误
+    closed: bool,
+}
+
+impl UnixEndpoint {
+    fn new(s_type: SocketType) -> Self {
+        Self {
+            s_type,
+            inner: Mutex::new(EndpointInner {
+                local: UnixAddr::Unnamed,
+                messages: VecDeque::new(),
+                len: 0,
+                capacity: UNIX_BUF_SIZE,
+                listening: false,
+                backlog: 0,
+                pending: VecDeque::new(),
+                eof: false,
+                closed: false,
+            }),
+            wait_queue: WaitQueue::new(),
+            poll_queue: Arc::new(PollQueue::new()),
+        }
+    }
+
+    fn local_addr(&self) -> UnixAddr {
+        self.inner.lock().local.clone()
+    }
+
+    fn notify(&self, events: PollEvents) {
+        self.wait_queue.wake_all();
+        self.poll_queue.notify(events);
+    }
+}
 
 /// Unix 协议族下的套接字结构
-#[allow(unused)]
 pub struct UnixSocket {
+    s_type: SocketType,
+    endpoint: Arc<UnixEndpoint>,
+    nonblock: AtomicBool,
+    /// 一条消息的最大长度
+    send_buf: AtomicUsize,
+    /// 接收超时时间对应的时钟周期数，为 0 时表示不会超时
+    recv_timeout: AtomicUsize,
+    /// 发送超时时间对应的时钟周期数，为 0 时表示不会超时
+    send_timeout: AtomicUsize,
     inner: Mutex<UnixSocketInner>,
 }
 
 struct UnixSocketInner {
-    remote: Weak<SocketFile>,
-    buf: Vec<u8>,
+    /// 在地址表中绑定的地址
+    bound: Option<AddrKey>,
+    /// 对端的接收端。对于数据报套接字，是 `connect` 指定的默认目的地址
+    peer: Option<Arc<UnixEndpoint>>,
+    /// 是否已经关闭写端
+    shutdown_write: bool,
+}
+
+/// 在 `endpoint` 的等待队列上阻塞等待，直到 `cond` 成立
+///
+/// 如果当前任务有待处理的信号则返回 `EINTR`，超过 `deadline` 时返回 `EAGAIN`。
+fn wait_event<F>(endpoint: &UnixEndpoint, deadline: Option<usize>, mut cond: F) -> AlienResult<()>
+where
+    F: FnMut(&EndpointInner) -> bool,
+{
+    endpoint
+        .wait_queue
+        .wait_event_interruptible_timeout(|| cond(&endpoint.inner.lock()), deadline)
+        .map_err(|e| match e {
+            LinuxErrno::ETIMEDOUT => LinuxErrno::EAGAIN,
+            e => e,
+        })
+}
+
+/// 在地址表中查找已绑定到 `addr` 的套接字，`inode` 为路径地址对应的套接字文件
+fn lookup(addr: &UnixAddr, inode: Option<UnixInode>) -> AlienResult<Arc<UnixEndpoint>> {
+    let key = AddrKey::new(addr, inode)?;
+    UNIX_ADDRS
+        .lock()
+        .get(&key)
+        .and_then(|endpoint| endpoint.upgrade())
+        .ok_or(LinuxErrno::ECONNREFUSED)
 }
 
 impl UnixSocket {
     /// 创建一个新的 Unix 协议族下的套接字结构
-    pub fn new() -> Self {
+    pub fn new(s_type: SocketType) -> Self {
         Self {
+            s_type,
+            endpoint: Arc::new(UnixEndpoint::new(s_type)),
+            nonblock: AtomicBool::new(false),
+            send_buf: AtomicUsize::new(UNIX_BUF_SIZE),
+            recv_timeout: AtomicUsize::new(0),
+            send_timeout: AtomicUsize::new(0),
             inner: Mutex::new(UnixSocketInner {
-                remote: Weak::<SocketFile>::new(),
-                buf: Vec::new(),
+                bound: None,
+                peer: None,
+                shutdown_write: false,
             }),
         }
     }
 
-    /// UnixSocket 的 connect 操作
-    pub fn connect(&self, _file_path: String) -> AlienResult<()> {
-        Err(LinuxErrno::ENOENT)
+    /// 将两个套接字互相连接，用于 `socketpair`
+    pub fn pair(&self, other: &UnixSocket) {
+        self.inner.lock().peer = Some(other.endpoint.clone());
+        other.inner.lock().peer = Some(self.endpoint.clone());
+    }
+
+    pub fn set_nonblocking(&self, nonblock: bool) {
+        self.nonblock.store(nonblock, Ordering::Relaxed);
+    }
+
+    /// 设置接收队列的最大字节数 (`SO_RCVBUF`)
+    pub fn set_recv_buffer_size(&self, size: usize) {
+        self.endpoint.inner.lock().capacity = size;
+    }
+
+    /// 设置数据报和有序分组套接字一条消息的最大长度 (`SO_SNDBUF`)
+    pub fn set_send_buffer_size(&self, size: usize) {
+        self.send_buf.store(size, Ordering::Relaxed);
+    }
+
+    /// 设置接收超时时间 (`SO_RCVTIMEO`)，单位为时钟周期
+    pub fn set_recv_timeout(&self, timeout: usize) {
+        self.recv_timeout.store(timeout, Ordering::Relaxed);
+    }
+
+    /// 设置发送超时时间 (`SO_SNDTIMEO`)，单位为时钟周期
+    pub fn set_send_timeout(&self, timeout: usize) {
+        self.send_timeout.store(timeout, Ordering::Relaxed);
+    }
+
+    /// 根据超时时间计算阻塞等待的截止时间
+    fn deadline(timeout: &AtomicUsize) -> Option<usize> {
+        match timeout.load(Ordering::Relaxed) {
+            0 => None,
+            timeout => Some(read_timer() + timeout),
+        }
+    }
+
+    fn is_nonblocking(&self) -> bool {
+        self.nonblock.load(Ordering::Relaxed)
     }
 
-    pub fn set_remote(&self, remote: &Arc<SocketFile>) {
-        self.inner.lock().remote = Arc::downgrade(remote);
+    fn is_connection_based(&self) -> bool {
+        self.s_type != SocketType::SOCK_DGRAM
     }
 
-    pub fn send_to(&self, buf: &[u8]) -> AlienResult<usize> {
-        if let Some(remote) = self.inner.lock().remote.upgrade() {
-            let socket_guard = remote.get_socketdata()?;
-            match socket_guard.socket {
-                Socket::Unix(ref unix_socket) => {
-                    unix_socket.inner.lock().buf.extend_from_slice(buf);
-                    Ok(buf.len())
+    /// 将套接字绑定到 `addr`，`inode` 为绑定到路径地址时创建的套接字文件
+    ///
+    /// 如果 `addr` 为 [`UnixAddr::Unnamed`]，会自动绑定到一个抽象命名空间中的名字。
+    pub fn bind(&self, addr: UnixAddr, inode: Option<UnixInode>) -> AlienResult<()> {
+        let mut inner = self.inner.lock();
+        if inner.bound.is_some() {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let mut addrs = UNIX_ADDRS.lock();
+        let (addr, key) = match addr {
+            UnixAddr::Unnamed => loop {
+                let id = AUTOBIND_ID.fetch_add(1, Ordering::Relaxed) & 0xfffff;
+                let name = format!("{:05x}", id).into_bytes();
+                let key = AddrKey::Abstract(name.clone());
+                if addrs.get(&key).and_then(|e| e.upgrade()).is_none() {
+                    break (UnixAddr::Abstract(name), key);
+                }
+            },
+            addr => {
+                let key = AddrKey::new(&addr, inode)?;
+                if addrs.get(&key).and_then(|e| e.upgrade()).is_some() {
+                    return Err(LinuxErrno::EADDRINUSE);
                 }
-                _ => Err(LinuxErrno::EINVAL),
+                (addr, key)
+            }
+        };
+        addrs.insert(key.clone(), Arc::downgrade(&self.endpoint));
+        self.endpoint.inner.lock().local = addr;
+        inner.bound = Some(key);
+        Ok(())
+    }
+
+    /// 开始监听连接请求，仅限于流式和有序分组套接字
+    pub fn listen(&self, backlog: usize) -> AlienResult<()> {
+        if !self.is_connection_based() {
+            return Err(LinuxErrno::EOPNOTSUPP);
+        }
+        let inner = self.inner.lock();
+        if inner.bound.is_none() || inner.peer.is_some() {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let mut endpoint = self.endpoint.inner.lock();
+        endpoint.listening = true;
+        endpoint.backlog = backlog.clamp(1, UNIX_MAX_BACKLOG);
+        Ok(())
+    }
+
+    /// 取出一个等待中的连接，没有连接时阻塞
+    pub fn accept(&self) -> AlienResult<UnixSocket> {
+        let deadline = Self::deadline(&self.recv_timeout);
+        loop {
+            let mut endpoint = self.endpoint.inner.lock();
+            if !endpoint.listening {
+                return Err(LinuxErrno::EINVAL);
+            }
+            if let Some(socket) = endpoint.pending.pop_front() {
+                drop(endpoint);
+                // 等待连接的队列有了空间
+                self.endpoint.wait_queue.wake_all();
+                return Ok(socket);
+            }
+            drop(endpoint);
+            if self.is_nonblocking() {
+                return Err(LinuxErrno::EAGAIN);
+            }
+            wait_event(&self.endpoint, deadline, |endpoint| {
+                !endpoint.listening || !endpoint.pending.is_empty()
+            })?;
+        }
+    }
+
+    /// 连接到绑定在 `addr` 上的套接字，`inode` 为路径地址对应的套接字文件
+    ///
+    /// 对于数据报套接字，只是设置默认的目的地址。
+    pub fn connect(&self, addr: UnixAddr, inode: Option<UnixInode>) -> AlienResult<()> {
+        let target = lookup(&addr, inode)?;
+        if target.s_type != self.s_type {
+            return Err(LinuxErrno::EPROTOTYPE);
+        }
+        if !self.is_connection_based() {
+            self.inner.lock().peer = Some(target);
+            return Ok(());
+        }
+        if self.endpoint.inner.lock().listening {
+            return Err(LinuxErrno::EINVAL);
+        }
+        let deadline = Self::deadline(&self.send_timeout);
+        loop {
+            // 等待期间不能持有自身的锁，每次被唤醒后重新检查连接状态
+            let mut inner = self.inner.lock();
+            if inner.peer.is_some() {
+                return Err(LinuxErrno::EISCONN);
+            }
+            let mut listener = target.inner.lock();
+            if !listener.listening || listener.closed {
+                return Err(LinuxErrno::ECONNREFUSED);
+            }
+            if listener.pending.len() < listener.backlog {
+                // the accepted socket has the same address as the listening socket
+                let server = UnixSocket::new(self.s_type);
+                server.endpoint.inner.lock().local = listener.local.clone();
+                server.inner.lock().peer = Some(self.endpoint.clone());
+                inner.peer = Some(server.endpoint.clone());
+                listener.pending.push_back(server);
+                drop(listener);
+                target.notify(PollEvents::EPOLLIN);
+                return Ok(());
+            }
+            drop(listener);
+            drop(inner);
+            if self.is_nonblocking() {
+                return Err(LinuxErrno::EAGAIN);
             }
+            wait_event(&target, deadline, |listener| {
+                !listener.listening || listener.closed || listener.pending.len() < listener.backlog
+            })?;
+        }
+    }
+
+    /// 发送消息，`files` 为随消息传递的文件，`dest` 为数据报套接字的目的地址，`inode` 为路径地址对应的套接字文件
+    ///
+    /// 流式套接字会阻塞直到所有数据都被放入对端的接收队列，数据报和有序分组套接字的消息不会被拆分。
+    pub fn send(
+        &self,
+        buf: &[u8],
+        files: Vec<Arc<dyn File>>,
+        dest: Option<UnixAddr>,
+        inode: Option<UnixInode>,
+    ) -> AlienResult<usize> {
+        let inner = self.inner.lock();
+        if inner.shutdown_write {
+            return Err(LinuxErrno::EPIPE);
+        }
+        let peer = match dest {
+            Some(_) if self.is_connection_based() => return Err(LinuxErrno::EISCONN),
+            Some(addr) => lookup(&addr, inode)?,
+            None => inner.peer.clone().ok_or(LinuxErrno::ENOTCONN)?,
+        };
+        drop(inner);
+        if peer.s_type != self.s_type {
+            return Err(LinuxErrno::EPROTOTYPE);
+        }
+        let from = self.endpoint.local_addr();
+        if self.s_type == SocketType::SOCK_STREAM {
+            self.send_stream(&peer, buf, files, from)
         } else {
-            Err(LinuxErrno::ENOTCONN)
+            self.send_message(&peer, buf, files, from)
+        }
+    }
+
+    fn send_stream(
+        &self,
+        peer: &UnixEndpoint,
+        buf: &[u8],
+        mut files: Vec<Arc<dyn File>>,
+        from: UnixAddr,
+    ) -> AlienResult<usize> {
+        // 与 Linux 一致，不发送长度为 0 的数据，随之传递的文件也被丢弃。
+        // 空的消息在接收端看起来与对端关闭相同，接收方会误以为读到了文件末尾
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let deadline = Self::deadline(&self.send_timeout);
+        let mut count = 0;
+        loop {
+            let mut endpoint = peer.inner.lock();
+            if endpoint.closed {
+                return if count > 0 {
+                    Ok(count)
+                } else {
+                    Err(LinuxErrno::EPIPE)
+                };
+            }
+            let space = endpoint.capacity.saturating_sub(endpoint.len);
+            if space > 0 {
+                let n = min(space, buf.len() - count);
+                endpoint.messages.push_back(UnixMessage {
+                    data: buf[count..count + n].to_vec(),
+                    files: core::mem::take(&mut files),
+                    from: from.clone(),
+                });
+                endpoint.len += n;
+                count += n;
+                drop(endpoint);
+                peer.notify(PollEvents::EPOLLIN);
+                if count == buf.len() {
+                    return Ok(count);
+                }
+            } else {
+                drop(endpoint);
+            }
+            if self.is_nonblocking() {
+                return if count > 0 {
+                    Ok(count)
+                } else {
+                    Err(LinuxErrno::EAGAIN)
+                };
+            }
+            let res = wait_event(peer, deadline, |endpoint| {
+                endpoint.closed || endpoint.len < endpoint.capacity
+            });
+            if let Err(e) = res {
+                return if count > 0 { Ok(count) } else { Err(e) };
+            }
+        }
+    }
+
+    fn send_message(
+        &self,
+        peer: &UnixEndpoint,
+        buf: &[u8],
+        files: Vec<Arc<dyn File>>,
+        from: UnixAddr,
+    ) -> AlienResult<usize> {
+        if buf.len() > self.send_buf.load(Ordering::Relaxed) {
+            return Err(LinuxErrno::EMSGSIZE);
+        }
+        let deadline = Self::deadline(&self.send_timeout);
+        loop {
+            let mut endpoint = peer.inner.lock();
+            if endpoint.closed {
+                return if self.is_connection_based() {
+                    Err(LinuxErrno::EPIPE)
+                } else {
+                    Err(LinuxErrno::ECONNREFUSED)
+                };
+            }
+            // a message larger than the queue can still be sent when the queue is empty
+            if endpoint.len == 0 || endpoint.len + buf.len() <= endpoint.capacity {
+                endpoint.messages.push_back(UnixMessage {
+                    data: buf.to_vec(),
+                    files,
+                    from,
+                });
+                endpoint.len += buf.len();
+                drop(endpoint);
+                peer.notify(PollEvents::EPOLLIN);
+                return Ok(buf.len());
+            }
+            drop(endpoint);
+            if self.is_nonblocking() {
+                return Err(LinuxErrno::EAGAIN);
+            }
+            wait_event(peer, deadline, |endpoint| {
+                endpoint.closed
+                    || endpoint.len == 0
+                    || endpoint.len + buf.len() <= endpoint.capacity
+            })?;
+        }
+    }
+
+    /// 接收消息，返回接收的字节数、随消息传递的文件和发送方的地址
+    ///
+    /// 数据报和有序分组套接字每次接收一条消息，超出 `buf` 的部分被丢弃。
+    pub fn recv(&self, buf: &mut [u8]) -> AlienResult<(usize, Vec<Arc<dyn File>>, UnixAddr)> {
+        let deadline = Self::deadline(&self.recv_timeout);
+        loop {
+            let mut endpoint = self.endpoint.inner.lock();
+            if endpoint.listening {
+                return Err(LinuxErrno::EINVAL);
+            }
+            if !endpoint.messages.is_empty() {
+                let res = if self.s_type == SocketType::SOCK_STREAM {
+                    Self::recv_stream(&mut endpoint, buf)
+                } else {
+                    let message = endpoint.messages.pop_front().unwrap();
+                    endpoint.len -= message.data.len();
+                    let n = min(buf.len(), message.data.len());
+                    buf[..n].copy_from_slice(&message.data[..n]);
+                    (n, message.files, message.from)
+                };
+                drop(endpoint);
+                // 接收队列有了空间，对端可以继续发送
+                self.endpoint.wait_queue.wake_all();
+                if let Some(peer) = self.inner.lock().peer.clone() {
+                    peer.notify(PollEvents::EPOLLOUT);
+                }
+                return Ok(res);
+            }
+            if endpoint.eof {
+                return Ok((0, Vec::new(), UnixAddr::Unnamed));
+            }
+            drop(endpoint);
+            if self.is_connection_based() && self.inner.lock().peer.is_none() {
+                return Err(LinuxErrno::ENOTCONN);
+            }
+            if self.is_nonblocking() {
+                return Err(LinuxErrno::EAGAIN);
+            }
+            wait_event(&self.endpoint, deadline, |endpoint| {
+                endpoint.listening || !endpoint.messages.is_empty() || endpoint.eof
+            })?;
+        }
+    }
+
+    /// 从流式套接字的接收队列中读取数据，可以跨越多条消息，但不会将携带文件的消息与之前的数据合并读取
+    fn recv_stream(
+        endpoint: &mut EndpointInner,
+        buf: &mut [u8],
+    ) -> (usize, Vec<Arc<dyn File>>, UnixAddr) {
+        let mut count = 0;
+        let mut files = Vec::new();
+        let from = endpoint.messages.front().unwrap().from.clone();
+        while count < buf.len() {
+            let Some(message) = endpoint.messages.front_mut() else {
+                break;
+            };
+            if count > 0 && !message.files.is_empty() {
+                break;
+            }
+            let n = min(buf.len() - count, message.data.len());
+            buf[count..count + n].copy_from_slice(&message.data[..n]);
+            files.append(&mut message.files);
+            if n == message.data.len() {
+                endpoint.messages.pop_front();
+            } else {
+                message.data.drain(..n);
+            }
+            endpoint.len -= n;
+            count += n;
+        }
+        (count, files, from)
+    }
+
+    /// 关闭套接字的读端和(或)写端
+    pub fn shutdown(&self, read: bool, write: bool) -> AlienResult<()> {
+        let mut inner = self.inner.lock();
+        if self.is_connection_based() && inner.peer.is_none() {
+            return Err(LinuxErrno::ENOTCONN);
+        }
+        if read {
+            let mut endpoint = self.endpoint.inner.lock();
+            endpoint.eof = true;
+            endpoint.closed = true;
+        }
+        let mut peer = None;
+        if write {
+            inner.shutdown_write = true;
+            if self.is_connection_based() {
+                if let Some(endpoint) = &inner.peer {
+                    endpoint.inner.lock().eof = true;
+                    peer = Some(endpoint.clone());
+                }
+            }
+        }
+        drop(inner);
+        self.endpoint.notify(PollEvents::empty());
+        if let Some(peer) = peer {
+            peer.notify(PollEvents::EPOLLIN);
         }
+        Ok(())
     }
 
+    /// 套接字的本地地址
+    pub fn local_addr(&self) -> UnixAddr {
+        self.endpoint.local_addr()
+    }
+
+    /// 已连接的对端地址
+    pub fn peer_addr(&self) -> Option<UnixAddr> {
+        self.inner
+            .lock()
+            .peer
+            .as_ref()
+            .map(|peer| peer.local_addr())
+    }
+
+    /// 套接字的就绪事件通知队列
+    pub fn poll_queue(&self) -> Arc<PollQueue> {
+        self.endpoint.poll_queue.clone()
+    }
+
+    /// 是否有数据或者连接请求可以读取，对端关闭时也认为是可读的
     pub fn ready_read(&self) -> bool {
-        self.inner.lock().buf.len() > 0
+        let endpoint = self.endpoint.inner.lock();
+        !endpoint.messages.is_empty() || !endpoint.pending.is_empty() || endpoint.eof
     }
 
-    pub fn recvfrom(&self, buf: &mut [u8]) -> AlienResult<usize> {
-        let inner_buf = &mut self.inner.lock().buf;
-        if inner_buf.len() > 0 {
-            let len = inner_buf.len().min(buf.len());
-            buf[..len].copy_from_slice(&inner_buf[..len]);
-            inner_buf.drain(..len);
-            Ok(len)
-        } else {
-            Err(LinuxErrno::EAGAIN)
+    /// 对端的接收队列是否还有空间，对端关闭时也认为是可写的
+    pub fn ready_write(&self) -> bool {
+        let inner = self.inner.lock();
+        match &inner.peer {
+            Some(peer) => {
+                let endpoint = peer.inner.lock();
+                endpoint.closed || endpoint.len < endpoint.capacity
+            }
+            None => !self.is_connection_based(),
+        }
+    }
+}
+
+impl Drop for UnixSocket {
+    fn drop(&mut self) {
+        let inner = self.inner.lock();
+        // files in flight and pending connections may hold other sockets, drop them without holding the lock
+        let (messages, pending) = {
+            let mut endpoint = self.endpoint.inner.lock();
+            endpoint.eof = true;
+            endpoint.closed = true;
+            endpoint.listening = false;
+            endpoint.len = 0;
+            (
+                core::mem::take(&mut endpoint.messages),
+                core::mem::take(&mut endpoint.pending),
+            )
+        };
+        // 阻塞在该接收端上的发送方和连接方会得到错误
+        self.endpoint.wait_queue.wake_all();
+        if self.is_connection_based() {
+            if let Some(peer) = &inner.peer {
+                peer.inner.lock().eof = true;
+                peer.notify(PollEvents::EPOLLIN | PollEvents::EPOLLHUP);
+            }
+        }
+        if let Some(key) = &inner.bound {
+            let mut addrs = UNIX_ADDRS.lock();
+            if addrs
+                .get(key)
+                .is_some_and(|e| e.as_ptr() == Arc::as_ptr(&self.endpoint))
+            {
+                addrs.remove(key);
+            }
         }
+        drop(inner);
+        drop(messages);
+        drop(pending);
     }
 }
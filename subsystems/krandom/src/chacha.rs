@@ -0,0 +1,63 @@
+//! ChaCha20 分组函数
+//!
+//! Reference: [RFC 8439](https://www.rfc-editor.org/rfc/rfc8439)
+
+/// "expand 32-byte k"
+const CONSTANTS: [u32; 4] = [0x6170_7865, 0x3320_646e, 0x7962_2d32, 0x6b20_6574];
+
+/// ChaCha20 输出块的大小，单位为字节
+pub const CHACHA_BLOCK_SIZE: usize = 64;
+
+#[inline(always)]
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// 对状态进行 20 轮 ChaCha 置换，不包括最后与输入状态相加的一步
+pub fn chacha_permute(state: &mut [u32; 16]) {
+    for _ in 0..10 {
+        quarter_round(state, 0, 4, 8, 12);
+        quarter_round(state, 1, 5, 9, 13);
+        quarter_round(state, 2, 6, 10, 14);
+        quarter_round(state, 3, 7, 11, 15);
+        quarter_round(state, 0, 5, 10, 15);
+        quarter_round(state, 1, 6, 11, 12);
+        quarter_round(state, 2, 7, 8, 13);
+        quarter_round(state, 3, 4, 9, 14);
+    }
+}
+
+/// 对状态进行置换后与原状态相加，得到的结果无法反推出原状态
+pub fn chacha_feed_forward(state: &[u32; 16]) -> [u32; 16] {
+    let mut out = *state;
+    chacha_permute(&mut out);
+    out.iter_mut()
+        .zip(state.iter())
+        .for_each(|(o, s)| *o = o.wrapping_add(*s));
+    out
+}
+
+/// 使用密钥 `key`、块计数 `counter` 和 `nonce` 生成一个 ChaCha20 输出块
+pub fn chacha20_block(key: &[u32; 8], counter: u64, nonce: u64) -> [u8; CHACHA_BLOCK_SIZE] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&CONSTANTS);
+    state[4..12].copy_from_slice(key);
+    state[12] = counter as u32;
+    state[13] = (counter >> 32) as u32;
+    state[14] = nonce as u32;
+    state[15] = (nonce >> 32) as u32;
+    let words = chacha_feed_forward(&state);
+    let mut block = [0u8; CHACHA_BLOCK_SIZE];
+    block
+        .chunks_exact_mut(4)
+        .zip(words.iter())
+        .for_each(|(bytes, word)| bytes.copy_from_slice(&word.to_le_bytes()));
+    block
+}
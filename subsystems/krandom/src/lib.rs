@@ -0,0 +1,308 @@
+//! 内核随机数生成器
+//!
+//! 随机数的产生分为两部分:
+//! - 熵池: 收集中断到来的时间、时钟抖动以及硬件随机数发生器(如 virtio-rng)产生的数据，并估计其中包含的熵。
+//!   熵池以 ChaCha 置换作为混合函数，输入的数据不断与池中的状态混合。
+//! - CRNG: 以 ChaCha20 为核心的确定性随机数生成器，密钥来自熵池。每次生成随机数时都会先用 ChaCha20
+//!   的输出替换密钥(fast key erasure)，因此即使之后密钥泄露，也无法推出之前产生的随机数。
+//!
+//! 熵池中积累了至少 [`CRNG_INIT_BITS`] 比特的熵之后 CRNG 才算初始化完成，此后每隔 [`CRNG_RESEED_INTERVAL`]
+//! 秒重新从熵池中获取密钥。`getrandom`、`/dev/random` 与 `/dev/urandom` 使用同一个 CRNG，
+//! 它们的区别只在于 CRNG 初始化完成之前是否等待。
+#![no_std]
+
+mod chacha;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+use arch::{hart_id, read_timer};
+use chacha::{chacha20_block, chacha_feed_forward, CHACHA_BLOCK_SIZE};
+use constants::{AlienResult, LinuxErrno};
+use ksync::{wait::WaitQueue, Mutex};
+use platform::config::CLOCK_FREQ;
+use spin::Once;
+
+/// CRNG 初始化完成所需的熵，单位为比特
+pub const CRNG_INIT_BITS: usize = 256;
+/// CRNG 重新设置密钥的间隔，单位为秒
+pub const CRNG_RESEED_INTERVAL: usize = 60;
+/// 熵池最多记录的熵，单位为比特
+const POOL_MAX_BITS: usize = 256;
+/// 熵池中直接与输入混合的部分的大小，单位为字节
+const POOL_RATE: usize = 32;
+/// 一次时间事件最多计入的熵，单位为比特
+const TIMER_EVENT_MAX_BITS: usize = 11;
+/// 每次重新设置密钥时从硬件随机数发生器读取的字节数
+const HWRNG_SEED_BYTES: usize = 32;
+/// 等待 CRNG 初始化完成时采样时钟的间隔，单位为时钟周期(10ms)
+const CRNG_WAIT_INTERVAL: usize = CLOCK_FREQ / 100;
+
+/// 估计时间事件中包含的熵
+///
+/// 记录事件之间的间隔以及间隔的一阶、二阶差分，取三者中绝对值最小的一个作为事件时间不可预测程度的估计。
+/// 周期性的事件(例如时钟中断)的差分很小，因此计入的熵也很少。
+struct TimerState {
+    last_time: usize,
+    last_delta: isize,
+    last_delta2: isize,
+}
+
+impl TimerState {
+    const fn new() -> Self {
+        Self {
+            last_time: 0,
+            last_delta: 0,
+            last_delta2: 0,
+        }
+    }
+
+    /// 记录一次发生在 `now` 的事件，返回估计的熵
+    fn record(&mut self, now: usize) -> usize {
+        let delta = now.wrapping_sub(self.last_time) as isize;
+        self.last_time = now;
+        let delta2 = delta.wrapping_sub(self.last_delta);
+        self.last_delta = delta;
+        let delta3 = delta2.wrapping_sub(self.last_delta2);
+        self.last_delta2 = delta2;
+        // 最低位通常是可预测的，不计入
+        let min = delta
+            .unsigned_abs()
+            .min(delta2.unsigned_abs())
+            .min(delta3.unsigned_abs())
+            >> 1;
+        if min == 0 {
+            0
+        } else {
+            (min.ilog2() as usize).min(TIMER_EVENT_MAX_BITS)
+        }
+    }
+}
+
+struct EntropyPool {
+    /// 前 [`POOL_RATE`] 字节与输入直接混合，其余部分只经过置换改变
+    state: [u32; 16],
+    /// 下一个输入字节的位置
+    pos: usize,
+    /// 估计池中包含的熵，单位为比特
+    entropy_bits: usize,
+    interrupt_timer: TimerState,
+    timer: TimerState,
+}
+
+impl EntropyPool {
+    const fn new() -> Self {
+        Self {
+            state: [0; 16],
+            pos: 0,
+            entropy_bits: 0,
+            interrupt_timer: TimerState::new(),
+            timer: TimerState::new(),
+        }
+    }
+
+    fn mix(&mut self, data: &[u8]) {
+        for &byte in data {
+            self.state[self.pos / 4] ^= (byte as u32) << (self.pos % 4 * 8);
+            self.pos += 1;
+            if self.pos == POOL_RATE {
+                self.state = chacha_feed_forward(&self.state);
+                self.pos = 0;
+            }
+        }
+    }
+
+    fn mix_usize(&mut self, value: usize) {
+        self.mix(&value.to_le_bytes());
+    }
+
+    fn credit(&mut self, bits: usize) {
+        self.entropy_bits = (self.entropy_bits + bits).min(POOL_MAX_BITS);
+    }
+
+    /// 从熵池中取出一个密钥，池中的熵被清空
+    ///
+    /// 取出密钥后池的状态会被替换为不可逆变换的结果，因此无法由之后的池状态推出取出的密钥。
+    fn extract(&mut self) -> [u32; 8] {
+        // 标记输入的结束，使不同长度的输入不会得到相同的状态
+        self.state[self.pos / 4] ^= 0x80 << (self.pos % 4 * 8);
+        self.state[15] ^= 1;
+        let out = chacha_feed_forward(&self.state);
+        let mut key = [0u32; 8];
+        key.copy_from_slice(&out[..8]);
+        self.state[..8].fill(0);
+        self.state[8..].copy_from_slice(&out[8..]);
+        self.pos = 0;
+        self.entropy_bits = 0;
+        key
+    }
+}
+
+struct Crng {
+    key: [u32; 8],
+}
+
+impl Crng {
+    const fn new() -> Self {
+        Self { key: [0; 8] }
+    }
+
+    /// 将新的密钥与当前的密钥混合，早期质量较差的密钥不会降低之后的密钥的质量
+    fn reseed(&mut self, key: &[u32; 8]) {
+        self.key
+            .iter_mut()
+            .zip(key.iter())
+            .for_each(|(k, n)| *k ^= *n);
+    }
+
+    /// 取出一个只用于本次输出的密钥，同时用 ChaCha20 的输出替换当前的密钥
+    fn take_output_key(&mut self) -> [u32; 8] {
+        let output_key = self.key;
+        let block = chacha20_block(&self.key, 0, 0);
+        block
+            .chunks_exact(4)
+            .zip(self.key.iter_mut())
+            .for_each(|(bytes, k)| *k = u32::from_le_bytes(bytes.try_into().unwrap()));
+        output_key
+    }
+}
+
+static POOL: Mutex<EntropyPool> = Mutex::new(EntropyPool::new());
+static CRNG: Mutex<Crng> = Mutex::new(Crng::new());
+static CRNG_READY: AtomicBool = AtomicBool::new(false);
+/// 等待 CRNG 初始化完成的任务
+static CRNG_INIT_WAIT: WaitQueue = WaitQueue::new();
+/// 上一次重新设置密钥的时间
+static LAST_RESEED: AtomicUsize = AtomicUsize::new(0);
+/// 硬件随机数发生器的读取函数，返回读取到的字节数
+static HWRNG: Once<fn(&mut [u8]) -> usize> = Once::new();
+
+/// 使用启动时的时间等不可信的数据为 CRNG 设置初始密钥
+///
+/// 这些数据不计入熵，只是保证在 CRNG 初始化完成之前，不同的启动产生的随机数也不相同。
+pub fn init_random() {
+    let key = {
+        let mut pool = POOL.lock();
+        pool.mix_usize(read_timer());
+        pool.mix_usize(hart_id());
+        let bits = pool.entropy_bits;
+        let key = pool.extract();
+        pool.entropy_bits = bits;
+        key
+    };
+    CRNG.lock().reseed(&key);
+}
+
+/// CRNG 是否已经初始化完成
+pub fn crng_ready() -> bool {
+    CRNG_READY.load(Ordering::Acquire)
+}
+
+/// 向熵池中加入数据但不计入熵，用于设备序列号、用户写入 `/dev/random` 的数据等
+pub fn add_device_randomness(data: &[u8]) {
+    let mut pool = POOL.lock();
+    pool.mix_usize(read_timer());
+    pool.mix(data);
+}
+
+/// 记录一次外部中断，中断到来的时间会被加入熵池
+pub fn add_interrupt_randomness(irq: usize) {
+    let now = read_timer();
+    let mut pool = POOL.lock();
+    pool.mix_usize(now);
+    pool.mix_usize(irq);
+    let bits = pool.interrupt_timer.record(now);
+    credit_entropy(&mut pool, bits);
+}
+
+/// 采样一次时钟，记录时钟中断或者调度带来的时间抖动
+///
+/// 等待 CRNG 初始化的任务每次醒来时都会调用它，即使没有其它熵源，CRNG 最终也会初始化完成。
+pub fn add_timer_randomness() {
+    let now = read_timer();
+    let mut pool = POOL.lock();
+    pool.mix_usize(now);
+    let bits = pool.timer.record(now);
+    credit_entropy(&mut pool, bits);
+}
+
+/// 加入硬件随机数发生器产生的数据，每个字节计入 8 比特熵
+pub fn add_hwgenerator_randomness(data: &[u8]) {
+    let mut pool = POOL.lock();
+    pool.mix(data);
+    credit_entropy(&mut pool, data.len() * 8);
+}
+
+/// 注册硬件随机数发生器，之后每次重新设置密钥时都会从中读取数据
+pub fn register_hwrng(read: fn(&mut [u8]) -> usize) {
+    HWRNG.call_once(|| read);
+    pull_hwrng();
+}
+
+fn pull_hwrng() {
+    if let Some(read) = HWRNG.get() {
+        let mut buf = [0u8; HWRNG_SEED_BYTES];
+        let len = read(&mut buf).min(buf.len());
+        add_hwgenerator_randomness(&buf[..len]);
+    }
+}
+
+/// 增加熵池中的熵，熵第一次达到 [`CRNG_INIT_BITS`] 时用熵池为 CRNG 设置密钥
+fn credit_entropy(pool: &mut EntropyPool, bits: usize) {
+    pool.credit(bits);
+    if !crng_ready() && pool.entropy_bits >= CRNG_INIT_BITS {
+        let key = pool.extract();
+        CRNG.lock().reseed(&key);
+        LAST_RESEED.store(read_timer(), Ordering::Relaxed);
+        CRNG_READY.store(true, Ordering::Release);
+        CRNG_INIT_WAIT.wake_all();
+    }
+}
+
+/// 等待 CRNG 初始化完成，收到信号时返回 `EINTR`
+///
+/// 熵池中的熵第一次达到 [`CRNG_INIT_BITS`] 时唤醒等待的任务。等待期间每隔 10ms 醒来采样一次时钟，
+/// 醒来时间的抖动同样会计入熵池。
+pub fn wait_for_random_bytes() -> AlienResult<()> {
+    while !crng_ready() {
+        let deadline = read_timer() + CRNG_WAIT_INTERVAL;
+        match CRNG_INIT_WAIT.wait_event_interruptible_timeout(crng_ready, Some(deadline)) {
+            Ok(()) => {}
+            Err(LinuxErrno::ETIMEDOUT) => add_timer_randomness(),
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(())
+}
+
+/// CRNG 初始化完成后，每隔 [`CRNG_RESEED_INTERVAL`] 秒重新从熵池中获取密钥
+fn crng_reseed_if_needed() {
+    if !crng_ready() {
+        return;
+    }
+    let now = read_timer();
+    let last = LAST_RESEED.load(Ordering::Relaxed);
+    if now.wrapping_sub(last) < CRNG_RESEED_INTERVAL * CLOCK_FREQ
+        || LAST_RESEED
+            .compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed)
+            .is_err()
+    {
+        return;
+    }
+    pull_hwrng();
+    let key = POOL.lock().extract();
+    CRNG.lock().reseed(&key);
+}
+
+/// 使用 CRNG 填充 `buf`，不会等待 CRNG 初始化完成
+///
+/// 需要保证随机数质量的调用者应当先通过 [`crng_ready`] 检查 CRNG 是否已经初始化完成。
+pub fn get_random_bytes(buf: &mut [u8]) {
+    crng_reseed_if_needed();
+    let key = CRNG.lock().take_output_key();
+    buf.chunks_mut(CHACHA_BLOCK_SIZE)
+        .enumerate()
+        .for_each(|(counter, chunk)| {
+            let block = chacha20_block(&key, counter as u64 + 1, 0);
+            chunk.copy_from_slice(&block[..chunk.len()]);
+        });
+}
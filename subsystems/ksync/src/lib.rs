@@ -1,5 +1,10 @@
 #![no_std]
 
+extern crate alloc;
+
+pub mod poll;
+pub mod wait;
+
 use core::cell::{RefCell, RefMut};
 
 use arch::{hart_id, interrupt_disable, interrupt_enable, is_interrupt_enable};
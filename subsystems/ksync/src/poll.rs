@@ -0,0 +1,91 @@
+//! 文件就绪事件的通知队列
+//!
+//! 管道、套接字、eventfd 等文件的状态发生变化时，通过 [`PollQueue::notify`] 通知所有注册在其上的等待者。
+//! 等待者(例如 epoll 实例中的监听项)不需要反复轮询文件，只在收到通知后再检查文件的状态。
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+
+use constants::io::PollEvents;
+
+use crate::Mutex;
+
+/// 注册在 [`PollQueue`] 上的等待者
+pub trait PollWaiter: Send + Sync {
+    /// 文件的状态发生了变化，`events` 为新产生的事件，为空时表示事件未知，需要等待者自己检查文件的状态
+    ///
+    /// 返回等待者是否关心这次通知。
+    fn notify(&self, events: PollEvents) -> bool;
+    /// 是否为独占的等待者(`EPOLLEXCLUSIVE`)，一次通知只会唤醒第一个接受通知的独占等待者
+    fn exclusive(&self) -> bool {
+        false
+    }
+}
+
+/// 文件的就绪事件通知队列，只保存等待者的弱引用，等待者被释放后自动从队列中移除
+pub struct PollQueue {
+    waiters: Mutex<Vec<Weak<dyn PollWaiter>>>,
+}
+
+impl Default for PollQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for PollQueue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PollQueue")
+            .field("waiters", &self.waiters.lock().len())
+            .finish()
+    }
+}
+
+impl PollQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// 注册一个等待者
+    pub fn register(&self, waiter: &Arc<dyn PollWaiter>) {
+        let mut waiters = self.waiters.lock();
+        waiters.retain(|w| w.strong_count() > 0);
+        waiters.push(Arc::downgrade(waiter));
+    }
+
+    /// 移除一个等待者
+    pub fn unregister(&self, waiter: &Arc<dyn PollWaiter>) {
+        let ptr = Arc::as_ptr(waiter) as *const ();
+        self.waiters
+            .lock()
+            .retain(|w| w.strong_count() > 0 && w.as_ptr() as *const () != ptr);
+    }
+
+    /// 通知所有等待者文件的状态发生了变化
+    ///
+    /// 非独占的等待者都会收到通知，独占的等待者中只有第一个接受通知的会被唤醒。
+    /// 等待者在队列的锁之外被调用，因此可以在通知中再次访问队列。
+    pub fn notify(&self, events: PollEvents) {
+        let waiters = {
+            let mut waiters = self.waiters.lock();
+            waiters.retain(|w| w.strong_count() > 0);
+            if waiters.is_empty() {
+                return;
+            }
+            waiters.iter().filter_map(Weak::upgrade).collect::<Vec<_>>()
+        };
+        let mut exclusive_woken = false;
+        for waiter in waiters {
+            if waiter.exclusive() {
+                if !exclusive_woken {
+                    exclusive_woken = waiter.notify(events);
+                }
+            } else {
+                waiter.notify(events);
+            }
+        }
+    }
+}
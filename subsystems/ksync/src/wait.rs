@@ -0,0 +1,277 @@
+//! 通用的等待队列
+//!
+//! 管道、套接字、futex 以及设备驱动等需要阻塞的路径把当前任务挂在 [`WaitQueue`] 上睡眠，
+//! 条件满足后由另一方通过 [`WaitQueue::wake_one`] 或 [`WaitQueue::wake_all`] 唤醒，等待期间不会占用 CPU。
+//!
+//! 等待者先通过 [`WaitQueue::prepare_to_wait`] 进入队列并将任务设置为等待状态，然后再检查等待的条件，
+//! 条件不满足时才让出 CPU，因此在检查条件之后、让出 CPU 之前到来的唤醒不会被遗漏。
+//! 一般直接使用 [`WaitQueue::wait_event`] 系列函数即可。
+use alloc::{collections::VecDeque, sync::Arc, vec::Vec};
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use constants::{AlienError, AlienResult};
+use shim::KTask;
+
+use crate::Mutex;
+
+/// 等待队列中的一个等待者
+pub struct Waiter {
+    task: Arc<dyn KTask>,
+    /// 是否已经被 `wake_*` 从队列中取出并唤醒
+    woken: AtomicBool,
+}
+
+impl Waiter {
+    pub fn new(task: Arc<dyn KTask>) -> Self {
+        Self {
+            task,
+            woken: AtomicBool::new(false),
+        }
+    }
+
+    /// 以当前任务创建一个等待者
+    pub fn current() -> Arc<Self> {
+        let task = shim::current_task().expect("no current task");
+        Arc::new(Self::new(task))
+    }
+
+    /// 等待者对应的任务
+    pub fn task(&self) -> &Arc<dyn KTask> {
+        &self.task
+    }
+
+    /// 自上一次 [`WaitQueue::prepare_to_wait`] 以来是否被唤醒过
+    ///
+    /// 任务也可能因为超时或者信号而醒来，此时等待者仍然在队列中，该函数返回 `false`。
+    pub fn is_woken(&self) -> bool {
+        self.woken.load(Ordering::Acquire)
+    }
+
+    /// 等待直到被唤醒，等待期间等待者可以被 [`WaitQueue::requeue`] 转移到其它队列
+    ///
+    /// 调用前需要通过 [`WaitQueue::prepare_to_wait`] 进入队列。超时返回 `ETIMEDOUT`，在 `interruptible` 时收到信号返回 `EINTR`，
+    /// 返回错误时等待者仍然位于某个队列中，需要由调用者移除。
+    pub fn wait(&self, deadline: Option<usize>, interruptible: bool) -> AlienResult<()> {
+        let res = loop {
+            self.task.to_wait();
+            if self.is_woken() {
+                break Ok(());
+            }
+            if interruptible && self.task.have_signal() {
+                break Err(AlienError::EINTR);
+            }
+            if deadline.is_some_and(|deadline| arch::read_timer() >= deadline) {
+                break Err(AlienError::ETIMEDOUT);
+            }
+            shim::wait_timeout(deadline, interruptible);
+        };
+        shim::finish_wait();
+        res
+    }
+
+    /// 清除唤醒标记并将任务设置为等待状态
+    ///
+    /// 用于等待者不在 [`WaitQueue`] 中、而是由调用者自行管理的场合(例如 futex)，
+    /// 调用者需要在持有自己的锁时调用，之后通过 [`Waiter::wait`] 等待 [`Waiter::wake`]。
+    pub fn prepare(&self) {
+        self.woken.store(false, Ordering::Release);
+        self.task.to_wait();
+    }
+
+    /// 唤醒等待者
+    pub fn wake(&self) {
+        self.woken.store(true, Ordering::Release);
+        shim::wake_up(self.task.clone());
+    }
+}
+
+/// 等待队列，按照进入队列的顺序唤醒等待者
+pub struct WaitQueue {
+    waiters: Mutex<VecDeque<Arc<Waiter>>>,
+}
+
+impl Default for WaitQueue {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl core::fmt::Debug for WaitQueue {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("WaitQueue")
+            .field("waiters", &self.waiters.lock().len())
+            .finish()
+    }
+}
+
+impl WaitQueue {
+    pub const fn new() -> Self {
+        Self {
+            waiters: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// 队列中等待者的数量
+    pub fn len(&self) -> usize {
+        self.waiters.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.waiters.lock().is_empty()
+    }
+
+    /// 将等待者放入队列(已经在队列中时不会重复放入)，并将其任务设置为等待状态
+    ///
+    /// 之后调用者需要再次检查等待的条件，条件不满足时调用 [`shim::wait_timeout`] 让出 CPU，
+    /// 等待结束后调用 [`WaitQueue::finish_wait`]。
+    pub fn prepare_to_wait(&self, waiter: &Arc<Waiter>) {
+        waiter.woken.store(false, Ordering::Release);
+        let mut waiters = self.waiters.lock();
+        if !waiters.iter().any(|w| Arc::ptr_eq(w, waiter)) {
+            waiters.push_back(waiter.clone());
+        }
+        // 在持有队列锁时进入等待状态，唤醒者从队列中取出等待者时任务一定已经处于等待状态
+        waiter.task.to_wait();
+    }
+
+    /// 结束等待，将等待者从队列中移除并恢复任务的运行状态
+    pub fn finish_wait(&self, waiter: &Arc<Waiter>) {
+        self.remove(waiter);
+        shim::finish_wait();
+    }
+
+    /// 将等待者从队列中移除，返回等待者是否在队列中
+    pub fn remove(&self, waiter: &Arc<Waiter>) -> bool {
+        let mut waiters = self.waiters.lock();
+        let len = waiters.len();
+        waiters.retain(|w| !Arc::ptr_eq(w, waiter));
+        waiters.len() != len
+    }
+
+    /// 唤醒队首的等待者，返回是否有等待者被唤醒
+    pub fn wake_one(&self) -> bool {
+        self.wake_n(1) == 1
+    }
+
+    /// 唤醒队列中所有的等待者，返回被唤醒的等待者数量
+    pub fn wake_all(&self) -> usize {
+        self.wake_n(usize::MAX)
+    }
+
+    /// 按顺序唤醒最多 `n` 个等待者，返回被唤醒的等待者数量
+    pub fn wake_n(&self, n: usize) -> usize {
+        self.wake_filter(n, |_| true)
+    }
+
+    /// 按顺序唤醒最多 `n` 个满足 `filter` 的等待者，返回被唤醒的等待者数量
+    pub fn wake_filter<F>(&self, n: usize, mut filter: F) -> usize
+    where
+        F: FnMut(&Arc<Waiter>) -> bool,
+    {
+        let woken = self.take_filter(n, &mut filter);
+        let count = woken.len();
+        // 在队列的锁之外唤醒任务
+        woken.iter().for_each(|waiter| waiter.wake());
+        count
+    }
+
+    /// 将最多 `n` 个等待者从当前队列转移到 `other` 中，不唤醒它们，返回转移的等待者数量
+    pub fn requeue(&self, other: &WaitQueue, n: usize) -> usize {
+        if core::ptr::eq(self, other) {
+            return 0;
+        }
+        let moved = self.take_filter(n, &mut |_| true);
+        let count = moved.len();
+        other.waiters.lock().extend(moved);
+        count
+    }
+
+    fn take_filter<F>(&self, n: usize, filter: &mut F) -> Vec<Arc<Waiter>>
+    where
+        F: FnMut(&Arc<Waiter>) -> bool,
+    {
+        let mut waiters = self.waiters.lock();
+        let mut taken = Vec::new();
+        let mut i = 0;
+        while i < waiters.len() && taken.len() < n {
+            if filter(&waiters[i]) {
+                taken.push(waiters.remove(i).unwrap());
+            } else {
+                i += 1;
+            }
+        }
+        taken
+    }
+
+    /// 不可中断地等待，直到 `cond` 返回 `true`
+    pub fn wait_event<F>(&self, cond: F)
+    where
+        F: FnMut() -> bool,
+    {
+        let _ = self.wait_event_inner(cond, None, false);
+    }
+
+    /// 等待直到 `cond` 返回 `true`，收到信号时返回 `EINTR`
+    pub fn wait_event_interruptible<F>(&self, cond: F) -> AlienResult<()>
+    where
+        F: FnMut() -> bool,
+    {
+        self.wait_event_inner(cond, None, true)
+    }
+
+    /// 不可中断地等待，直到 `cond` 返回 `true` 或者 cpu 时钟到达 `deadline`，超时返回 `ETIMEDOUT`
+    pub fn wait_event_timeout<F>(&self, cond: F, deadline: usize) -> AlienResult<()>
+    where
+        F: FnMut() -> bool,
+    {
+        self.wait_event_inner(cond, Some(deadline), false)
+    }
+
+    /// 等待直到 `cond` 返回 `true`，`deadline` 为 `None` 时不会超时
+    ///
+    /// 超时返回 `ETIMEDOUT`，收到信号时返回 `EINTR`。
+    pub fn wait_event_interruptible_timeout<F>(
+        &self,
+        cond: F,
+        deadline: Option<usize>,
+    ) -> AlienResult<()>
+    where
+        F: FnMut() -> bool,
+    {
+        self.wait_event_inner(cond, deadline, true)
+    }
+
+    fn wait_event_inner<F>(
+        &self,
+        mut cond: F,
+        deadline: Option<usize>,
+        interruptible: bool,
+    ) -> AlienResult<()>
+    where
+        F: FnMut() -> bool,
+    {
+        if cond() {
+            return Ok(());
+        }
+        let waiter = Waiter::current();
+        let res = loop {
+            self.prepare_to_wait(&waiter);
+            if cond() {
+                break Ok(());
+            }
+            if interruptible && waiter.task.have_signal() {
+                break Err(AlienError::EINTR);
+            }
+            if deadline.is_some_and(|deadline| arch::read_timer() >= deadline) {
+                break Err(AlienError::ETIMEDOUT);
+            }
+            shim::wait_timeout(deadline, interruptible);
+        };
+        self.finish_wait(&waiter);
+        // 被唤醒后却因为超时或信号放弃了等待，将这次唤醒转交给下一个等待者
+        if res.is_err() && waiter.is_woken() {
+            self.wake_one();
+        }
+        res
+    }
+}
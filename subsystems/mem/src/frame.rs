@@ -1,4 +1,4 @@
-use alloc::format;
+use alloc::{format, vec::Vec};
 use core::{
     mem::forget,
     ops::{Deref, DerefMut},
@@ -104,11 +104,40 @@ impl DerefMut for FrameTracker {
     }
 }
 
+/// 内存不足时用于回收内存的回调，参数为希望回收的页数，返回实际回收的页数
+pub type Shrinker = fn(usize) -> usize;
+
+static SHRINKERS: Mutex<Vec<Shrinker>> = Mutex::new(Vec::new());
+
+/// 注册一个内存回收回调，物理页分配失败时会依次调用已注册的回调回收内存
+pub fn register_shrinker(shrinker: Shrinker) {
+    SHRINKERS.lock().push(shrinker);
+}
+
+/// 调用所有已注册的回调回收 `count` 页内存，返回实际回收的页数
+fn shrink(count: usize) -> usize {
+    let shrinkers = SHRINKERS.lock().clone();
+    let mut freed = 0;
+    for shrinker in shrinkers {
+        if freed >= count {
+            break;
+        }
+        freed += shrinker(count - freed);
+    }
+    freed
+}
+
 pub fn alloc_frame_trackers(count: usize) -> FrameTracker {
-    let frame = FRAME_ALLOCATOR
-        .lock()
-        .alloc_pages(count, FRAME_SIZE)
-        .expect(format!("alloc {} frame failed", count).as_str());
+    let frame = loop {
+        // the allocator lock must be released before calling shrinkers, they will free frames
+        let frame = FRAME_ALLOCATOR.lock().alloc_pages(count, FRAME_SIZE);
+        if let Ok(frame) = frame {
+            break frame;
+        }
+        if shrink(count) == 0 {
+            panic!("alloc {} frame failed", count);
+        }
+    };
     trace!("alloc frame [{}] start page: {:#x}", count, frame);
     for i in 0..count {
         let refs = FRAME_REF_MANAGER.lock().add_ref(frame + i);
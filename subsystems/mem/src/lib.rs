@@ -15,7 +15,10 @@ mod manager;
 mod talc_wrapper;
 mod vmm;
 
-pub use frame::{alloc_frame_trackers, alloc_frames, free_frames, FrameTracker, VmmPageAllocator};
+pub use frame::{
+    alloc_frame_trackers, alloc_frames, free_frames, register_shrinker, FrameTracker, Shrinker,
+    VmmPageAllocator,
+};
 pub use manager::FRAME_REF_MANAGER;
 use platform::config::HEAP_SIZE;
 pub use vmm::{
@@ -44,6 +44,10 @@ impl FrameRefManager {
             panic!("dec page {:#x?} ref error", id);
         }
     }
+    /// 获取页帧的引用计数，不由引用计数管理的页帧(如设备内存和页缓存)返回 0
+    pub fn ref_count(&self, id: usize) -> usize {
+        self.record.get(&id).copied().unwrap_or(0)
+    }
     pub fn get_ref(&self, id: usize) -> usize {
         if let Some(count) = self.record.get(&id) {
             *count
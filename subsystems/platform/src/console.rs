@@ -2,12 +2,13 @@ use core::fmt::{Arguments, Result, Write};
 
 use ksync::Mutex;
 use preprint::Print;
+
+use crate::kmsg::{kmsg_record, DEFAULT_MESSAGE_LOGLEVEL, LOG_KERN};
 /// 系统启动初期使用的输出函数
 #[macro_export]
 macro_rules! print {
     ($($arg:tt)*) => {
-        let hard_id = arch::hart_id();
-        $crate::console::__print(format_args!("[{}] {}", hard_id, format_args!($($arg)*)))
+        $crate::console::__print(format_args!($($arg)*))
     };
 }
 
@@ -63,9 +64,21 @@ static STDOUT: Mutex<Stdout> = Mutex::new(Stdout);
 
 /// 输出函数
 /// 对参数进行输出 主要使用在输出相关的宏中 如println
+///
+/// 输出的内容同时会以默认级别记录到内核日志缓冲区中，见[`crate::kmsg`]
 #[doc(hidden)]
 pub fn __print(args: Arguments) {
-    STDOUT.lock().write_fmt(args).unwrap();
+    kmsg_record(LOG_KERN, DEFAULT_MESSAGE_LOGLEVEL, args);
+    console_print(args);
+}
+
+/// 只输出到控制台，不记录到内核日志缓冲区
+pub(crate) fn console_print(args: Arguments) {
+    let hart_id = arch::hart_id();
+    STDOUT
+        .lock()
+        .write_fmt(format_args!("[{}] {}", hart_id, args))
+        .unwrap();
 }
 
 /// 系统启动初期使用的输出函数
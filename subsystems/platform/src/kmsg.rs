@@ -0,0 +1,271 @@
+//! 内核日志环形缓冲区
+//!
+//! 所有通过 `println!` 和 `log` 输出的内容都会以记录的形式保存在这里，供 `syslog` 系统调用和 `/dev/kmsg` 读取。
+//! 每条记录带有序号、日志级别、时间戳以及产生记录的核号。
+//!
+//! 缓冲区由固定数量的槽位组成，序号为 `seq` 的记录保存在第 `seq % KMSG_RECORDS` 个槽位中。
+//! 写者通过原子操作获得序号后直接写入槽位，读者通过槽位中的状态检查读到的记录是否完整、是否已经被覆盖，
+//! 整个过程不需要加锁，因此可以在中断处理以及 panic 时使用。只有存在等待新记录的读者时，写者才会获取等待队列的锁。
+use core::{
+    cell::UnsafeCell,
+    fmt::{Arguments, Write},
+    sync::atomic::{fence, AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering},
+};
+
+use constants::AlienResult;
+use ksync::wait::WaitQueue;
+
+/// 缓冲区中最多保存的记录数量
+pub const KMSG_RECORDS: usize = 512;
+/// 每条记录中文本的最大长度，超出的部分会被截断
+pub const KMSG_TEXT_MAX: usize = 224;
+/// 格式化后的一行记录的最大长度
+pub const KMSG_LINE_MAX: usize = KMSG_TEXT_MAX + 64;
+
+/// 内核产生的日志记录
+pub const LOG_KERN: u8 = 0;
+/// 用户态写入的日志记录
+pub const LOG_USER: u8 = 1;
+
+/// 没有指明级别的记录(如 `println!` 输出)使用的级别，即 `KERN_WARNING`
+pub const DEFAULT_MESSAGE_LOGLEVEL: u8 = 4;
+/// 默认的控制台日志级别
+pub const DEFAULT_CONSOLE_LOGLEVEL: u8 = 7;
+/// 控制台日志级别可以设置的最小值
+pub const MINIMUM_CONSOLE_LOGLEVEL: u8 = 1;
+
+/// 控制台日志级别，只有级别数值小于它的 `log` 记录才会输出到控制台
+static CONSOLE_LOGLEVEL: AtomicU8 = AtomicU8::new(DEFAULT_CONSOLE_LOGLEVEL);
+
+/// 获取控制台日志级别
+pub fn console_loglevel() -> u8 {
+    CONSOLE_LOGLEVEL.load(Ordering::Relaxed)
+}
+
+/// 设置控制台日志级别，小于 [`MINIMUM_CONSOLE_LOGLEVEL`] 的值会被提升为该值
+pub fn set_console_loglevel(level: u8) {
+    CONSOLE_LOGLEVEL.store(level.max(MINIMUM_CONSOLE_LOGLEVEL), Ordering::Relaxed);
+}
+
+/// 一条日志记录
+#[derive(Debug, Clone, Copy)]
+pub struct KmsgRecord {
+    /// 记录的序号，从 0 开始递增
+    pub seq: u64,
+    /// 产生记录的时间，单位为微秒
+    pub timestamp: u64,
+    /// 记录的来源，[`LOG_KERN`] 或者 [`LOG_USER`] 等
+    pub facility: u8,
+    /// 日志级别，0(`KERN_EMERG`) 到 7(`KERN_DEBUG`)
+    pub level: u8,
+    /// 产生记录的核号
+    pub hart: u8,
+    len: u8,
+    text: [u8; KMSG_TEXT_MAX],
+}
+
+impl KmsgRecord {
+    const fn empty() -> Self {
+        Self {
+            seq: 0,
+            timestamp: 0,
+            facility: 0,
+            level: 0,
+            hart: 0,
+            len: 0,
+            text: [0; KMSG_TEXT_MAX],
+        }
+    }
+
+    /// 记录的文本，不包含结尾的换行符
+    pub fn text(&self) -> &[u8] {
+        &self.text[..self.len as usize]
+    }
+
+    /// 记录的优先级，由来源和日志级别组成
+    pub fn priority(&self) -> u8 {
+        (self.facility << 3) | self.level
+    }
+
+    /// 按照 `/dev/kmsg` 的格式输出记录: `<优先级>,<序号>,<时间戳>,-;<文本>\n`
+    pub fn kmsg_line(&self) -> KmsgLine {
+        let mut line = KmsgLine::new();
+        let _ = write!(
+            line,
+            "{},{},{},-;",
+            self.priority(),
+            self.seq,
+            self.timestamp
+        );
+        line.push_bytes(self.text());
+        line.push_bytes(b"\n");
+        line
+    }
+
+    /// 按照 `syslog` 系统调用的格式输出记录: `<级别>[秒.微秒] <文本>\n`
+    pub fn syslog_line(&self) -> KmsgLine {
+        let mut line = KmsgLine::new();
+        let _ = write!(
+            line,
+            "<{}>[{:>5}.{:06}] ",
+            self.level,
+            self.timestamp / 1_000_000,
+            self.timestamp % 1_000_000
+        );
+        line.push_bytes(self.text());
+        line.push_bytes(b"\n");
+        line
+    }
+}
+
+impl Write for KmsgRecord {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        let len = self.len as usize;
+        let copy = s.len().min(KMSG_TEXT_MAX - len);
+        self.text[len..len + copy].copy_from_slice(&s.as_bytes()[..copy]);
+        self.len += copy as u8;
+        Ok(())
+    }
+}
+
+/// 格式化后的一行日志记录
+pub struct KmsgLine {
+    buf: [u8; KMSG_LINE_MAX],
+    len: usize,
+}
+
+impl KmsgLine {
+    fn new() -> Self {
+        Self {
+            buf: [0; KMSG_LINE_MAX],
+            len: 0,
+        }
+    }
+
+    fn push_bytes(&mut self, bytes: &[u8]) {
+        let copy = bytes.len().min(KMSG_LINE_MAX - self.len);
+        self.buf[self.len..self.len + copy].copy_from_slice(&bytes[..copy]);
+        self.len += copy;
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+}
+
+impl Write for KmsgLine {
+    fn write_str(&mut self, s: &str) -> core::fmt::Result {
+        self.push_bytes(s.as_bytes());
+        Ok(())
+    }
+}
+
+/// 读取记录失败的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KmsgReadError {
+    /// 记录还没有被写入
+    NotYet,
+    /// 记录已经被更新的记录覆盖，最旧的记录可以通过 [`kmsg_first_seq`] 获得
+    Overwritten,
+}
+
+struct KmsgSlot {
+    /// 0 表示槽位为空；`2 * seq + 1` 表示序号为 `seq` 的记录正在写入；`2 * seq + 2` 表示写入完成
+    state: AtomicU64,
+    record: UnsafeCell<KmsgRecord>,
+}
+
+unsafe impl Sync for KmsgSlot {}
+
+impl KmsgSlot {
+    const fn new() -> Self {
+        Self {
+            state: AtomicU64::new(0),
+            record: UnsafeCell::new(KmsgRecord::empty()),
+        }
+    }
+}
+
+static KMSG_SLOTS: [KmsgSlot; KMSG_RECORDS] = [const { KmsgSlot::new() }; KMSG_RECORDS];
+/// 下一条记录的序号
+static KMSG_NEXT_SEQ: AtomicU64 = AtomicU64::new(0);
+/// 等待新记录的读者
+static KMSG_WAIT_QUEUE: WaitQueue = WaitQueue::new();
+/// 正在等待新记录的读者数量，为 0 时写者不需要唤醒读者
+static KMSG_WAITERS: AtomicUsize = AtomicUsize::new(0);
+/// 写者正在唤醒读者。唤醒的过程中产生的记录不会再次唤醒读者，避免重复获取等待队列的锁
+static KMSG_WAKING: AtomicBool = AtomicBool::new(false);
+
+/// 当前时间，单位为微秒
+fn timestamp_us() -> u64 {
+    (arch::read_timer() as u128 * 1_000_000 / crate::config::CLOCK_FREQ as u128) as u64
+}
+
+/// 向缓冲区中加入一条记录，文本结尾的换行符会被去掉
+pub fn kmsg_record(facility: u8, level: u8, args: Arguments) {
+    let seq = KMSG_NEXT_SEQ.fetch_add(1, Ordering::Relaxed);
+    let slot = &KMSG_SLOTS[seq as usize % KMSG_RECORDS];
+    slot.state.store(2 * seq + 1, Ordering::Relaxed);
+    fence(Ordering::Release);
+    // Safety: 读者会在读取前后检查槽位状态，丢弃读取过程中被修改过的记录
+    let record = unsafe { &mut *slot.record.get() };
+    record.seq = seq;
+    record.timestamp = timestamp_us();
+    record.facility = facility & 0x1f;
+    record.level = level & 0x7;
+    record.hart = arch::hart_id() as u8;
+    record.len = 0;
+    let _ = record.write_fmt(args);
+    while record.len > 0 && record.text[record.len as usize - 1] == b'\n' {
+        record.len -= 1;
+    }
+    slot.state.store(2 * seq + 2, Ordering::Release);
+    if KMSG_WAITERS.load(Ordering::SeqCst) != 0 && !KMSG_WAKING.swap(true, Ordering::Acquire) {
+        KMSG_WAIT_QUEUE.wake_all();
+        KMSG_WAKING.store(false, Ordering::Release);
+    }
+}
+
+/// 等待直到序号为 `seq` 的记录写入完成，收到信号时返回 `EINTR`
+pub fn kmsg_wait(seq: u64) -> AlienResult<()> {
+    KMSG_WAITERS.fetch_add(1, Ordering::SeqCst);
+    let res = KMSG_WAIT_QUEUE
+        .wait_event_interruptible(|| !matches!(kmsg_read(seq), Err(KmsgReadError::NotYet)));
+    KMSG_WAITERS.fetch_sub(1, Ordering::SeqCst);
+    res
+}
+
+/// 读取序号为 `seq` 的记录
+pub fn kmsg_read(seq: u64) -> Result<KmsgRecord, KmsgReadError> {
+    if seq >= KMSG_NEXT_SEQ.load(Ordering::Acquire) {
+        return Err(KmsgReadError::NotYet);
+    }
+    let slot = &KMSG_SLOTS[seq as usize % KMSG_RECORDS];
+    let expected = 2 * seq + 2;
+    let state = slot.state.load(Ordering::Acquire);
+    if state > expected {
+        return Err(KmsgReadError::Overwritten);
+    }
+    if state < expected {
+        // 写者已经获得了序号，但是还没有写完
+        return Err(KmsgReadError::NotYet);
+    }
+    let record = unsafe { core::ptr::read_volatile(slot.record.get()) };
+    fence(Ordering::Acquire);
+    if slot.state.load(Ordering::Relaxed) != expected {
+        return Err(KmsgReadError::Overwritten);
+    }
+    Ok(record)
+}
+
+/// 缓冲区中最旧的记录的序号
+pub fn kmsg_first_seq() -> u64 {
+    KMSG_NEXT_SEQ
+        .load(Ordering::Acquire)
+        .saturating_sub(KMSG_RECORDS as u64)
+}
+
+/// 下一条记录的序号
+pub fn kmsg_next_seq() -> u64 {
+    KMSG_NEXT_SEQ.load(Ordering::Acquire)
+}
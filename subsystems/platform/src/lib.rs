@@ -11,6 +11,7 @@ use ::config::CPU_NUM;
 pub use common_riscv::basic::MachineInfo as PlatformInfo;
 use spin::Once;
 
+pub mod kmsg;
 pub mod logging;
 #[cfg(feature = "qemu_riscv")]
 mod qemu_riscv;
@@ -87,3 +88,13 @@ static MACHINE_INFO: Once<PlatformInfo> = Once::new();
 pub fn platform_machine_info() -> PlatformInfo {
     MACHINE_INFO.get().unwrap().clone()
 }
+
+/// Get the value of a `key=value` option from the kernel command line
+pub fn platform_cmdline_option(key: &str) -> Option<&'static str> {
+    let info = MACHINE_INFO.get()?;
+    let bootargs = info.bootargs.as_ref()?;
+    let cmdline = core::str::from_utf8(&bootargs[..info.bootargs_len]).ok()?;
+    cmdline
+        .split_whitespace()
+        .find_map(|option| option.strip_prefix(key)?.strip_prefix('='))
+}
@@ -1,5 +1,10 @@
 use log::{self, Level, LevelFilter, Log, Metadata, Record};
 
+use crate::{
+    console::console_print,
+    kmsg::{console_loglevel, kmsg_record, LOG_KERN},
+};
+
 struct SimpleLogger;
 
 impl Log for SimpleLogger {
@@ -10,6 +15,16 @@ impl Log for SimpleLogger {
         if !self.enabled(record.metadata()) {
             return;
         }
+        let module = record.module_path().unwrap_or("<unknown>");
+        let level = syslog_level(record.level());
+        kmsg_record(
+            LOG_KERN,
+            level,
+            format_args!("[{}] {}", module, record.args()),
+        );
+        if level >= console_loglevel() {
+            return;
+        }
         let color = match record.level() {
             Level::Error => 31, // Red
             Level::Warn => 93,  // BrightYellow
@@ -17,21 +32,30 @@ impl Log for SimpleLogger {
             Level::Debug => 32, // Green
             Level::Trace => 90, // BrightBlack
         };
-        let module = record.module_path().unwrap_or("<unknown>");
         if !module.contains("bpf_basic") && record.level() != LevelFilter::Error {
             return;
         }
-        println!(
-            "\u{1B}[{}m[{:>1}] [{}] {}\u{1B}[0m",
+        console_print(format_args!(
+            "\u{1B}[{}m[{:>1}] [{}] {}\u{1B}[0m\n",
             color,
             record.level(),
             module,
             record.args(),
-        );
+        ));
     }
     fn flush(&self) {}
 }
 
+/// `log` 中的日志级别对应的 syslog 日志级别
+fn syslog_level(level: Level) -> u8 {
+    match level {
+        Level::Error => 3,                // KERN_ERR
+        Level::Warn => 4,                 // KERN_WARNING
+        Level::Info => 6,                 // KERN_INFO
+        Level::Debug | Level::Trace => 7, // KERN_DEBUG
+    }
+}
+
 pub fn init_logger() {
     println!("Init logger {:?}", option_env!("LOG"));
     log::set_logger(&SimpleLogger).unwrap();
@@ -10,6 +10,18 @@ pub trait KTask: Send + Sync + DowncastSync {
     fn to_wait(&self);
     fn to_wakeup(&self);
     fn have_signal(&self) -> bool;
+    /// The process id of the task.
+    fn pid(&self) -> usize;
+    /// The process group id of the task.
+    fn pgid(&self) -> usize;
+    /// The session id of the task.
+    fn sid(&self) -> usize;
+    /// Whether the signal `sig` is blocked or ignored by the task.
+    fn signal_blocked_or_ignored(&self, sig: usize) -> bool;
+    /// The effective user id of the task.
+    fn euid(&self) -> u32;
+    /// The effective group id of the task.
+    fn egid(&self) -> u32;
 }
 
 impl_downcast!(sync KTask);
@@ -19,9 +31,15 @@ pub trait KTaskShim: Send + Sync {
     fn current_task(&self) -> Option<Arc<dyn KTask>>;
     fn put_task(&self, task: Arc<dyn KTask>);
     fn suspend(&self);
+    fn sleep_until(&self, end_time: usize);
     fn schedule_now(&self, task: Arc<dyn KTask>);
+    fn wake_up(&self, task: Arc<dyn KTask>);
+    fn wait_timeout(&self, deadline: Option<usize>, interruptible: bool);
+    fn finish_wait(&self);
     fn transfer_ptr_raw(&self, ptr: usize) -> usize;
     fn transfer_buf_raw(&self, src: usize, size: usize) -> Vec<&mut [u8]>;
+    fn kill_pgrp(&self, pgid: usize, sig: usize);
+    fn pgrp_in_session(&self, pgid: usize, sid: usize) -> bool;
 }
 
 impl dyn KTaskShim {
@@ -59,6 +77,15 @@ impl dyn KTaskShim {
             start += len;
         }
     }
+    fn copy_bytes_to_task(&self, src: &[u8], dst: *mut u8) {
+        let bufs = self.transfer_buf_raw(dst as usize, src.len());
+        let mut start = 0;
+        for buffer in bufs {
+            let len = buffer.len().min(src.len() - start);
+            buffer[..len].copy_from_slice(&src[start..start + len]);
+            start += len;
+        }
+    }
     fn transfer_ptr_mut<T>(&self, ptr: *mut T) -> &'static mut T {
         let ptr = ptr as usize;
         let ptr = self.transfer_ptr_raw(ptr);
@@ -101,6 +128,16 @@ pub fn suspend() {
         .suspend()
 }
 #[cfg(feature = "lib")]
+/// Sleep the current task until the cpu clock reaches `end_time`.
+///
+/// The task may be woken up earlier by a signal, so the caller should check its condition again.
+pub fn sleep_until(end_time: usize) {
+    KTASK_SHIM
+        .get()
+        .expect("ktask_shim not initialized")
+        .sleep_until(end_time)
+}
+#[cfg(feature = "lib")]
 /// Put the task back to the task queue.
 pub fn put_task(task: Arc<dyn KTask>) {
     KTASK_SHIM
@@ -117,6 +154,49 @@ pub fn schedule_now(task: Arc<dyn KTask>) {
         .schedule_now(task);
 }
 #[cfg(feature = "lib")]
+/// Wake up a task that is waiting, do nothing if the task is not waiting.
+pub fn wake_up(task: Arc<dyn KTask>) {
+    KTASK_SHIM
+        .get()
+        .expect("ktask_shim not initialized")
+        .wake_up(task);
+}
+#[cfg(feature = "lib")]
+/// Give up the cpu until the current task is woken up.
+///
+/// The task must have been set to waiting by `KTask::to_wait`. It is also woken up when the cpu
+/// clock reaches `deadline`, or when a signal arrives if `interruptible` is true.
+pub fn wait_timeout(deadline: Option<usize>, interruptible: bool) {
+    KTASK_SHIM
+        .get()
+        .expect("ktask_shim not initialized")
+        .wait_timeout(deadline, interruptible);
+}
+#[cfg(feature = "lib")]
+/// Stop waiting and make the current task running again.
+pub fn finish_wait() {
+    KTASK_SHIM
+        .get()
+        .expect("ktask_shim not initialized")
+        .finish_wait();
+}
+#[cfg(feature = "lib")]
+/// Send the signal `sig` to every process in the process group `pgid`.
+pub fn kill_pgrp(pgid: usize, sig: usize) {
+    KTASK_SHIM
+        .get()
+        .expect("ktask_shim not initialized")
+        .kill_pgrp(pgid, sig);
+}
+#[cfg(feature = "lib")]
+/// Whether the process group `pgid` exists in the session `sid`.
+pub fn pgrp_in_session(pgid: usize, sid: usize) -> bool {
+    KTASK_SHIM
+        .get()
+        .expect("ktask_shim not initialized")
+        .pgrp_in_session(pgid, sid)
+}
+#[cfg(feature = "lib")]
 pub fn copy_data_to_task<T: 'static + Copy>(src: *const T, dst: *mut T) {
     KTASK_SHIM
         .get()
@@ -131,6 +211,14 @@ pub fn copy_data_from_task<T: 'static + Copy>(src: *const T, dst: *mut T) {
         .copy_data_from_task(src, dst);
 }
 #[cfg(feature = "lib")]
+/// Copy `src` to the user buffer `dst`, the buffer may cross pages.
+pub fn copy_bytes_to_task(src: &[u8], dst: *mut u8) {
+    KTASK_SHIM
+        .get()
+        .expect("ktask_shim not initialized")
+        .copy_bytes_to_task(src, dst);
+}
+#[cfg(feature = "lib")]
 pub fn transfer_ptr_mut<T>(ptr: *mut T) -> &'static mut T {
     KTASK_SHIM
         .get()
@@ -0,0 +1,190 @@
+//! `/dev/input/eventN` 设备文件
+//!
+//! 每个输入设备在 `/dev/input` 中对应一个 `eventN`，包括启动后才出现的设备。
+//! 每次打开都会创建一个独立的 [`EvDevClient`]，因此每个打开的文件都能读到设备的全部事件。
+use alloc::{collections::BTreeMap, format, sync::Arc};
+use core::fmt::{Debug, Formatter};
+
+use constants::{
+    io::{OpenFlags, PollEvents, SeekFrom},
+    AlienResult, DeviceId, LinuxErrno,
+};
+use devices::{EvDev, EvDevClient};
+use ksync::{poll::PollQueue, Mutex};
+use log::{error, info};
+use spin::{Lazy, Once};
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodeType},
+    VfsResult,
+};
+
+use super::{alloc_device_id, register_device, register_device_open};
+use crate::{kfile::File, perm::apply_inode_owner};
+
+/// `/dev/input` 目录
+static INPUT_DIR: Once<Arc<dyn VfsInode>> = Once::new();
+
+/// 设备文件对应的输入设备
+static EVDEVS: Lazy<Mutex<BTreeMap<DeviceId, Arc<EvDev>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// 在 `input_dir` 中为已有的以及之后出现的输入设备创建设备文件
+pub fn init_evdev(input_dir: Arc<dyn VfsInode>) {
+    INPUT_DIR.call_once(|| input_dir);
+    devices::set_input_listener(add_evdev_node);
+}
+
+/// 为输入设备创建 `/dev/input/eventN`
+fn add_evdev_node(evdev: Arc<EvDev>) {
+    let device_id = alloc_device_id(VfsNodeType::CharDevice);
+    let name = format!("event{}", evdev.index());
+    let res = INPUT_DIR.get().unwrap().create(
+        &name,
+        'c'.into(),
+        "rw-rw----".into(),
+        Some(device_id.id()),
+    );
+    if let Err(e) = res {
+        error!("create /dev/input/{} failed: {:?}", name, e);
+        return;
+    }
+    EVDEVS.lock().insert(device_id, evdev);
+    register_device_open(device_id, EvdevFile::open);
+    register_device(Arc::new(EvdevDevice { device_id }));
+    info!("input device {} id: {}", name, device_id.id());
+}
+
+/// evdev 设备，打开时由 [`EvdevFile::open`] 创建文件，设备本身不支持读写
+struct EvdevDevice {
+    device_id: DeviceId,
+}
+
+impl VfsFile for EvdevDevice {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+}
+
+impl VfsInode for EvdevDevice {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_rdev: self.device_id.id(),
+            ..Default::default()
+        })
+    }
+
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::CharDevice
+    }
+}
+
+/// 打开的 `/dev/input/eventN`
+pub struct EvdevFile {
+    dentry: Arc<dyn VfsDentry>,
+    open_flag: Mutex<OpenFlags>,
+    client: Arc<EvDevClient>,
+}
+
+impl Debug for EvdevFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EvdevFile")
+            .field("open_flag", &self.open_flag)
+            .finish()
+    }
+}
+
+impl EvdevFile {
+    pub fn open(dentry: Arc<dyn VfsDentry>, open_flag: OpenFlags) -> AlienResult<Arc<dyn File>> {
+        let device_id = DeviceId::from(dentry.inode()?.get_attr()?.st_rdev);
+        let evdev = EVDEVS
+            .lock()
+            .get(&device_id)
+            .cloned()
+            .ok_or(LinuxErrno::ENODEV)?;
+        Ok(Arc::new(Self {
+            dentry,
+            open_flag: Mutex::new(open_flag),
+            client: evdev.open(),
+        }))
+    }
+}
+
+impl File for EvdevFile {
+    fn read(&self, buf: &mut [u8]) -> AlienResult<usize> {
+        let nonblock = self.open_flag.lock().contains(OpenFlags::O_NONBLOCK);
+        self.client.read(buf, nonblock)
+    }
+
+    fn write(&self, _buf: &[u8]) -> AlienResult<usize> {
+        Err(LinuxErrno::EINVAL)
+    }
+
+    fn seek(&self, _pos: SeekFrom) -> AlienResult<u64> {
+        Err(LinuxErrno::ESPIPE)
+    }
+
+    fn get_attr(&self) -> AlienResult<VfsFileStat> {
+        let inode = self.dentry.inode()?;
+        let mut attr = inode.get_attr()?;
+        apply_inode_owner(&inode, &mut attr);
+        Ok(attr)
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> AlienResult<usize> {
+        self.client.ioctl(cmd, arg)
+    }
+
+    fn set_open_flag(&self, flag: OpenFlags) {
+        *self.open_flag.lock() = flag;
+    }
+
+    fn get_open_flag(&self) -> OpenFlags {
+        *self.open_flag.lock()
+    }
+
+    fn dentry(&self) -> Arc<dyn VfsDentry> {
+        self.dentry.clone()
+    }
+
+    fn inode(&self) -> Arc<dyn VfsInode> {
+        self.dentry.inode().unwrap()
+    }
+
+    fn is_readable(&self) -> bool {
+        let open_flag = self.open_flag.lock();
+        open_flag.contains(OpenFlags::O_RDONLY) | open_flag.contains(OpenFlags::O_RDWR)
+    }
+
+    fn is_writable(&self) -> bool {
+        let open_flag = self.open_flag.lock();
+        open_flag.contains(OpenFlags::O_WRONLY) | open_flag.contains(OpenFlags::O_RDWR)
+    }
+
+    fn is_append(&self) -> bool {
+        false
+    }
+
+    fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        Ok(self.client.poll(event))
+    }
+
+    fn poll_queue(&self) -> Option<&PollQueue> {
+        Some(self.client.poll_queue())
+    }
+}
@@ -0,0 +1,396 @@
+//! `/dev/fb0` 帧缓存设备
+//!
+//! 将 GPU 的帧缓存以 Linux fbdev 的接口提供给用户态：`FBIOGET_VSCREENINFO`、`FBIOGET_FSCREENINFO` 获取显示模式，
+//! 共享映射 `/dev/fb0` 会直接映射帧缓存所在的物理内存。
+//!
+//! 对映射的修改无法被内核察觉，因此帧缓存被映射期间由内核线程周期性地调用 [`fb_refresh`] 将其刷新到屏幕上，
+//! 此外 `FBIOPAN_DISPLAY` 和 `write` 之后也会刷新，双缓冲的程序在切换缓冲区时可以立即看到结果。
+use alloc::sync::Arc;
+use core::{
+    fmt::{Debug, Formatter},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use config::FRAME_SIZE;
+use constants::{
+    io::{OpenFlags, PollEvents, SeekFrom},
+    AlienResult, DeviceId, LinuxErrno,
+};
+use device_interface::GpuDevice;
+use devices::GPU_DEVICE;
+use ksync::Mutex;
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodeType},
+    VfsResult,
+};
+
+use crate::{kfile::File, perm::apply_inode_owner};
+
+const FBIOGET_VSCREENINFO: u32 = 0x4600;
+const FBIOPUT_VSCREENINFO: u32 = 0x4601;
+const FBIOGET_FSCREENINFO: u32 = 0x4602;
+const FBIOPAN_DISPLAY: u32 = 0x4606;
+const FBIOBLANK: u32 = 0x4611;
+const FBIO_WAITFORVSYNC: u32 = 0x40044620;
+
+const FB_TYPE_PACKED_PIXELS: u32 = 0;
+const FB_VISUAL_TRUECOLOR: u32 = 2;
+const FB_ACTIVATE_NOW: u32 = 0;
+const FB_VMODE_NONINTERLACED: u32 = 0;
+
+/// virtio-gpu 的帧缓存为 `B8G8R8A8`，每个像素 4 字节
+const BITS_PER_PIXEL: u32 = 32;
+
+/// 帧缓存现有的映射数量，不为 0 时由内核线程周期性地刷新
+static FB_MAPPINGS: AtomicUsize = AtomicUsize::new(0);
+/// 通过 `write` 修改了帧缓存，需要刷新
+static FB_DIRTY: AtomicBool = AtomicBool::new(false);
+
+/// 颜色分量在像素中的位置，与 Linux 中的 `struct fb_bitfield` 相同
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct FbBitfield {
+    offset: u32,
+    length: u32,
+    msb_right: u32,
+}
+
+impl FbBitfield {
+    const fn new(offset: u32, length: u32) -> Self {
+        Self {
+            offset,
+            length,
+            msb_right: 0,
+        }
+    }
+}
+
+/// 可变的显示模式，与 Linux 中的 `struct fb_var_screeninfo` 相同
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct FbVarScreenInfo {
+    xres: u32,
+    yres: u32,
+    xres_virtual: u32,
+    yres_virtual: u32,
+    xoffset: u32,
+    yoffset: u32,
+    bits_per_pixel: u32,
+    grayscale: u32,
+    red: FbBitfield,
+    green: FbBitfield,
+    blue: FbBitfield,
+    transp: FbBitfield,
+    nonstd: u32,
+    activate: u32,
+    /// 屏幕的物理尺寸，单位为毫米，未知时为 `u32::MAX`
+    height: u32,
+    width: u32,
+    accel_flags: u32,
+    pixclock: u32,
+    left_margin: u32,
+    right_margin: u32,
+    upper_margin: u32,
+    lower_margin: u32,
+    hsync_len: u32,
+    vsync_len: u32,
+    sync: u32,
+    vmode: u32,
+    rotate: u32,
+    colorspace: u32,
+    reserved: [u32; 4],
+}
+
+/// 固定的显示参数，与 Linux 中的 `struct fb_fix_screeninfo` 相同
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct FbFixScreenInfo {
+    id: [u8; 16],
+    smem_start: usize,
+    smem_len: u32,
+    fb_type: u32,
+    type_aux: u32,
+    visual: u32,
+    xpanstep: u16,
+    ypanstep: u16,
+    ywrapstep: u16,
+    line_length: u32,
+    mmio_start: usize,
+    mmio_len: u32,
+    accel: u32,
+    capabilities: u16,
+    reserved: [u16; 2],
+}
+
+fn var_screen_info(gpu: &dyn GpuDevice) -> FbVarScreenInfo {
+    let (xres, yres) = gpu.resolution();
+    FbVarScreenInfo {
+        xres,
+        yres,
+        xres_virtual: xres,
+        yres_virtual: yres,
+        bits_per_pixel: BITS_PER_PIXEL,
+        red: FbBitfield::new(16, 8),
+        green: FbBitfield::new(8, 8),
+        blue: FbBitfield::new(0, 8),
+        transp: FbBitfield::new(0, 0),
+        activate: FB_ACTIVATE_NOW,
+        height: u32::MAX,
+        width: u32::MAX,
+        vmode: FB_VMODE_NONINTERLACED,
+        ..Default::default()
+    }
+}
+
+fn fix_screen_info(gpu: &dyn GpuDevice) -> FbFixScreenInfo {
+    let fb = gpu.get_framebuffer();
+    let (xres, _) = gpu.resolution();
+    let mut id = [0u8; 16];
+    id[..10].copy_from_slice(b"virtio_gpu");
+    FbFixScreenInfo {
+        id,
+        smem_start: fb.as_ptr() as usize,
+        smem_len: fb.len() as u32,
+        fb_type: FB_TYPE_PACKED_PIXELS,
+        visual: FB_VISUAL_TRUECOLOR,
+        line_length: xres * BITS_PER_PIXEL / 8,
+        ..Default::default()
+    }
+}
+
+/// 将被修改的帧缓存刷新到屏幕上，由内核线程周期性地调用
+pub fn fb_refresh() {
+    let dirty = FB_DIRTY.swap(false, Ordering::AcqRel);
+    if dirty || FB_MAPPINGS.load(Ordering::Acquire) > 0 {
+        if let Some(gpu) = GPU_DEVICE.get() {
+            gpu.flush();
+        }
+    }
+}
+
+/// `/dev/fb0` 设备，每次打开都会创建一个 [`FbFile`]，因此设备本身不支持读写
+pub struct FbDevice {
+    device_id: DeviceId,
+}
+
+impl FbDevice {
+    pub fn new(device_id: DeviceId) -> Self {
+        Self { device_id }
+    }
+    pub fn device_id(&self) -> DeviceId {
+        self.device_id
+    }
+}
+
+impl VfsFile for FbDevice {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+}
+
+impl VfsInode for FbDevice {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_rdev: self.device_id.id(),
+            ..Default::default()
+        })
+    }
+
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::CharDevice
+    }
+}
+
+/// 打开的 `/dev/fb0`，读写的位置为帧缓存中的偏移
+pub struct FbFile {
+    dentry: Arc<dyn VfsDentry>,
+    open_flag: Mutex<OpenFlags>,
+    gpu: Arc<dyn GpuDevice>,
+    pos: Mutex<usize>,
+}
+
+impl Debug for FbFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("FbFile")
+            .field("open_flag", &self.open_flag)
+            .field("pos", &self.pos)
+            .finish()
+    }
+}
+
+impl FbFile {
+    pub fn open(dentry: Arc<dyn VfsDentry>, open_flag: OpenFlags) -> AlienResult<Arc<dyn File>> {
+        let gpu = GPU_DEVICE.get().ok_or(LinuxErrno::ENODEV)?.clone();
+        Ok(Arc::new(Self {
+            dentry,
+            open_flag: Mutex::new(open_flag),
+            gpu,
+            pos: Mutex::new(0),
+        }))
+    }
+}
+
+impl File for FbFile {
+    /// 从帧缓存末尾之后读取返回 0
+    fn read(&self, buf: &mut [u8]) -> AlienResult<usize> {
+        let fb = self.gpu.get_framebuffer();
+        let mut pos = self.pos.lock();
+        if *pos >= fb.len() {
+            return Ok(0);
+        }
+        let len = buf.len().min(fb.len() - *pos);
+        buf[..len].copy_from_slice(&fb[*pos..*pos + len]);
+        *pos += len;
+        Ok(len)
+    }
+
+    /// 写到帧缓存末尾之后返回 `EFBIG`
+    fn write(&self, buf: &[u8]) -> AlienResult<usize> {
+        let fb = self.gpu.get_framebuffer();
+        let mut pos = self.pos.lock();
+        if *pos >= fb.len() {
+            return if buf.is_empty() {
+                Ok(0)
+            } else {
+                Err(LinuxErrno::EFBIG)
+            };
+        }
+        let len = buf.len().min(fb.len() - *pos);
+        fb[*pos..*pos + len].copy_from_slice(&buf[..len]);
+        *pos += len;
+        FB_DIRTY.store(true, Ordering::Release);
+        Ok(len)
+    }
+
+    fn seek(&self, pos: SeekFrom) -> AlienResult<u64> {
+        let len = self.gpu.get_framebuffer().len() as i64;
+        let mut cur = self.pos.lock();
+        let new_pos = match pos {
+            SeekFrom::Start(offset) => offset as i64,
+            SeekFrom::Current(offset) => *cur as i64 + offset,
+            SeekFrom::End(offset) => len + offset,
+        };
+        if new_pos < 0 {
+            return Err(LinuxErrno::EINVAL);
+        }
+        *cur = new_pos as usize;
+        Ok(new_pos as u64)
+    }
+
+    fn get_attr(&self) -> AlienResult<VfsFileStat> {
+        let inode = self.dentry.inode()?;
+        let mut attr = inode.get_attr()?;
+        apply_inode_owner(&inode, &mut attr);
+        Ok(attr)
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> AlienResult<usize> {
+        let gpu = self.gpu.as_ref();
+        match cmd {
+            FBIOGET_VSCREENINFO => {
+                shim::copy_data_to_task(&var_screen_info(gpu), arg as *mut FbVarScreenInfo);
+                Ok(0)
+            }
+            FBIOPUT_VSCREENINFO => {
+                // 显示模式由 GPU 决定，只接受与当前模式相同的设置
+                let current = var_screen_info(gpu);
+                let mut var = current;
+                shim::copy_data_from_task(arg as *const FbVarScreenInfo, &mut var);
+                if var.xres != current.xres
+                    || var.yres != current.yres
+                    || var.xres_virtual > current.xres_virtual
+                    || var.yres_virtual > current.yres_virtual
+                    || (var.bits_per_pixel != 0 && var.bits_per_pixel != BITS_PER_PIXEL)
+                {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                shim::copy_data_to_task(&current, arg as *mut FbVarScreenInfo);
+                Ok(0)
+            }
+            FBIOGET_FSCREENINFO => {
+                shim::copy_data_to_task(&fix_screen_info(gpu), arg as *mut FbFixScreenInfo);
+                Ok(0)
+            }
+            FBIOPAN_DISPLAY => {
+                // 虚拟分辨率与实际分辨率相同，只能显示整个帧缓存
+                let mut var = FbVarScreenInfo::default();
+                shim::copy_data_from_task(arg as *const FbVarScreenInfo, &mut var);
+                if var.xoffset != 0 || var.yoffset != 0 {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                gpu.flush();
+                Ok(0)
+            }
+            FBIOBLANK => Ok(0),
+            FBIO_WAITFORVSYNC => {
+                gpu.flush();
+                Ok(0)
+            }
+            _ => Err(LinuxErrno::ENOTTY),
+        }
+    }
+
+    fn set_open_flag(&self, flag: OpenFlags) {
+        *self.open_flag.lock() = flag;
+    }
+
+    fn get_open_flag(&self) -> OpenFlags {
+        *self.open_flag.lock()
+    }
+
+    fn dentry(&self) -> Arc<dyn VfsDentry> {
+        self.dentry.clone()
+    }
+
+    fn inode(&self) -> Arc<dyn VfsInode> {
+        self.dentry.inode().unwrap()
+    }
+
+    fn is_readable(&self) -> bool {
+        let open_flag = self.open_flag.lock();
+        open_flag.contains(OpenFlags::O_RDONLY) | open_flag.contains(OpenFlags::O_RDWR)
+    }
+
+    fn is_writable(&self) -> bool {
+        let open_flag = self.open_flag.lock();
+        open_flag.contains(OpenFlags::O_WRONLY) | open_flag.contains(OpenFlags::O_RDWR)
+    }
+
+    fn is_append(&self) -> bool {
+        false
+    }
+
+    fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        Ok(event & (PollEvents::EPOLLIN | PollEvents::EPOLLOUT))
+    }
+
+    fn mmap_phys(&self, offset: usize, len: usize) -> AlienResult<usize> {
+        let fb = self.gpu.get_framebuffer();
+        let size = fb.len().div_ceil(FRAME_SIZE) * FRAME_SIZE;
+        if offset % FRAME_SIZE != 0 || offset.checked_add(len).is_none_or(|end| end > size) {
+            return Err(LinuxErrno::EINVAL);
+        }
+        FB_MAPPINGS.fetch_add(1, Ordering::AcqRel);
+        Ok(fb.as_ptr() as usize + offset)
+    }
+
+    fn munmap_phys(&self, _offset: usize, _len: usize) {
+        FB_MAPPINGS.fetch_sub(1, Ordering::AcqRel);
+    }
+}
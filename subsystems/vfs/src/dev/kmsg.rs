@@ -0,0 +1,225 @@
+use alloc::{string::String, sync::Arc};
+use core::fmt::{Debug, Formatter};
+
+use constants::{
+    io::{OpenFlags, PollEvents, SeekFrom},
+    AlienResult, LinuxErrno,
+};
+use ksync::Mutex;
+use platform::kmsg::{
+    kmsg_first_seq, kmsg_next_seq, kmsg_read, kmsg_record, kmsg_wait, KmsgReadError,
+    DEFAULT_MESSAGE_LOGLEVEL, LOG_USER,
+};
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    file::VfsFile,
+    inode::{InodeAttr, VfsInode},
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType},
+    VfsResult,
+};
+
+use crate::{dev::DeviceId, kfile::File, perm::apply_inode_owner};
+
+/// `/dev/kmsg` 设备，读写内核日志缓冲区
+///
+/// 每次打开都会创建一个 [`KmsgFile`]，它记录了自己的读取位置，因此设备本身不支持读取。
+pub struct KmsgDevice {
+    device_id: DeviceId,
+}
+
+impl KmsgDevice {
+    pub fn new(device_id: DeviceId) -> Self {
+        Self { device_id }
+    }
+    pub fn device_id(&self) -> DeviceId {
+        self.device_id
+    }
+}
+
+impl VfsFile for KmsgDevice {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+    fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        write_user_record(buf);
+        Ok(buf.len())
+    }
+}
+
+impl VfsInode for KmsgDevice {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+
+    fn node_perm(&self) -> VfsNodePerm {
+        VfsNodePerm::empty()
+    }
+
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_rdev: self.device_id.id(),
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::CharDevice
+    }
+}
+
+/// 将用户态写入的内容作为一条日志记录。内容可以以 `<优先级>` 开头，用于指明日志级别和来源
+fn write_user_record(buf: &[u8]) {
+    let mut level = DEFAULT_MESSAGE_LOGLEVEL;
+    let mut facility = LOG_USER;
+    let mut text = buf;
+    if let Some(end) = buf
+        .strip_prefix(b"<")
+        .and_then(|rest| rest.iter().position(|&c| c == b'>'))
+    {
+        let priority = core::str::from_utf8(&buf[1..end + 1])
+            .ok()
+            .and_then(|priority| priority.parse::<u8>().ok());
+        if let Some(priority) = priority {
+            level = priority & 0x7;
+            // 用户态不能伪造内核产生的记录
+            if priority >> 3 != 0 {
+                facility = priority >> 3;
+            }
+            text = &buf[end + 2..];
+        }
+    }
+    kmsg_record(
+        facility,
+        level,
+        format_args!("{}", String::from_utf8_lossy(text)),
+    );
+}
+
+/// 打开的 `/dev/kmsg` 文件，每次读取返回一条日志记录
+pub struct KmsgFile {
+    dentry: Arc<dyn VfsDentry>,
+    open_flag: Mutex<OpenFlags>,
+    /// 下一次读取的记录序号
+    seq: Mutex<u64>,
+}
+
+impl Debug for KmsgFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("KmsgFile")
+            .field("open_flag", &self.open_flag)
+            .field("seq", &self.seq)
+            .finish()
+    }
+}
+
+impl KmsgFile {
+    /// 打开 `/dev/kmsg`，从缓冲区中最旧的记录开始读取
+    pub fn open(dentry: Arc<dyn VfsDentry>, open_flag: OpenFlags) -> AlienResult<Arc<dyn File>> {
+        Ok(Arc::new(Self {
+            dentry,
+            open_flag: Mutex::new(open_flag),
+            seq: Mutex::new(kmsg_first_seq()),
+        }))
+    }
+}
+
+impl File for KmsgFile {
+    fn read(&self, buf: &mut [u8]) -> AlienResult<usize> {
+        loop {
+            let next = {
+                let mut seq = self.seq.lock();
+                match kmsg_read(*seq) {
+                    Ok(record) => {
+                        let line = record.kmsg_line();
+                        let line = line.as_bytes();
+                        if buf.len() < line.len() {
+                            return Err(LinuxErrno::EINVAL);
+                        }
+                        buf[..line.len()].copy_from_slice(line);
+                        *seq += 1;
+                        return Ok(line.len());
+                    }
+                    Err(KmsgReadError::Overwritten) => {
+                        // 告诉读者有记录丢失，下一次从最旧的记录开始读取
+                        *seq = kmsg_first_seq();
+                        return Err(LinuxErrno::EPIPE);
+                    }
+                    Err(KmsgReadError::NotYet) => *seq,
+                }
+            };
+            if self.open_flag.lock().contains(OpenFlags::O_NONBLOCK) {
+                return Err(LinuxErrno::EAGAIN);
+            }
+            kmsg_wait(next)?;
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> AlienResult<usize> {
+        write_user_record(buf);
+        Ok(buf.len())
+    }
+
+    fn seek(&self, pos: SeekFrom) -> AlienResult<u64> {
+        let new_seq = match pos {
+            SeekFrom::Start(0) => kmsg_first_seq(),
+            SeekFrom::End(0) => kmsg_next_seq(),
+            SeekFrom::Start(_) | SeekFrom::End(_) => return Err(LinuxErrno::ESPIPE),
+            SeekFrom::Current(_) => return Err(LinuxErrno::EINVAL),
+        };
+        *self.seq.lock() = new_seq;
+        Ok(0)
+    }
+
+    fn get_attr(&self) -> AlienResult<VfsFileStat> {
+        let inode = self.dentry.inode()?;
+        let mut attr = inode.get_attr()?;
+        apply_inode_owner(&inode, &mut attr);
+        Ok(attr)
+    }
+
+    fn set_open_flag(&self, flag: OpenFlags) {
+        *self.open_flag.lock() = flag;
+    }
+
+    fn get_open_flag(&self) -> OpenFlags {
+        *self.open_flag.lock()
+    }
+
+    fn dentry(&self) -> Arc<dyn VfsDentry> {
+        self.dentry.clone()
+    }
+
+    fn inode(&self) -> Arc<dyn VfsInode> {
+        self.dentry.inode().unwrap()
+    }
+
+    fn is_readable(&self) -> bool {
+        let open_flag = self.open_flag.lock();
+        open_flag.contains(OpenFlags::O_RDONLY) | open_flag.contains(OpenFlags::O_RDWR)
+    }
+
+    fn is_writable(&self) -> bool {
+        let open_flag = self.open_flag.lock();
+        open_flag.contains(OpenFlags::O_WRONLY) | open_flag.contains(OpenFlags::O_RDWR)
+    }
+
+    fn is_append(&self) -> bool {
+        false
+    }
+
+    fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        let mut res = PollEvents::empty();
+        if event.contains(PollEvents::EPOLLIN) && *self.seq.lock() < kmsg_next_seq() {
+            res |= PollEvents::EPOLLIN;
+        }
+        if event.contains(PollEvents::EPOLLOUT) {
+            res |= PollEvents::EPOLLOUT;
+        }
+        Ok(res)
+    }
+}
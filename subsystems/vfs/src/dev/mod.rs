@@ -1,15 +1,18 @@
 use alloc::{collections::BTreeMap, sync::Arc};
 
-use constants::DeviceId;
+use constants::{io::OpenFlags, AlienResult, DeviceId};
 use devfs::DevKernelProvider;
 use devices::{
-    BLKDevice, GPUDevice, INPUTDevice, RTCDevice, UARTDevice, BLOCK_DEVICE, GPU_DEVICE,
-    KEYBOARD_INPUT_DEVICE, MOUSE_INPUT_DEVICE, RTC_DEVICE, UART_DEVICE,
+    BLKDevice, GPUDevice, INPUTDevice, RTCDevice, UARTDevice, GPU_DEVICE, KEYBOARD_INPUT_DEVICE,
+    MOUSE_INPUT_DEVICE, RTC_DEVICE, UART_TTY,
 };
-use ksync::Mutex;
+use fb::{FbDevice, FbFile};
+use kmsg::{KmsgDevice, KmsgFile};
+use ksync::{poll::PollQueue, Mutex};
 use log::info;
 use null::NullDevice;
-use random::RandomDevice;
+use pty::{PtmxDevice, PtyMaster};
+use random::{RandomDevice, RandomFile};
 use spin::Lazy;
 use vfscore::{
     dentry::VfsDentry,
@@ -18,12 +21,85 @@ use vfscore::{
     utils::{VfsNodeType, VfsTimeSpec},
 };
 
+use crate::kfile::{File, KernelFile};
+
+mod evdev;
+mod fb;
+mod kmsg;
 mod null;
+mod pty;
 mod random;
 
+pub use fb::fb_refresh;
+pub use pty::{devpts_root, init_devpts};
+
 pub static DEVICES: Lazy<Mutex<BTreeMap<DeviceId, Arc<dyn VfsInode>>>> =
     Lazy::new(|| Mutex::new(BTreeMap::new()));
 
+/// 打开设备时创建文件的函数
+pub type DeviceOpenFn = fn(Arc<dyn VfsDentry>, OpenFlags) -> AlienResult<Arc<dyn File>>;
+
+/// 每次打开都需要创建独立文件的设备，例如每个打开的 `/dev/kmsg` 都有自己的读取位置
+static DEVICE_OPENS: Lazy<Mutex<BTreeMap<DeviceId, DeviceOpenFn>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// 为设备 `device_id` 注册打开时使用的函数
+pub fn register_device_open(device_id: DeviceId, open: DeviceOpenFn) {
+    DEVICE_OPENS.lock().insert(device_id, open);
+}
+
+/// 删除设备 `device_id` 的打开函数
+pub fn unregister_device_open(device_id: DeviceId) {
+    DEVICE_OPENS.lock().remove(&device_id);
+}
+
+/// 打开设备文件 `dentry`。如果设备注册了打开函数，则由它创建文件，否则返回 `None`，由调用者创建普通的文件
+pub fn open_device(
+    dentry: &Arc<dyn VfsDentry>,
+    open_flag: OpenFlags,
+) -> Option<AlienResult<Arc<dyn File>>> {
+    let inode = dentry.inode().ok()?;
+    if !matches!(
+        inode.inode_type(),
+        VfsNodeType::CharDevice | VfsNodeType::BlockDevice
+    ) {
+        return None;
+    }
+    let device_id = DeviceId::from(inode.get_attr().ok()?.st_rdev);
+    let open = DEVICE_OPENS.lock().get(&device_id).copied()?;
+    Some(open(dentry.clone(), open_flag))
+}
+
+/// 设备状态发生变化时通知的队列，例如串口收到数据时会通知 `/dev/tty` 上的 epoll
+static DEVICE_POLL_QUEUES: Lazy<Mutex<BTreeMap<DeviceId, &'static PollQueue>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// 为设备 `device_id` 注册就绪事件的通知队列
+pub fn register_device_poll_queue(device_id: DeviceId, queue: &'static PollQueue) {
+    DEVICE_POLL_QUEUES.lock().insert(device_id, queue);
+}
+
+/// 获取设备文件 `dentry` 的就绪事件通知队列，不是设备文件或者设备没有注册时返回 `None`
+pub fn device_poll_queue(dentry: &Arc<dyn VfsDentry>) -> Option<&'static PollQueue> {
+    let inode = dentry.inode().ok()?;
+    if !matches!(
+        inode.inode_type(),
+        VfsNodeType::CharDevice | VfsNodeType::BlockDevice
+    ) {
+        return None;
+    }
+    let device_id = DeviceId::from(inode.get_attr().ok()?.st_rdev);
+    DEVICE_POLL_QUEUES.lock().get(&device_id).copied()
+}
+
+/// 打开串口终端，调用者是没有控制终端的会话首进程时，串口终端成为它的控制终端
+fn open_uart_tty(dentry: Arc<dyn VfsDentry>, open_flag: OpenFlags) -> AlienResult<Arc<dyn File>> {
+    if let Some(tty) = UART_TTY.get() {
+        tty.open(open_flag.contains(OpenFlags::O_NOCTTY));
+    }
+    Ok(Arc::new(KernelFile::new(dentry, open_flag)))
+}
+
 pub static DEVICE_ID_MANAGER: Lazy<Mutex<DeviceIdManager>> =
     Lazy::new(|| Mutex::new(DeviceIdManager::new()));
 
@@ -94,10 +170,18 @@ impl DevKernelProvider for DevFsProviderImpl {
 /// |-- zero
 /// |-- random
 /// |-- urandom
+/// |-- kmsg
 /// |-- tty
+/// |-- ptmx
+/// |-- pts (the devpts will be mounted here)
 /// |-- shm (a ramfs will be mounted here)
 /// |-- misc
 ///    |-- rtc
+/// |-- input
+///    |-- event0
+///    |-- ...
+/// |-- fb0 (if there is a gpu)
+/// |-- vda, vda1, ... (every block device and its partitions)
 /// ```
 pub fn init_devfs(devfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
     let root = devfs.i_mount(0, "/dev", None, &[]).unwrap();
@@ -107,6 +191,8 @@ pub fn init_devfs(devfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
     let zero_device = Arc::new(NullDevice::new(alloc_device_id(VfsNodeType::CharDevice)));
     let random_device = Arc::new(RandomDevice::new(alloc_device_id(VfsNodeType::CharDevice)));
     let urandom_device = Arc::new(RandomDevice::new(alloc_device_id(VfsNodeType::CharDevice)));
+    let kmsg_device = Arc::new(KmsgDevice::new(alloc_device_id(VfsNodeType::CharDevice)));
+    let ptmx_device = Arc::new(PtmxDevice::new(alloc_device_id(VfsNodeType::CharDevice)));
 
     root_inode
         .create(
@@ -140,18 +226,46 @@ pub fn init_devfs(devfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
             Some(urandom_device.device_id().id()),
         )
         .unwrap();
+    root_inode
+        .create(
+            "kmsg",
+            'c'.into(),
+            "rw-r--r--".into(),
+            Some(kmsg_device.device_id().id()),
+        )
+        .unwrap();
+    root_inode
+        .create(
+            "ptmx",
+            'c'.into(),
+            "rw-rw-rw-".into(),
+            Some(ptmx_device.device_id().id()),
+        )
+        .unwrap();
 
     register_device(null_device);
     register_device(zero_device);
+    register_device_open(random_device.device_id(), RandomFile::open);
     register_device(random_device);
     register_device(urandom_device);
+    register_device_open(kmsg_device.device_id(), KmsgFile::open);
+    register_device(kmsg_device);
+    register_device_open(ptmx_device.device_id(), PtyMaster::open);
+    register_device(ptmx_device);
 
+    root_inode
+        .create("pts", VfsNodeType::Dir, "rwxr-xr-x".into(), None)
+        .unwrap();
     root_inode
         .create("shm", VfsNodeType::Dir, "rwxrwxrwx".into(), None)
         .unwrap();
     root_inode
         .create("misc", VfsNodeType::Dir, "rwxrwxrwx".into(), None)
         .unwrap();
+    let input_dir = root_inode
+        .create("input", VfsNodeType::Dir, "rwxr-xr-x".into(), None)
+        .unwrap();
+    evdev::init_evdev(input_dir);
 
     scan_system_devices(root_inode);
     // todo!(tty,shm,misc)
@@ -160,21 +274,25 @@ pub fn init_devfs(devfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
 }
 
 fn scan_system_devices(root: Arc<dyn VfsInode>) {
-    BLOCK_DEVICE.get().map(|blk| {
+    for entry in devices::block_devices() {
         let block_device = Arc::new(BLKDevice::new(
             alloc_device_id(VfsNodeType::BlockDevice),
-            blk.clone(),
+            entry.device.clone(),
         ));
         root.create(
-            "sda",
+            &entry.name,
             VfsNodeType::BlockDevice,
             "rw-rw----".into(),
             Some(block_device.device_id().id()),
         )
         .unwrap();
-        info!("block device id: {}", block_device.device_id().id());
+        info!(
+            "block device {} id: {}",
+            entry.name,
+            block_device.device_id().id()
+        );
         register_device(block_device);
-    });
+    }
     GPU_DEVICE.get().map(|gpu| {
         let gpu_device = Arc::new(GPUDevice::new(
             alloc_device_id(VfsNodeType::CharDevice),
@@ -189,6 +307,17 @@ fn scan_system_devices(root: Arc<dyn VfsInode>) {
         .unwrap();
         info!("gpu device id: {}", gpu_device.device_id().id());
         register_device(gpu_device);
+        let fb_device = Arc::new(FbDevice::new(alloc_device_id(VfsNodeType::CharDevice)));
+        root.create(
+            "fb0",
+            'c'.into(),
+            "rw-rw----".into(),
+            Some(fb_device.device_id().id()),
+        )
+        .unwrap();
+        info!("framebuffer device id: {}", fb_device.device_id().id());
+        register_device_open(fb_device.device_id(), FbFile::open);
+        register_device(fb_device);
     });
     KEYBOARD_INPUT_DEVICE.get().map(|input| {
         let input_device = Arc::new(INPUTDevice::new(
@@ -237,19 +366,21 @@ fn scan_system_devices(root: Arc<dyn VfsInode>) {
         info!("rtc device id: {}", rtc_device.device_id().id());
         register_device(rtc_device);
     });
-    UART_DEVICE.get().map(|uart| {
+    UART_TTY.get().map(|tty| {
         let uart_device = Arc::new(UARTDevice::new(
             alloc_device_id(VfsNodeType::CharDevice),
-            uart.clone(),
+            tty.clone(),
         ));
         root.create(
             "tty",
             VfsNodeType::BlockDevice,
-            "rw-rw----".into(),
+            "rw-rw-rw-".into(),
             Some(uart_device.device_id().id()),
         )
         .unwrap();
         info!("uart device id: {}", uart_device.device_id().id());
+        register_device_poll_queue(uart_device.device_id(), tty.poll_queue());
+        register_device_open(uart_device.device_id(), open_uart_tty);
         register_device(uart_device);
     });
 }
@@ -0,0 +1,541 @@
+//! 伪终端(pty)
+//!
+//! 每次打开 `/dev/ptmx` 都会创建一对伪终端：打开得到的文件是主设备，对应的从设备出现在 devpts 文件系统中，
+//! 即 `/dev/pts/<n>`。从设备是一个完整的 [`Tty`]，与串口终端共用同一套行规程：
+//! 写入主设备的数据作为从设备终端的输入，从设备终端的输出则由主设备读出。
+//!
+//! 新创建的从设备处于锁定状态，需要通过主设备的 `TIOCSPTLCK` (即 `unlockpt`) 解锁后才能打开，
+//! `TIOCGPTN` (即 `ptsname`) 获取从设备的编号。从设备的属主在创建时被设置为打开 `/dev/ptmx` 的用户，
+//! 因此 `grantpt` 不需要做额外的工作。
+//!
+//! 从设备终端的输出缓冲区大小有限，缓冲区满时写从设备的任务等待主设备读取数据。
+//!
+//! 主设备被关闭时从设备终端被挂断，从设备文件随之从 devpts 中删除；从设备被全部关闭后，读主设备返回 `EIO`。
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    format,
+    sync::{Arc, Weak},
+};
+use core::{
+    fmt::{Debug, Formatter},
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use constants::{
+    io::{OpenFlags, PollEvents, SeekFrom},
+    AlienResult, DeviceId, LinuxErrno,
+};
+use devices::{Tty, TtyDriver};
+use ksync::{poll::PollQueue, wait::WaitQueue, Mutex};
+use spin::{Lazy, Once};
+use vfscore::{
+    dentry::VfsDentry,
+    error::VfsError,
+    file::VfsFile,
+    fstype::VfsFsType,
+    inode::{InodeAttr, VfsInode},
+    path::VfsPath,
+    superblock::VfsSuperBlock,
+    utils::{VfsFileStat, VfsNodePerm, VfsNodeType, VfsPollEvents},
+    VfsResult,
+};
+
+use super::{
+    alloc_device_id, register_device, register_device_open, unregister_device,
+    unregister_device_open,
+};
+use crate::{
+    kfile::File,
+    perm::{apply_inode_owner, set_inode_owner, InodeOwner},
+};
+
+/// 获取从设备的编号
+const TIOCGPTN: u32 = 0x80045430;
+/// 锁定或者解锁从设备
+const TIOCSPTLCK: u32 = 0x40045431;
+/// 获取从设备是否被锁定
+const TIOCGPTLCK: u32 = 0x80045439;
+
+/// 最多同时存在的伪终端数量
+const PTY_MAX: usize = 1024;
+
+/// 从设备终端输出、等待主设备读取的数据的字节数上限，缓冲区中的数据超过上限后写从设备的任务需要等待
+const PTY_BUF_SIZE: usize = 8192;
+
+/// 从设备文件的权限位，与 `grantpt` 设置的相同
+const PTS_MODE: u32 = 0o620;
+
+/// devpts 文件系统的根目录，所有挂载点共享同一个实例
+static DEVPTS_ROOT: Once<Arc<dyn VfsDentry>> = Once::new();
+
+/// 所有存活的伪终端，以编号为键
+static PTYS: Lazy<Mutex<BTreeMap<usize, Weak<Pty>>>> = Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// 创建 devpts 文件系统，之后打开 `/dev/ptmx` 创建的从设备都位于其中
+pub fn init_devpts(devpts: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
+    let root = devpts.i_mount(0, "/dev/pts", None, &[]).unwrap();
+    DEVPTS_ROOT.call_once(|| root.clone());
+    println!("devpts init success");
+    root
+}
+
+/// devpts 文件系统的根目录，再次挂载 devpts 时使用
+pub fn devpts_root() -> Arc<dyn VfsDentry> {
+    DEVPTS_ROOT.get().unwrap().clone()
+}
+
+/// 一对伪终端
+struct Pty {
+    index: usize,
+    slave_device_id: DeviceId,
+    /// 从设备的终端
+    tty: Arc<Tty>,
+    /// 从设备终端输出的、等待主设备读取的数据，超过 [`PTY_BUF_SIZE`] 的部分不超过一次输出处理的结果
+    output: Mutex<VecDeque<u8>>,
+    /// 等待从设备输出的主设备读者
+    master_read_queue: WaitQueue,
+    master_poll_queue: PollQueue,
+    /// 从设备是否被锁定
+    locked: AtomicBool,
+    /// 打开的从设备文件数量
+    slave_opens: AtomicUsize,
+    /// 从设备曾经被打开过并且已经被全部关闭
+    slave_closed: AtomicBool,
+}
+
+impl Pty {
+    fn notify_master(&self, events: PollEvents) {
+        self.master_read_queue.wake_all();
+        self.master_poll_queue.notify(events);
+    }
+}
+
+/// 从设备终端的驱动，将终端的输出交给主设备
+struct PtyDriver(Weak<Pty>);
+
+impl TtyDriver for PtyDriver {
+    fn write(&self, buf: &[u8]) {
+        if let Some(pty) = self.0.upgrade() {
+            pty.output.lock().extend(buf.iter().copied());
+            pty.notify_master(PollEvents::EPOLLIN);
+        }
+    }
+
+    fn write_room(&self) -> usize {
+        self.0.upgrade().map_or(0, |pty| {
+            PTY_BUF_SIZE.saturating_sub(pty.output.lock().len())
+        })
+    }
+}
+
+/// `/dev/ptmx` 设备，每次打开都会创建一个 [`PtyMaster`]，因此设备本身不支持读写
+pub struct PtmxDevice {
+    device_id: DeviceId,
+}
+
+impl PtmxDevice {
+    pub fn new(device_id: DeviceId) -> Self {
+        Self { device_id }
+    }
+    pub fn device_id(&self) -> DeviceId {
+        self.device_id
+    }
+}
+
+impl VfsFile for PtmxDevice {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+}
+
+impl VfsInode for PtmxDevice {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+
+    fn node_perm(&self) -> VfsNodePerm {
+        VfsNodePerm::empty()
+    }
+
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_rdev: self.device_id.id(),
+            ..Default::default()
+        })
+    }
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::CharDevice
+    }
+}
+
+/// devpts 中的从设备，打开时由 [`PtySlave::open`] 创建文件，设备本身不支持读写
+struct PtsDevice {
+    device_id: DeviceId,
+}
+
+impl VfsFile for PtsDevice {
+    fn read_at(&self, _offset: u64, _buf: &mut [u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+    fn write_at(&self, _offset: u64, _buf: &[u8]) -> VfsResult<usize> {
+        Err(VfsError::Invalid)
+    }
+}
+
+impl VfsInode for PtsDevice {
+    fn get_super_block(&self) -> VfsResult<Arc<dyn VfsSuperBlock>> {
+        Err(VfsError::NoSys)
+    }
+
+    fn set_attr(&self, _attr: InodeAttr) -> VfsResult<()> {
+        Ok(())
+    }
+
+    fn get_attr(&self) -> VfsResult<VfsFileStat> {
+        Ok(VfsFileStat {
+            st_rdev: self.device_id.id(),
+            ..Default::default()
+        })
+    }
+
+    fn inode_type(&self) -> VfsNodeType {
+        VfsNodeType::CharDevice
+    }
+}
+
+/// 分配一个未被使用的伪终端编号
+fn alloc_index(ptys: &BTreeMap<usize, Weak<Pty>>) -> AlienResult<usize> {
+    (0..PTY_MAX)
+        .find(|index| !ptys.contains_key(index))
+        .ok_or(LinuxErrno::ENOSPC)
+}
+
+/// 伪终端的主设备，即打开 `/dev/ptmx` 得到的文件
+pub struct PtyMaster {
+    dentry: Arc<dyn VfsDentry>,
+    open_flag: Mutex<OpenFlags>,
+    pty: Arc<Pty>,
+}
+
+impl Debug for PtyMaster {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PtyMaster")
+            .field("index", &self.pty.index)
+            .field("open_flag", &self.open_flag)
+            .finish()
+    }
+}
+
+impl PtyMaster {
+    /// 打开 `/dev/ptmx`，创建一对新的伪终端并在 devpts 中创建被锁定的从设备
+    pub fn open(dentry: Arc<dyn VfsDentry>, open_flag: OpenFlags) -> AlienResult<Arc<dyn File>> {
+        let mut ptys = PTYS.lock();
+        let index = alloc_index(&ptys)?;
+        let slave_device_id = alloc_device_id(VfsNodeType::CharDevice);
+        let pty = Arc::new_cyclic(|pty: &Weak<Pty>| Pty {
+            index,
+            slave_device_id,
+            tty: Tty::new(Arc::new(PtyDriver(pty.clone()))),
+            output: Mutex::new(VecDeque::new()),
+            master_read_queue: WaitQueue::new(),
+            master_poll_queue: PollQueue::new(),
+            locked: AtomicBool::new(true),
+            slave_opens: AtomicUsize::new(0),
+            slave_closed: AtomicBool::new(false),
+        });
+
+        let root = devpts_root();
+        let inode = root.inode()?.create(
+            &format!("{}", index),
+            'c'.into(),
+            "rw--w----".into(),
+            Some(slave_device_id.id()),
+        )?;
+        if let Some(task) = shim::current_task() {
+            set_inode_owner(
+                &inode,
+                InodeOwner {
+                    uid: task.euid(),
+                    gid: task.egid(),
+                    mode: PTS_MODE,
+                },
+            )?;
+        }
+        register_device_open(slave_device_id, PtySlave::open);
+        register_device(Arc::new(PtsDevice {
+            device_id: slave_device_id,
+        }));
+        ptys.insert(index, Arc::downgrade(&pty));
+        Ok(Arc::new(Self {
+            dentry,
+            open_flag: Mutex::new(open_flag),
+            pty,
+        }))
+    }
+
+    fn is_nonblock(&self) -> bool {
+        self.open_flag.lock().contains(OpenFlags::O_NONBLOCK)
+    }
+}
+
+impl Drop for PtyMaster {
+    fn drop(&mut self) {
+        let pty = &self.pty;
+        PTYS.lock().remove(&pty.index);
+        unregister_device_open(pty.slave_device_id);
+        unregister_device(pty.slave_device_id);
+        let root = devpts_root();
+        let _ = VfsPath::new(root.clone(), root)
+            .join(format!("{}", pty.index))
+            .and_then(|path| path.unlink());
+        pty.tty.hangup();
+    }
+}
+
+impl File for PtyMaster {
+    /// 读取从设备终端的输出，从设备被全部关闭并且没有剩余的数据时返回 `EIO`
+    fn read(&self, buf: &mut [u8]) -> AlienResult<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let pty = &self.pty;
+        loop {
+            let count = {
+                let mut output = pty.output.lock();
+                let count = buf.len().min(output.len());
+                buf[..count]
+                    .iter_mut()
+                    .zip(output.drain(..count))
+                    .for_each(|(dst, src)| *dst = src);
+                count
+            };
+            if count > 0 {
+                // 缓冲区有了空闲空间，唤醒等待写从设备的任务
+                pty.tty.write_wakeup();
+                return Ok(count);
+            }
+            if pty.slave_closed.load(Ordering::Acquire) {
+                return Err(LinuxErrno::EIO);
+            }
+            if self.is_nonblock() {
+                return Err(LinuxErrno::EAGAIN);
+            }
+            pty.master_read_queue.wait_event_interruptible(|| {
+                !pty.output.lock().is_empty() || pty.slave_closed.load(Ordering::Acquire)
+            })?;
+        }
+    }
+
+    /// 写入的数据作为从设备终端的输入
+    fn write(&self, buf: &[u8]) -> AlienResult<usize> {
+        self.pty.tty.receive(buf);
+        Ok(buf.len())
+    }
+
+    fn seek(&self, _pos: SeekFrom) -> AlienResult<u64> {
+        Err(LinuxErrno::ESPIPE)
+    }
+
+    fn get_attr(&self) -> AlienResult<VfsFileStat> {
+        let inode = self.dentry.inode()?;
+        let mut attr = inode.get_attr()?;
+        apply_inode_owner(&inode, &mut attr);
+        Ok(attr)
+    }
+
+    /// 除了伪终端特有的命令以外，其余的命令作用于从设备的终端
+    fn ioctl(&self, cmd: u32, arg: usize) -> AlienResult<usize> {
+        let pty = &self.pty;
+        match cmd {
+            TIOCGPTN => {
+                *shim::transfer_ptr_mut(arg as *mut u32) = pty.index as u32;
+                Ok(0)
+            }
+            TIOCSPTLCK => {
+                let lock = *shim::transfer_ptr(arg as *const i32);
+                pty.locked.store(lock != 0, Ordering::Release);
+                Ok(0)
+            }
+            TIOCGPTLCK => {
+                *shim::transfer_ptr_mut(arg as *mut i32) =
+                    pty.locked.load(Ordering::Acquire) as i32;
+                Ok(0)
+            }
+            _ => pty.tty.link_ioctl(cmd, arg).map_err(Into::into),
+        }
+    }
+
+    fn set_open_flag(&self, flag: OpenFlags) {
+        *self.open_flag.lock() = flag;
+    }
+
+    fn get_open_flag(&self) -> OpenFlags {
+        *self.open_flag.lock()
+    }
+
+    fn dentry(&self) -> Arc<dyn VfsDentry> {
+        self.dentry.clone()
+    }
+
+    fn inode(&self) -> Arc<dyn VfsInode> {
+        self.dentry.inode().unwrap()
+    }
+
+    fn is_readable(&self) -> bool {
+        let open_flag = self.open_flag.lock();
+        open_flag.contains(OpenFlags::O_RDONLY) | open_flag.contains(OpenFlags::O_RDWR)
+    }
+
+    fn is_writable(&self) -> bool {
+        let open_flag = self.open_flag.lock();
+        open_flag.contains(OpenFlags::O_WRONLY) | open_flag.contains(OpenFlags::O_RDWR)
+    }
+
+    fn is_append(&self) -> bool {
+        false
+    }
+
+    fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        let pty = &self.pty;
+        let mut res = PollEvents::empty();
+        if event.contains(PollEvents::EPOLLIN) && !pty.output.lock().is_empty() {
+            res |= PollEvents::EPOLLIN;
+        }
+        if event.contains(PollEvents::EPOLLOUT) {
+            res |= PollEvents::EPOLLOUT;
+        }
+        if pty.slave_closed.load(Ordering::Acquire) {
+            res |= PollEvents::EPOLLHUP;
+        }
+        Ok(res)
+    }
+
+    fn poll_queue(&self) -> Option<&PollQueue> {
+        Some(&self.pty.master_poll_queue)
+    }
+}
+
+/// 打开的伪终端从设备，即 `/dev/pts/<n>`
+pub struct PtySlave {
+    dentry: Arc<dyn VfsDentry>,
+    open_flag: Mutex<OpenFlags>,
+    pty: Arc<Pty>,
+}
+
+impl Debug for PtySlave {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("PtySlave")
+            .field("index", &self.pty.index)
+            .field("open_flag", &self.open_flag)
+            .finish()
+    }
+}
+
+impl PtySlave {
+    /// 打开从设备，从设备被锁定或者主设备已经被关闭时返回 `EIO`
+    pub fn open(dentry: Arc<dyn VfsDentry>, open_flag: OpenFlags) -> AlienResult<Arc<dyn File>> {
+        let device_id = DeviceId::from(dentry.inode()?.get_attr()?.st_rdev);
+        let pty = PTYS
+            .lock()
+            .values()
+            .filter_map(Weak::upgrade)
+            .find(|pty| pty.slave_device_id == device_id)
+            .ok_or(LinuxErrno::EIO)?;
+        if pty.locked.load(Ordering::Acquire) || pty.tty.is_hung_up() {
+            return Err(LinuxErrno::EIO);
+        }
+        pty.slave_opens.fetch_add(1, Ordering::AcqRel);
+        pty.slave_closed.store(false, Ordering::Release);
+        pty.tty.open(open_flag.contains(OpenFlags::O_NOCTTY));
+        Ok(Arc::new(Self {
+            dentry,
+            open_flag: Mutex::new(open_flag),
+            pty,
+        }))
+    }
+}
+
+impl Drop for PtySlave {
+    fn drop(&mut self) {
+        let pty = &self.pty;
+        if pty.slave_opens.fetch_sub(1, Ordering::AcqRel) == 1 {
+            pty.slave_closed.store(true, Ordering::Release);
+            pty.notify_master(PollEvents::EPOLLHUP);
+        }
+    }
+}
+
+impl File for PtySlave {
+    fn read(&self, buf: &mut [u8]) -> AlienResult<usize> {
+        self.pty.tty.read(buf).map_err(Into::into)
+    }
+
+    fn write(&self, buf: &[u8]) -> AlienResult<usize> {
+        let nonblock = self.open_flag.lock().contains(OpenFlags::O_NONBLOCK);
+        self.pty.tty.write(buf, nonblock).map_err(Into::into)
+    }
+
+    fn seek(&self, _pos: SeekFrom) -> AlienResult<u64> {
+        Err(LinuxErrno::ESPIPE)
+    }
+
+    fn get_attr(&self) -> AlienResult<VfsFileStat> {
+        let inode = self.dentry.inode()?;
+        let mut attr = inode.get_attr()?;
+        apply_inode_owner(&inode, &mut attr);
+        Ok(attr)
+    }
+
+    fn ioctl(&self, cmd: u32, arg: usize) -> AlienResult<usize> {
+        self.pty.tty.ioctl(cmd, arg).map_err(Into::into)
+    }
+
+    fn set_open_flag(&self, flag: OpenFlags) {
+        *self.open_flag.lock() = flag;
+    }
+
+    fn get_open_flag(&self) -> OpenFlags {
+        *self.open_flag.lock()
+    }
+
+    fn dentry(&self) -> Arc<dyn VfsDentry> {
+        self.dentry.clone()
+    }
+
+    fn inode(&self) -> Arc<dyn VfsInode> {
+        self.dentry.inode().unwrap()
+    }
+
+    fn is_readable(&self) -> bool {
+        let open_flag = self.open_flag.lock();
+        open_flag.contains(OpenFlags::O_RDONLY) | open_flag.contains(OpenFlags::O_RDWR)
+    }
+
+    fn is_writable(&self) -> bool {
+        let open_flag = self.open_flag.lock();
+        open_flag.contains(OpenFlags::O_WRONLY) | open_flag.contains(OpenFlags::O_RDWR)
+    }
+
+    fn is_append(&self) -> bool {
+        false
+    }
+
+    fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        let res = self
+            .pty
+            .tty
+            .poll(VfsPollEvents::from_bits_truncate(event.bits() as u16))?;
+        Ok(PollEvents::from_bits_truncate(res.bits() as u32))
+    }
+
+    fn poll_queue(&self) -> Option<&PollQueue> {
+        Some(self.pty.tty.poll_queue())
+    }
+}
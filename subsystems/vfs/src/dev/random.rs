@@ -1,7 +1,14 @@
 use alloc::sync::Arc;
+use core::fmt::{Debug, Formatter};
 
-use arch::read_timer;
+use constants::{
+    io::{OpenFlags, PollEvents, SeekFrom},
+    AlienResult, LinuxErrno,
+};
+use krandom::{add_device_randomness, crng_ready, get_random_bytes, wait_for_random_bytes};
+use ksync::Mutex;
 use vfscore::{
+    dentry::VfsDentry,
     error::VfsError,
     file::VfsFile,
     inode::{InodeAttr, VfsInode},
@@ -10,7 +17,12 @@ use vfscore::{
     VfsResult,
 };
 
-use crate::dev::DeviceId;
+use crate::{dev::DeviceId, kfile::File, perm::apply_inode_owner};
+
+/// `/dev/random` 与 `/dev/urandom` 设备，读取内核 CRNG 产生的随机数
+///
+/// 写入的数据会被加入熵池，但不计入熵。`/dev/random` 在 CRNG 初始化完成之前需要等待，
+/// 每次打开都会创建一个 [`RandomFile`]，而 `/dev/urandom` 的读取总是直接返回。
 pub struct RandomDevice {
     device_id: DeviceId,
 }
@@ -25,14 +37,11 @@ impl RandomDevice {
 
 impl VfsFile for RandomDevice {
     fn read_at(&self, _offset: u64, buf: &mut [u8]) -> VfsResult<usize> {
-        let mut current_time = read_timer();
-        buf.iter_mut().for_each(|x| {
-            *x = current_time as u8;
-            current_time = current_time.wrapping_sub(1);
-        });
+        get_random_bytes(buf);
         Ok(buf.len())
     }
     fn write_at(&self, _offset: u64, buf: &[u8]) -> VfsResult<usize> {
+        add_device_randomness(buf);
         Ok(buf.len())
     }
 }
@@ -61,3 +70,96 @@ impl VfsInode for RandomDevice {
         VfsNodeType::CharDevice
     }
 }
+
+/// 打开的 `/dev/random` 文件，CRNG 初始化完成之前读取会等待，设置了 `O_NONBLOCK` 时返回 `EAGAIN`
+pub struct RandomFile {
+    dentry: Arc<dyn VfsDentry>,
+    open_flag: Mutex<OpenFlags>,
+}
+
+impl Debug for RandomFile {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("RandomFile")
+            .field("open_flag", &self.open_flag)
+            .finish()
+    }
+}
+
+impl RandomFile {
+    pub fn open(dentry: Arc<dyn VfsDentry>, open_flag: OpenFlags) -> AlienResult<Arc<dyn File>> {
+        Ok(Arc::new(Self {
+            dentry,
+            open_flag: Mutex::new(open_flag),
+        }))
+    }
+}
+
+impl File for RandomFile {
+    fn read(&self, buf: &mut [u8]) -> AlienResult<usize> {
+        if !crng_ready() {
+            if self.open_flag.lock().contains(OpenFlags::O_NONBLOCK) {
+                return Err(LinuxErrno::EAGAIN);
+            }
+            wait_for_random_bytes()?;
+        }
+        get_random_bytes(buf);
+        Ok(buf.len())
+    }
+
+    fn write(&self, buf: &[u8]) -> AlienResult<usize> {
+        add_device_randomness(buf);
+        Ok(buf.len())
+    }
+
+    fn seek(&self, _pos: SeekFrom) -> AlienResult<u64> {
+        Ok(0)
+    }
+
+    fn get_attr(&self) -> AlienResult<VfsFileStat> {
+        let inode = self.dentry.inode()?;
+        let mut attr = inode.get_attr()?;
+        apply_inode_owner(&inode, &mut attr);
+        Ok(attr)
+    }
+
+    fn set_open_flag(&self, flag: OpenFlags) {
+        *self.open_flag.lock() = flag;
+    }
+
+    fn get_open_flag(&self) -> OpenFlags {
+        *self.open_flag.lock()
+    }
+
+    fn dentry(&self) -> Arc<dyn VfsDentry> {
+        self.dentry.clone()
+    }
+
+    fn inode(&self) -> Arc<dyn VfsInode> {
+        self.dentry.inode().unwrap()
+    }
+
+    fn is_readable(&self) -> bool {
+        let open_flag = self.open_flag.lock();
+        open_flag.contains(OpenFlags::O_RDONLY) | open_flag.contains(OpenFlags::O_RDWR)
+    }
+
+    fn is_writable(&self) -> bool {
+        let open_flag = self.open_flag.lock();
+        open_flag.contains(OpenFlags::O_WRONLY) | open_flag.contains(OpenFlags::O_RDWR)
+    }
+
+    fn is_append(&self) -> bool {
+        false
+    }
+
+    fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        let mut res = PollEvents::empty();
+        if event.contains(PollEvents::EPOLLIN) && crng_ready() {
+            res |= PollEvents::EPOLLIN;
+        }
+        if event.contains(PollEvents::EPOLLOUT) {
+            res |= PollEvents::EPOLLOUT;
+        }
+        Ok(res)
+    }
+}
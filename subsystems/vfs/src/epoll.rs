@@ -1,57 +1,403 @@
-use alloc::{collections::BTreeMap, sync::Arc};
+//! epoll 实例
+//!
+//! 每个被监听的文件对应一个 [`EpollItem`]。支持通知的文件(管道、套接字、eventfd、timerfd、tty 以及 epoll 本身)
+//! 在状态变化时通过 [`PollQueue`] 通知监听项，监听项将自己加入 epoll 的就绪链表并唤醒等待的任务，
+//! 因此 `epoll_wait` 只需要检查就绪链表中的文件。不支持通知的文件(如普通文件)每次等待时都需要轮询。
+//!
+//! 水平触发的监听项在报告事件后会被重新放回就绪链表，下一次等待时再次检查；边沿触发(`EPOLLET`)的监听项
+//! 只有在下一次收到通知时才会再次被检查；`EPOLLONESHOT` 的监听项报告一次事件后被禁用，直到 `EPOLL_CTL_MOD`。
+use alloc::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Weak},
+    vec::Vec,
+};
+use core::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 
 use constants::{
     epoll::{EpollCtlOp, EpollEvent},
-    io::{OpenFlags, SeekFrom},
-    AlienError, AlienResult,
+    io::{OpenFlags, PollEvents, SeekFrom},
+    AlienError, AlienResult, LinuxErrno,
+};
+use ksync::{
+    poll::{PollQueue, PollWaiter},
+    wait::WaitQueue,
+    Mutex,
 };
-use ksync::{Mutex, MutexGuard};
 use vfscore::{dentry::VfsDentry, inode::VfsInode, utils::VfsFileStat};
 
 use crate::kfile::File;
 
-#[derive(Debug)]
+/// 多个 epoll 实例等待同一个文件时，只唤醒其中一个
+pub const EPOLLEXCLUSIVE: u32 = 1 << 28;
+/// 阻止系统进入休眠，Alien 中忽略
+pub const EPOLLWAKEUP: u32 = 1 << 29;
+/// 报告一次事件后禁用监听项
+pub const EPOLLONESHOT: u32 = 1 << 30;
+/// 边沿触发
+pub const EPOLLET: u32 = 1 << 31;
+/// 控制监听项行为的标志位，它们不是文件的事件
+const EPOLL_CTL_FLAGS: u32 = EPOLLEXCLUSIVE | EPOLLWAKEUP | EPOLLONESHOT | EPOLLET;
+/// `EPOLLEXCLUSIVE` 只能与这些事件一起使用
+const EPOLL_EXCLUSIVE_OK: u32 = PollEvents::EPOLLIN.bits()
+    | PollEvents::EPOLLOUT.bits()
+    | PollEvents::EPOLLERR.bits()
+    | PollEvents::EPOLLHUP.bits()
+    | EPOLLWAKEUP
+    | EPOLLET
+    | EPOLLEXCLUSIVE;
+/// epoll 实例之间最多的嵌套层数
+const EP_MAX_NESTS: usize = 4;
+
+/// epoll 中的一个监听项
+struct EpollItem {
+    fd: usize,
+    file: Weak<dyn File>,
+    /// 监听的事件以及控制标志位，`EPOLLONESHOT` 的监听项被禁用后只剩下控制标志位
+    events: AtomicU32,
+    /// 用户数据
+    event: Mutex<EpollEvent>,
+    /// 文件是否会通知状态的变化，不会通知的文件每次等待时都需要轮询
+    notified: bool,
+    /// 是否在就绪链表中
+    queued: AtomicBool,
+    /// 是否已经从 epoll 中移除
+    removed: AtomicBool,
+    /// 轮询的文件上一次检查到的事件，用于模拟边沿触发
+    last_events: AtomicU32,
+    epoll: Weak<EventPoll>,
+    me: Weak<EpollItem>,
+}
+
+impl EpollItem {
+    fn interest(&self) -> u32 {
+        self.events.load(Ordering::Acquire) & !EPOLL_CTL_FLAGS
+    }
+
+    fn file_poll(&self, file: &Arc<dyn File>, interest: u32) -> u32 {
+        file.poll(PollEvents::from_bits_truncate(interest))
+            .map(|events| events.bits() & interest)
+            .unwrap_or(PollEvents::EPOLLERR.bits() & interest)
+    }
+
+    /// 检查文件是否有需要报告的事件，不改变监听项的状态
+    fn peek(&self) -> bool {
+        let interest = self.interest();
+        if interest == 0 || self.removed.load(Ordering::Acquire) {
+            return false;
+        }
+        self.file
+            .upgrade()
+            .map(|file| self.file_poll(&file, interest) != 0)
+            .unwrap_or(false)
+    }
+
+    /// 检查文件的事件，返回需要报告给用户的事件以及监听项是否需要重新放回就绪链表
+    fn check(&self, file: &Arc<dyn File>) -> Option<(EpollEvent, bool)> {
+        let flags = self.events.load(Ordering::Acquire);
+        let interest = flags & !EPOLL_CTL_FLAGS;
+        if interest == 0 {
+            return None;
+        }
+        let mut revents = self.file_poll(file, interest);
+        if !self.notified && flags & EPOLLET != 0 {
+            let last = self.last_events.swap(revents, Ordering::AcqRel);
+            revents &= !last;
+        }
+        if revents == 0 {
+            return None;
+        }
+        if flags & EPOLLONESHOT != 0 {
+            self.events
+                .store(flags & EPOLL_CTL_FLAGS, Ordering::Release);
+        }
+        let mut event = *self.event.lock();
+        event.events = PollEvents::from_bits_truncate(revents);
+        let requeue = self.notified && flags & (EPOLLET | EPOLLONESHOT) == 0;
+        Some((event, requeue))
+    }
+}
+
+impl PollWaiter for EpollItem {
+    fn notify(&self, events: PollEvents) -> bool {
+        if self.removed.load(Ordering::Acquire) {
+            return false;
+        }
+        let interest = self.interest();
+        if interest == 0 || (!events.is_empty() && events.bits() & interest == 0) {
+            return false;
+        }
+        match (self.epoll.upgrade(), self.me.upgrade()) {
+            (Some(ep), Some(item)) => {
+                ep.queue(item);
+                ep.wake_up();
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn exclusive(&self) -> bool {
+        self.events.load(Ordering::Acquire) & EPOLLEXCLUSIVE != 0
+    }
+}
+
+/// epoll 实例的状态，被 [`EpollFile`] 以及其中的监听项共享
+struct EventPoll {
+    items: Mutex<BTreeMap<usize, Arc<EpollItem>>>,
+    /// 收到通知、需要检查的监听项
+    ready: Mutex<VecDeque<Arc<EpollItem>>>,
+    /// 文件不支持通知，需要轮询的监听项
+    polled: Mutex<Vec<Arc<EpollItem>>>,
+    /// 在 `epoll_wait` 中等待的任务
+    wait_queue: WaitQueue,
+    /// epoll 实例自己也可以被 poll 或者被其它 epoll 实例监听
+    poll_queue: PollQueue,
+}
+
+impl EventPoll {
+    fn new() -> Self {
+        Self {
+            items: Mutex::new(BTreeMap::new()),
+            ready: Mutex::new(VecDeque::new()),
+            polled: Mutex::new(Vec::new()),
+            wait_queue: WaitQueue::new(),
+            poll_queue: PollQueue::new(),
+        }
+    }
+
+    fn queue(&self, item: Arc<EpollItem>) {
+        if !item.queued.swap(true, Ordering::AcqRel) {
+            self.ready.lock().push_back(item);
+        }
+    }
+
+    /// 唤醒所有等待的任务，并通知监听了该 epoll 实例的文件
+    fn wake_up(&self) {
+        self.wait_queue.wake_all();
+        self.poll_queue.notify(PollEvents::EPOLLIN);
+    }
+
+    fn remove_item(&self, item: &Arc<EpollItem>) {
+        item.removed.store(true, Ordering::Release);
+        {
+            let mut items = self.items.lock();
+            if items.get(&item.fd).is_some_and(|i| Arc::ptr_eq(i, item)) {
+                items.remove(&item.fd);
+            }
+        }
+        if item.notified {
+            if let Some(file) = item.file.upgrade() {
+                if let Some(queue) = file.poll_queue() {
+                    queue.unregister(&(item.clone() as Arc<dyn PollWaiter>));
+                }
+            }
+        } else {
+            self.polled.lock().retain(|i| !Arc::ptr_eq(i, item));
+        }
+    }
+
+    /// 检查从当前实例出发是否可以到达 `target`，或者嵌套的层数超过了 [`EP_MAX_NESTS`]
+    fn loop_check(&self, target: &EventPoll, depth: usize) -> AlienResult<()> {
+        if core::ptr::eq(self, target) || depth >= EP_MAX_NESTS {
+            return Err(LinuxErrno::ELOOP);
+        }
+        let files = self
+            .items
+            .lock()
+            .values()
+            .filter_map(|item| item.file.upgrade())
+            .collect::<Vec<_>>();
+        for file in files {
+            if let Some(epoll) = file.downcast_ref::<EpollFile>() {
+                epoll.ep.loop_check(target, depth + 1)?;
+            }
+        }
+        Ok(())
+    }
+}
+
 pub struct EpollFile {
     #[allow(unused)]
     flags: OpenFlags,
-    interest: Mutex<BTreeMap<usize, EpollEvent>>,
+    ep: Arc<EventPoll>,
+}
+
+impl core::fmt::Debug for EpollFile {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_struct("EpollFile")
+            .field("flags", &self.flags)
+            .field("items", &self.ep.items.lock().len())
+            .finish()
+    }
 }
 
 impl EpollFile {
     pub fn new(flags: OpenFlags) -> Self {
         EpollFile {
             flags,
-            interest: Mutex::new(BTreeMap::new()),
+            ep: Arc::new(EventPoll::new()),
         }
     }
-    pub fn ctl(&self, op: EpollCtlOp, fd: usize, events: EpollEvent) -> AlienResult<()> {
+
+    /// 添加、修改或者删除文件描述符 `fd` 对应的监听项，`file` 为 `fd` 当前指向的文件
+    pub fn ctl(
+        &self,
+        op: EpollCtlOp,
+        fd: usize,
+        file: Arc<dyn File>,
+        mut event: EpollEvent,
+    ) -> AlienResult<()> {
+        let target_epoll = file.downcast_ref::<EpollFile>();
+        if let Some(target) = target_epoll {
+            if Arc::ptr_eq(&target.ep, &self.ep) {
+                return Err(LinuxErrno::EINVAL);
+            }
+        }
+        let mut flags = event.events.bits();
+        if flags & EPOLLEXCLUSIVE != 0
+            && (matches!(op, EpollCtlOp::EpollCtlMod)
+                || flags & !EPOLL_EXCLUSIVE_OK != 0
+                || target_epoll.is_some())
+        {
+            return Err(LinuxErrno::EINVAL);
+        }
+        // 错误和挂起事件总是会被报告
+        flags |= PollEvents::EPOLLERR.bits() | PollEvents::EPOLLHUP.bits();
+        let existing = self.ep.items.lock().get(&fd).cloned().filter(|item| {
+            // 文件描述符被关闭后重新分配给了其它文件，旧的监听项已经失效
+            item.file.upgrade().is_some_and(|f| Arc::ptr_eq(&f, &file))
+        });
         match op {
             EpollCtlOp::EpollCtlAdd => {
-                self.interest.lock().insert(fd, events);
-                Ok(())
+                if existing.is_some() {
+                    return Err(LinuxErrno::EEXIST);
+                }
+                if let Some(target) = target_epoll {
+                    target.ep.loop_check(&self.ep, 1)?;
+                }
+                let stale = self.ep.items.lock().get(&fd).cloned();
+                if let Some(stale) = stale {
+                    self.ep.remove_item(&stale);
+                }
+                let notified = file.poll_queue().is_some();
+                event.events = PollEvents::empty();
+                let item = Arc::new_cyclic(|me| EpollItem {
+                    fd,
+                    file: Arc::downgrade(&file),
+                    events: AtomicU32::new(flags),
+                    event: Mutex::new(event),
+                    notified,
+                    queued: AtomicBool::new(false),
+                    removed: AtomicBool::new(false),
+                    last_events: AtomicU32::new(0),
+                    epoll: Arc::downgrade(&self.ep),
+                    me: me.clone(),
+                });
+                self.ep.items.lock().insert(fd, item.clone());
+                match file.poll_queue() {
+                    Some(queue) => {
+                        queue.register(&(item.clone() as Arc<dyn PollWaiter>));
+                        // 文件可能已经就绪，先检查一次
+                        self.ep.queue(item);
+                    }
+                    None => self.ep.polled.lock().push(item),
+                }
+                self.ep.wake_up();
             }
             EpollCtlOp::EpollCtlDel => {
-                self.interest.lock().remove(&fd);
-                Ok(())
+                let item = existing.ok_or(LinuxErrno::ENOENT)?;
+                self.ep.remove_item(&item);
             }
             EpollCtlOp::EpollCtlMod => {
-                self.interest.lock().insert(fd, events);
-                Ok(())
+                let item = existing.ok_or(LinuxErrno::ENOENT)?;
+                if item.events.load(Ordering::Acquire) & EPOLLEXCLUSIVE != 0 {
+                    return Err(LinuxErrno::EINVAL);
+                }
+                event.events = PollEvents::empty();
+                *item.event.lock() = event;
+                item.last_events.store(0, Ordering::Release);
+                item.events.store(flags, Ordering::Release);
+                if item.notified {
+                    self.ep.queue(item);
+                }
+                self.ep.wake_up();
+            }
+        }
+        Ok(())
+    }
+
+    /// 收集最多 `max` 个就绪的事件，不会阻塞
+    pub fn collect(&self, max: usize) -> Vec<EpollEvent> {
+        let mut res = Vec::new();
+        let mut requeue = Vec::new();
+        let mut closed = Vec::new();
+        while res.len() < max {
+            let Some(item) = self.ep.ready.lock().pop_front() else {
+                break;
+            };
+            item.queued.store(false, Ordering::Release);
+            if item.removed.load(Ordering::Acquire) {
+                continue;
+            }
+            let Some(file) = item.file.upgrade() else {
+                closed.push(item);
+                continue;
+            };
+            if let Some((event, again)) = item.check(&file) {
+                res.push(event);
+                if again {
+                    requeue.push(item);
+                }
+            }
+        }
+        let polled = self.ep.polled.lock().clone();
+        for item in polled {
+            if res.len() >= max {
+                break;
+            }
+            match item.file.upgrade() {
+                Some(file) => {
+                    if let Some((event, _)) = item.check(&file) {
+                        res.push(event);
+                    }
+                }
+                None => closed.push(item),
             }
         }
+        // 文件被关闭后，监听项自动从 epoll 中移除
+        closed.iter().for_each(|item| self.ep.remove_item(item));
+        // 水平触发的监听项放回就绪链表，下次等待时再次检查
+        requeue.into_iter().for_each(|item| self.ep.queue(item));
+        res
     }
-    pub fn interest(&self) -> MutexGuard<BTreeMap<usize, EpollEvent>> {
-        self.interest.lock()
+
+    /// 就绪链表是否为空
+    pub fn ready_is_empty(&self) -> bool {
+        self.ep.ready.lock().is_empty()
+    }
+
+    /// 是否有需要轮询的监听项
+    pub fn has_polled(&self) -> bool {
+        !self.ep.polled.lock().is_empty()
+    }
+
+    /// 等待直到就绪链表不为空，监听项收到通知时唤醒等待的任务
+    ///
+    /// cpu 时钟到达 `deadline` 时返回 `ETIMEDOUT`，收到信号时返回 `EINTR`。
+    pub fn wait(&self, deadline: Option<usize>) -> AlienResult<()> {
+        self.ep
+            .wait_queue
+            .wait_event_interruptible_timeout(|| !self.ready_is_empty(), deadline)
     }
 }
 
 impl File for EpollFile {
     fn read(&self, _buf: &mut [u8]) -> AlienResult<usize> {
-        todo!()
+        Err(AlienError::EINVAL)
     }
 
     fn write(&self, _buf: &[u8]) -> AlienResult<usize> {
-        todo!()
+        Err(AlienError::EINVAL)
     }
 
     fn seek(&self, _pos: SeekFrom) -> AlienResult<u64> {
@@ -59,7 +405,7 @@ impl File for EpollFile {
     }
 
     fn get_attr(&self) -> AlienResult<VfsFileStat> {
-        todo!()
+        Err(AlienError::ENOSYS)
     }
 
     fn dentry(&self) -> Arc<dyn VfsDentry> {
@@ -81,4 +427,20 @@ impl File for EpollFile {
     fn is_append(&self) -> bool {
         true
     }
+
+    fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        if !event.contains(PollEvents::EPOLLIN) {
+            return Ok(PollEvents::empty());
+        }
+        let ready = self.ep.ready.lock().iter().cloned().collect::<Vec<_>>();
+        let polled = self.ep.polled.lock().clone();
+        if ready.iter().chain(polled.iter()).any(|item| item.peek()) {
+            return Ok(PollEvents::EPOLLIN);
+        }
+        Ok(PollEvents::empty())
+    }
+
+    fn poll_queue(&self) -> Option<&PollQueue> {
+        Some(&self.ep.poll_queue)
+    }
 }
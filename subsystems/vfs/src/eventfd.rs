@@ -1,4 +1,4 @@
-use alloc::{collections::VecDeque, sync::Arc};
+use alloc::sync::Arc;
 use core::{fmt::Debug, sync::atomic::AtomicU32};
 
 use constants::{
@@ -6,8 +6,7 @@ use constants::{
     io::{PollEvents, SeekFrom},
     AlienError, AlienResult,
 };
-use ksync::Mutex;
-use shim::KTask;
+use ksync::{poll::PollQueue, wait::WaitQueue, Mutex};
 use vfscore::{dentry::VfsDentry, inode::VfsInode, utils::VfsFileStat};
 
 use crate::kfile::File;
@@ -30,7 +29,8 @@ impl EventFd {
 
 pub struct EventFdInode {
     eventfd: Mutex<EventFd>,
-    wait_queue: Mutex<VecDeque<Arc<dyn KTask>>>,
+    wait_queue: WaitQueue,
+    poll_queue: PollQueue,
 }
 
 impl Debug for EventFdInode {
@@ -45,7 +45,8 @@ impl EventFdInode {
     pub fn new(eventfd: EventFd) -> Self {
         EventFdInode {
             eventfd: Mutex::new(eventfd),
-            wait_queue: Mutex::new(VecDeque::new()),
+            wait_queue: WaitQueue::new(),
+            poll_queue: PollQueue::new(),
         }
     }
 }
@@ -55,31 +56,24 @@ impl File for EventFdInode {
         if buf.len() < 8 {
             return Err(AlienError::EINVAL);
         }
-        let mut val = loop {
-            let val = self.eventfd.lock().count;
-            if val != 0 {
-                break val;
+        let val = loop {
+            let mut eventfd = self.eventfd.lock();
+            if eventfd.count != 0 {
+                if eventfd.flags.contains(EventFdFlags::EFD_SEMAPHORE) {
+                    eventfd.count -= 1;
+                    break 1;
+                }
+                break core::mem::take(&mut eventfd.count);
             }
-            if self
-                .eventfd
-                .lock()
-                .flags
-                .contains(EventFdFlags::EFD_NONBLOCK)
-            {
+            if eventfd.flags.contains(EventFdFlags::EFD_NONBLOCK) {
                 return Err(AlienError::EAGAIN);
             }
-            let task = shim::take_current_task().unwrap();
-            task.to_wait();
-            self.wait_queue.lock().push_back(task.clone());
-            shim::schedule_now(task); // yield current task
+            drop(eventfd);
+            self.wait_queue
+                .wait_event_interruptible(|| self.eventfd.lock().count != 0)?;
         };
-        let mut eventfd = self.eventfd.lock();
-        if eventfd.flags.contains(EventFdFlags::EFD_SEMAPHORE) {
-            eventfd.count -= 1;
-            val = 1;
-        } else {
-            eventfd.count = 0;
-        }
+        self.wait_queue.wake_all();
+        self.poll_queue.notify(PollEvents::EPOLLOUT);
         let val_bytes = val.to_ne_bytes();
         buf[..8].copy_from_slice(&val_bytes);
         return Ok(8);
@@ -93,8 +87,9 @@ impl File for EventFdInode {
             return Err(AlienError::EINVAL);
         }
         loop {
-            let eventfd = self.eventfd.lock();
+            let mut eventfd = self.eventfd.lock();
             if u64::MAX - eventfd.count > val {
+                eventfd.count += val;
                 break;
             }
             // block until a read() is performed  on the
@@ -104,18 +99,11 @@ impl File for EventFdInode {
                 return Err(AlienError::EAGAIN);
             }
             drop(eventfd);
-            // self.wait_queue.sleep();
-            let task = shim::take_current_task().unwrap();
-            task.to_wait();
-            self.wait_queue.lock().push_back(task.clone());
-            shim::schedule_now(task); // yield current task
-        }
-        let mut eventfd = self.eventfd.lock();
-        eventfd.count += val;
-        while let Some(task) = self.wait_queue.lock().pop_front() {
-            task.to_wakeup();
-            shim::put_task(task);
+            self.wait_queue
+                .wait_event_interruptible(|| u64::MAX - self.eventfd.lock().count > val)?;
         }
+        self.wait_queue.wake_all();
+        self.poll_queue.notify(PollEvents::EPOLLIN);
         return Ok(8);
     }
     fn read_at(&self, _offset: u64, buf: &mut [u8]) -> AlienResult<usize> {
@@ -163,6 +151,10 @@ impl File for EventFdInode {
         }
         return Ok(events);
     }
+
+    fn poll_queue(&self) -> Option<&PollQueue> {
+        Some(&self.poll_queue)
+    }
 }
 
 pub fn eventfd(init_val: u32, flags: u32) -> AlienResult<Arc<dyn File>> {
@@ -6,7 +6,7 @@ use constants::{
     AlienResult, LinuxErrno,
 };
 use downcast_rs::{impl_downcast, DowncastSync};
-use ksync::Mutex;
+use ksync::{poll::PollQueue, Mutex};
 use vfscore::{
     dentry::VfsDentry,
     error::VfsError,
@@ -15,7 +15,11 @@ use vfscore::{
     utils::{VfsFileStat, VfsNodeType, VfsPollEvents},
 };
 
-use crate::system_root_fs;
+use crate::{
+    page_cache::{file_page_cache, find_page_cache},
+    perm::apply_inode_owner,
+    system_root_fs,
+};
 
 pub struct KernelFile {
     pos: Mutex<u64>,
@@ -87,9 +91,22 @@ pub trait File: DowncastSync + Debug {
     fn poll(&self, _event: PollEvents) -> AlienResult<PollEvents> {
         panic!("poll is not implemented for :{:?}", self)
     }
+    /// 文件状态发生变化时会通知的队列，返回 `None` 的文件只能通过轮询检查状态
+    fn poll_queue(&self) -> Option<&PollQueue> {
+        None
+    }
     fn mmap(&self, _addr: usize, _len: usize, _offset: usize) -> AlienResult<()> {
         Err(LinuxErrno::ENOSYS)
     }
+    /// 文件内容为连续的设备内存时，返回 `offset` 处的物理地址，`MAP_SHARED` 映射会直接映射这段内存
+    ///
+    /// 普通文件返回 `ENODEV`，映射范围超出设备内存时返回 `EINVAL`。
+    /// 每次成功的调用都表示建立了一个映射，映射解除时会调用 [`File::munmap_phys`]。
+    fn mmap_phys(&self, _offset: usize, _len: usize) -> AlienResult<usize> {
+        Err(LinuxErrno::ENODEV)
+    }
+    /// 通过 [`File::mmap_phys`] 建立的映射被解除
+    fn munmap_phys(&self, _offset: usize, _len: usize) {}
 }
 
 impl_downcast!(sync  File);
@@ -124,6 +141,9 @@ impl File for KernelFile {
         }
         drop(open_flag);
         let inode = self.dentry.inode()?;
+        if let Some(cache) = file_page_cache(&inode) {
+            return cache.read(offset, buf);
+        }
         let read = inode.read_at(offset, buf)?;
         Ok(read)
     }
@@ -137,6 +157,9 @@ impl File for KernelFile {
             return Err(LinuxErrno::EPERM);
         }
         let inode = self.dentry.inode()?;
+        if let Some(cache) = file_page_cache(&inode) {
+            return cache.write(offset, buf);
+        }
         let write = inode.write_at(offset, buf)?;
         Ok(write)
     }
@@ -177,7 +200,10 @@ impl File for KernelFile {
 
     /// Gets the file attributes.
     fn get_attr(&self) -> AlienResult<VfsFileStat> {
-        self.dentry.inode()?.get_attr().map_err(Into::into)
+        let inode = self.dentry.inode()?;
+        let mut attr = inode.get_attr()?;
+        apply_inode_owner(&inode, &mut attr);
+        Ok(attr)
     }
 
     fn ioctl(&self, _cmd: u32, _arg: usize) -> AlienResult<usize> {
@@ -242,9 +268,11 @@ impl File for KernelFile {
             return Err(LinuxErrno::EINVAL);
         }
         let dt = self.dentry();
-        VfsPath::new(system_root_fs(), dt)
-            .truncate(len)
-            .map_err(Into::into)
+        VfsPath::new(system_root_fs(), dt).truncate(len)?;
+        if let Some(cache) = find_page_cache(&self.inode()) {
+            cache.truncate(len as usize);
+        }
+        Ok(())
     }
     fn is_readable(&self) -> bool {
         let open_flag = self.open_flag.lock();
@@ -267,6 +295,10 @@ impl File for KernelFile {
             .map(|e| PollEvents::from_bits_truncate(e.bits() as u32));
         res.map_err(Into::into)
     }
+
+    fn poll_queue(&self) -> Option<&PollQueue> {
+        crate::dev::device_poll_queue(&self.dentry)
+    }
 }
 
 fn vfsnodetype2dirent64(ty: VfsNodeType) -> DirentType {
@@ -6,18 +6,24 @@ extern crate alloc;
 extern crate platform;
 use alloc::{
     collections::BTreeMap,
+    format,
     string::{String, ToString},
     sync::Arc,
+    vec::Vec,
 };
 use core::ops::Index;
 
-use constants::AlienResult;
+use constants::{AlienResult, LinuxErrno};
 use dynfs::DynFsKernelProvider;
 use ksync::Mutex;
 use spin::{Lazy, Once};
-#[cfg(feature = "ext")]
-use vfscore::inode::VfsInode;
-use vfscore::{dentry::VfsDentry, fstype::VfsFsType, path::VfsPath, utils::VfsTimeSpec};
+use vfscore::{
+    dentry::VfsDentry,
+    fstype::VfsFsType,
+    inode::VfsInode,
+    path::VfsPath,
+    utils::{VfsNodeType, VfsTimeSpec},
+};
 
 use crate::dev::DevFsProviderImpl;
 pub mod dev;
@@ -27,6 +33,8 @@ pub mod eventfd;
 mod extffi;
 mod initrd;
 pub mod kfile;
+pub mod page_cache;
+pub mod perm;
 pub mod pipefs;
 pub mod proc;
 pub mod ram;
@@ -38,6 +46,12 @@ pub static FS: Lazy<Mutex<BTreeMap<String, Arc<dyn VfsFsType>>>> =
 
 static SYSTEM_ROOT_FS: Once<Arc<dyn VfsDentry>> = Once::new();
 
+/// 挂载在块设备上的文件系统的根目录，`sync` 时需要同步这些文件系统
+static DISK_FS: Mutex<Vec<Arc<dyn VfsDentry>>> = Mutex::new(Vec::new());
+
+/// 只有一个实例的文件系统的根目录，以文件系统类型为键。这些文件系统的内容在初始化时创建，再次挂载时看到的是同一个实例
+static SHARED_FS: Mutex<BTreeMap<&'static str, Arc<dyn VfsDentry>>> = Mutex::new(BTreeMap::new());
+
 type SysFs = dynfs::DynFs<CommonFsProviderImpl, spin::Mutex<()>>;
 type ProcFs = dynfs::DynFs<CommonFsProviderImpl, spin::Mutex<()>>;
 type RamFs = ramfs::RamFs<CommonFsProviderImpl, spin::Mutex<()>>;
@@ -51,6 +65,12 @@ type DiskFs = fat_vfs::FatFs<CommonFsProviderImpl, spin::Mutex<()>>;
 #[cfg(feature = "ext")]
 type DiskFs = lwext4_vfs::ExtFs<CommonFsProviderImpl, spin::Mutex<()>>;
 
+/// 块设备上的文件系统的类型名，`diskfs` 为默认的名字
+#[cfg(feature = "fat")]
+const DISK_FS_TYPES: &[&str] = &["diskfs", "fat32", "vfat"];
+#[cfg(feature = "ext")]
+const DISK_FS_TYPES: &[&str] = &["diskfs", "ext4"];
+
 #[derive(Clone)]
 pub struct CommonFsProviderImpl;
 
@@ -88,6 +108,7 @@ fn register_all_fs() {
     let sysfs = Arc::new(SysFs::new(CommonFsProviderImpl, "sysfs"));
     let ramfs = Arc::new(RamFs::new(CommonFsProviderImpl));
     let devfs = Arc::new(DevFs::new(DevFsProviderImpl));
+    let devpts = Arc::new(DevFs::new(DevFsProviderImpl));
     let tmpfs = Arc::new(TmpFs::new(CommonFsProviderImpl));
     let pipefs = Arc::new(PipeFs::new(CommonFsProviderImpl, "pipefs"));
 
@@ -95,6 +116,7 @@ fn register_all_fs() {
     FS.lock().insert("sysfs".to_string(), sysfs);
     FS.lock().insert("ramfs".to_string(), ramfs);
     FS.lock().insert("devfs".to_string(), devfs);
+    FS.lock().insert("devpts".to_string(), devpts);
     FS.lock().insert("tmpfs".to_string(), tmpfs);
     FS.lock().insert("pipefs".to_string(), pipefs);
 
@@ -106,7 +128,10 @@ fn register_all_fs() {
         CommonFsProviderImpl,
     ));
 
-    FS.lock().insert("diskfs".to_string(), diskfs);
+    for name in DISK_FS_TYPES {
+        FS.lock().insert(name.to_string(), diskfs.clone());
+    }
+    page_cache::enable_page_cache(&diskfs.fs_name());
 
     println!("register fs success");
 }
@@ -114,10 +139,12 @@ fn register_all_fs() {
 /// Init the filesystem
 pub fn init_filesystem() -> AlienResult<()> {
     register_all_fs();
+    page_cache::init_page_cache();
     let ramfs_root = ram::init_ramfs(FS.lock().index("ramfs").clone());
     let procfs = FS.lock().index("procfs").clone();
     let procfs_root = proc::init_procfs(procfs);
     let devfs_root = dev::init_devfs(FS.lock().index("devfs").clone());
+    let devpts_root = dev::init_devpts(FS.lock().index("devpts").clone());
     let sysfs_root = sys::init_sysfs(FS.lock().index("sysfs").clone());
     let tmpfs_root = FS
         .lock()
@@ -127,10 +154,35 @@ pub fn init_filesystem() -> AlienResult<()> {
 
     pipefs::init_pipefs(FS.lock().index("pipefs").clone());
 
-    let path = VfsPath::new(ramfs_root.clone(), ramfs_root.clone());
+    let mut shared = SHARED_FS.lock();
+    shared.insert("procfs", procfs_root.clone());
+    shared.insert("sysfs", sysfs_root.clone());
+    shared.insert("devfs", devfs_root.clone());
+    drop(shared);
+
+    // 内核命令行中的 `root=/dev/xxx rootfstype=xxx` 指定块设备上的文件系统作为根文件系统，否则根文件系统为 ramfs
+    let disk_root = match platform::platform_cmdline_option("root") {
+        Some(source) => {
+            let fs_type = platform::platform_cmdline_option("rootfstype").unwrap_or("diskfs");
+            let dev = open_block_device(&devfs_root, source)?;
+            let root = mount_disk_fs(fs_type, dev, "/")?;
+            let root_inode = root.inode()?;
+            for name in ["proc", "sys", "dev", "tmp"] {
+                if root_inode.lookup(name).is_err() {
+                    root_inode.create(name, VfsNodeType::Dir, "rwxr-xr-x".into(), None)?;
+                }
+            }
+            println!("mount {} ({}) as root", source, fs_type);
+            Some(root)
+        }
+        None => None,
+    };
+    let root = disk_root.clone().unwrap_or_else(|| ramfs_root.clone());
+
+    let path = VfsPath::new(root.clone(), root.clone());
     path.join("proc")?.mount(procfs_root, 0)?;
     path.join("sys")?.mount(sysfs_root, 0)?;
-    path.join("dev")?.mount(devfs_root, 0)?;
+    path.join("dev")?.mount(devfs_root.clone(), 0)?;
     path.join("tmp")?.mount(tmpfs_root.clone(), 0)?;
 
     let shm_ramfs = FS
@@ -139,24 +191,32 @@ pub fn init_filesystem() -> AlienResult<()> {
         .clone()
         .i_mount(0, "/dev/shm", None, &[])?;
     path.join("dev/shm")?.mount(shm_ramfs, 0)?;
+    path.join("dev/pts")?.mount(devpts_root, 0)?;
 
-    let diskfs = FS.lock().index("diskfs").clone();
-    let blk_inode = path
-        .join("/dev/sda")?
-        .open(None)
-        .expect("open /dev/sda failed")
-        .inode()?;
-
-    let diskfs_root = diskfs.i_mount(0, "/tests", Some(blk_inode), &[])?;
-    path.join("tests")?.mount(diskfs_root, 0)?;
+    if disk_root.is_none() {
+        // 第一个没有分区表的磁盘或者第一个分区挂载在 /tests
+        let test_disk = devices::block_devices()
+            .into_iter()
+            .find(|entry| entry.partitions == 0);
+        match test_disk {
+            Some(entry) => {
+                let dev = open_block_device(&devfs_root, &format!("/dev/{}", entry.name))?;
+                let diskfs_root = mount_disk_fs("diskfs", dev, "/tests")?;
+                path.join("tests")?.mount(diskfs_root, 0)?;
+                println!("mount /dev/{} on /tests", entry.name);
+            }
+            None => println!("There is no block device, skip mounting /tests"),
+        }
+    }
     println!("mount fs success");
 
-    vfscore::path::print_fs_tree(&mut VfsOutPut, ramfs_root.clone(), "".to_string(), false)
-        .unwrap();
+    vfscore::path::print_fs_tree(&mut VfsOutPut, root.clone(), "".to_string(), false).unwrap();
 
-    initrd::populate_initrd(ramfs_root.clone())?;
+    if disk_root.is_none() {
+        initrd::populate_initrd(ramfs_root.clone())?;
+    }
 
-    SYSTEM_ROOT_FS.call_once(|| ramfs_root);
+    SYSTEM_ROOT_FS.call_once(|| root);
     println!("Init filesystem success");
     Ok(())
 }
@@ -186,3 +246,113 @@ pub fn system_support_fs(fs_name: &str) -> Option<Arc<dyn VfsFsType>> {
         }
     })
 }
+
+/// 打开 devfs 中的块设备，`source` 为 `/dev/vda1` 形式的路径
+fn open_block_device(
+    devfs_root: &Arc<dyn VfsDentry>,
+    source: &str,
+) -> AlienResult<Arc<dyn VfsInode>> {
+    let name = source.strip_prefix("/dev/").ok_or(LinuxErrno::ENOTBLK)?;
+    let path = VfsPath::new(devfs_root.clone(), devfs_root.clone());
+    let inode = path.join(name)?.open(None)?.inode()?;
+    if inode.inode_type() != VfsNodeType::BlockDevice {
+        return Err(LinuxErrno::ENOTBLK);
+    }
+    Ok(inode)
+}
+
+/// 在块设备 `dev` 上创建一个 `fs_type` 类型的文件系统，返回文件系统的根目录
+fn mount_disk_fs(
+    fs_type: &str,
+    dev: Arc<dyn VfsInode>,
+    dir: &str,
+) -> AlienResult<Arc<dyn VfsDentry>> {
+    if !DISK_FS_TYPES.contains(&fs_type) {
+        return Err(LinuxErrno::EINVAL);
+    }
+    let fs = system_support_fs(fs_type).ok_or(LinuxErrno::ENODEV)?;
+    let root = fs.i_mount(0, dir, Some(dev), &[])?;
+    register_disk_fs(root.clone());
+    Ok(root)
+}
+
+/// 创建一个 `fs_type` 类型的文件系统用于挂载在 `dir` 上，返回文件系统的根目录
+///
+/// 块设备上的文件系统使用 `source` 指定的块设备，其它文件系统忽略 `source`。procfs、sysfs、devfs 和 devpts
+/// 只有一个实例，挂载时返回初始化时创建的根目录；tmpfs 和 ramfs 每次挂载都创建新的实例。
+/// 文件系统类型不存在时返回 `ENODEV`，`source` 不是块设备时返回 `ENOTBLK`。
+pub fn mount_fs(fs_type: &str, source: &str, dir: &str) -> AlienResult<Arc<dyn VfsDentry>> {
+    // Linux 中的类型名
+    let fs_type = match fs_type {
+        "proc" => "procfs",
+        "devtmpfs" => "devfs",
+        fs_type => fs_type,
+    };
+    if let Some(root) = SHARED_FS.lock().get(fs_type) {
+        return Ok(root.clone());
+    }
+    match fs_type {
+        // devpts 只有一个实例，所有挂载点看到的都是同一组从设备
+        "devpts" => Ok(dev::devpts_root()),
+        // pipefs 只在内核中用于创建管道，不能被挂载
+        "pipefs" => Err(LinuxErrno::EINVAL),
+        _ if DISK_FS_TYPES.contains(&fs_type) => {
+            let root = system_root_fs();
+            let dev = VfsPath::new(root.clone(), root)
+                .join(source)?
+                .open(None)?
+                .inode()?;
+            if dev.inode_type() != VfsNodeType::BlockDevice {
+                return Err(LinuxErrno::ENOTBLK);
+            }
+            mount_disk_fs(fs_type, dev, dir)
+        }
+        _ => {
+            let fs = system_support_fs(fs_type).ok_or(LinuxErrno::ENODEV)?;
+            Ok(fs.i_mount(0, dir, None, &[])?)
+        }
+    }
+}
+
+/// 记录一个挂载在块设备上的文件系统
+pub fn register_disk_fs(root: Arc<dyn VfsDentry>) {
+    DISK_FS.lock().push(root);
+}
+
+/// 文件系统被卸载时删除记录
+pub fn unregister_disk_fs(root: &Arc<dyn VfsDentry>) {
+    DISK_FS
+        .lock()
+        .retain(|fs| !core::ptr::addr_eq(Arc::as_ptr(fs), Arc::as_ptr(root)));
+}
+
+/// 将所有脏数据写回设备，包括页缓存、文件系统的元数据以及块设备缓存，返回时数据已经写入设备
+pub fn sync_all() -> AlienResult<()> {
+    page_cache::sync_all_page_cache()?;
+    let roots = DISK_FS.lock().clone();
+    for root in roots {
+        root.inode()?.get_super_block()?.sync_fs(true)?;
+    }
+    devices::flush_block_device()
+}
+
+/// 将 `inode` 所在文件系统的脏数据写回设备
+pub fn sync_fs(inode: &Arc<dyn VfsInode>) -> AlienResult<()> {
+    page_cache::sync_all_page_cache()?;
+    if let Ok(sb) = inode.get_super_block() {
+        sb.sync_fs(true)?;
+    }
+    devices::flush_block_device()
+}
+
+/// 将 `inode` 的脏数据写回设备
+pub fn fsync_inode(inode: &Arc<dyn VfsInode>) -> AlienResult<()> {
+    if let Some(cache) = page_cache::find_page_cache(inode) {
+        cache.sync()?;
+    }
+    inode.fsync()?;
+    if let Ok(sb) = inode.get_super_block() {
+        sb.sync_fs(true)?;
+    }
+    devices::flush_block_device()
+}
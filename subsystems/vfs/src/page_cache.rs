@@ -0,0 +1,335 @@
+//! 文件页缓存
+//!
+//! 只有通过 [`enable_page_cache`] 登记的文件系统类型(块设备上的文件系统)中的普通文件才会在读写时使用 [`PageCache`]，
+//! 缓存页以文件内的页号为索引。`read`/`write`、内存映射的缺页处理以及 `sendfile`/`copy_file_range`
+//! 都通过 [`KernelFile`](crate::kfile::KernelFile) 访问同一份页缓存。procfs、sysfs、devfs、ramfs 等文件系统的内容
+//! 由 inode 直接提供，只有在被共享映射时才会创建页缓存，此时读写也经过页缓存，以便与映射保持一致。
+//!
+//! 写操作采用写穿透的方式，在写入文件的同时更新已缓存的页，因此普通写入不会产生脏页。共享映射 (`MAP_SHARED`)
+//! 直接映射缓存页，被修改的页通过 [`CachePage::mark_dirty`] 标记为脏页，并由 [`PageCache::write_back`] 写回文件。
+//!
+//! 物理页不足时，[`shrink_page_cache`] 会回收没有被映射的干净页。
+use alloc::{
+    collections::BTreeMap,
+    string::{String, ToString},
+    sync::Arc,
+    vec::Vec,
+};
+use core::{
+    cmp::min,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+use config::FRAME_SIZE;
+use constants::AlienResult;
+use ksync::Mutex;
+use mem::{alloc_frame_trackers, register_shrinker, FrameTracker};
+use spin::Lazy;
+use vfscore::{inode::VfsInode, utils::VfsNodeType};
+
+/// 顺序读取时一次预读的页数
+const READAHEAD_PAGES: usize = 8;
+
+/// 全局的页缓存表，以 inode 的地址为键
+static PAGE_CACHES: Lazy<Mutex<BTreeMap<usize, Arc<PageCache>>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// 读写时使用页缓存的文件系统类型名
+static CACHED_FS_TYPES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// 一个缓存页
+#[derive(Debug)]
+pub struct CachePage {
+    frame: FrameTracker,
+    dirty: AtomicBool,
+    /// 最近是否被访问过，用于回收时给予第二次机会
+    referenced: AtomicBool,
+}
+
+impl CachePage {
+    fn new(frame: FrameTracker) -> Self {
+        Self {
+            frame,
+            dirty: AtomicBool::new(false),
+            referenced: AtomicBool::new(true),
+        }
+    }
+
+    /// 缓存页的物理地址
+    pub fn phys_addr(&self) -> usize {
+        self.frame.start()
+    }
+
+    pub fn mark_dirty(&self) {
+        self.dirty.store(true, Ordering::Release);
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        self.dirty.load(Ordering::Acquire)
+    }
+
+    fn as_slice(&self) -> &[u8] {
+        &self.frame[..FRAME_SIZE]
+    }
+
+    /// 缓存页可能同时被映射到用户地址空间中，与用户态对映射的访问一样，这里不做同步
+    #[allow(clippy::mut_from_ref)]
+    fn as_mut_slice(&self) -> &mut [u8] {
+        unsafe { core::slice::from_raw_parts_mut(self.frame.as_ptr(), FRAME_SIZE) }
+    }
+}
+
+/// 一个文件的页缓存
+pub struct PageCache {
+    inode: Arc<dyn VfsInode>,
+    pages: Mutex<BTreeMap<usize, Arc<CachePage>>>,
+    /// 顺序读取时下一次期望访问的页号，用于判断是否需要预读
+    next_index: AtomicUsize,
+}
+
+impl PageCache {
+    fn new(inode: Arc<dyn VfsInode>) -> Self {
+        Self {
+            inode,
+            pages: Mutex::new(BTreeMap::new()),
+            next_index: AtomicUsize::new(0),
+        }
+    }
+
+    fn file_size(&self) -> AlienResult<usize> {
+        Ok(self.inode.get_attr()?.st_size as usize)
+    }
+
+    /// 获取文件中第 `index` 页的缓存页，如果还未缓存，则从文件中读入
+    ///
+    /// 如果访问是顺序的，会同时预读之后的 [`READAHEAD_PAGES`] 页。超出文件末尾的部分以 0 填充。
+    pub fn get_page(&self, index: usize) -> AlienResult<Arc<CachePage>> {
+        let sequential = self.next_index.swap(index + 1, Ordering::Relaxed) == index;
+        if let Some(page) = self.pages.lock().get(&index) {
+            page.referenced.store(true, Ordering::Relaxed);
+            return Ok(page.clone());
+        }
+        let window = if sequential {
+            let size_pages = (self.file_size()? + FRAME_SIZE - 1) / FRAME_SIZE;
+            min(READAHEAD_PAGES, size_pages.saturating_sub(index)).max(1)
+        } else {
+            1
+        };
+        for ra_index in (index + 1..index + window).rev() {
+            if !self.pages.lock().contains_key(&ra_index) {
+                let page = self.read_page(ra_index)?;
+                self.pages.lock().entry(ra_index).or_insert(page);
+            }
+        }
+        // the frame must be allocated without holding the lock, the shrinker may be called
+        let page = self.read_page(index)?;
+        let page = self.pages.lock().entry(index).or_insert(page).clone();
+        Ok(page)
+    }
+
+    /// 从文件中读入第 `index` 页
+    fn read_page(&self, index: usize) -> AlienResult<Arc<CachePage>> {
+        let mut frame = alloc_frame_trackers(1);
+        frame.fill(0);
+        self.inode
+            .read_at((index * FRAME_SIZE) as u64, &mut frame[..FRAME_SIZE])?;
+        Ok(Arc::new(CachePage::new(frame)))
+    }
+
+    /// 通过页缓存从文件的 `offset` 处读取数据
+    pub fn read(&self, offset: u64, buf: &mut [u8]) -> AlienResult<usize> {
+        let size = self.file_size()?;
+        let offset = offset as usize;
+        if offset >= size {
+            return Ok(0);
+        }
+        let len = min(buf.len(), size - offset);
+        let mut count = 0;
+        while count < len {
+            let pos = offset + count;
+            let page = self.get_page(pos / FRAME_SIZE)?;
+            let page_offset = pos % FRAME_SIZE;
+            let n = min(FRAME_SIZE - page_offset, len - count);
+            buf[count..count + n].copy_from_slice(&page.as_slice()[page_offset..page_offset + n]);
+            count += n;
+        }
+        Ok(len)
+    }
+
+    /// 向文件的 `offset` 处写入数据，并更新已缓存的页
+    pub fn write(&self, offset: u64, buf: &[u8]) -> AlienResult<usize> {
+        let write = self.inode.write_at(offset, buf)?;
+        let offset = offset as usize;
+        let pages = self.pages.lock();
+        for (index, page) in pages.range(offset / FRAME_SIZE..=(offset + write) / FRAME_SIZE) {
+            let page_start = index * FRAME_SIZE;
+            let start = offset.max(page_start);
+            let end = min(offset + write, page_start + FRAME_SIZE);
+            if start >= end {
+                continue;
+            }
+            page.as_mut_slice()[start - page_start..end - page_start]
+                .copy_from_slice(&buf[start - offset..end - offset]);
+        }
+        Ok(write)
+    }
+
+    /// 文件大小被修改为 `size` 后调用，丢弃超出文件末尾的缓存
+    ///
+    /// 仍被映射的页无法丢弃，超出文件末尾的部分会被清零。
+    pub fn truncate(&self, size: usize) {
+        let mut pages = self.pages.lock();
+        pages.retain(|index, page| {
+            let page_start = index * FRAME_SIZE;
+            if page_start + FRAME_SIZE <= size {
+                return true;
+            }
+            let keep = Arc::strong_count(page) > 1;
+            if keep || page_start < size {
+                let start = size.saturating_sub(page_start);
+                page.as_mut_slice()[start..].fill(0);
+                return true;
+            }
+            false
+        });
+    }
+
+    /// 如果第 `index` 页被缓存并且是脏页，将其写回文件
+    ///
+    /// 只会写回位于文件大小之内的部分，共享映射无法扩展文件。
+    pub fn write_back(&self, index: usize) -> AlienResult<()> {
+        let page = match self.pages.lock().get(&index) {
+            Some(page) => page.clone(),
+            None => return Ok(()),
+        };
+        if !page.dirty.swap(false, Ordering::AcqRel) {
+            return Ok(());
+        }
+        let offset = index * FRAME_SIZE;
+        let size = self.file_size()?;
+        if offset >= size {
+            return Ok(());
+        }
+        let len = min(FRAME_SIZE, size - offset);
+        let res = self.inode.write_at(offset as u64, &page.as_slice()[..len]);
+        if res.is_err() {
+            page.mark_dirty();
+        }
+        res?;
+        Ok(())
+    }
+
+    /// 将所有脏页写回文件
+    pub fn sync(&self) -> AlienResult<()> {
+        let indexes = self
+            .pages
+            .lock()
+            .iter()
+            .filter(|(_, page)| page.is_dirty())
+            .map(|(index, _)| *index)
+            .collect::<Vec<_>>();
+        for index in indexes {
+            self.write_back(index)?;
+        }
+        Ok(())
+    }
+
+    /// 回收最多 `count` 个没有被映射的干净页，最近访问过的页会被跳过一次，返回回收的页数
+    fn shrink(&self, count: usize) -> usize {
+        let mut pages = self.pages.lock();
+        let mut freed = 0;
+        pages.retain(|_, page| {
+            if freed >= count || Arc::strong_count(page) > 1 || page.is_dirty() {
+                return true;
+            }
+            if page.referenced.swap(false, Ordering::Relaxed) {
+                return true;
+            }
+            freed += 1;
+            false
+        });
+        freed
+    }
+}
+
+/// 获取 `inode` 对应的页缓存，如果不存在则创建
+pub fn page_cache(inode: &Arc<dyn VfsInode>) -> Arc<PageCache> {
+    let key = Arc::as_ptr(inode) as *const u8 as usize;
+    PAGE_CACHES
+        .lock()
+        .entry(key)
+        .or_insert_with(|| Arc::new(PageCache::new(inode.clone())))
+        .clone()
+}
+
+/// 让 `fs_name` 类型的文件系统中的普通文件在读写时使用页缓存
+pub fn enable_page_cache(fs_name: &str) {
+    let mut types = CACHED_FS_TYPES.lock();
+    if !types.iter().any(|name| name == fs_name) {
+        types.push(fs_name.to_string());
+    }
+}
+
+/// `inode` 所在的文件系统是否在读写时使用页缓存
+fn uses_page_cache(inode: &Arc<dyn VfsInode>) -> bool {
+    let Ok(sb) = inode.get_super_block() else {
+        return false;
+    };
+    let fs_name = sb.fs_type().fs_name();
+    CACHED_FS_TYPES.lock().iter().any(|name| *name == fs_name)
+}
+
+/// 读写 `inode` 时使用的页缓存
+///
+/// 使用页缓存的文件系统中的普通文件总是返回页缓存，其它文件只有在页缓存已经存在(被共享映射过)时才返回。
+pub fn file_page_cache(inode: &Arc<dyn VfsInode>) -> Option<Arc<PageCache>> {
+    if inode.inode_type() != VfsNodeType::File {
+        return None;
+    }
+    if uses_page_cache(inode) {
+        Some(page_cache(inode))
+    } else {
+        find_page_cache(inode)
+    }
+}
+
+/// 如果 `inode` 存在页缓存，返回该页缓存
+pub fn find_page_cache(inode: &Arc<dyn VfsInode>) -> Option<Arc<PageCache>> {
+    let key = Arc::as_ptr(inode) as *const u8 as usize;
+    PAGE_CACHES.lock().get(&key).cloned()
+}
+
+/// 将所有文件的脏页写回文件
+pub fn sync_all_page_cache() -> AlienResult<()> {
+    let caches = PAGE_CACHES.lock().values().cloned().collect::<Vec<_>>();
+    for cache in caches {
+        cache.sync()?;
+    }
+    Ok(())
+}
+
+/// 内存不足时回收页缓存，返回回收的页数
+///
+/// 每个缓存页至多需要两轮扫描才会被回收，没有缓存页且未被使用的页缓存会被一并删除。
+pub fn shrink_page_cache(count: usize) -> usize {
+    let caches = PAGE_CACHES.lock().values().cloned().collect::<Vec<_>>();
+    let mut freed = 0;
+    for _ in 0..2 {
+        for cache in caches.iter() {
+            if freed >= count {
+                break;
+            }
+            freed += cache.shrink(count - freed);
+        }
+    }
+    drop(caches);
+    PAGE_CACHES
+        .lock()
+        .retain(|_, cache| Arc::strong_count(cache) > 1 || !cache.pages.lock().is_empty());
+    freed
+}
+
+/// 注册页缓存的内存回收回调
+pub fn init_page_cache() {
+    register_shrinker(shrink_page_cache);
+}
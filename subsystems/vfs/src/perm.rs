@@ -0,0 +1,128 @@
+//! 文件的属主和权限位
+//!
+//! 新建文件以及 `chown`/`chmod` 设置的属主和权限位通过 `set_attr` 保存在 inode 中，由文件系统负责持久化。
+//!
+//! devfs、ramfs 等内存文件系统中 inode 与文件的生命周期相同，文件系统不保存属主时，属主和权限位保存在以 inode 的地址为键的表中，
+//! `stat` 和权限检查时使用表中的值覆盖文件系统报告的值。其它无法保存属主的文件系统(例如 fat32、procfs)返回 `EPERM`，
+//! 以免 inode 被重新创建后属主和权限位恢复为文件系统的默认值。
+use alloc::{
+    collections::BTreeMap,
+    sync::{Arc, Weak},
+};
+
+use constants::{AlienResult, LinuxErrno};
+use ksync::Mutex;
+use spin::Lazy;
+use vfscore::{
+    inode::{InodeAttr, VfsInode},
+    utils::VfsFileStat,
+};
+
+/// 权限位以及 set-user-ID、set-group-ID 和 sticky 位
+pub const MODE_MASK: u32 = 0o7777;
+pub const S_ISUID: u32 = 0o4000;
+pub const S_ISGID: u32 = 0o2000;
+pub const S_ISVTX: u32 = 0o1000;
+
+/// inode 与文件同生共死的内存文件系统，文件系统不保存属主时可以使用属主表
+const OWNER_TABLE_FS: &[&str] = &["ramfs", "tmpfs", "devfs"];
+
+/// 内存文件系统的属主表，以 inode 的地址为键
+static INODE_OWNERS: Lazy<Mutex<BTreeMap<usize, (Weak<dyn VfsInode>, InodeOwner)>>> =
+    Lazy::new(|| Mutex::new(BTreeMap::new()));
+
+/// 文件的属主和权限位
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InodeOwner {
+    pub uid: u32,
+    pub gid: u32,
+    /// 低 12 位，即 [`MODE_MASK`] 中的部分
+    pub mode: u32,
+}
+
+fn inode_key(inode: &Arc<dyn VfsInode>) -> usize {
+    Arc::as_ptr(inode) as *const u8 as usize
+}
+
+/// 查找 `inode` 在表中的记录，同时清理已经失效的记录
+fn lookup(inode: &Arc<dyn VfsInode>) -> Option<InodeOwner> {
+    let key = inode_key(inode);
+    let mut owners = INODE_OWNERS.lock();
+    let (weak, owner) = owners.get(&key)?;
+    match weak.upgrade() {
+        Some(live) if Arc::ptr_eq(&live, inode) => Some(*owner),
+        _ => {
+            owners.remove(&key);
+            None
+        }
+    }
+}
+
+/// 文件系统报告的属主和权限位
+fn fs_owner(inode: &Arc<dyn VfsInode>) -> AlienResult<InodeOwner> {
+    let attr = inode.get_attr()?;
+    let perm = u32::from(inode.node_perm().bits());
+    Ok(InodeOwner {
+        uid: attr.st_uid as u32,
+        gid: attr.st_gid as u32,
+        mode: (attr.st_mode as u32 & (S_ISUID | S_ISGID | S_ISVTX)) | (perm & 0o777),
+    })
+}
+
+/// `inode` 所在的文件系统能否使用属主表
+fn can_use_owner_table(inode: &Arc<dyn VfsInode>) -> bool {
+    inode
+        .get_super_block()
+        .map(|sb| OWNER_TABLE_FS.contains(&sb.fs_type().fs_name().as_str()))
+        .unwrap_or(false)
+}
+
+/// 获取文件的属主和权限位
+pub fn inode_owner(inode: &Arc<dyn VfsInode>) -> AlienResult<InodeOwner> {
+    match lookup(inode) {
+        Some(owner) => Ok(owner),
+        None => fs_owner(inode),
+    }
+}
+
+/// 设置文件的属主和权限位
+///
+/// 文件系统无法保存属主和权限位、并且不是内存文件系统时返回 `EPERM`。
+pub fn set_inode_owner(inode: &Arc<dyn VfsInode>, owner: InodeOwner) -> AlienResult<()> {
+    let owner = InodeOwner {
+        mode: owner.mode & MODE_MASK,
+        ..owner
+    };
+    let stat = inode.get_attr()?;
+    let stored = inode
+        .set_attr(InodeAttr {
+            mode: owner.mode,
+            uid: owner.uid,
+            gid: owner.gid,
+            atime: stat.st_atime,
+            mtime: stat.st_mtime,
+            ctime: stat.st_ctime,
+        })
+        .is_ok()
+        && fs_owner(inode)? == owner;
+    if stored {
+        INODE_OWNERS.lock().remove(&inode_key(inode));
+        return Ok(());
+    }
+    if !can_use_owner_table(inode) {
+        return Err(LinuxErrno::EPERM);
+    }
+    INODE_OWNERS
+        .lock()
+        .insert(inode_key(inode), (Arc::downgrade(inode), owner));
+    Ok(())
+}
+
+/// 使用表中的记录覆盖 `stat` 中的属主和权限位
+pub fn apply_inode_owner(inode: &Arc<dyn VfsInode>, stat: &mut VfsFileStat) {
+    if let Some(owner) = lookup(inode) {
+        stat.st_uid = owner.uid as _;
+        stat.st_gid = owner.gid as _;
+        stat.st_mode = ((stat.st_mode as u32 & !MODE_MASK) | owner.mode) as _;
+    }
+}
@@ -4,17 +4,19 @@ mod mem;
 mod mounts;
 
 use alloc::sync::Arc;
-use core::ops::Index;
 
 use dynfs::DynFsDirInode;
 use filesystem::SystemSupportFS;
 use interrupt::InterruptRecord;
 use mem::MemInfo;
 use mounts::MountInfo;
-use vfscore::{dentry::VfsDentry, error::VfsError, fstype::VfsFsType, path::VfsPath};
+use spin::Once;
+use vfscore::{dentry::VfsDentry, error::VfsError, fstype::VfsFsType};
 
-use crate::{CommonFsProviderImpl, FS};
+use crate::CommonFsProviderImpl;
 pub type ProcFsDirInodeImpl = DynFsDirInode<CommonFsProviderImpl, spin::Mutex<()>>;
+/// procfs 的根目录，内核通过它动态地添加或删除 `/proc/<pid>` 和 `/proc/self`
+pub static PROC_FS_ROOT: Once<Arc<dyn VfsDentry>> = Once::new();
 
 ///
 /// ```bash
@@ -24,6 +26,8 @@ pub type ProcFsDirInodeImpl = DynFsDirInode<CommonFsProviderImpl, spin::Mutex<()
 /// |-- mounts
 /// |-- filesystems
 /// ```
+///
+/// 与进程相关的 `self` 和 `<pid>` 目录由内核在创建进程时添加。
 // todo!(use ramfs instead of dynfs)
 pub fn init_procfs(procfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
     let root_dt = procfs.i_mount(0, "/proc", None, &[]).unwrap();
@@ -46,20 +50,7 @@ pub fn init_procfs(procfs: Arc<dyn VfsFsType>) -> Arc<dyn VfsDentry> {
         .add_file_manually("filesystems", Arc::new(support_fs), "r--r--r--".into())
         .unwrap();
 
-    root_inode
-        .add_dir_manually("self", "r-xr-xr-x".into())
-        .unwrap();
-
-    let path = VfsPath::new(root_dt.clone(), root_dt.clone());
-    let ramfs = FS.lock().index("ramfs").clone();
-    let fake_ramfs = ramfs.i_mount(0, "/proc/self", None, &[]).unwrap();
-    path.join("self").unwrap().mount(fake_ramfs, 0).unwrap();
-
-    path.join("self/exe")
-        .unwrap()
-        .symlink("/bin/busybox")
-        .unwrap();
-
+    PROC_FS_ROOT.call_once(|| root_dt.clone());
     println!("procfs init success");
 
     root_dt
@@ -1,4 +1,7 @@
-use alloc::sync::Arc;
+use alloc::{
+    sync::{Arc, Weak},
+    vec::Vec,
+};
 use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use constants::{
@@ -6,7 +9,7 @@ use constants::{
     time::{ClockId, ITimeSpec, TimeSpec},
     AlienError, AlienResult,
 };
-use ksync::Mutex;
+use ksync::{poll::PollQueue, wait::WaitQueue, Mutex};
 use timer::{TimeNow, ToClock};
 use vfscore::{dentry::VfsDentry, inode::VfsInode, utils::VfsFileStat};
 
@@ -23,6 +26,41 @@ pub struct TimerFile {
     disable: AtomicBool,
     #[allow(unused)]
     id: ClockId,
+    /// Notified when the timer expires
+    poll_queue: PollQueue,
+    /// Readers blocked until the timer expires or is reset
+    wait_queue: WaitQueue,
+    /// Incremented every time the timer is reset
+    version: AtomicUsize,
+}
+
+/// All timerfds, checked on every timer interrupt so that epoll is notified when they expire
+static TIMERFDS: Mutex<Vec<Weak<TimerFile>>> = Mutex::new(Vec::new());
+
+/// Register a timerfd so that it is checked by [`check_timerfds`]
+pub fn register_timerfd(file: &Arc<TimerFile>) {
+    let mut timerfds = TIMERFDS.lock();
+    timerfds.retain(|f| f.strong_count() > 0);
+    timerfds.push(Arc::downgrade(file));
+}
+
+/// Called on timer interrupt, notify the waiters of the timerfds that have expired
+pub fn check_timerfds() {
+    let files = {
+        let mut timerfds = TIMERFDS.lock();
+        timerfds.retain(|f| f.strong_count() > 0);
+        timerfds
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect::<Vec<_>>()
+    };
+    files
+        .into_iter()
+        .filter(|file| file.calculate_ticks() != 0)
+        .for_each(|file| {
+            file.wait_queue.wake_all();
+            file.poll_queue.notify(PollEvents::EPOLLIN);
+        });
 }
 
 impl TimerFile {
@@ -35,6 +73,9 @@ impl TimerFile {
             timer_next_clock: AtomicUsize::new(0),
             disable: AtomicBool::new(true),
             id,
+            poll_queue: PollQueue::new(),
+            wait_queue: WaitQueue::new(),
+            version: AtomicUsize::new(0),
         }
     }
 
@@ -61,11 +102,15 @@ impl TimerFile {
         self.timer_next_clock.store(next_clock, Ordering::Relaxed);
         self.timer_interval_clock
             .store(interval_clock, Ordering::Relaxed);
+        self.version.fetch_add(1, Ordering::AcqRel);
+        // readers sleeping on the old expiration time need to recalculate it
+        self.wait_queue.wake_all();
     }
 
-    pub fn calculate_ticks(&self) {
+    /// Update the number of expirations, return the number of new expirations
+    pub fn calculate_ticks(&self) -> usize {
         if self.disable.load(Ordering::Relaxed) {
-            return;
+            return 0;
         }
         let now = TimeSpec::now().to_clock();
         let mut t_ticks = 0;
@@ -78,11 +123,27 @@ impl TimerFile {
                 let nums = diff / interval_clock;
                 t_ticks += nums;
             }
-            // update next_clock
-            let next_clock = now + interval_clock;
-            self.timer_next_clock.store(next_clock, Ordering::Relaxed);
+            // update next_clock, the timer interrupt may be checking the timer at the same time
+            let next_clock_new = now + interval_clock;
+            if self
+                .timer_next_clock
+                .compare_exchange(
+                    next_clock,
+                    next_clock_new,
+                    Ordering::Relaxed,
+                    Ordering::Relaxed,
+                )
+                .is_err()
+            {
+                return 0;
+            }
+            if interval_clock == 0 {
+                // a one-shot timer only expires once
+                self.disable.store(true, Ordering::Relaxed);
+            }
             self.ticks.fetch_add(t_ticks, Ordering::Relaxed);
         }
+        t_ticks
     }
 }
 
@@ -92,6 +153,7 @@ impl File for TimerFile {
             return Err(AlienError::EINVAL);
         }
         let ticks = loop {
+            let version = self.version.load(Ordering::Acquire);
             self.calculate_ticks();
             let ticks = self.ticks.load(Ordering::Relaxed);
             if ticks != 0 {
@@ -101,8 +163,20 @@ impl File for TimerFile {
             }
             if self.flags.contains(OpenFlags::O_NONBLOCK) {
                 return Err(AlienError::EAGAIN);
-            } else {
-                shim::suspend();
+            }
+            // sleep until the next expiration or until the timer is reset
+            let deadline = (!self.disable.load(Ordering::Relaxed))
+                .then(|| self.timer_next_clock.load(Ordering::Relaxed));
+            let res = self.wait_queue.wait_event_interruptible_timeout(
+                || {
+                    self.ticks.load(Ordering::Relaxed) != 0
+                        || self.version.load(Ordering::Acquire) != version
+                },
+                deadline,
+            );
+            match res {
+                Ok(()) | Err(AlienError::ETIMEDOUT) => {}
+                Err(e) => return Err(e),
             }
         };
         let bytes = ticks.to_ne_bytes();
@@ -152,9 +226,14 @@ impl File for TimerFile {
         true
     }
     fn poll(&self, event: PollEvents) -> AlienResult<PollEvents> {
+        self.calculate_ticks();
         if self.ticks.load(Ordering::Relaxed) != 0 && event.contains(PollEvents::EPOLLIN) {
             return Ok(PollEvents::EPOLLIN);
         }
         Ok(PollEvents::empty())
     }
+
+    fn poll_queue(&self) -> Option<&PollQueue> {
+        Some(&self.poll_queue)
+    }
 }
@@ -28,7 +28,7 @@ fn main() -> isize {
             loop {
                 let mut exit_code: i32 = 0;
                 let tid = wait(&mut exit_code);
-                if tid == -1 {
+                if tid < 0 {
                     m_yield();
                     continue;
                 }